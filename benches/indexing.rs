@@ -0,0 +1,319 @@
+use std::{fs, hint::black_box, sync::Arc};
+
+use acropolis_common::{BlockHash, BlockInfo, BlockIntent, BlockStatus, Era};
+use acropolis_module_custom_indexer::chain_index::ChainIndex;
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use pallas_traverse::MultiEraBlock;
+use scooper_v2::{
+    bigint::BigInt,
+    cardano_types::{ADA_ASSET_CLASS, AssetClass, TransactionInput},
+    historical_state::{BlockMeta, HistoricalState},
+    multisig::Multisig,
+    persistence::{
+        self, NoOpSundaeV3Dao, PersistedTxo, PersistenceConfig, SundaeV3Dao, SundaeV3TxChanges,
+    },
+    protocol::DEFAULT_ADA_RIDER,
+    sundaev3::{
+        Destination, Ident, Order, OrderDatum, PoolDatum, SingletonValue, SundaeV3HistoricalState,
+        SundaeV3Indexer, SundaeV3State, SwapDirection, ValidationProfile, empty_cons, explain_swap,
+        get_pool_reserves, pool_nft_asset, swap_direction, swap_fee, swap_takes, validate_order,
+    },
+    value,
+};
+use tokio::{
+    runtime::Runtime,
+    sync::{Mutex, broadcast, watch},
+};
+
+const BLOCK: &str = "testdata/scoop-pool.block";
+
+fn block_info(block: &MultiEraBlock) -> BlockInfo {
+    BlockInfo {
+        status: BlockStatus::Volatile,
+        intent: BlockIntent::none(),
+        slot: block.slot(),
+        number: 1,
+        hash: BlockHash::new(*block.hash()),
+        epoch: 0,
+        epoch_slot: 0,
+        new_epoch: false,
+        tip_slot: None,
+        timestamp: 0,
+        era: Era::Conway,
+    }
+}
+
+fn new_indexer(state: Arc<Mutex<SundaeV3HistoricalState>>) -> SundaeV3Indexer {
+    let protocol = serde_json::from_reader(fs::File::open("testdata/protocol").unwrap()).unwrap();
+    SundaeV3Indexer::new(
+        state,
+        watch::Sender::default(),
+        broadcast::channel(16).0,
+        protocol,
+        2160,
+        Box::new(NoOpSundaeV3Dao),
+    )
+}
+
+/// Decodes and filters every tx of a block with a scoop in it, the work the
+/// indexer does for each block it's sent.
+fn bench_ingest_block(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let bytes = fs::read(BLOCK).unwrap();
+    let block = MultiEraBlock::decode(&bytes).unwrap();
+    let info = block_info(&block);
+    let txs: Vec<Vec<u8>> = block.txs().iter().map(|tx| tx.encode()).collect();
+
+    c.bench_function("ingest scoop block", |b| {
+        b.to_async(&runtime).iter_batched(
+            || new_indexer(Arc::default()),
+            |mut indexer| {
+                let (info, txs) = (&info, &txs);
+                async move {
+                    for tx in txs {
+                        indexer.handle_onchain_tx_bytes(info, tx).await.unwrap();
+                    }
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+/// Applies a block over a state with thousands of pools and orders, which
+/// copies the state into a new slot of history.
+fn bench_commit_block(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let bytes = fs::read(BLOCK).unwrap();
+    let block = MultiEraBlock::decode(&bytes).unwrap();
+    let info = block_info(&block);
+
+    // Pad out the state the block leaves behind
+    let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+    runtime.block_on(async {
+        let mut indexer = new_indexer(state.clone());
+        for tx in block.txs() {
+            indexer
+                .handle_onchain_tx_bytes(&info, &tx.encode())
+                .await
+                .unwrap();
+        }
+    });
+    let mut large = runtime.block_on(state.lock()).latest().into_owned();
+    let pool = large.pools.values().next().cloned().unwrap();
+    for index in 0..5_000u32 {
+        let mut ident = [0; 28];
+        ident[..4].copy_from_slice(&index.to_be_bytes());
+        large.pools.insert(Ident::new(&ident), pool.clone());
+    }
+    if let Some(order) = large.orders.first().cloned() {
+        large.orders.resize(20_000, order);
+    }
+
+    let meta = |slot: u64| BlockMeta {
+        slot,
+        height: slot,
+        hash: BlockHash::new([0; 32]),
+        era: Era::Conway,
+    };
+    c.bench_function("commit_block over a large state", |b| {
+        b.iter_batched(
+            || {
+                let mut history = HistoricalState::<SundaeV3State>::new();
+                history.restore(0, large.clone());
+                history
+            },
+            |mut history| {
+                history
+                    .commit_block(&meta(1), |state| {
+                        black_box(state);
+                    })
+                    .unwrap();
+                history
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+/// Runs a scoop's worth of swaps against one pool, alternating direction so the
+/// reserves stay balanced.
+fn bench_swap_replay(c: &mut Criterion) {
+    let fee = BigInt::from(30);
+    let replay = |reserves: (BigInt, BigInt), gives: BigInt| {
+        let (mut a, mut b) = reserves;
+        for i in 0..40 {
+            let (give_reserve, take_reserve) = if i % 2 == 0 {
+                (&mut a, &mut b)
+            } else {
+                (&mut b, &mut a)
+            };
+            let takes = swap_takes(give_reserve, take_reserve, &gives, &fee).unwrap();
+            *give_reserve += &gives;
+            *take_reserve = &*take_reserve - &takes;
+        }
+        (a, b)
+    };
+
+    let mut group = c.benchmark_group("replay 40 swaps");
+    group.bench_function("u64 reserves", |bench| {
+        bench.iter(|| {
+            replay(
+                (
+                    BigInt::from(1_000_000_000_000i64),
+                    BigInt::from(4_000_000_000_000i64),
+                ),
+                black_box(BigInt::from(10_000_000)),
+            )
+        })
+    });
+    let huge = BigInt::from(u64::MAX) * BigInt::from(u64::MAX);
+    group.bench_function("reserves past i128", |bench| {
+        bench.iter(|| {
+            replay(
+                (huge.clone(), huge.clone()),
+                black_box(BigInt::from(u64::MAX)),
+            )
+        })
+    });
+    group.finish();
+
+    c.bench_function("explain_swap", |bench| {
+        let (a, b) = (
+            BigInt::from(1_000_000_000_000i64),
+            BigInt::from(4_000_000_000_000i64),
+        );
+        bench.iter(|| explain_swap(&a, &b, black_box(&BigInt::from(10_000_000)), &fee))
+    });
+}
+
+/// Validates 40 orders against their pool and replays their swaps, the
+/// per-order work of building a 40-order scoop.
+fn bench_scoop_orders(c: &mut Criterion) {
+    let policy = [0xaa; 28];
+    let rberry = AssetClass::from_pair((vec![0x66; 28], b"RBERRY".to_vec()));
+    let pool = PoolDatum {
+        ident: Ident::new(&[1; 28]),
+        assets: (ADA_ASSET_CLASS, rberry.clone()),
+        circulating_lp: BigInt::from(2_000_000_000_000i64),
+        bid_fees_per_10_thousand: BigInt::from(30),
+        ask_fees_per_10_thousand: BigInt::from(30),
+        fee_manager: None,
+        market_open: BigInt::from(0),
+        protocol_fees: BigInt::from(3_000_000),
+    };
+    let pool_value = value!(
+        1_000_003_000_000,
+        (&rberry, 4_000_000_000_000),
+        (&pool_nft_asset(&policy, &pool.ident), 1)
+    );
+
+    let side = |asset: &AssetClass, amount: i64| SingletonValue {
+        policy: asset.policy.to_vec(),
+        token: asset.token.to_vec(),
+        amount: BigInt::from(amount),
+    };
+    let scoop_fee = 1_000_000;
+    let extra_ada = DEFAULT_ADA_RIDER as i128 + scoop_fee as i128;
+    let orders: Vec<_> = (0..40)
+        .map(|i| {
+            let (action, value) = if i % 2 == 0 {
+                (
+                    Order::Swap(side(&ADA_ASSET_CLASS, 10_000_000), side(&rberry, 1)),
+                    value!(10_000_000 + extra_ada,),
+                )
+            } else {
+                (
+                    Order::Swap(side(&rberry, 40_000_000), side(&ADA_ASSET_CLASS, 1)),
+                    value!(extra_ada, (&rberry, 40_000_000)),
+                )
+            };
+            let datum = OrderDatum {
+                ident: Some(pool.ident.clone()),
+                owner: Multisig::Signature(vec![0; 28]),
+                scoop_fee: BigInt::from(scoop_fee),
+                destination: Destination::SelfDestination,
+                action,
+                extra: empty_cons(),
+            };
+            (datum, value)
+        })
+        .collect();
+
+    c.bench_function("scoop 40 orders", |bench| {
+        bench.iter(|| {
+            let (mut a, mut b) = get_pool_reserves(&pool, &pool_value);
+            for (datum, value) in &orders {
+                validate_order(
+                    datum,
+                    value,
+                    &pool,
+                    &pool_value,
+                    &policy,
+                    DEFAULT_ADA_RIDER,
+                    ValidationProfile::Strict,
+                )
+                .unwrap();
+                let Order::Swap(gives, takes) = &datum.action else {
+                    unreachable!();
+                };
+                let direction = swap_direction(gives, takes);
+                let fee = swap_fee(&pool, &direction);
+                let (give_reserve, take_reserve) = match direction {
+                    SwapDirection::AtoB => (&mut a, &mut b),
+                    SwapDirection::BtoA => (&mut b, &mut a),
+                };
+                let out = swap_takes(give_reserve, take_reserve, &gives.amount, fee).unwrap();
+                *give_reserve += &gives.amount;
+                *take_reserve -= &out;
+            }
+            black_box((a, b))
+        })
+    });
+}
+
+/// Writes a tx's worth of txo changes to an in-memory sqlite database.
+fn bench_apply_tx_changes(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let persistence = runtime
+        .block_on(persistence::connect(&PersistenceConfig::default()))
+        .unwrap();
+    let dao = persistence.sundae_v3_dao();
+    let dao = dao.as_ref();
+    let txo = fs::read(BLOCK).unwrap()[..256].to_vec();
+
+    let mut slot = 0;
+    c.bench_function("sqlite apply_tx_changes", |b| {
+        b.to_async(&runtime).iter_batched(
+            || {
+                slot += 1;
+                let mut changes = SundaeV3TxChanges::new(slot, slot);
+                let mut hash = [0; 32];
+                hash[..8].copy_from_slice(&slot.to_be_bytes());
+                for index in 0..4 {
+                    changes.created_txos.push(PersistedTxo {
+                        txo_id: TransactionInput::new(hash.into(), index),
+                        txo_type: "order".to_string(),
+                        created_slot: slot,
+                        created_block_hash: Some(vec![0; 32]),
+                        era: 6,
+                        txo: txo.clone(),
+                    });
+                }
+                changes
+            },
+            |changes| async move { dao.apply_tx_changes(changes).await.unwrap() },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_ingest_block,
+    bench_commit_block,
+    bench_swap_replay,
+    bench_scoop_orders,
+    bench_apply_tx_changes
+);
+criterion_main!(benches);
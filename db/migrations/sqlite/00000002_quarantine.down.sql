@@ -0,0 +1,2 @@
+DROP INDEX sundae_v3_quarantined_txos_slot_idx;
+DROP TABLE sundae_v3_quarantined_txos;
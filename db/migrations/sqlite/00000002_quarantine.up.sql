@@ -0,0 +1,11 @@
+CREATE TABLE sundae_v3_quarantined_txos (
+    tx_id BLOB NOT NULL,
+    txo_index INT NOT NULL,
+    txo_type TEXT NOT NULL,
+    slot BIGINT NOT NULL,
+    era INT NOT NULL,
+    reason TEXT NOT NULL,
+    txo BLOB NOT NULL,
+    PRIMARY KEY (tx_id, txo_index)
+);
+CREATE INDEX sundae_v3_quarantined_txos_slot_idx ON sundae_v3_quarantined_txos (slot);
@@ -0,0 +1,10 @@
+CREATE TABLE sundae_v3_pool_reserves (
+    ident BLOB NOT NULL,
+    epoch BIGINT NOT NULL,
+    slot BIGINT NOT NULL,
+    reserve_a TEXT NOT NULL,
+    reserve_b TEXT NOT NULL,
+    circulating_lp TEXT NOT NULL,
+    PRIMARY KEY (ident, epoch)
+);
+CREATE INDEX sundae_v3_pool_reserves_slot_idx ON sundae_v3_pool_reserves (slot);
@@ -0,0 +1,6 @@
+CREATE TABLE api_keys (
+    name TEXT NOT NULL PRIMARY KEY,
+    secret_hash BLOB NOT NULL UNIQUE,
+    pools TEXT NOT NULL,
+    admin BOOLEAN NOT NULL
+);
@@ -0,0 +1,6 @@
+CREATE TABLE sundae_v3_pool_txs (
+    tx_hash BLOB NOT NULL PRIMARY KEY,
+    slot BIGINT NOT NULL,
+    tx BLOB NOT NULL
+);
+CREATE INDEX sundae_v3_pool_txs_slot_idx ON sundae_v3_pool_txs (slot);
@@ -0,0 +1,2 @@
+DROP INDEX sundae_v3_settings_history_slot_idx;
+DROP TABLE sundae_v3_settings_history;
@@ -0,0 +1,8 @@
+CREATE TABLE sundae_v3_settings_history (
+    tx_id BLOB NOT NULL,
+    txo_index BIGINT NOT NULL,
+    slot BIGINT NOT NULL,
+    datum BLOB NOT NULL,
+    PRIMARY KEY (tx_id, txo_index)
+);
+CREATE INDEX sundae_v3_settings_history_slot_idx ON sundae_v3_settings_history (slot);
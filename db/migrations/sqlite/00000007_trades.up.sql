@@ -0,0 +1,12 @@
+CREATE TABLE sundae_v3_trades (
+    order_tx_id BLOB NOT NULL,
+    order_txo_index BIGINT NOT NULL,
+    ident BLOB NOT NULL,
+    slot BIGINT NOT NULL,
+    tx_hash BLOB NOT NULL,
+    a_to_b BOOLEAN NOT NULL,
+    gives TEXT NOT NULL,
+    takes TEXT NOT NULL,
+    PRIMARY KEY (order_tx_id, order_txo_index)
+);
+CREATE INDEX sundae_v3_trades_ident_slot_idx ON sundae_v3_trades (ident, slot);
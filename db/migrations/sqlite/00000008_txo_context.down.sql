@@ -0,0 +1,3 @@
+ALTER TABLE sundae_v3_txos DROP COLUMN spent_block_hash;
+ALTER TABLE sundae_v3_txos DROP COLUMN spent_tx_hash;
+ALTER TABLE sundae_v3_txos DROP COLUMN created_block_hash;
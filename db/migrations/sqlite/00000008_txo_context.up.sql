@@ -0,0 +1,3 @@
+ALTER TABLE sundae_v3_txos ADD COLUMN created_block_hash BLOB;
+ALTER TABLE sundae_v3_txos ADD COLUMN spent_tx_hash BLOB;
+ALTER TABLE sundae_v3_txos ADD COLUMN spent_block_hash BLOB;
@@ -0,0 +1,2 @@
+DROP INDEX sundae_v3_scoops_ident_slot_idx;
+DROP TABLE sundae_v3_scoops;
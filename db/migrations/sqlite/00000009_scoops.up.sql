@@ -0,0 +1,8 @@
+CREATE TABLE sundae_v3_scoops (
+    tx_hash BLOB NOT NULL,
+    ident BLOB NOT NULL,
+    slot BIGINT NOT NULL,
+    orders BIGINT NOT NULL,
+    PRIMARY KEY (tx_hash, ident)
+);
+CREATE INDEX sundae_v3_scoops_ident_slot_idx ON sundae_v3_scoops (ident, slot);
@@ -0,0 +1,2 @@
+DROP INDEX sundae_v3_order_latencies_scooped_slot_idx;
+DROP TABLE sundae_v3_order_latencies;
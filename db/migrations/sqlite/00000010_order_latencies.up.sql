@@ -0,0 +1,10 @@
+CREATE TABLE sundae_v3_order_latencies (
+    order_tx_id BLOB NOT NULL,
+    order_txo_index BIGINT NOT NULL,
+    ident BLOB NOT NULL,
+    created_slot BIGINT NOT NULL,
+    scooped_slot BIGINT NOT NULL,
+    scooped_at BIGINT NOT NULL,
+    PRIMARY KEY (order_tx_id, order_txo_index)
+);
+CREATE INDEX sundae_v3_order_latencies_scooped_slot_idx ON sundae_v3_order_latencies (scooped_slot);
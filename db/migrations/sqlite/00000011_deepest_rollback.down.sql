@@ -0,0 +1 @@
+DROP TABLE sundae_v3_deepest_rollback;
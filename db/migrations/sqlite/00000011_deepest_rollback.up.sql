@@ -0,0 +1,6 @@
+CREATE TABLE sundae_v3_deepest_rollback (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    depth BIGINT NOT NULL,
+    from_slot BIGINT NOT NULL,
+    to_slot BIGINT NOT NULL
+);
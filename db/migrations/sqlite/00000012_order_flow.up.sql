@@ -0,0 +1,10 @@
+CREATE TABLE sundae_v3_order_flow (
+    order_tx_id BLOB NOT NULL,
+    order_txo_index BIGINT NOT NULL,
+    ident BLOB NOT NULL,
+    slot BIGINT NOT NULL,
+    datum BLOB NOT NULL,
+    lovelace BIGINT NOT NULL,
+    PRIMARY KEY (order_tx_id, order_txo_index)
+);
+CREATE INDEX sundae_v3_order_flow_slot_idx ON sundae_v3_order_flow (slot);
@@ -0,0 +1,2 @@
+ALTER TABLE sundae_v3_order_flow DROP COLUMN position;
+ALTER TABLE sundae_v3_order_flow DROP COLUMN created_slot;
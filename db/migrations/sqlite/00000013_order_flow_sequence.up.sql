@@ -0,0 +1,2 @@
+ALTER TABLE sundae_v3_order_flow ADD COLUMN created_slot BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE sundae_v3_order_flow ADD COLUMN position BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+DROP TABLE extension_records;
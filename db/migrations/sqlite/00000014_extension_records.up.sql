@@ -0,0 +1,7 @@
+CREATE TABLE extension_records (
+    namespace TEXT NOT NULL,
+    key BLOB NOT NULL,
+    slot BIGINT NOT NULL,
+    value BLOB,
+    PRIMARY KEY (namespace, key, slot)
+);
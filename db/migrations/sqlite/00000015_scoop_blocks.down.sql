@@ -0,0 +1,2 @@
+ALTER TABLE sundae_v3_scoops DROP COLUMN tx_index;
+ALTER TABLE sundae_v3_scoops DROP COLUMN block_hash;
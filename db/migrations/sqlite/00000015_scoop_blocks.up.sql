@@ -0,0 +1,2 @@
+ALTER TABLE sundae_v3_scoops ADD COLUMN block_hash BLOB;
+ALTER TABLE sundae_v3_scoops ADD COLUMN tx_index BIGINT;
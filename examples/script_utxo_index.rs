@@ -0,0 +1,134 @@
+//! Runs the scooper's indexer along with an extension index which keeps the
+//! UTxOs held at one script, such as a partner protocol's, and how much ADA
+//! each holds. Its records live in the scooper's database under the
+//! `script-utxos` namespace, and its cursor under `ext/script-utxos`.
+//!
+//! cargo run --example script_utxo_index -- <config file> <script hash hex>
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::{Context, Result, bail};
+use async_trait::async_trait;
+use pallas_addresses::Address;
+use pallas_traverse::MultiEraTx;
+use scooper_v2::{
+    cardano_types::Slot,
+    config::{self, AppConfig},
+    extensions::{BlockInfo, ChainIndex, ChainIndexFactory, ExtensionDao, ExtensionIndexes, Point},
+    persistence,
+    runtime::ScooperRuntime,
+};
+use tracing::info;
+
+struct ScriptUtxoIndex {
+    script_hash: Vec<u8>,
+    store: Box<dyn ExtensionDao>,
+}
+
+/// Records are keyed by tx hash then output index, and hold the lovelace.
+fn utxo_key(tx_hash: &[u8], index: u64) -> Vec<u8> {
+    [tx_hash, &index.to_be_bytes()].concat()
+}
+
+#[async_trait]
+impl ChainIndex for ScriptUtxoIndex {
+    fn name(&self) -> String {
+        // Replaced by the namespace it's registered under
+        "script-utxos".to_string()
+    }
+
+    async fn handle_onchain_tx_bytes(&mut self, info: &BlockInfo, raw_tx: &[u8]) -> Result<()> {
+        let slot = Slot(info.slot);
+        let tx = MultiEraTx::decode(raw_tx)?;
+        for input in tx.consumes() {
+            let key = utxo_key(input.hash().as_ref(), input.index());
+            if self.store.get(&key).await?.is_some() {
+                self.store.put(slot, &key, None).await?;
+            }
+        }
+        for (index, output) in tx.produces() {
+            let at_script = matches!(
+                output.address()?,
+                Address::Shelley(address)
+                    if address.payment().is_script()
+                        && address.payment().as_hash().as_ref() == self.script_hash
+            );
+            if at_script {
+                let key = utxo_key(tx.hash().as_ref(), index as u64);
+                let lovelace = output.value().coin().to_be_bytes();
+                self.store.put(slot, &key, Some(&lovelace)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_rollback(&mut self, point: &Point) -> Result<()> {
+        self.store.rollback(Slot(point.slot())).await
+    }
+
+    async fn reset(&mut self, point: &Point) -> Result<Point> {
+        self.store.rollback(Slot(0)).await?;
+        Ok(point.clone())
+    }
+}
+
+struct ScriptUtxoFactory {
+    script_hash: Vec<u8>,
+}
+
+#[async_trait]
+impl ChainIndexFactory for ScriptUtxoFactory {
+    async fn build(
+        &self,
+        store: Box<dyn ExtensionDao>,
+    ) -> Result<Box<dyn ChainIndex + Send + Sync>> {
+        // Everything lives in the store, so there's nothing to load
+        let utxos = store.entries().await?.len();
+        info!(utxos, "loaded script utxos");
+        Ok(Box::new(ScriptUtxoIndex {
+            script_hash: self.script_hash.clone(),
+            store,
+        }))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [config_file, script_hash] = args.as_slice() else {
+        bail!("usage: script_utxo_index <config file> <script hash hex>");
+    };
+    let config_file = Path::new(config_file);
+    let config = config::load_config(config_file, &[])?;
+    let app_config = config.clone().try_deserialize::<AppConfig>()?;
+    tracing_subscriber::fmt::init();
+
+    let Some(protocol) = &app_config.protocol else {
+        bail!("no protocol set in the config");
+    };
+    let protocol = protocol.resolve(config::config_dir(config_file))?;
+    let persistence = persistence::connect(&app_config.persistence).await?;
+
+    let mut extensions = ExtensionIndexes::new();
+    extensions.register(
+        "script-utxos",
+        Point::Origin,
+        ScriptUtxoFactory {
+            script_hash: hex::decode(script_hash).context("script hash isn't hex")?,
+        },
+    )?;
+    let runtime = ScooperRuntime::start_with_extensions(
+        Arc::new(config),
+        &app_config,
+        protocol,
+        persistence,
+        Point::Origin,
+        extensions,
+    )
+    .await?;
+
+    tokio::signal::ctrl_c().await?;
+    runtime.shutdown();
+    runtime.join().await?;
+    Ok(())
+}
@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pallas_primitives::PlutusData;
+use plutus_parser::AsPlutus;
+use scooper_v2::sundaev3::PoolRedeemer;
+
+// Parsing must never panic on arbitrary on-chain data, and anything we can
+// parse must survive a round trip back through PlutusData.
+fuzz_target!(|data: &[u8]| {
+    let Ok(plutus_data) = minicbor::decode::<PlutusData>(data) else {
+        return;
+    };
+    let Ok(parsed) = PoolRedeemer::from_plutus(plutus_data.clone()) else {
+        return;
+    };
+    let expected = PoolRedeemer::from_plutus(plutus_data).unwrap();
+    let reparsed = PoolRedeemer::from_plutus(parsed.to_plutus()).unwrap();
+    assert_eq!(reparsed, expected);
+});
@@ -0,0 +1,310 @@
+//! Signed statements of the state we publish, so anyone who keeps them can
+//! show if we ever served two different views of the same block.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, Result, bail};
+use pallas_crypto::{
+    hash::{Hash, Hasher},
+    key::ed25519::SecretKey,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    cardano_types::TransactionInput,
+    sundaev3::{SundaeV3State, SundaeV3Update},
+};
+
+/// The CBOR header of a 32 byte bytestring, as cardano-cli wraps keys in.
+const CBOR_KEY_PREFIX: &str = "5820";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AttestationConfig {
+    /// The scooper's ed25519 signing key, as a cardano-cli `.skey` file or
+    /// 32 hex bytes. Without one, nothing is attested.
+    pub signing_key_file: Option<PathBuf>,
+}
+
+/// The latest state we published, and our signature over it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Attestation {
+    /// The slot of the block the state was built from, or of the state
+    /// itself when it was restored from the database
+    pub slot: u64,
+    /// The block the state was built from, or empty for a state restored
+    /// from the database
+    #[serde(serialize_with = "hex::serialize")]
+    pub block_hash: Vec<u8>,
+    #[serde(serialize_with = "hex::serialize")]
+    pub state_hash: Vec<u8>,
+    #[serde(serialize_with = "hex::serialize")]
+    pub public_key: Vec<u8>,
+    #[serde(serialize_with = "hex::serialize")]
+    pub key_hash: Vec<u8>,
+    /// Over the slot as 8 big-endian bytes, followed by the block hash and
+    /// the state hash
+    #[serde(serialize_with = "hex::serialize")]
+    pub signature: Vec<u8>,
+}
+
+pub type LatestAttestation = Arc<RwLock<Option<Attestation>>>;
+
+/// A blake2b-256 hash of every UTxO in the state. The UTxO references alone
+/// pin down their contents, so two states with the same hash are the same.
+pub fn state_hash(state: &SundaeV3State) -> Hash<32> {
+    let mut hasher = Hasher::<256>::new();
+    let mut add = |tag: u8, input: &TransactionInput| {
+        hasher.input(&[tag]);
+        hasher.input(input.0.transaction_id.as_ref());
+        hasher.input(&input.0.index.to_be_bytes());
+    };
+    for pool in state.pools.values() {
+        add(b'p', &pool.input);
+    }
+    let mut orders: Vec<_> = state.orders.iter().map(|order| &order.input).collect();
+    orders.sort();
+    for order in orders {
+        add(b'o', order);
+    }
+    if let Some(settings) = &state.settings {
+        add(b's', &settings.input);
+    }
+    hasher.finalize()
+}
+
+fn message(slot: u64, block_hash: &[u8], state_hash: &[u8]) -> Vec<u8> {
+    [&slot.to_be_bytes()[..], block_hash, state_hash].concat()
+}
+
+/// Signs each state the indexer publishes.
+pub struct Attestor {
+    key: SecretKey,
+    latest: LatestAttestation,
+}
+
+impl Attestor {
+    pub fn new(config: &AttestationConfig) -> Result<Option<Self>> {
+        let Some(path) = &config.signing_key_file else {
+            return Ok(None);
+        };
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading signing key {}", path.display()))?;
+        let attestor = Self {
+            key: parse_signing_key(&contents)?,
+            latest: LatestAttestation::default(),
+        };
+        info!(
+            key_hash = hex::encode(attestor.key_hash()),
+            "attesting to published state"
+        );
+        Ok(Some(attestor))
+    }
+
+    pub fn latest(&self) -> LatestAttestation {
+        self.latest.clone()
+    }
+
+    fn key_hash(&self) -> Hash<28> {
+        Hasher::<224>::hash(self.key.public_key().as_ref())
+    }
+
+    /// Signs the update's state as of the block it was built from, which a
+    /// rollback can leave some slots before the update's own.
+    pub fn attest(&self, update: &SundaeV3Update) -> Attestation {
+        let (slot, block_hash) = match &update.block {
+            Some(block) => (block.slot, block.hash.to_vec()),
+            None => (update.slot, vec![]),
+        };
+        let state_hash = state_hash(&update.state);
+        let signature = self
+            .key
+            .sign(message(slot, &block_hash, state_hash.as_ref()));
+        Attestation {
+            slot,
+            block_hash,
+            state_hash: state_hash.to_vec(),
+            public_key: self.key.public_key().as_ref().to_vec(),
+            key_hash: self.key_hash().to_vec(),
+            signature: signature.as_ref().to_vec(),
+        }
+    }
+
+    pub async fn run(
+        self,
+        mut updates: watch::Receiver<SundaeV3Update>,
+        shutdown: CancellationToken,
+    ) {
+        loop {
+            let attestation = { self.attest(&updates.borrow_and_update()) };
+            *self.latest.write().unwrap() = Some(attestation);
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                res = updates.changed() => {
+                    if res.is_err() {
+                        warn!("state updates ended, no longer attesting");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn parse_signing_key(contents: &str) -> Result<SecretKey> {
+    let contents = contents.trim();
+    let hex_key = if contents.starts_with('{') {
+        let envelope: serde_json::Value = serde_json::from_str(contents)?;
+        let Some(cbor_hex) = envelope["cborHex"].as_str() else {
+            bail!("signing key file has no cborHex");
+        };
+        cbor_hex
+            .strip_prefix(CBOR_KEY_PREFIX)
+            .unwrap_or(cbor_hex)
+            .to_string()
+    } else {
+        contents.to_string()
+    };
+    let bytes = hex::decode(&hex_key)?;
+    let Ok(bytes) = <[u8; 32]>::try_from(bytes.as_slice()) else {
+        bail!("signing keys are 32 bytes, not {}", bytes.len());
+    };
+    Ok(SecretKey::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use acropolis_common::{BlockHash, Era, Point};
+    use pallas_crypto::key::ed25519::{PublicKey, Signature};
+    use pallas_traverse::MultiEraBlock;
+    use tokio::sync::{Mutex, broadcast};
+
+    use super::*;
+    use crate::{
+        historical_state::BlockMeta,
+        mock_chain::MockChainSource,
+        persistence::{self, PersistenceConfig},
+        sundaev3::{SundaeV3HistoricalState, SundaeV3Indexer},
+    };
+
+    const KEY: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+
+    fn attestor() -> Result<Attestor> {
+        Ok(Attestor {
+            key: parse_signing_key(KEY)?,
+            latest: LatestAttestation::default(),
+        })
+    }
+
+    fn update(slot: u64, block: Option<BlockMeta>) -> SundaeV3Update {
+        SundaeV3Update {
+            slot,
+            block,
+            ..SundaeV3Update::default()
+        }
+    }
+
+    fn block(slot: u64, byte: u8) -> BlockMeta {
+        BlockMeta {
+            slot,
+            height: 0,
+            hash: BlockHash::new([byte; 32]),
+            era: Era::Conway,
+        }
+    }
+
+    /// Whether the attestation's signature is over `signed`.
+    fn verify(attestation: &Attestation, signed: &[u8]) -> Result<bool> {
+        let public_key: [u8; 32] = attestation.public_key.as_slice().try_into()?;
+        let signature: [u8; 64] = attestation.signature.as_slice().try_into()?;
+        Ok(PublicKey::from(public_key).verify(signed, &Signature::from(signature)))
+    }
+
+    #[test]
+    fn should_sign_the_state_hash() -> Result<()> {
+        let envelope = serde_json::json!({
+            "type": "PaymentSigningKeyShelley_ed25519",
+            "cborHex": format!("{CBOR_KEY_PREFIX}{KEY}"),
+        });
+        let key = parse_signing_key(&envelope.to_string())?;
+        assert_eq!(
+            key.public_key().as_ref(),
+            parse_signing_key(KEY)?.public_key().as_ref()
+        );
+
+        let attestation = attestor()?.attest(&update(42, Some(block(42, 1))));
+        assert_eq!(attestation.slot, 42);
+        assert_eq!(attestation.block_hash, vec![1; 32]);
+        let state_hash = state_hash(&SundaeV3State::default()).to_vec();
+        assert_eq!(attestation.state_hash, state_hash);
+        assert!(verify(&attestation, &message(42, &[1; 32], &state_hash))?);
+        assert!(!verify(&attestation, &message(43, &[1; 32], &state_hash))?);
+
+        // A state restored from the database has no block to sign
+        let attestation = attestor()?.attest(&update(42, None));
+        assert!(attestation.block_hash.is_empty());
+        assert!(verify(&attestation, &message(42, &[], &state_hash))?);
+        Ok(())
+    }
+
+    #[test]
+    fn should_tell_apart_blocks_at_the_same_slot() -> Result<()> {
+        // The same state on two forks, as after a rollback
+        let ours = attestor()?.attest(&update(42, Some(block(42, 1))));
+        let theirs = attestor()?.attest(&update(42, Some(block(42, 2))));
+        assert_eq!(ours.state_hash, theirs.state_hash);
+        assert_ne!(ours.block_hash, theirs.block_hash);
+        assert_ne!(ours.signature, theirs.signature);
+        assert!(!verify(
+            &ours,
+            &message(42, &theirs.block_hash, &theirs.state_hash)
+        )?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_sign_the_slot_of_the_block_after_a_rollback() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let protocol = serde_json::from_reader(fs::File::open("testdata/protocol")?)?;
+        let broadcaster = watch::Sender::default();
+        let updates = broadcaster.subscribe();
+        let mut indexer = SundaeV3Indexer::new(
+            Arc::new(Mutex::new(SundaeV3HistoricalState::new())),
+            broadcaster,
+            broadcast::channel(16).0,
+            protocol,
+            2160,
+            persistence.sundae_v3_dao(),
+        );
+
+        // Rolling back to a slot after the block leaves it the latest one
+        let bytes = fs::read("testdata/scoop-pool.block")?;
+        let block = MultiEraBlock::decode(&bytes)?;
+        MockChainSource::new()
+            .roll_forward(&bytes)
+            .roll_backward(Point::Specific {
+                slot: block.slot() + 5,
+                hash: BlockHash::new([0; 32]),
+            })
+            .run(&mut indexer)
+            .await?;
+        let update = updates.borrow().clone();
+        assert_eq!(update.slot, block.slot() + 5);
+
+        // The signature is over a slot and hash which are on chain together
+        let attestation = attestor()?.attest(&update);
+        assert_eq!(attestation.slot, block.slot());
+        assert_eq!(attestation.block_hash, block.hash().to_vec());
+        let signed = message(block.slot(), &*block.hash(), &attestation.state_hash);
+        assert!(verify(&attestation, &signed)?);
+        Ok(())
+    }
+}
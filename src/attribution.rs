@@ -0,0 +1,216 @@
+//! Which frontend or aggregator orders came from, told by what they put in
+//! the order's `extra` field or destination. Scooped orders' datums are kept
+//! whole, so changing the matchers re-attributes every order already indexed.
+
+use std::collections::BTreeMap;
+
+use pallas_primitives::Fragment;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    persistence::OrderFlow,
+    sundaev3::{AikenDatum, Destination, OrderDatum},
+};
+
+/// What orders no source matches are counted under.
+pub const UNATTRIBUTED: &str = "unknown";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AttributionConfig {
+    /// Tried in order, so the first source to match an order gets it
+    pub sources: Vec<SourceMatcher>,
+}
+
+/// Names a source, and how to recognize its orders. Every condition given
+/// must hold, and a matcher without any matches nothing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SourceMatcher {
+    pub name: String,
+    /// Hex bytes found in the CBOR of the order's `extra` field
+    #[serde(default, with = "hex::serde")]
+    pub extra_contains: Vec<u8>,
+    /// Hex payment key or script hash of the order's fixed destination
+    #[serde(default, with = "hex::serde")]
+    pub destination_hash: Vec<u8>,
+    /// Hex bytes found in the datum, or datum hash, sent to the destination
+    #[serde(default, with = "hex::serde")]
+    pub destination_datum_contains: Vec<u8>,
+}
+
+impl SourceMatcher {
+    fn matches(&self, datum: &OrderDatum) -> bool {
+        if self.extra_contains.is_empty()
+            && self.destination_hash.is_empty()
+            && self.destination_datum_contains.is_empty()
+        {
+            return false;
+        }
+        if !self.extra_contains.is_empty() {
+            let Ok(extra) = datum.extra.encode_fragment() else {
+                return false;
+            };
+            if !contains(&extra, &self.extra_contains) {
+                return false;
+            }
+        }
+        let fixed = match &datum.destination {
+            Destination::Fixed(address, datum) => Some((address, datum)),
+            Destination::SelfDestination => None,
+        };
+        if !self.destination_hash.is_empty()
+            && fixed.is_none_or(|(address, _)| {
+                address.payment_credential.hash() != self.destination_hash
+            })
+        {
+            return false;
+        }
+        if !self.destination_datum_contains.is_empty() {
+            let datum = match fixed {
+                Some((_, AikenDatum::DatumHash(bytes) | AikenDatum::InlineDatum(bytes))) => bytes,
+                _ => return false,
+            };
+            if !contains(datum, &self.destination_datum_contains) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// Orders and ADA volume attributed to one source.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SourceVolume {
+    pub orders: u64,
+    pub lovelace: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Attribution {
+    sources: Vec<SourceMatcher>,
+}
+
+impl Attribution {
+    pub fn new(config: &AttributionConfig) -> Self {
+        Self {
+            sources: config.sources.clone(),
+        }
+    }
+
+    /// The name of the first source which matches the order, if any does.
+    pub fn source(&self, datum: &OrderDatum) -> Option<&str> {
+        self.sources
+            .iter()
+            .find(|source| source.matches(datum))
+            .map(|source| source.name.as_str())
+    }
+
+    /// Totals scooped orders by source. Orders whose datum can't be decoded
+    /// are counted as unattributed.
+    pub fn aggregate<'a>(
+        &self,
+        flow: impl IntoIterator<Item = &'a OrderFlow>,
+    ) -> BTreeMap<String, SourceVolume> {
+        let mut sources = BTreeMap::<String, SourceVolume>::new();
+        for order in flow {
+            let datum = order.decode_datum().ok();
+            let source = datum
+                .as_ref()
+                .and_then(|datum| self.source(datum))
+                .unwrap_or(UNATTRIBUTED);
+            let volume = sources.entry(source.to_string()).or_default();
+            volume.orders += 1;
+            volume.lovelace += order.lovelace;
+        }
+        sources
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas_primitives::{BoundedBytes, Constr, MaybeIndefArray, PlutusData};
+    use plutus_parser::AsPlutus;
+
+    use super::*;
+    use crate::{
+        cardano_types::TransactionInput,
+        sundaev3::{
+            Credential, Ident, Order, PlutusAddress,
+            test_utils::{self, nothing},
+        },
+    };
+
+    fn datum(extra: &[u8], destination: Destination) -> OrderDatum {
+        OrderDatum {
+            ident: Some(Ident::new(&[1; 28])),
+            destination,
+            extra: PlutusData::Constr(Constr {
+                tag: 121,
+                any_constructor: None,
+                fields: MaybeIndefArray::Def(vec![PlutusData::BoundedBytes(BoundedBytes::from(
+                    extra.to_vec(),
+                ))]),
+            }),
+            ..test_utils::datum(Order::Swap(nothing(), nothing()))
+        }
+    }
+
+    fn flow(datum: OrderDatum, lovelace: u64) -> OrderFlow {
+        OrderFlow {
+            ident: Ident::new(&[1; 28]),
+            order: TransactionInput::new([0; 32].into(), 0),
+            slot: 10,
+            created_slot: 5,
+            position: 0,
+            datum: datum.to_plutus().encode_fragment().unwrap(),
+            lovelace,
+        }
+    }
+
+    #[test]
+    fn should_attribute_orders_to_the_first_matching_source() {
+        let config: AttributionConfig = serde_json::from_value(serde_json::json!({
+            "sources": [
+                { "name": "frontend", "extra-contains": "cafe" },
+                { "name": "aggregator", "destination-hash": "aa".repeat(28) },
+                { "name": "nothing" },
+            ]
+        }))
+        .unwrap();
+        let attribution = Attribution::new(&config);
+        let aggregator = Destination::Fixed(
+            PlutusAddress {
+                payment_credential: Credential::Script(vec![0xaa; 28]),
+                stake_credential: None,
+            },
+            AikenDatum::NoDatum,
+        );
+
+        let report = attribution.aggregate(&[
+            flow(datum(&[0xca, 0xfe], Destination::SelfDestination), 5),
+            flow(datum(&[0xca, 0xfe], aggregator.clone()), 7),
+            flow(datum(&[], aggregator), 11),
+            flow(datum(&[], Destination::SelfDestination), 13),
+            OrderFlow {
+                datum: vec![0xff],
+                ..flow(datum(&[], Destination::SelfDestination), 17)
+            },
+        ]);
+        let volume = |orders, lovelace| SourceVolume { orders, lovelace };
+        assert_eq!(
+            report,
+            BTreeMap::from([
+                ("frontend".to_string(), volume(2, 12)),
+                ("aggregator".to_string(), volume(1, 11)),
+                (UNATTRIBUTED.to_string(), volume(2, 30)),
+            ])
+        );
+    }
+}
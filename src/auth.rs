@@ -0,0 +1,217 @@
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    persistence::{ApiKey, ApiKeyDao},
+    sundaev3::Ident,
+};
+
+/// Access control for the admin server. With no root key configured, every
+/// request is allowed, as before API keys existed.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AuthConfig {
+    /// A secret with full access, used to create the other keys
+    pub root_api_key: Option<String>,
+}
+
+impl ApiKey {
+    pub fn root() -> Self {
+        Self {
+            name: "root".to_string(),
+            pools: vec![],
+            admin: true,
+        }
+    }
+
+    pub fn can_see_pool(&self, ident: &Ident) -> bool {
+        self.pools.is_empty() || self.pools.contains(ident)
+    }
+
+    pub fn sees_all_pools(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    /// Whether the key can see orders for `ident`. Orders which any pool can
+    /// take are for keys which see every pool.
+    pub fn can_see_orders_for(&self, ident: Option<&Ident>) -> bool {
+        match ident {
+            Some(ident) => self.can_see_pool(ident),
+            None => self.sees_all_pools(),
+        }
+    }
+}
+
+/// Paths only admin keys may call. These either change how the scooper runs,
+/// or report on every pool at once.
+pub fn is_admin_only(path: &str) -> bool {
+    path == "/api-keys"
+        || path.starts_with("/api-keys/")
+        || path.starts_with("/cursors")
+        || path.starts_with("/tx/")
+        || matches!(
+            path,
+            "/resync-from-acropolis" | "/soft-resync" | "/webhooks" | "/quarantine" | "/metrics"
+        )
+}
+
+/// Secrets are only stored hashed.
+pub fn hash_secret(secret: &str) -> Vec<u8> {
+    Sha256::digest(secret.as_bytes()).to_vec()
+}
+
+pub fn generate_secret() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+/// Resolves the `Authorization: Bearer <secret>` header to the key it belongs
+/// to. Returns `None` if the caller is not allowed in at all.
+pub async fn authenticate(
+    config: &AuthConfig,
+    dao: &dyn ApiKeyDao,
+    authorization: Option<&str>,
+) -> Result<Option<ApiKey>> {
+    let Some(root) = &config.root_api_key else {
+        return Ok(Some(ApiKey::root()));
+    };
+    let Some(secret) = authorization.and_then(|h| h.strip_prefix("Bearer ")) else {
+        return Ok(None);
+    };
+    let secret_hash = hash_secret(secret);
+    // Comparing hashes, so that timing says nothing about the root secret
+    if secret_hash == hash_secret(root) {
+        return Ok(Some(ApiKey::root()));
+    }
+    dao.find_api_key(&secret_hash).await
+}
+
+/// Parses `name=..&pools=ab,cd&admin=true` for creating a key.
+pub fn parse_new_key(query: Option<&str>) -> Result<ApiKey> {
+    let mut name = None;
+    let mut pools = vec![];
+    let mut admin = false;
+    for (key, value) in query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|kv| kv.split_once('='))
+    {
+        match key {
+            "name" => name = Some(value.to_string()),
+            "pools" => {
+                for pool in value.split(',').filter(|p| !p.is_empty()) {
+                    pools.push(pool.parse()?);
+                }
+            }
+            "admin" => admin = value.parse()?,
+            other => bail!("unrecognized parameter \"{other}\""),
+        }
+    }
+    let Some(name) = name.filter(|n| !n.is_empty() && n != "root") else {
+        bail!("a key needs a name other than \"root\"");
+    };
+    Ok(ApiKey { name, pools, admin })
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::sundaev3::IDENT_SIZE;
+
+    struct OneKeyDao(ApiKey);
+
+    #[async_trait]
+    impl ApiKeyDao for OneKeyDao {
+        async fn save_api_key(&self, key: &ApiKey, secret_hash: &[u8]) -> Result<()> {
+            let _ = (key, secret_hash);
+            Ok(())
+        }
+        async fn find_api_key(&self, secret_hash: &[u8]) -> Result<Option<ApiKey>> {
+            Ok((secret_hash == hash_secret("tenant-secret")).then(|| self.0.clone()))
+        }
+        async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+            Ok(vec![self.0.clone()])
+        }
+        async fn delete_api_key(&self, name: &str) -> Result<bool> {
+            let _ = name;
+            Ok(false)
+        }
+    }
+
+    #[tokio::test]
+    async fn should_authenticate_bearer_secrets() -> Result<()> {
+        let tenant = ApiKey {
+            name: "tenant".to_string(),
+            pools: vec![Ident::new(&[0xab])],
+            admin: false,
+        };
+        let dao = OneKeyDao(tenant.clone());
+
+        let open = AuthConfig::default();
+        assert_eq!(authenticate(&open, &dao, None).await?, Some(ApiKey::root()));
+
+        let locked = AuthConfig {
+            root_api_key: Some("root-secret".to_string()),
+        };
+        assert_eq!(authenticate(&locked, &dao, None).await?, None);
+        assert_eq!(
+            authenticate(&locked, &dao, Some("Bearer root-secret")).await?,
+            Some(ApiKey::root())
+        );
+        assert_eq!(
+            authenticate(&locked, &dao, Some("Bearer tenant-secret")).await?,
+            Some(tenant.clone())
+        );
+        assert_eq!(
+            authenticate(&locked, &dao, Some("Bearer wrong")).await?,
+            None
+        );
+
+        assert!(tenant.can_see_pool(&Ident::new(&[0xab])));
+        assert!(!tenant.can_see_pool(&Ident::new(&[0xcd])));
+        assert!(tenant.can_see_orders_for(Some(&Ident::new(&[0xab]))));
+        assert!(!tenant.can_see_orders_for(None));
+        assert!(ApiKey::root().can_see_orders_for(None));
+        Ok(())
+    }
+
+    #[test]
+    fn should_keep_scoped_keys_out_of_admin_paths() {
+        for path in [
+            "/metrics",
+            "/api-keys",
+            "/api-keys/tenant",
+            "/tx/ab",
+            "/webhooks",
+        ] {
+            assert!(is_admin_only(path), "{path}");
+        }
+        // Competition is reported for the key's own pools
+        for path in ["/competition", "/pools", "/orders", "/pool/ab"] {
+            assert!(!is_admin_only(path), "{path}");
+        }
+    }
+
+    #[test]
+    fn should_parse_new_key() -> Result<()> {
+        let (ab, cd) = ("ab".repeat(IDENT_SIZE), "cd".repeat(IDENT_SIZE));
+        assert_eq!(
+            parse_new_key(Some(&format!("name=tenant&pools={ab},{cd}")))?,
+            ApiKey {
+                name: "tenant".to_string(),
+                pools: vec![
+                    Ident::new(&[0xab; IDENT_SIZE]),
+                    Ident::new(&[0xcd; IDENT_SIZE])
+                ],
+                admin: false,
+            }
+        );
+        assert!(parse_new_key(Some("name=ops&admin=true"))?.admin);
+        assert!(parse_new_key(Some("name=tenant&pools=ab")).is_err());
+        assert!(parse_new_key(Some(&format!("pools={ab}"))).is_err());
+        assert!(parse_new_key(Some("name=root")).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use crate::config::RestartConfig;
+
+/// Exponential backoff with jitter, which gives up after too many consecutive
+/// failures.
+pub struct Backoff {
+    initial_backoff_secs: u64,
+    max_backoff_secs: u64,
+    max_failures: u32,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(config: RestartConfig) -> Self {
+        Self::with_limits(
+            config.initial_backoff_secs,
+            config.max_backoff_secs,
+            config.max_failures,
+        )
+    }
+
+    /// `max_failures` of 0 never gives up.
+    pub fn with_limits(
+        initial_backoff_secs: u64,
+        max_backoff_secs: u64,
+        max_failures: u32,
+    ) -> Self {
+        Self {
+            initial_backoff_secs,
+            max_backoff_secs,
+            max_failures,
+            failures: 0,
+        }
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Records a failure, and returns how long to wait before trying again.
+    /// Returns `None` once we've failed too many times in a row.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        self.failures += 1;
+        if self.max_failures > 0 && self.failures >= self.max_failures {
+            return None;
+        }
+        let exponent = (self.failures - 1).min(63);
+        let secs = self
+            .initial_backoff_secs
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff_secs);
+        let millis = secs.saturating_mul(1000);
+        Some(Duration::from_millis(rand::random_range(
+            millis / 2..=millis,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_failures: u32) -> RestartConfig {
+        RestartConfig {
+            initial_backoff_secs: 2,
+            max_backoff_secs: 10,
+            max_failures,
+        }
+    }
+
+    #[test]
+    fn should_back_off_exponentially_up_to_the_max() {
+        let mut backoff = Backoff::new(config(0));
+        for expected_secs in [2, 4, 8, 10, 10] {
+            let delay = backoff.next_delay().unwrap();
+            let max = Duration::from_secs(expected_secs);
+            assert!(
+                delay >= max / 2 && delay <= max,
+                "{delay:?} not near {max:?}"
+            );
+        }
+        assert_eq!(backoff.failures(), 5);
+    }
+
+    #[test]
+    fn should_give_up_after_max_failures() {
+        let mut backoff = Backoff::new(config(3));
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_none());
+
+        backoff.reset();
+        assert!(backoff.next_delay().is_some());
+    }
+}
@@ -1,7 +1,8 @@
-use num_traits::cast::ToPrimitive;
+use num_traits::{Signed, Zero, cast::ToPrimitive};
 use pallas_primitives::PlutusData;
 use plutus_parser::AsPlutus;
 use std::fmt;
+use std::str::FromStr;
 
 #[derive(Eq, Ord, PartialEq, PartialOrd, Clone, Debug)]
 pub struct BigInt(num_bigint::BigInt);
@@ -14,6 +15,15 @@ impl BigInt {
     pub fn to_f64(&self) -> Option<f64> {
         self.0.to_f64()
     }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        self.0.to_u64()
+    }
+
+    /// For arithmetic which can skip the heap when the values are small.
+    pub fn to_i128(&self) -> Option<i128> {
+        self.0.to_i128()
+    }
 }
 
 impl fmt::Display for BigInt {
@@ -74,6 +84,18 @@ impl std::ops::Add<BigInt> for &BigInt {
     }
 }
 
+impl std::ops::AddAssign for BigInt {
+    fn add_assign(&mut self, other: BigInt) {
+        self.0 += other.0
+    }
+}
+
+impl std::ops::AddAssign<&BigInt> for BigInt {
+    fn add_assign(&mut self, other: &BigInt) {
+        self.0 += &other.0
+    }
+}
+
 impl std::ops::Sub for BigInt {
     type Output = BigInt;
     fn sub(self, other: BigInt) -> BigInt {
@@ -81,6 +103,20 @@ impl std::ops::Sub for BigInt {
     }
 }
 
+impl std::ops::Sub<&BigInt> for &BigInt {
+    type Output = BigInt;
+    fn sub(self, other: &BigInt) -> BigInt {
+        BigInt(&self.0 - &other.0)
+    }
+}
+
+impl std::ops::Sub<&BigInt> for BigInt {
+    type Output = BigInt;
+    fn sub(self, other: &BigInt) -> BigInt {
+        Self(self.0 - &other.0)
+    }
+}
+
 impl std::ops::SubAssign for BigInt {
     fn sub_assign(&mut self, other: BigInt) {
         self.0 -= other.0
@@ -96,7 +132,7 @@ impl std::ops::SubAssign<&BigInt> for BigInt {
 impl std::ops::Mul for BigInt {
     type Output = BigInt;
     fn mul(self, other: BigInt) -> BigInt {
-        BigInt(&self.0 * &other.0)
+        BigInt(self.0 * other.0)
     }
 }
 
@@ -110,14 +146,14 @@ impl std::ops::Mul<&BigInt> for &BigInt {
 impl std::ops::Mul<&BigInt> for BigInt {
     type Output = BigInt;
     fn mul(self, other: &BigInt) -> BigInt {
-        BigInt(&self.0 * &other.0)
+        BigInt(self.0 * &other.0)
     }
 }
 
 impl std::ops::Mul<BigInt> for &BigInt {
     type Output = BigInt;
     fn mul(self, other: BigInt) -> BigInt {
-        BigInt(&self.0 * &other.0)
+        BigInt(&self.0 * other.0)
     }
 }
 
@@ -127,6 +163,40 @@ impl std::ops::MulAssign for BigInt {
     }
 }
 
+impl std::ops::MulAssign<&BigInt> for BigInt {
+    fn mul_assign(&mut self, other: &BigInt) {
+        self.0 *= &other.0
+    }
+}
+
+/// Rounds towards negative infinity, like `divideInteger` on-chain.
+impl std::ops::Div<&BigInt> for &BigInt {
+    type Output = BigInt;
+    fn div(self, other: &BigInt) -> BigInt {
+        let quotient = &self.0 / &other.0;
+        let remainder = &self.0 % &other.0;
+        if remainder.sign() != num_bigint::Sign::NoSign && (remainder.sign() != other.0.sign()) {
+            BigInt(quotient - 1)
+        } else {
+            BigInt(quotient)
+        }
+    }
+}
+
+impl std::ops::Div for BigInt {
+    type Output = BigInt;
+    fn div(self, other: BigInt) -> BigInt {
+        &self / &other
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = num_bigint::ParseBigIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.parse()?))
+    }
+}
+
 impl serde::Serialize for BigInt {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -139,6 +209,106 @@ impl serde::Serialize for BigInt {
     }
 }
 
+/// An exact ratio of two integers, for prices and fee fractions which would
+/// otherwise be rounded as floats. Always kept in lowest terms, with a
+/// positive denominator.
+#[derive(Eq, Ord, PartialEq, PartialOrd, Clone, Debug)]
+pub struct BigRational(num_rational::BigRational);
+
+impl BigRational {
+    /// How many decimal places the decimal rendering is truncated to.
+    pub const DECIMAL_PLACES: usize = 12;
+
+    /// `None` if `denominator` is zero.
+    pub fn new(numerator: BigInt, denominator: BigInt) -> Option<Self> {
+        if denominator.0.is_zero() {
+            return None;
+        }
+        Some(Self(num_rational::BigRational::new(
+            numerator.0,
+            denominator.0,
+        )))
+    }
+
+    pub fn numerator(&self) -> BigInt {
+        BigInt(self.0.numer().clone())
+    }
+
+    pub fn denominator(&self) -> BigInt {
+        BigInt(self.0.denom().clone())
+    }
+
+    /// `None` if the ratio is zero.
+    pub fn recip(&self) -> Option<Self> {
+        if self.0.is_zero() {
+            return None;
+        }
+        Some(Self(self.0.recip()))
+    }
+
+    /// Only for reporting; anything compared should stay exact.
+    pub fn to_f64(&self) -> Option<f64> {
+        self.0.to_f64()
+    }
+
+    /// The ratio in decimal, truncated towards zero after `places` digits,
+    /// without trailing zeros.
+    pub fn to_decimal(&self, places: usize) -> String {
+        let numerator = self.0.numer();
+        let denominator = self.0.denom();
+        let mut decimal = if numerator.is_negative() {
+            "-".to_string()
+        } else {
+            String::new()
+        };
+        let whole = numerator.abs() / denominator;
+        let mut remainder = numerator.abs() % denominator;
+        decimal.push_str(&whole.to_string());
+        let mut digits = String::new();
+        for _ in 0..places {
+            if remainder.is_zero() {
+                break;
+            }
+            remainder *= 10;
+            digits.push_str(&(&remainder / denominator).to_string());
+            remainder %= denominator;
+        }
+        let digits = digits.trim_end_matches('0');
+        if !digits.is_empty() {
+            decimal.push('.');
+            decimal.push_str(digits);
+        }
+        decimal
+    }
+}
+
+impl From<BigInt> for BigRational {
+    fn from(value: BigInt) -> Self {
+        Self(num_rational::BigRational::from_integer(value.0))
+    }
+}
+
+impl fmt::Display for BigRational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_decimal(Self::DECIMAL_PLACES))
+    }
+}
+
+/// Serializes as `{"numerator": 3, "denominator": 2, "decimal": "1.5"}`.
+impl serde::Serialize for BigRational {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("BigRational", 3)?;
+        state.serialize_field("numerator", &self.numerator())?;
+        state.serialize_field("denominator", &self.denominator())?;
+        state.serialize_field("decimal", &self.to_string())?;
+        state.end()
+    }
+}
+
 impl AsPlutus for BigInt {
     fn from_plutus(data: PlutusData) -> Result<Self, plutus_parser::DecodeError> {
         let b: pallas_primitives::BigInt = AsPlutus::from_plutus(data)?;
@@ -191,7 +361,7 @@ impl AsPlutus for BigInt {
 
 #[cfg(test)]
 mod tests {
-    use super::BigInt;
+    use super::{BigInt, BigRational};
     use plutus_parser::AsPlutus;
 
     #[test]
@@ -239,4 +409,35 @@ mod tests {
         let big_int_from = AsPlutus::from_plutus(pd_from).unwrap();
         assert_eq!(x, big_int_from);
     }
+
+    #[test]
+    fn division_rounds_down() {
+        let div = |a: i64, b: i64| BigInt::from(a) / BigInt::from(b);
+        assert_eq!(div(7, 2), BigInt::from(3));
+        assert_eq!(div(-7, 2), BigInt::from(-4));
+        assert_eq!(div(7, -2), BigInt::from(-4));
+        assert_eq!(div(-6, 2), BigInt::from(-3));
+    }
+
+    fn ratio(numerator: i64, denominator: i64) -> BigRational {
+        BigRational::new(BigInt::from(numerator), BigInt::from(denominator)).unwrap()
+    }
+
+    #[test]
+    fn rational_renders_exactly() {
+        assert_eq!(ratio(6, 4), ratio(3, 2));
+        assert_eq!(ratio(3, -2).denominator(), BigInt::from(2));
+        assert_eq!(ratio(3, 2).to_string(), "1.5");
+        assert_eq!(ratio(-1, 3).to_string(), "-0.333333333333");
+        assert_eq!(ratio(10, 5).to_string(), "2");
+        assert_eq!(ratio(1, 8).to_decimal(2), "0.12");
+        assert_eq!(
+            serde_json::to_value(ratio(1, 10)).unwrap(),
+            serde_json::json!({ "numerator": 1, "denominator": 10, "decimal": "0.1" })
+        );
+        assert!(ratio(1, 3) < ratio(1, 2));
+        assert_eq!(ratio(2, 3).recip(), Some(ratio(3, 2)));
+        assert_eq!(ratio(0, 3).recip(), None);
+        assert_eq!(BigRational::new(BigInt::from(1), BigInt::from(0)), None);
+    }
 }
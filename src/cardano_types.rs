@@ -3,7 +3,7 @@
 use pallas_addresses::Address;
 use pallas_primitives::conway::{DatumOption, MintedDatumOption, NativeScript};
 use pallas_primitives::{Hash, PlutusData, PlutusScript};
-use pallas_traverse::MultiEraOutput;
+use pallas_traverse::{MultiEraOutput, MultiEraTx};
 use serde::ser::SerializeMap;
 use serde::{Serialize, Serializer};
 
@@ -12,10 +12,35 @@ use std::fmt;
 
 use plutus_parser::AsPlutus;
 
+use crate::bigint::BigInt;
+use crate::interned::Interned;
 use crate::serde_compat::serialize_address;
-use crate::sundaev3::{OrderDatum, PoolDatum};
+use crate::sundaev3::{OrderDatum, PoolDatum, Versioned};
 pub type Bytes = Vec<u8>;
 
+/// An absolute slot number.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Slot(pub u64);
+
+/// A block number, counted from genesis. Not interchangeable with a [`Slot`],
+/// since most slots have no block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct BlockHeight(pub u64);
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for BlockHeight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, serde::Serialize)]
 pub enum ScriptRef {
     Native(NativeScript),
@@ -24,8 +49,8 @@ pub enum ScriptRef {
     PlutusV3(PlutusScript<3>),
 }
 
-pub const ADA_POLICY: Vec<u8> = vec![];
-pub const ADA_TOKEN: Vec<u8> = vec![];
+pub const ADA_POLICY: Interned = Interned::EMPTY;
+pub const ADA_TOKEN: Interned = Interned::EMPTY;
 
 pub const ADA_ASSET_CLASS: AssetClass = AssetClass {
     policy: ADA_POLICY,
@@ -34,8 +59,8 @@ pub const ADA_ASSET_CLASS: AssetClass = AssetClass {
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct AssetClass {
-    pub policy: Vec<u8>,
-    pub token: Vec<u8>,
+    pub policy: Interned,
+    pub token: Interned,
 }
 
 impl serde::Serialize for AssetClass {
@@ -56,21 +81,21 @@ impl serde::Serialize for AssetClass {
 
 impl AsPlutus for AssetClass {
     fn from_plutus(data: PlutusData) -> Result<Self, plutus_parser::DecodeError> {
-        let (policy, token) = AsPlutus::from_plutus(data)?;
-        Ok(AssetClass { policy, token })
+        let (policy, token): (Vec<u8>, Vec<u8>) = AsPlutus::from_plutus(data)?;
+        Ok(AssetClass::from_pair((policy, token)))
     }
 
     fn to_plutus(self) -> PlutusData {
-        let tuple = (self.policy, self.token);
+        let tuple = (self.policy.to_vec(), self.token.to_vec());
         tuple.to_plutus()
     }
 }
 
 impl AssetClass {
-    pub fn from_pair(pair: (Vec<u8>, Vec<u8>)) -> AssetClass {
+    pub fn from_pair(pair: (impl Into<Interned>, impl Into<Interned>)) -> AssetClass {
         AssetClass {
-            policy: pair.0,
-            token: pair.1,
+            policy: pair.0.into(),
+            token: pair.1.into(),
         }
     }
 }
@@ -90,8 +115,8 @@ impl fmt::Display for AssetClass {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Value(pub BTreeMap<Bytes, BTreeMap<Bytes, i128>>);
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Value(pub BTreeMap<Interned, BTreeMap<Interned, i128>>);
 
 #[macro_export]
 macro_rules! value {
@@ -112,17 +137,19 @@ impl Value {
         Value(BTreeMap::new())
     }
 
+    /// The quantity held of `asset_class`, if there is any.
+    pub fn get_ref(&self, asset_class: &AssetClass) -> Option<&i128> {
+        self.0
+            .get(asset_class.policy.as_slice())?
+            .get(asset_class.token.as_slice())
+    }
+
     pub fn get_asset_class(&self, asset_class: &AssetClass) -> i128 {
-        if let Some(assets) = self.0.get(&asset_class.policy)
-            && let Some(quantity) = assets.get(&asset_class.token)
-        {
-            return *quantity;
-        }
-        0
+        self.get_ref(asset_class).copied().unwrap_or(0)
     }
 
     pub fn insert(&mut self, asset_class: &AssetClass, quantity: i128) {
-        match self.0.get_mut(&asset_class.policy) {
+        match self.0.get_mut(asset_class.policy.as_slice()) {
             Some(tokens) => {
                 tokens.insert(asset_class.token.clone(), quantity);
             }
@@ -135,6 +162,35 @@ impl Value {
     }
 }
 
+impl Value {
+    /// The assets whose quantities differ, with `self - other` for each.
+    pub fn diff(&self, other: &Value) -> Vec<(AssetClass, BigInt)> {
+        let mut deltas: BTreeMap<AssetClass, i128> = BTreeMap::new();
+        let assets = |value: &Value| {
+            value.0.iter().flat_map(|(policy, tokens)| {
+                tokens.iter().map(|(token, quantity)| {
+                    let asset = AssetClass {
+                        policy: policy.clone(),
+                        token: token.clone(),
+                    };
+                    (asset, *quantity)
+                })
+            })
+        };
+        for (asset, quantity) in assets(self) {
+            *deltas.entry(asset).or_default() += quantity;
+        }
+        for (asset, quantity) in assets(other) {
+            *deltas.entry(asset).or_default() -= quantity;
+        }
+        deltas
+            .into_iter()
+            .filter(|(_, delta)| *delta != 0)
+            .map(|(asset, delta)| (asset, BigInt::from(delta)))
+            .collect()
+    }
+}
+
 impl serde::Serialize for Value {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -168,8 +224,8 @@ impl serde::Serialize for Value {
 #[derive(PartialEq, Eq, Debug)]
 pub enum Datum {
     None,
-    ParsedOrder(OrderDatum),
-    ParsedPool(PoolDatum),
+    ParsedOrder(Versioned<OrderDatum>),
+    ParsedPool(Versioned<PoolDatum>),
 }
 
 impl Serialize for Datum {
@@ -244,19 +300,66 @@ pub fn convert_datum(datum: Option<MintedDatumOption>) -> Datum {
     }
 }
 
+/// Describes why a datum could not be parsed as a `T`, for outputs which
+/// should have carried one.
+pub fn datum_error<T: AsPlutus>(datum: Option<MintedDatumOption>) -> String {
+    match datum {
+        None => "output has no datum".to_string(),
+        Some(MintedDatumOption::Hash(h)) => {
+            format!("output has datum hash {h} instead of an inline datum")
+        }
+        Some(MintedDatumOption::Data(d)) => match T::from_plutus(d.0.unwrap()) {
+            Ok(_) => "datum parsed, but as the wrong type".to_string(),
+            Err(error) => format!("{error:?}"),
+        },
+    }
+}
+
+/// A readable view of a whole transaction, for investigating what happened on chain.
+#[derive(Debug, serde::Serialize)]
+pub struct TxSummary {
+    #[serde(serialize_with = "hex::serialize")]
+    pub hash: Vec<u8>,
+    pub fee: Option<u64>,
+    pub inputs: Vec<TransactionInput>,
+    pub outputs: Vec<TransactionOutput>,
+}
+
+impl TxSummary {
+    pub fn decode(raw_tx: &[u8]) -> anyhow::Result<Self> {
+        let tx = MultiEraTx::decode(raw_tx)?;
+        let inputs = tx
+            .inputs()
+            .iter()
+            .map(|i| TransactionInput::new(*i.hash(), i.index()))
+            .collect();
+        let outputs = tx
+            .outputs()
+            .iter()
+            .map(convert_transaction_output)
+            .collect();
+        Ok(Self {
+            hash: tx.hash().to_vec(),
+            fee: tx.fee(),
+            inputs,
+            outputs,
+        })
+    }
+}
+
 pub fn convert_value<'b>(value: pallas_traverse::MultiEraValue<'b>) -> Value {
     let mut result = BTreeMap::new();
     let mut ada_policy = BTreeMap::new();
-    ada_policy.insert(vec![], value.coin().into());
-    result.insert(vec![], ada_policy);
+    ada_policy.insert(Interned::EMPTY, value.coin().into());
+    result.insert(Interned::EMPTY, ada_policy);
     for policy in value.assets() {
         let mut p_map = BTreeMap::new();
         let pol = policy.policy();
         for asset in policy.assets() {
             let tok = asset.name();
-            p_map.insert(tok.to_vec(), asset.any_coin());
+            p_map.insert(Interned::new(tok), asset.any_coin());
         }
-        result.insert(pol.to_vec(), p_map);
+        result.insert(Interned::new(&pol[..]), p_map);
     }
     Value(result)
 }
@@ -298,4 +401,27 @@ mod tests {
         assert!(rberry < sberry);
         assert!(sberry < foobar);
     }
+
+    #[test]
+    fn should_diff_values_per_asset() {
+        let rberry = AssetClass::from_pair((vec![0x66, 0x67], vec![0x66, 0x66]));
+        let sberry = AssetClass::from_pair((vec![0x66, 0x67], vec![0x66, 0x67]));
+        let left = crate::value!(10, (&rberry, 5));
+        let right = crate::value!(10, (&rberry, 7), (&sberry, 1));
+        assert_eq!(
+            left.diff(&right),
+            vec![(rberry, BigInt::from(-2)), (sberry, BigInt::from(-1))]
+        );
+        assert!(left.diff(&left).is_empty());
+    }
+
+    #[test]
+    fn should_borrow_only_held_quantities() {
+        let rberry = AssetClass::from_pair((vec![0x66, 0x67], vec![0x66, 0x66]));
+        let sberry = AssetClass::from_pair((vec![0x66, 0x67], vec![0x66, 0x67]));
+        let value = crate::value!(10, (&rberry, 5));
+        assert_eq!(value.get_ref(&rberry), Some(&5));
+        assert_eq!(value.get_ref(&sberry), None);
+        assert_eq!(value.get_asset_class(&sberry), 0);
+    }
 }
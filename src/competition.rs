@@ -0,0 +1,257 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+
+use crate::{
+    cardano_types::TransactionInput,
+    sundaev3::{Ident, OrderEvent, OrderOutcome},
+};
+
+/// How long an order which left the book is remembered, waiting for the event
+/// which says who scooped it.
+const DEPARTED_GRACE_SLOTS: u64 = 3600;
+
+/// How our would-be scoops compare with the scoops other scoopers made.
+pub type CompetitionStats = Arc<Mutex<CompetitionByPool>>;
+
+/// The competition for each pool's orders, so a report can be limited to
+/// the pools its reader may see. Orders any pool can take are under `None`.
+#[derive(Clone, Debug, Default)]
+pub struct CompetitionByPool {
+    pools: BTreeMap<Option<Ident>, CompetitionReport>,
+}
+
+impl CompetitionByPool {
+    /// The report across the pools `visible` lets through.
+    pub fn report(&self, visible: impl Fn(Option<&Ident>) -> bool) -> CompetitionReport {
+        let mut report = CompetitionReport::default();
+        for (ident, pool) in &self.pools {
+            if visible(ident.as_ref()) {
+                report.add(pool);
+            }
+        }
+        let contested = report.won + report.lost;
+        report.win_share = (contested > 0).then(|| report.won as f64 / contested as f64);
+        report.average_lost_wait_slots =
+            (report.lost > 0).then(|| report.lost_wait_slots as f64 / report.lost as f64);
+        report
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CompetitionReport {
+    /// Scooped orders, by the hex key hash of the scooper which ran them.
+    /// Scoops under settings without a scooper list are counted as "unknown".
+    pub orders_by_scooper: BTreeMap<String, u64>,
+    /// Orders we would have scooped, scooped by one of our keys
+    pub won: u64,
+    /// Orders we would have scooped, scooped by someone else first
+    pub lost: u64,
+    /// Orders we never considered scoopable
+    pub not_scoopable: u64,
+    /// Our share of the orders we would have scooped
+    pub win_share: Option<f64>,
+    /// Mean slots lost orders waited between becoming scoopable and being scooped
+    pub average_lost_wait_slots: Option<f64>,
+    #[serde(skip)]
+    lost_wait_slots: u64,
+}
+
+impl CompetitionReport {
+    fn add(&mut self, other: &CompetitionReport) {
+        for (scooper, orders) in &other.orders_by_scooper {
+            *self.orders_by_scooper.entry(scooper.clone()).or_default() += orders;
+        }
+        self.won += other.won;
+        self.lost += other.lost;
+        self.not_scoopable += other.not_scoopable;
+        self.lost_wait_slots += other.lost_wait_slots;
+    }
+}
+
+#[derive(Debug)]
+struct Scoopable {
+    since: u64,
+    departed: Option<u64>,
+}
+
+/// Attributes each scooped order to the scooper which ran it, and counts the
+/// ones we'd have scooped ourselves as won or lost.
+pub struct CompetitionTracker {
+    key_hashes: Vec<Vec<u8>>,
+    scoopable: BTreeMap<TransactionInput, Scoopable>,
+    stats: CompetitionStats,
+}
+
+impl CompetitionTracker {
+    pub fn new(key_hashes: Vec<Vec<u8>>) -> Self {
+        Self {
+            key_hashes,
+            scoopable: BTreeMap::new(),
+            stats: CompetitionStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CompetitionStats {
+        self.stats.clone()
+    }
+
+    /// Notes which orders in the book we would scoop as of `slot`. An order
+    /// which stops being scoopable starts over if it becomes scoopable again.
+    pub fn update<'a>(
+        &mut self,
+        slot: u64,
+        book: impl IntoIterator<Item = (&'a TransactionInput, bool)>,
+    ) {
+        let mut in_book = BTreeMap::new();
+        for (order, scoopable) in book {
+            in_book.insert(order, scoopable);
+        }
+        self.scoopable
+            .retain(|order, entry| match in_book.get(order) {
+                Some(scoopable) => {
+                    entry.departed = None;
+                    *scoopable
+                }
+                None => {
+                    // Its event usually arrives just after the update which drops it
+                    let departed = *entry.departed.get_or_insert(slot);
+                    slot <= departed + DEPARTED_GRACE_SLOTS
+                }
+            });
+        for (order, scoopable) in in_book {
+            if scoopable && !self.scoopable.contains_key(order) {
+                self.scoopable.insert(
+                    order.clone(),
+                    Scoopable {
+                        since: slot,
+                        departed: None,
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn record(&mut self, event: &OrderEvent) {
+        let scoopable = self.scoopable.remove(&event.order);
+        if event.outcome != OrderOutcome::Scooped {
+            return;
+        }
+        let mut pools = self.stats.lock().unwrap();
+        let stats = pools.pools.entry(event.datum.ident.clone()).or_default();
+        let scooper = event
+            .scooper
+            .as_ref()
+            .map_or_else(|| "unknown".to_string(), hex::encode);
+        *stats.orders_by_scooper.entry(scooper).or_default() += 1;
+        let ours = event
+            .scooper
+            .as_ref()
+            .is_some_and(|key| self.key_hashes.contains(key));
+        match scoopable {
+            Some(_) if ours => stats.won += 1,
+            Some(scoopable) => {
+                stats.lost += 1;
+                stats.lost_wait_slots += event.slot.saturating_sub(scoopable.since);
+            }
+            None => stats.not_scoopable += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cardano_types::ADA_ASSET_CLASS,
+        sundaev3::{
+            Order, OrderDatum,
+            test_utils::{datum, event},
+        },
+    };
+
+    fn order(index: u64) -> TransactionInput {
+        TransactionInput::new([0; 32].as_slice().into(), index)
+    }
+
+    fn scooped(index: u64, slot: u64, scooper: Option<u8>) -> OrderEvent {
+        scooped_from(None, index, slot, scooper)
+    }
+
+    fn scooped_from(
+        ident: Option<Ident>,
+        index: u64,
+        slot: u64,
+        scooper: Option<u8>,
+    ) -> OrderEvent {
+        let datum = OrderDatum {
+            ident,
+            ..datum(Order::Record(ADA_ASSET_CLASS))
+        };
+        OrderEvent {
+            order: order(index),
+            slot,
+            scooper: scooper.map(|key| vec![key; 28]),
+            ..event(OrderOutcome::Scooped, datum)
+        }
+    }
+
+    #[test]
+    fn should_count_wins_and_losses() {
+        let mut tracker = CompetitionTracker::new(vec![vec![1; 28]]);
+        let (a, b, c) = (order(0), order(1), order(2));
+        tracker.update(10, [(&a, true), (&b, true), (&c, false)]);
+        tracker.update(15, [(&a, true), (&b, true), (&c, false)]);
+
+        // The update which drops an order can come before its event
+        tracker.update(20, [(&b, true)]);
+        tracker.record(&scooped(0, 20, Some(1)));
+        tracker.record(&scooped(2, 20, Some(2)));
+        tracker.record(&scooped(1, 30, Some(2)));
+
+        let stats = tracker.stats().lock().unwrap().report(|_| true);
+        assert_eq!((stats.won, stats.lost, stats.not_scoopable), (1, 1, 1));
+        assert_eq!(stats.win_share, Some(0.5));
+        assert_eq!(stats.average_lost_wait_slots, Some(20.0));
+        assert_eq!(stats.orders_by_scooper[&hex::encode([2; 28])], 2);
+    }
+
+    #[test]
+    fn should_restart_the_wait_when_an_order_becomes_scoopable_again() {
+        let mut tracker = CompetitionTracker::new(vec![]);
+        let a = order(0);
+        tracker.update(10, [(&a, true)]);
+        tracker.update(20, [(&a, false)]);
+        tracker.update(30, [(&a, true)]);
+        tracker.record(&scooped(0, 35, None));
+
+        let stats = tracker.stats().lock().unwrap().report(|_| true);
+        assert_eq!(stats.lost, 1);
+        assert_eq!(stats.average_lost_wait_slots, Some(5.0));
+        assert_eq!(stats.orders_by_scooper["unknown"], 1);
+    }
+
+    #[test]
+    fn should_report_only_the_visible_pools() {
+        let mut tracker = CompetitionTracker::new(vec![vec![1; 28]]);
+        let (visible, hidden) = (Ident::new(&[1; 28]), Ident::new(&[2; 28]));
+        let a = order(0);
+        tracker.update(10, [(&a, true)]);
+        tracker.record(&scooped_from(Some(visible.clone()), 0, 20, Some(1)));
+        tracker.record(&scooped_from(Some(hidden), 1, 20, Some(2)));
+        tracker.record(&scooped(2, 20, Some(2)));
+
+        let stats = tracker.stats().lock().unwrap().clone();
+        let report = stats.report(|ident| ident == Some(&visible));
+        assert_eq!((report.won, report.lost, report.not_scoopable), (1, 0, 0));
+        assert_eq!(report.win_share, Some(1.0));
+        assert!(!report.orders_by_scooper.contains_key(&hex::encode([2; 28])));
+
+        let report = stats.report(|_| true);
+        assert_eq!(report.not_scoopable, 2);
+        assert_eq!(report.orders_by_scooper[&hex::encode([2; 28])], 2);
+    }
+}
@@ -1,24 +1,226 @@
-use std::path::Path;
+use std::{fs, net::SocketAddr, path::Path};
 
-use anyhow::Result;
-use config::{Config, File};
+use anyhow::{Context, Result, bail};
+use config::{Config, File, FileFormat};
 use serde::Deserialize;
 
-use crate::persistence::PersistenceConfig;
+use crate::{
+    attestation::AttestationConfig,
+    attribution::AttributionConfig,
+    auth::AuthConfig,
+    notifications::NotificationsConfig,
+    persistence::{CursorConfig, InstrumentationConfig, PersistenceConfig},
+    protocol::ProtocolConfig,
+    scooper::ScooperConfig,
+    screening::ScreeningConfig,
+    sundaev3::{BroadcastConfig, OrderLimits, PoolAliases},
+    telemetry::TelemetryConfig,
+};
 
 pub const ROLLBACK_LIMIT: u64 = 2160;
 
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
+    /// Used when no protocol is given on the command line
+    #[serde(default)]
+    pub protocol: Option<ProtocolConfig>,
+    #[serde(default)]
+    pub components: ComponentsConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
     #[serde(default)]
     pub persistence: PersistenceConfig,
+    #[serde(default)]
+    pub instrumentation: InstrumentationConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    #[serde(default)]
+    pub cursors: CursorConfig,
+    #[serde(default)]
+    pub restart: RestartConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub scooper: ScooperConfig,
+    #[serde(default)]
+    pub broadcast: BroadcastConfig,
+    #[serde(default)]
+    pub screening: Option<ScreeningConfig>,
+    #[serde(default)]
+    pub order_limits: OrderLimits,
+    #[serde(default)]
+    pub pool_aliases: PoolAliases,
+    #[serde(default)]
+    pub attestation: AttestationConfig,
+    #[serde(default)]
+    pub attribution: AttributionConfig,
+}
+
+/// Which parts this process runs, so roles can be split across nodes sharing
+/// a database.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ComponentsConfig {
+    /// Sync from the chain and write to the database. Without it, the state
+    /// is reloaded from the database as another node writes it.
+    pub indexer: bool,
+    pub scooper: bool,
+    pub admin: bool,
+    /// How often the database is checked for new blocks without an indexer
+    pub follow_interval_ms: u64,
+}
+
+impl Default for ComponentsConfig {
+    fn default() -> Self {
+        Self {
+            indexer: true,
+            scooper: true,
+            admin: true,
+            follow_interval_ms: 2000,
+        }
+    }
 }
 
-pub fn load_config(config_path: &Path) -> Result<Config> {
-    Ok(Config::builder()
-        .add_source(File::with_name("config/acropolis"))
-        .add_source(File::with_name(&config_path.to_string_lossy()))
-        .build()?)
+/// Where the admin server listens.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AdminConfig {
+    pub listen_address: SocketAddr,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            listen_address: SocketAddr::from(([127, 0, 0, 1], 9999)),
+        }
+    }
+}
+
+/// How the manager loop retries when the acropolis process fails to start.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct RestartConfig {
+    pub initial_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    /// Consecutive failures before giving up until a restart is requested. 0 never gives up.
+    pub max_failures: u32,
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff_secs: 5,
+            max_backoff_secs: 300,
+            max_failures: 10,
+        }
+    }
+}
+
+/// An endpoint to notify when orders are scooped or cancelled.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Key for the HMAC-SHA256 signature sent with each payload
+    pub secret: String,
+    /// Hex pool idents to notify about. Empty means all pools.
+    #[serde(default)]
+    pub pools: Vec<String>,
+    /// Hex key or script hashes of order owners to notify about. Empty means all owners.
+    #[serde(default)]
+    pub owners: Vec<String>,
+    #[serde(default = "default_webhook_attempts")]
+    pub max_attempts: u32,
+    #[serde(default)]
+    pub retry: WebhookRetryConfig,
+}
+
+/// How long to wait between attempts to deliver to a webhook.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct WebhookRetryConfig {
+    pub initial_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+}
+
+impl Default for WebhookRetryConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff_secs: 1,
+            max_backoff_secs: 60,
+        }
+    }
+}
+
+fn default_webhook_attempts() -> u32 {
+    5
+}
+
+pub const ENV_PREFIX: &str = "SCOOPER_";
+
+/// The acropolis module wiring, built in so a single mounted config file is
+/// enough to run.
+const ACROPOLIS_DEFAULTS: &str = include_str!("../config/acropolis.toml");
+
+/// The directory relative paths in a config file are resolved against.
+pub fn config_dir(config_path: &Path) -> &Path {
+    config_path.parent().unwrap_or(Path::new(""))
+}
+
+/// Layers the built-in acropolis defaults, a `config/acropolis.toml` next to
+/// the config file if there is one, the config file, then `SCOOPER_`
+/// environment variables, then `key=value` overrides from the command line,
+/// each taking precedence over the last.
+pub fn load_config(config_path: &Path, cli_overrides: &[String]) -> Result<Config> {
+    let acropolis = config_dir(config_path).join("config/acropolis");
+    let mut builder = Config::builder()
+        .add_source(File::from_str(ACROPOLIS_DEFAULTS, FileFormat::Toml))
+        .add_source(File::with_name(&acropolis.to_string_lossy()).required(false))
+        .add_source(File::with_name(&config_path.to_string_lossy()));
+    for (key, value) in env_overrides(std::env::vars())? {
+        builder = builder.set_override(key, value)?;
+    }
+    for pair in cli_overrides {
+        let Some((key, value)) = pair.split_once('=') else {
+            bail!("expected key=value, got \"{pair}\"");
+        };
+        builder = builder.set_override(key, value)?;
+    }
+    Ok(builder.build()?)
+}
+
+/// Maps `SCOOPER_AUTH__ROOT_API_KEY` to `auth.root-api-key`: `__` separates
+/// levels and `_` stands for `-`. With a `_FILE` suffix, the value is read
+/// from that file instead, for secrets mounted into containers.
+pub fn env_overrides(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<Vec<(String, String)>> {
+    let mut overrides = vec![];
+    for (name, value) in vars {
+        let Some(name) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let (name, value) = match name.strip_suffix("_FILE") {
+            Some(name) => {
+                let contents = fs::read_to_string(&value)
+                    .with_context(|| format!("could not read {ENV_PREFIX}{name}_FILE"))?;
+                (name, contents.trim_end().to_string())
+            }
+            None => (name, value),
+        };
+        let key = name
+            .to_lowercase()
+            .split("__")
+            .map(|level| level.replace('_', "-"))
+            .collect::<Vec<_>>()
+            .join(".");
+        overrides.push((key, value));
+    }
+    Ok(overrides)
 }
 
 pub fn use_mithril(cfg: &Config) -> bool {
@@ -26,3 +228,71 @@ pub fn use_mithril(cfg: &Config) -> bool {
         .map(|m| m == "mithril")
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Network;
+
+    #[test]
+    fn should_load_a_single_config_file() -> Result<()> {
+        let dir = std::env::temp_dir().join("scooper-single-config");
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("scooper.toml");
+        fs::write(
+            &path,
+            r#"
+protocol = "preview"
+
+[global.startup]
+network-name = "preview"
+
+[scooper]
+log-dir = "/var/log/scooper"
+
+[admin]
+listen-address = "0.0.0.0:9000"
+"#,
+        )?;
+        let config = load_config(&path, &[])?;
+        // The acropolis wiring comes from the built-in defaults
+        assert_eq!(
+            config.get_string("message-bus.internal.class")?,
+            "in-memory"
+        );
+        assert_eq!(config.get_string("global.startup.network-name")?, "preview");
+
+        let app_config = config.try_deserialize::<AppConfig>()?;
+        let protocol = app_config.protocol.as_ref().map(|p| p.resolve(&dir));
+        assert_eq!(protocol.transpose()?, Some(Network::Preview.protocol()));
+        assert_eq!(app_config.scooper.log_dir, Path::new("/var/log/scooper"));
+        assert_eq!(
+            app_config.admin.listen_address,
+            SocketAddr::from(([0, 0, 0, 0], 9000))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_map_env_vars_to_config_keys() -> Result<()> {
+        let secret = std::env::temp_dir().join("scooper-root-api-key");
+        fs::write(&secret, "hunter2\n")?;
+        let vars = [
+            ("SCOOPER_AUTH__ROOT_API_KEY_FILE", secret.to_str().unwrap()),
+            ("SCOOPER_RESTART__MAX_FAILURES", "3"),
+            ("PATH", "/usr/bin"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        assert_eq!(
+            env_overrides(vars)?,
+            vec![
+                ("auth.root-api-key".to_string(), "hunter2".to_string()),
+                ("restart.max-failures".to_string(), "3".to_string()),
+            ]
+        );
+
+        let missing = [("SCOOPER_X_FILE".to_string(), "/nonexistent".to_string())];
+        assert!(env_overrides(missing).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+use thiserror::Error;
+
+use crate::sundaev3::ValidationError;
+
+/// Errors which reach API callers. Each has a stable `code` that clients can
+/// branch on, independent of the human-readable message.
+#[derive(Debug, Error)]
+pub enum ScooperError {
+    #[error("unauthorized")]
+    Unauthorized,
+    #[error("forbidden")]
+    Forbidden,
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+    #[error("internal error")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl ScooperError {
+    pub fn not_found(what: impl Into<String>) -> Self {
+        Self::NotFound(what.into())
+    }
+
+    /// For errors caused by what the caller sent, such as a bad query string.
+    pub fn bad_request(err: impl Into<anyhow::Error>) -> Self {
+        Self::BadRequest(format!("{:#}", err.into()))
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::NotFound(_) => "not_found",
+            Self::BadRequest(_) => "bad_request",
+            Self::Validation(_) => "validation",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::Unauthorized => 401,
+            Self::Forbidden => 403,
+            Self::NotFound(_) => 404,
+            Self::BadRequest(_) | Self::Validation(_) => 400,
+            Self::Internal(_) => 500,
+        }
+    }
+}
+
+impl Serialize for ScooperError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("ScooperError", 3)?;
+        s.serialize_field("code", self.code())?;
+        // Internal errors are logged, not shown to callers
+        s.serialize_field("message", &self.to_string())?;
+        if let Self::Validation(err) = self {
+            s.serialize_field("details", err)?;
+        }
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_serialize_code_and_message() {
+        let json = |err: ScooperError| serde_json::to_value(err).unwrap();
+        assert_eq!(
+            json(ScooperError::not_found("No such pool")),
+            serde_json::json!({ "code": "not_found", "message": "No such pool" })
+        );
+        assert_eq!(
+            json(anyhow::anyhow!("database is locked").into()),
+            serde_json::json!({ "code": "internal", "message": "internal error" })
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Runs indexes of other protocols alongside SundaeV3, in the same process and
+//! database. An extension is any [`ChainIndex`], built by its
+//! [`ChainIndexFactory`] each time the pipeline starts and given an
+//! [`ExtensionDao`] of its own. Its cursor is stored under its namespace, so
+//! it halts, unhalts and resumes independently of the SundaeV3 index.
+//!
+//! `examples/script_utxo_index.rs` is a worked example.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+
+pub use acropolis_common::{BlockInfo, Point};
+pub use acropolis_module_custom_indexer::chain_index::ChainIndex;
+
+pub use crate::persistence::ExtensionDao;
+use crate::persistence::Persistence;
+
+/// What extension indexes' names start with, to keep their cursors apart.
+pub const EXTENSION_INDEX_PREFIX: &str = "ext/";
+
+/// Builds an extension index, each time the pipeline starts.
+#[async_trait]
+pub trait ChainIndexFactory: Send + Sync + 'static {
+    /// Builds the index, loading whatever it keeps in `store`.
+    async fn build(
+        &self,
+        store: Box<dyn ExtensionDao>,
+    ) -> Result<Box<dyn ChainIndex + Send + Sync>>;
+}
+
+struct Registered {
+    namespace: String,
+    start: Point,
+    factory: Box<dyn ChainIndexFactory>,
+}
+
+/// The extension indexes a [`crate::runtime::ScooperRuntime`] runs.
+#[derive(Clone, Default)]
+pub struct ExtensionIndexes {
+    indexes: Vec<Arc<Registered>>,
+}
+
+impl ExtensionIndexes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an index, which syncs from `start` until it has a cursor. The
+    /// namespace names both its cursor and its records, and is made of
+    /// lowercase letters, digits and dashes.
+    pub fn register(
+        &mut self,
+        namespace: &str,
+        start: Point,
+        factory: impl ChainIndexFactory,
+    ) -> Result<()> {
+        let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-';
+        if namespace.is_empty() || !namespace.chars().all(valid) {
+            bail!(
+                "extension namespace \"{namespace}\" must be lowercase letters, digits and dashes"
+            );
+        }
+        if self.namespaces().contains(namespace) {
+            bail!("extension namespace \"{namespace}\" is already registered");
+        }
+        self.indexes.push(Arc::new(Registered {
+            namespace: namespace.to_string(),
+            start,
+            factory: Box::new(factory),
+        }));
+        Ok(())
+    }
+
+    pub fn namespaces(&self) -> BTreeSet<&str> {
+        self.indexes
+            .iter()
+            .map(|index| index.namespace.as_str())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indexes.is_empty()
+    }
+
+    /// Builds every index, with the point each starts from without a cursor.
+    pub(crate) async fn build(
+        &self,
+        persistence: &dyn Persistence,
+    ) -> Result<Vec<(NamespacedIndex, Point)>> {
+        let mut built = vec![];
+        for index in &self.indexes {
+            let store = persistence.extension_dao(&index.namespace);
+            built.push((
+                NamespacedIndex {
+                    name: index_name(&index.namespace),
+                    inner: index.factory.build(store).await?,
+                },
+                index.start.clone(),
+            ));
+        }
+        Ok(built)
+    }
+}
+
+/// The name an extension's cursor is stored under.
+pub fn index_name(namespace: &str) -> String {
+    format!("{EXTENSION_INDEX_PREFIX}{namespace}")
+}
+
+/// Renames an extension index into its namespace, whatever it calls itself.
+pub(crate) struct NamespacedIndex {
+    name: String,
+    inner: Box<dyn ChainIndex + Send + Sync>,
+}
+
+#[async_trait]
+impl ChainIndex for NamespacedIndex {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn handle_onchain_tx_bytes(&mut self, info: &BlockInfo, raw_tx: &[u8]) -> Result<()> {
+        self.inner.handle_onchain_tx_bytes(info, raw_tx).await
+    }
+
+    async fn handle_rollback(&mut self, point: &Point) -> Result<()> {
+        self.inner.handle_rollback(point).await
+    }
+
+    async fn reset(&mut self, point: &Point) -> Result<Point> {
+        self.inner.reset(point).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::{PersistenceConfig, connect};
+
+    struct Counter;
+
+    #[async_trait]
+    impl ChainIndex for Counter {
+        fn name(&self) -> String {
+            "counter".to_string()
+        }
+
+        async fn handle_onchain_tx_bytes(&mut self, _: &BlockInfo, _: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        async fn handle_rollback(&mut self, _: &Point) -> Result<()> {
+            Ok(())
+        }
+
+        async fn reset(&mut self, point: &Point) -> Result<Point> {
+            Ok(point.clone())
+        }
+    }
+
+    struct CounterFactory;
+
+    #[async_trait]
+    impl ChainIndexFactory for CounterFactory {
+        async fn build(
+            &self,
+            store: Box<dyn ExtensionDao>,
+        ) -> Result<Box<dyn ChainIndex + Send + Sync>> {
+            store
+                .put(crate::cardano_types::Slot(1), b"built", Some(b""))
+                .await?;
+            Ok(Box::new(Counter))
+        }
+    }
+
+    #[tokio::test]
+    async fn should_build_indexes_in_their_namespaces() -> Result<()> {
+        let mut extensions = ExtensionIndexes::new();
+        extensions.register("partner", Point::Origin, CounterFactory)?;
+        assert!(
+            extensions
+                .register("partner", Point::Origin, CounterFactory)
+                .is_err()
+        );
+        assert!(
+            extensions
+                .register("Partner", Point::Origin, CounterFactory)
+                .is_err()
+        );
+        assert!(
+            extensions
+                .register("", Point::Origin, CounterFactory)
+                .is_err()
+        );
+
+        let persistence = connect(&PersistenceConfig::default()).await?;
+        let built = extensions.build(persistence.as_ref()).await?;
+        assert_eq!(built.len(), 1);
+        assert_eq!(built[0].0.name(), "ext/partner");
+        assert_eq!(
+            persistence.extension_dao("partner").get(b"built").await?,
+            Some(vec![])
+        );
+        Ok(())
+    }
+}
@@ -1,9 +1,52 @@
 use std::{borrow::Cow, collections::BTreeMap};
 
+use acropolis_common::{BlockHash, BlockInfo, Era, Point};
 use anyhow::{Result, bail};
 
+/// The block a state entry was built from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockMeta {
+    pub slot: u64,
+    pub height: u64,
+    pub hash: BlockHash,
+    pub era: Era,
+}
+
+impl BlockMeta {
+    pub fn point(&self) -> Point {
+        Point::Specific {
+            slot: self.slot,
+            hash: self.hash,
+        }
+    }
+}
+
+impl From<&BlockInfo> for BlockMeta {
+    fn from(info: &BlockInfo) -> Self {
+        Self {
+            slot: info.slot,
+            height: info.number,
+            hash: info.hash,
+            era: info.era,
+        }
+    }
+}
+
+struct Entry<T> {
+    /// Missing for state restored from the database, where we don't know which
+    /// block it came from
+    block: Option<BlockMeta>,
+    state: T,
+}
+
 pub struct HistoricalState<T> {
-    slots: BTreeMap<u64, T>,
+    slots: BTreeMap<u64, Entry<T>>,
+}
+
+impl<T: Default + Clone> Default for HistoricalState<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: Default + Clone> HistoricalState<T> {
@@ -15,31 +58,82 @@ impl<T: Default + Clone> HistoricalState<T> {
 
     pub fn latest(&self) -> Cow<'_, T> {
         match self.slots.last_key_value() {
-            Some((_, v)) => Cow::Borrowed(v),
+            Some((_, e)) => Cow::Borrowed(&e.state),
             None => Cow::Owned(T::default()),
         }
     }
 
-    pub fn update_slot(&mut self, slot: u64) -> Result<&mut T> {
-        let Some((&latest_slot, _)) = self.slots.last_key_value() else {
-            return Ok(self.slots.entry(slot).or_default());
-        };
-        if latest_slot > slot {
-            bail!("cannot update slot {slot} because we are on slot {latest_slot}");
+    /// The state as of `slot`, with every block up to and including it
+    /// applied. Slots before the oldest state we keep have none.
+    pub fn latest_at_slot(&self, slot: u64) -> Option<&T> {
+        let (_, entry) = self.slots.range(..=slot).next_back()?;
+        Some(&entry.state)
+    }
+
+    /// The most recent block applied to the state, if we saw it happen.
+    pub fn latest_block(&self) -> Option<&BlockMeta> {
+        self.slots.last_key_value()?.1.block.as_ref()
+    }
+
+    /// The range of slots we can currently roll back across.
+    pub fn slot_range(&self) -> Option<(u64, u64)> {
+        let (oldest, _) = self.slots.first_key_value()?;
+        let (latest, _) = self.slots.last_key_value()?;
+        Some((*oldest, *latest))
+    }
+
+    /// Replaces all history with a single state, e.g. one loaded from the database.
+    pub fn restore(&mut self, slot: u64, state: T) {
+        self.slots.clear();
+        self.slots.insert(slot, Entry { block: None, state });
+    }
+
+    /// Blocks are applied in slot order, and only one block per slot.
+    fn check_block(&self, block: &BlockMeta) -> Result<()> {
+        let slot = block.slot;
+        if let Some((&latest_slot, latest)) = self.slots.last_key_value() {
+            if latest_slot > slot {
+                bail!("cannot update slot {slot} because we are on slot {latest_slot}");
+            }
+            if latest_slot == slot && latest.block.as_ref().is_some_and(|b| b != block) {
+                bail!(
+                    "cannot apply block {} over another block in the same slot",
+                    block.point()
+                );
+            }
         }
-        if latest_slot == slot {
-            return Ok(self.slots.get_mut(&slot).unwrap());
+        Ok(())
+    }
+
+    /// The state `block` would change, to work out what it changes from
+    /// before handing that to `commit_block` once everything else is done.
+    pub fn state_for_block(&self, block: &BlockMeta) -> Result<Cow<'_, T>> {
+        self.check_block(block)?;
+        Ok(self.latest())
+    }
+
+    /// Applies `change` to the state as of `block`. The state before it is
+    /// copied on the block's first change, and only then.
+    pub fn commit_block(&mut self, block: &BlockMeta, change: impl FnOnce(&mut T)) -> Result<()> {
+        self.check_block(block)?;
+        if !self.slots.contains_key(&block.slot) {
+            let state = self.latest().into_owned();
+            self.slots.insert(block.slot, Entry { block: None, state });
         }
-        let last_entry = match self.slots.range(..slot).last() {
-            Some((_, e)) => e.clone(),
-            None => T::default(),
-        };
-        Ok(self.slots.entry(slot).or_insert(last_entry))
+        let entry = self.slots.get_mut(&block.slot).unwrap();
+        entry.block = Some(block.clone());
+        change(&mut entry.state);
+        Ok(())
     }
 
-    pub fn prune_history(&mut self, rollback_limit: u64) -> bool {
+    /// Drops history which we can no longer roll back to, keeping the state as of
+    /// `min_height`. This is the same cutoff the database prunes at.
+    pub fn prune_below_height(&mut self, min_height: u64) -> bool {
         let mut pruned = false;
-        while self.slots.len() > rollback_limit as usize {
+        while let Some(next_block) = self.slots.values().nth(1).map(|e| e.block.as_ref()) {
+            if next_block.is_none_or(|b| b.height > min_height) {
+                break;
+            }
             self.slots.pop_first();
             pruned = true;
         }
@@ -49,7 +143,8 @@ impl<T: Default + Clone> HistoricalState<T> {
     pub fn rollback_to_slot(&mut self, slot: u64) -> Vec<(u64, T)> {
         let mut rolled_back = vec![];
         while self.slots.last_key_value().is_some_and(|(s, _)| *s > slot) {
-            rolled_back.push(self.slots.pop_last().unwrap());
+            let (slot, entry) = self.slots.pop_last().unwrap();
+            rolled_back.push((slot, entry.state));
         }
         rolled_back
     }
@@ -57,21 +152,54 @@ impl<T: Default + Clone> HistoricalState<T> {
     pub fn rollback_to_origin(&mut self) {
         self.slots.clear();
     }
+
+    /// Checks that an index resuming from `cursor` would pick up where this
+    /// state left off, rather than skipping or replaying blocks.
+    pub fn verify_resume_point(&self, cursor: &Point) -> Result<()> {
+        let Some(tip) = self.latest_block().map(BlockMeta::point) else {
+            return Ok(());
+        };
+        if cursor.slot() < tip.slot() {
+            bail!("cursor {cursor} is behind indexed state at {tip}");
+        }
+        if cursor.slot() == tip.slot() && *cursor != tip {
+            bail!("cursor {cursor} disagrees with indexed state at {tip}");
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn block(slot: u64) -> BlockMeta {
+        block_with_hash(slot, 0)
+    }
+
+    fn block_with_hash(slot: u64, byte: u8) -> BlockMeta {
+        BlockMeta {
+            slot,
+            height: slot,
+            hash: BlockHash::new([byte; 32]),
+            era: Era::Conway,
+        }
+    }
+
+    /// Applies `block` as pushing `value` onto the state.
+    fn push(history: &mut HistoricalState<Vec<u8>>, block: &BlockMeta, value: u8) -> Result<()> {
+        history.commit_block(block, |state| state.push(value))
+    }
+
     #[test]
     fn should_copy_old_state_into_new_slot() -> Result<()> {
         let mut history = HistoricalState::<Vec<u8>>::new();
         assert!(history.latest().is_empty());
 
-        history.update_slot(0)?.push(1);
+        push(&mut history, &block(0), 1)?;
         assert_eq!(history.latest().as_ref(), &[1]);
 
-        history.update_slot(1)?.push(2);
+        push(&mut history, &block(1), 2)?;
         assert_eq!(history.latest().as_ref(), &[1, 2]);
 
         Ok(())
@@ -82,10 +210,10 @@ mod tests {
         let mut history = HistoricalState::<Vec<u8>>::new();
         assert!(history.latest().is_empty());
 
-        history.update_slot(0)?.push(1);
+        push(&mut history, &block(0), 1)?;
         assert_eq!(history.latest().as_ref(), &[1]);
 
-        history.update_slot(1)?.push(2);
+        push(&mut history, &block(1), 2)?;
         assert_eq!(history.latest().as_ref(), &[1, 2]);
 
         history.rollback_to_slot(0);
@@ -99,13 +227,115 @@ mod tests {
         let mut history = HistoricalState::<Vec<u8>>::new();
         assert!(history.latest().is_empty());
 
-        history.update_slot(0)?.push(1);
+        push(&mut history, &block(0), 1)?;
         assert_eq!(history.latest().as_ref(), &[1]);
 
-        history.update_slot(1)?.push(2);
+        push(&mut history, &block(1), 2)?;
         assert_eq!(history.latest().as_ref(), &[1, 2]);
 
-        assert!(history.update_slot(0).is_err());
+        assert!(history.state_for_block(&block(0)).is_err());
+        assert!(history.state_for_block(&block_with_hash(1, 1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_only_apply_committed_state() -> Result<()> {
+        let mut history = HistoricalState::<Vec<u8>>::new();
+        push(&mut history, &block(0), 1)?;
+
+        // Reading the state a block would change leaves history as it was
+        assert_eq!(history.state_for_block(&block(1))?.as_ref(), &[1]);
+        assert_eq!(history.slot_range(), Some((0, 0)));
+        assert_eq!(history.latest_block(), Some(&block(0)));
+        push(&mut history, &block(1), 2)?;
+        assert_eq!(history.latest().as_ref(), &[1, 2]);
+        assert_eq!(history.latest_block(), Some(&block(1)));
+
+        // and a later tx in the same block builds on it
+        assert_eq!(history.state_for_block(&block(1))?.as_ref(), &[1, 2]);
+        push(&mut history, &block(1), 3)?;
+        assert_eq!(history.latest().as_ref(), &[1, 2, 3]);
+        assert_eq!(history.slot_range(), Some((0, 1)));
+
+        assert!(history.state_for_block(&block(0)).is_err());
+        assert!(push(&mut history, &block_with_hash(1, 1), 4).is_err());
+        assert_eq!(history.latest().as_ref(), &[1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn should_track_slot_range() -> Result<()> {
+        let mut history = HistoricalState::<Vec<u8>>::new();
+        assert_eq!(history.slot_range(), None);
+
+        push(&mut history, &block(3), 1)?;
+        push(&mut history, &block(5), 2)?;
+        assert_eq!(history.slot_range(), Some((3, 5)));
+        assert_eq!(history.latest_block(), Some(&block(5)));
+
+        history.rollback_to_slot(4);
+        assert_eq!(history.slot_range(), Some((3, 3)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_read_state_at_a_slot() -> Result<()> {
+        let mut history = HistoricalState::<Vec<u8>>::new();
+        assert_eq!(history.latest_at_slot(0), None);
+
+        history.restore(2, vec![0]);
+        push(&mut history, &block(4), 4)?;
+        push(&mut history, &block(7), 7)?;
+        assert_eq!(history.latest_at_slot(1), None);
+        assert_eq!(history.latest_at_slot(2).unwrap(), &[0]);
+        // Slots without a block see the state of the block before
+        assert_eq!(history.latest_at_slot(6).unwrap(), &[0, 4]);
+        assert_eq!(history.latest_at_slot(7).unwrap(), &[0, 4, 7]);
+        assert_eq!(history.latest_at_slot(100).unwrap(), &[0, 4, 7]);
+
+        history.rollback_to_slot(5);
+        assert_eq!(history.latest_at_slot(7).unwrap(), &[0, 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn should_prune_by_height() -> Result<()> {
+        let mut history = HistoricalState::<Vec<u8>>::new();
+        history.restore(1, vec![0]);
+        for slot in 2..=5 {
+            push(&mut history, &block(slot), slot as u8)?;
+        }
+
+        // The state as of height 3 is kept, so we can still roll back to it
+        assert!(history.prune_below_height(3));
+        assert_eq!(history.slot_range(), Some((3, 5)));
+        assert!(!history.prune_below_height(3));
+
+        // but the latest state is never pruned
+        assert!(history.prune_below_height(100));
+        assert_eq!(history.slot_range(), Some((5, 5)));
+        assert_eq!(history.latest().as_ref(), &[0, 2, 3, 4, 5]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_verify_resume_point() -> Result<()> {
+        let mut history = HistoricalState::<Vec<u8>>::new();
+
+        // Nothing to compare against for state loaded from the database
+        history.restore(10, vec![]);
+        assert!(history.verify_resume_point(&Point::Origin).is_ok());
+
+        push(&mut history, &block_with_hash(10, 1), 1)?;
+        let point = |slot, byte| block_with_hash(slot, byte).point();
+        assert!(history.verify_resume_point(&point(10, 1)).is_ok());
+        assert!(history.verify_resume_point(&point(12, 2)).is_ok());
+        assert!(history.verify_resume_point(&point(10, 2)).is_err());
+        assert!(history.verify_resume_point(&point(9, 1)).is_err());
+        assert!(history.verify_resume_point(&Point::Origin).is_err());
+
         Ok(())
     }
 }
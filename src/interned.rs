@@ -0,0 +1,166 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+/// A byte string shared by everything holding the same bytes. Policy ids and
+/// token names repeat across thousands of orders and pools, so each distinct
+/// one is stored once. The empty string needs no allocation, which keeps ADA
+/// constant.
+#[derive(Clone, Default)]
+pub struct Interned(Option<Arc<[u8]>>);
+
+struct Interner {
+    strings: HashSet<Arc<[u8]>>,
+    /// How big the set can get before strings nothing holds any more are dropped
+    prune_at: usize,
+}
+
+const MIN_PRUNE_AT: usize = 1024;
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(|| {
+    Mutex::new(Interner {
+        strings: HashSet::new(),
+        prune_at: MIN_PRUNE_AT,
+    })
+});
+
+impl Interned {
+    pub const EMPTY: Interned = Interned(None);
+
+    pub fn new(bytes: &[u8]) -> Self {
+        if bytes.is_empty() {
+            return Self::EMPTY;
+        }
+        let mut interner = INTERNER.lock().unwrap();
+        if let Some(existing) = interner.strings.get(bytes) {
+            return Self(Some(existing.clone()));
+        }
+        if interner.strings.len() >= interner.prune_at {
+            interner.strings.retain(|s| Arc::strong_count(s) > 1);
+            interner.prune_at = (interner.strings.len() * 2).max(MIN_PRUNE_AT);
+        }
+        let bytes: Arc<[u8]> = bytes.into();
+        interner.strings.insert(bytes.clone());
+        Self(Some(bytes))
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.0.as_deref().unwrap_or_default()
+    }
+}
+
+impl Deref for Interned {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for Interned {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl Borrow<[u8]> for Interned {
+    fn borrow(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl From<&[u8]> for Interned {
+    fn from(bytes: &[u8]) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<&Vec<u8>> for Interned {
+    fn from(bytes: &Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Interned {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(&bytes)
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for Interned {
+    fn from(bytes: [u8; N]) -> Self {
+        Self::new(&bytes)
+    }
+}
+
+impl PartialEq for Interned {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for Interned {}
+
+impl PartialEq<[u8]> for Interned {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl PartialEq<Vec<u8>> for Interned {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl PartialOrd for Interned {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Interned {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl Hash for Interned {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl fmt::Debug for Interned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn should_share_equal_strings() {
+        let a = Interned::new(b"RBERRY");
+        let b = Interned::from(b"RBERRY".to_vec());
+        assert_eq!(a, b);
+        assert!(Arc::ptr_eq(a.0.as_ref().unwrap(), b.0.as_ref().unwrap()));
+        assert_eq!(Interned::new(&[]), Interned::EMPTY);
+        assert!(Interned::EMPTY.is_empty());
+
+        // Ordered and looked up like the bytes themselves
+        let map = BTreeMap::from([(a, 1), (Interned::EMPTY, 0)]);
+        assert_eq!(map.keys().next(), Some(&Interned::EMPTY));
+        assert_eq!(map.get(b"RBERRY".as_slice()), Some(&1));
+    }
+}
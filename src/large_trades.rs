@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bigint::BigInt,
+    cardano_types::{ADA_ASSET_CLASS, AssetClass},
+    sundaev3::{Ident, Order, SundaeV3Order, SundaeV3Pool},
+};
+
+/// Holds large swaps back for a while after they're placed, so they can't be
+/// sandwiched by someone watching the mempool. Orders are spent whole, so a
+/// large swap can't be split across scoops; it can only wait.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct LargeTradePolicy {
+    /// Swaps giving at least this much ADA, or the equivalent in the other
+    /// asset of an ADA pool, are large
+    pub min_lovelace: Option<u64>,
+    /// Swaps giving at least this fraction of the pool's reserve of the asset
+    /// they give are large
+    pub min_reserve_fraction: Option<f64>,
+    /// How many slots after it's placed a large swap waits before it's scooped
+    pub delay_slots: u64,
+}
+
+/// A swap the policy considers large against one pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LargeTrade {
+    pub pool: Ident,
+    pub gives: BigInt,
+    /// The first slot it can be scooped in
+    pub until_slot: u64,
+}
+
+impl LargeTradePolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.delay_slots > 0 && (self.min_lovelace.is_some() || self.min_reserve_fraction.is_some())
+    }
+
+    /// Whether `order` is a large swap against `pool`.
+    pub fn check(&self, order: &SundaeV3Order, pool: &SundaeV3Pool) -> Option<LargeTrade> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let Order::Swap(gives, _) = &order.datum.action else {
+            return None;
+        };
+        let asset = AssetClass::from_pair((&gives.policy, &gives.token));
+        let amount = gives.amount.to_f64()?;
+        let (coin_a, coin_b) = &pool.pool_datum.assets;
+        let reserve = |coin: &AssetClass| {
+            let mut reserve = BigInt::from(pool.value.get_asset_class(coin));
+            if *coin == ADA_ASSET_CLASS {
+                reserve -= &pool.pool_datum.protocol_fees;
+            }
+            reserve.to_f64()
+        };
+
+        let lovelace = if asset == ADA_ASSET_CLASS {
+            Some(amount)
+        } else if *coin_a == ADA_ASSET_CLASS && asset == *coin_b {
+            Some(amount * reserve(coin_a)? / reserve(coin_b)?)
+        } else {
+            None
+        };
+        let over_lovelace = self
+            .min_lovelace
+            .zip(lovelace)
+            .is_some_and(|(min, lovelace)| lovelace >= min as f64);
+        let over_fraction = self
+            .min_reserve_fraction
+            .zip(reserve(&asset))
+            .is_some_and(|(min, reserve)| reserve > 0.0 && amount / reserve >= min);
+
+        (over_lovelace || over_fraction).then(|| LargeTrade {
+            pool: pool.pool_datum.ident.clone(),
+            gives: gives.amount.clone(),
+            until_slot: order.slot + self.delay_slots,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sundaev3::test_utils::{self, datum, rberry, singleton},
+        value,
+    };
+
+    fn swap(gives: &AssetClass, amount: i64, takes: &AssetClass) -> SundaeV3Order {
+        let action = Order::Swap(singleton(gives, amount), singleton(takes, 0));
+        test_utils::order(0, 100, datum(action))
+    }
+
+    /// 1,000 ADA against 4,000 RBERRY, with 3 ADA of protocol fees.
+    fn pool() -> SundaeV3Pool {
+        test_utils::pool(value![1_003_000_000, (&rberry(), 4_000_000_000)], 3_000_000)
+    }
+
+    #[test]
+    fn should_flag_swaps_over_either_threshold() {
+        let policy = LargeTradePolicy {
+            min_lovelace: Some(100_000_000),
+            min_reserve_fraction: Some(0.2),
+            delay_slots: 20,
+        };
+        let large = |gives: i64| LargeTrade {
+            pool: Ident::new(&[3; 28]),
+            gives: BigInt::from(gives),
+            until_slot: 120,
+        };
+
+        let ada = swap(&ADA_ASSET_CLASS, 100_000_000, &rberry());
+        assert_eq!(policy.check(&ada, &pool()), Some(large(100_000_000)));
+        let ada = swap(&ADA_ASSET_CLASS, 99_000_000, &rberry());
+        assert_eq!(policy.check(&ada, &pool()), None);
+
+        // Priced in ADA through the pool, 400 RBERRY is worth 100 ADA
+        let tokens = swap(&rberry(), 400_000_000, &ADA_ASSET_CLASS);
+        assert_eq!(policy.check(&tokens, &pool()), Some(large(400_000_000)));
+        let tokens = swap(&rberry(), 399_000_000, &ADA_ASSET_CLASS);
+        assert_eq!(policy.check(&tokens, &pool()), None);
+
+        let by_fraction = LargeTradePolicy {
+            min_lovelace: None,
+            ..policy
+        };
+        let ada = swap(&ADA_ASSET_CLASS, 200_000_000, &rberry());
+        assert_eq!(by_fraction.check(&ada, &pool()), Some(large(200_000_000)));
+        let ada = swap(&ADA_ASSET_CLASS, 150_000_000, &rberry());
+        assert_eq!(by_fraction.check(&ada, &pool()), None);
+
+        assert_eq!(LargeTradePolicy::default().check(&ada, &pool()), None);
+    }
+}
@@ -0,0 +1,38 @@
+pub mod attestation;
+pub mod attribution;
+pub mod auth;
+pub mod backoff;
+pub mod bigint;
+pub mod cardano_types;
+pub mod clock;
+pub mod competition;
+pub mod config;
+pub mod error;
+pub mod export;
+pub mod extensions;
+pub mod historical_state;
+pub mod interned;
+pub mod large_trades;
+pub mod metrics;
+pub mod mev;
+#[cfg(test)]
+mod mock_chain;
+pub mod multisig;
+pub mod notifications;
+pub mod order_filter;
+pub mod owner_policy;
+pub mod persistence;
+pub mod plutus_json;
+pub mod protocol;
+pub mod redeemers;
+pub mod runtime;
+pub mod scooper;
+pub mod screening;
+mod serde_compat;
+pub mod snapshot;
+pub mod sundaev3;
+pub mod telemetry;
+pub mod verify;
+pub mod webhooks;
+
+pub use protocol::SundaeV3Protocol;
@@ -1,74 +1,85 @@
-use acropolis_common::messages::Message;
 use acropolis_common::{BlockHash, Point};
-use acropolis_module_block_unpacker::BlockUnpacker;
-use acropolis_module_custom_indexer::CustomIndexer;
-use acropolis_module_genesis_bootstrapper::GenesisBootstrapper;
-use acropolis_module_mithril_snapshot_fetcher::MithrilSnapshotFetcher;
-use acropolis_module_peer_network_interface::PeerNetworkInterface;
-use anyhow::{Result, anyhow};
-use caryatid_process::Process;
-use caryatid_sdk::module_registry::ModuleRegistry;
+use acropolis_module_custom_indexer::cursor_store::CursorEntry;
+use anyhow::{Result, anyhow, bail};
 use clap::Parser;
 use tokio::select;
 use tokio::signal::ctrl_c;
 use tokio::sync::Mutex;
+use tokio::task::{JoinError, JoinHandle};
 use tokio_util::sync::CancellationToken;
 
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use std::process;
 use std::sync::Arc;
-use std::time::Duration;
 use tracing::{Level, event, info, warn};
 
-mod bigint;
-mod cardano_types;
-mod config;
-mod historical_state;
-mod multisig;
-mod persistence;
-mod scooper;
-mod serde_compat;
-mod sundaev3;
+use serde::Serialize;
 
-use serde::{Deserialize, Serialize};
-
-use cardano_types::TransactionInput;
-use sundaev3::{Ident, validate_order};
-
-use http_body_util::Full;
+use http_body_util::{BodyExt, Full, Limited};
 use hyper::body::Bytes;
 use hyper::server::conn::http1;
-use hyper::{Request, Response, body::Incoming as IncomingBody};
+use hyper::{Method, Request, Response, StatusCode, body::Incoming as IncomingBody};
 use hyper_util::rt::TokioIo;
 use std::net::SocketAddr;
 use std::pin::Pin;
 use tokio::net::{TcpListener, TcpStream};
 
-use crate::config::AppConfig;
-use crate::persistence::Persistence;
-use crate::scooper::Scooper;
-use crate::sundaev3::{
-    PoolError, SundaeV3HistoricalState, SundaeV3Indexer, SundaeV3Update, ValidationError,
+use scooper_v2::SundaeV3Protocol;
+use scooper_v2::attestation::{Attestor, LatestAttestation};
+use scooper_v2::attribution::Attribution;
+use scooper_v2::auth::{self, AuthConfig};
+use scooper_v2::bigint::{BigInt, BigRational};
+use scooper_v2::cardano_types::{TransactionInput, TxSummary};
+use scooper_v2::clock::SystemClock;
+use scooper_v2::competition::CompetitionStats;
+use scooper_v2::config::{self, AppConfig};
+use scooper_v2::error::ScooperError;
+use scooper_v2::export::{self, ExportQuery};
+use scooper_v2::metrics;
+use scooper_v2::mev::MevReport;
+use scooper_v2::multisig::TimeWindow;
+use scooper_v2::notifications::Notifier;
+use scooper_v2::persistence::{
+    self, ApiKey, InstrumentedPersistence, Persistence, QuarantinedTxo, SchemaStatus, ScoopCadence,
+    SlaReport, Trade,
 };
-
-#[derive(Clone, Deserialize)]
-struct SundaeV3Protocol {
-    #[serde(with = "hex")]
-    order_script_hash: Vec<u8>,
-    #[serde(with = "hex")]
-    pool_script_hash: Vec<u8>,
-}
+use scooper_v2::plutus_json;
+use scooper_v2::runtime::{RestartRequest, ScooperRuntime, unhalt_index};
+use scooper_v2::scooper::Scooper;
+use scooper_v2::screening::Screening;
+use scooper_v2::snapshot::Snapshot;
+use scooper_v2::sundaev3::{
+    Credential, Ident, OrderDatum, OwnerError, PauseReason, PauseRules, PoolAliases, PoolError,
+    PoolFees, PoolIndex, PoolShard, SUNDAE_V3_INDEX_NAME, SettingsChange, SettingsDatum,
+    SundaeV3HistoricalState, SundaeV3Indexer, SundaeV3Order, SundaeV3Pool, SundaeV3State,
+    ValidationError, ValidationProfile, ValueError, get_pool_price, validate_order,
+};
+use scooper_v2::telemetry;
+use scooper_v2::verify::{self, Blockfrost};
+use scooper_v2::webhooks::{WebhookDispatcher, WebhookStatuses};
 
 #[derive(clap::Parser, Clone, Debug)]
 struct Args {
-    #[arg(short, long)]
-    protocol: PathBuf,
+    /// A network name (mainnet or preview), or the path to a protocol JSON file.
+    /// Read from the config's `protocol` key if not given.
+    #[arg(short, long, env = "SCOOPER_PROTOCOL")]
+    protocol: Option<String>,
 
     #[command(subcommand)]
     command: Commands,
 
-    #[arg(long, value_name = "PATH", default_value = "scooper.toml")]
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "SCOOPER_CONFIG",
+        default_value = "scooper.toml"
+    )]
     config: PathBuf,
+
+    /// Override a config value, taking precedence over the file and environment
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
 }
 
 const BLOCK_HASH_SIZE: usize = 32;
@@ -93,129 +104,951 @@ enum Commands {
         #[arg(short, long, value_parser=parse_block_hash)]
         block_hash: BlockHash,
     },
+    /// Clear the halted flag on an index's cursor, then exit
+    Unhalt {
+        index: String,
+
+        /// Rewind the index to this slot as well. Must be within the rollback window.
+        #[arg(short, long, requires = "block_hash")]
+        slot: Option<u64>,
+
+        #[arg(short, long, requires = "slot", value_parser=parse_block_hash)]
+        block_hash: Option<BlockHash>,
+    },
+    /// Compare the indexed state with the UTxOs at the protocol's scripts
+    /// according to another source, then exit. Fails if they differ.
+    Verify {
+        #[arg(long, value_enum)]
+        against: VerifySource,
+
+        /// The base URL of the source's API
+        #[arg(long)]
+        url: String,
+
+        #[arg(long, env = "BLOCKFROST_PROJECT_ID")]
+        project_id: Option<String>,
+    },
+    /// Inspect the database, then exit
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum VerifySource {
+    Blockfrost,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum DbCommands {
+    /// List the schema migrations this build knows about, and which are applied
+    Status,
+    /// Write the indexed pools, orders and settings to a snapshot file
+    ExportSnapshot { path: PathBuf },
+    /// Copy everything stored into the persistence configured in another file.
+    /// An interrupted copy picks up where it left off when run again.
+    Copy {
+        /// A config file whose persistence section is the target
+        #[arg(long)]
+        to: PathBuf,
+    },
 }
 
-#[derive(Clone)]
 struct AdminServer {
     index: Arc<Mutex<SundaeV3HistoricalState>>,
-    resync_tx: tokio::sync::broadcast::Sender<()>,
+    pools: PoolIndex,
+    restart_tx: tokio::sync::broadcast::Sender<RestartRequest>,
     protocol: SundaeV3Protocol,
+    persistence: Arc<dyn Persistence>,
+    webhooks: WebhookStatuses,
+    competition: CompetitionStats,
+    validation_profile: ValidationProfile,
+    screening: Option<Arc<Screening>>,
+    auth: AuthConfig,
+    aliases: PoolAliases,
+    attestation: Option<LatestAttestation>,
+    attribution: Attribution,
+    pause_rules: PauseRules,
 }
 
-impl hyper::service::Service<Request<IncomingBody>> for AdminServer {
+const QUARANTINE_LIMIT: u32 = 1000;
+const SETTINGS_HISTORY_LIMIT: u32 = 100;
+const DEFAULT_TRADES_LIMIT: u32 = 100;
+const MAX_TRADES_LIMIT: u32 = 1000;
+/// How many of a pool's latest scoops its cadence is computed over.
+const CADENCE_SCOOP_LIMIT: u32 = 1000;
+const SCOOP_HISTORY_LIMIT: u32 = 100;
+/// How many pools one `/pools/batch` request can ask for.
+const BATCH_POOL_LIMIT: usize = 100;
+const BATCH_BODY_LIMIT: usize = 64 * 1024;
+const DEFAULT_SLA_DAYS: u64 = 7;
+const MAX_SLA_DAYS: u64 = 90;
+/// Slots are one second long on every network since Shelley.
+const SLOTS_PER_DAY: u64 = 86_400;
+
+/// A static page which polls the JSON endpoints below.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Shares one `AdminServer` across every connection and request.
+#[derive(Clone)]
+struct AdminService(Arc<AdminServer>);
+
+impl hyper::service::Service<Request<IncomingBody>> for AdminService {
     type Response = Response<Full<Bytes>>;
     type Error = hyper::Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
     fn call(&self, req: Request<IncomingBody>) -> Self::Future {
-        let me = self.clone();
+        let me = self.0.clone();
         Box::pin(async move {
-            let s = me.do_call(req).await;
-            Ok(Response::builder().body(Full::new(Bytes::from(s))).unwrap())
+            if req.uri().path() == "/health" {
+                return Ok(me.health());
+            }
+            if req.uri().path() == "/" {
+                return Ok(Response::builder()
+                    .header(hyper::header::CONTENT_TYPE, "text/html; charset=utf-8")
+                    .body(Full::new(Bytes::from_static(DASHBOARD.as_bytes())))
+                    .unwrap());
+            }
+            let authorization = req
+                .headers()
+                .get(hyper::header::AUTHORIZATION)
+                .and_then(|h| h.to_str().ok());
+            let dao = me.persistence.api_key_dao();
+            let key = match auth::authenticate(&me.auth, dao.as_ref(), authorization).await {
+                Ok(Some(key)) => key,
+                Ok(None) => return Ok(me.error(ScooperError::Unauthorized)),
+                Err(e) => return Ok(me.error(e.into())),
+            };
+            match me.do_call(req, &key).await {
+                Ok(s) => Ok(Response::builder().body(Full::new(Bytes::from(s))).unwrap()),
+                Err(e) => Ok(me.error(e)),
+            }
         })
     }
 }
 
+#[derive(Serialize)]
+struct SettingsHistoryEntry<'a> {
+    txo: &'a TransactionInput,
+    slot: u64,
+    settings: &'a SettingsDatum,
+    /// What changed from the version before, if it is known
+    changes: Option<BTreeMap<String, SettingsChange>>,
+}
+
+#[derive(Serialize)]
+struct TradeEntry<'a> {
+    #[serde(flatten)]
+    trade: &'a Trade,
+    /// Units of B per unit of A
+    price: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct TradesResponse<'a> {
+    trades: Vec<TradeEntry<'a>>,
+    /// Where the next page starts, if there is one
+    next_from_slot: Option<u64>,
+}
+
 #[derive(Serialize)]
 struct QueryPoolResponse<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<&'a str>,
+    fees: PoolFees,
+    /// The credential the pool's ADA is staked with
+    delegation: Option<Credential>,
+    /// Whether governance has paused trading, so none of its orders are scooped
+    paused: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pause_reason: Option<PauseReason>,
     valid: Vec<&'a TransactionInput>,
+    /// Valid orders which fail checks the validation profile lets through
+    advisories: Vec<OrderAdvisories<'a>>,
+    /// Orders which would be valid, but are flagged by screening
+    screened: Vec<OrderScreened<'a>>,
     out_of_range: Vec<OrderOutOfRange<'a>>,
+    /// Orders their owner's time conditions don't allow yet
+    scheduled: Vec<OrderScheduled<'a>>,
     unrecoverable: Vec<OrderUnrecoverable<'a>>,
 }
 
+#[derive(Serialize)]
+struct PoolsBatchEntry<'a> {
+    pool: &'a SundaeV3Pool,
+    /// Units of B per unit of A, from the pool's reserves
+    price: Option<BigRational>,
+    cadence: &'a ScoopCadence,
+    #[serde(flatten)]
+    orders: QueryPoolResponse<'a>,
+}
+
+#[derive(Serialize)]
+struct PoolsBatchResponse<'a> {
+    /// The slot the pools are as of
+    slot: u64,
+    /// Keyed by the ident or alias each pool was asked for by
+    pools: BTreeMap<&'a str, PoolsBatchEntry<'a>>,
+    missing: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct OrderAdvisories<'a> {
+    order: &'a TransactionInput,
+    advisories: Vec<ValueError>,
+}
+
+#[derive(Serialize)]
+struct OrderScreened<'a> {
+    order: &'a TransactionInput,
+    reason: String,
+}
+
 #[derive(Serialize)]
 struct OrderOutOfRange<'a> {
     order: &'a TransactionInput,
-    reason: (f64, f64),
+    reason: (BigRational, BigRational),
+}
+
+#[derive(Serialize)]
+struct OrderScheduled<'a> {
+    order: &'a TransactionInput,
+    from_slot: u64,
 }
 
 #[derive(Serialize)]
 struct OrderUnrecoverable<'a> {
     order: &'a TransactionInput,
     reason: String,
+    error: ValidationError,
+}
+
+#[derive(Serialize)]
+struct CursorsResponse {
+    stored: HashMap<String, CursorEntry>,
+    in_memory: HashMap<String, InMemoryCursor>,
+}
+
+#[derive(Serialize)]
+struct InMemoryCursor {
+    oldest_slot: Option<u64>,
+    latest_slot: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct NewApiKey {
+    #[serde(flatten)]
+    key: ApiKey,
+    /// Only ever shown here, we keep just its hash
+    secret: String,
+}
+
+#[derive(Serialize)]
+struct PoolTxReport {
+    slot: u64,
+    /// The transaction CBOR, as it appeared on chain
+    tx: String,
+    summary: Option<TxSummary>,
+}
+
+#[derive(Serialize)]
+struct QuarantineReport<'a> {
+    #[serde(flatten)]
+    txo: &'a QuarantinedTxo,
+    datum: Option<serde_json::Value>,
+}
+
+impl<'a> QuarantineReport<'a> {
+    fn new(txo: &'a QuarantinedTxo) -> Self {
+        let datum = plutus_json::output_datum(txo.era, &txo.txo);
+        Self { txo, datum }
+    }
 }
 
 impl AdminServer {
-    async fn do_call(&self, req: Request<IncomingBody>) -> String {
-        if let Some(pool_id) = req.uri().path().strip_prefix("/pool/") {
-            let state = self.index.lock().await.latest().into_owned();
-            let id_bytes = hex::decode(pool_id).unwrap();
-            let ident = Ident::new(&id_bytes);
-            let pool = match state.pools.get(&ident).cloned() {
-                Some(p) => p,
-                None => {
-                    return "No such pool".into();
-                }
-            };
-            let mut response = QueryPoolResponse {
-                valid: vec![],
-                out_of_range: vec![],
-                unrecoverable: vec![],
-            };
-            for order in &state.orders {
-                if order.datum.ident.as_ref() != Some(&ident) {
-                    continue;
-                }
-                if let Err(err) = validate_order(
-                    &order.datum,
-                    &order.output.value,
-                    &pool.pool_datum,
-                    &pool.value,
-                    &self.protocol.pool_script_hash,
-                ) {
-                    if let ValidationError::PoolError(PoolError::OutOfRange {
-                        swap_price,
-                        pool_price,
-                    }) = err
-                    {
-                        response.out_of_range.push(OrderOutOfRange {
+    /// Why screening flags an order, if it's enabled and does.
+    fn screen(&self, datum: &OrderDatum) -> Option<String> {
+        self.screening.as_ref()?.screen(datum)
+    }
+
+    /// Sorts a pool's orders by whether, and why not, they can be scooped now.
+    fn query_pool<'a>(
+        &'a self,
+        ident: &Ident,
+        pool: &'a SundaeV3Pool,
+        orders: &'a [Arc<SundaeV3Order>],
+        slot: u64,
+        settings: Option<&SettingsDatum>,
+    ) -> QueryPoolResponse<'a> {
+        let now = self
+            .protocol
+            .slot_config
+            .map(|slot_config| (slot_config, BigInt::from(slot_config.slot_to_posix(slot))));
+        let pause_reason =
+            self.pause_rules
+                .check(&pool.pool_datum, settings, now.as_ref().map(|(_, now)| now));
+        let mut response = QueryPoolResponse {
+            alias: self.aliases.alias_of(ident),
+            fees: pool.pool_datum.fees(),
+            delegation: pool.stake_credential(),
+            paused: pause_reason.is_some(),
+            pause_reason,
+            valid: vec![],
+            advisories: vec![],
+            screened: vec![],
+            out_of_range: vec![],
+            scheduled: vec![],
+            unrecoverable: vec![],
+        };
+        for order in orders {
+            if let Some((slot_config, now)) = &now {
+                match order.datum.owner.time_window(now) {
+                    TimeWindow::Open => {}
+                    TimeWindow::From(time) => {
+                        response.scheduled.push(OrderScheduled {
                             order: &order.input,
-                            reason: (swap_price, pool_price),
+                            from_slot: slot_config.posix_to_slot(time.to_u64().unwrap_or(u64::MAX)),
                         });
-                    } else {
+                        continue;
+                    }
+                    TimeWindow::Closed => {
+                        let err = ValidationError::from(OwnerError::Expired);
                         response.unrecoverable.push(OrderUnrecoverable {
                             order: &order.input,
                             reason: err.to_string(),
+                            error: err,
+                        });
+                        continue;
+                    }
+                }
+            }
+            match validate_order(
+                &order.datum,
+                &order.output.value,
+                &pool.pool_datum,
+                &pool.value,
+                &self.protocol.pool_script_hash,
+                self.protocol.ada_rider,
+                self.validation_profile,
+            ) {
+                Ok(advisories) => {
+                    if let Some(reason) = self.screen(&order.datum) {
+                        response.screened.push(OrderScreened {
+                            order: &order.input,
+                            reason,
+                        });
+                        continue;
+                    }
+                    if !advisories.is_empty() {
+                        response.advisories.push(OrderAdvisories {
+                            order: &order.input,
+                            advisories,
                         });
                     }
-                } else {
                     response.valid.push(&order.input);
                 }
+                Err(ValidationError::PoolError(PoolError::OutOfRange {
+                    swap_price,
+                    pool_price,
+                })) => {
+                    response.out_of_range.push(OrderOutOfRange {
+                        order: &order.input,
+                        reason: (swap_price, pool_price),
+                    });
+                }
+                Err(err) => {
+                    response.unrecoverable.push(OrderUnrecoverable {
+                        order: &order.input,
+                        reason: err.to_string(),
+                        error: err,
+                    });
+                }
+            }
+        }
+        response
+    }
+
+    /// Everything `/pool/<ident>` and `/pool/<ident>/cadence` report, for many
+    /// pools at once. Pools which aren't indexed, or the key can't see, are
+    /// listed as missing rather than failing the batch.
+    async fn pools_batch(
+        &self,
+        req: Request<IncomingBody>,
+        key: &ApiKey,
+    ) -> Result<String, ScooperError> {
+        if req.method() != Method::POST {
+            return Err(ScooperError::bad_request(anyhow!("batches must be POSTed")));
+        }
+        let body = Limited::new(req.into_body(), BATCH_BODY_LIMIT)
+            .collect()
+            .await
+            .map_err(|e| ScooperError::bad_request(anyhow!("failed to read body: {e}")))?
+            .to_bytes();
+        let pool_ids = parse_batch_request(&body).map_err(ScooperError::bad_request)?;
+        let mut idents = vec![];
+        for pool_id in &pool_ids {
+            let ident = self
+                .aliases
+                .resolve(pool_id)
+                .map_err(ScooperError::bad_request)?;
+            idents.push(ident);
+        }
+
+        let slot = self.pools.slot();
+        let settings = self.pools.settings();
+        let dao = self.persistence.sundae_v3_dao();
+        let mut found = vec![];
+        let mut missing = vec![];
+        for (pool_id, ident) in pool_ids.iter().zip(&idents) {
+            match self.pools.get(ident) {
+                Some(shard) if key.can_see_pool(ident) => {
+                    let scoops = dao.load_scoops(ident, CADENCE_SCOOP_LIMIT).await?;
+                    found.push((pool_id, ident, shard, ScoopCadence::new(&scoops)));
+                }
+                _ => missing.push(pool_id.as_str()),
+            }
+        }
+        let pools = found
+            .iter()
+            .map(|(pool_id, ident, PoolShard { pool, orders }, cadence)| {
+                let entry = PoolsBatchEntry {
+                    pool,
+                    price: get_pool_price(
+                        &self.protocol.pool_script_hash,
+                        &pool.value,
+                        &pool.pool_datum.protocol_fees,
+                    ),
+                    cadence,
+                    orders: self.query_pool(
+                        ident,
+                        pool,
+                        orders,
+                        slot,
+                        settings.as_ref().map(|settings| &settings.datum),
+                    ),
+                };
+                (pool_id.as_str(), entry)
+            })
+            .collect();
+        let response = PoolsBatchResponse {
+            slot,
+            pools,
+            missing,
+        };
+        Ok(serde_json::to_string(&response).unwrap())
+    }
+
+    /// Reads the state as of `at_slot`, from the history kept for rollbacks.
+    /// The indexer waits on the lock meanwhile, so `read` should copy out only
+    /// what it needs.
+    async fn read_at<R>(
+        &self,
+        at_slot: u64,
+        read: impl FnOnce(&SundaeV3State) -> R,
+    ) -> Result<R, ScooperError> {
+        let index = self.index.lock().await;
+        match index.latest_at_slot(at_slot) {
+            Some(state) => Ok(read(state)),
+            None => Err(ScooperError::bad_request(anyhow!(
+                "slot {at_slot} is outside the rollback window"
+            ))),
+        }
+    }
+
+    fn health(&self) -> Response<Full<Bytes>> {
+        let not_ready =
+            metrics::INDEXER_CIRCUIT_OPEN.get() > 0 || metrics::SCOOPER_DEAUTHORIZED.get() > 0;
+        let (status, body) = if not_ready {
+            (StatusCode::SERVICE_UNAVAILABLE, "not ready")
+        } else {
+            (StatusCode::OK, "health")
+        };
+        Response::builder()
+            .status(status)
+            .body(Full::new(Bytes::from(body)))
+            .unwrap()
+    }
+
+    fn error(&self, err: ScooperError) -> Response<Full<Bytes>> {
+        if let ScooperError::Internal(e) = &err {
+            tracing::error!("Failed to handle request: {e:#}");
+        }
+        Response::builder()
+            .status(err.status())
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(serde_json::to_string(&err).unwrap())))
+            .unwrap()
+    }
+
+    async fn export(
+        &self,
+        key: &ApiKey,
+        table: &str,
+        query: Option<&str>,
+    ) -> Result<String, ScooperError> {
+        let query = ExportQuery::parse(query).map_err(ScooperError::bad_request)?;
+        let columns = query.columns.as_deref();
+        let dao = self.persistence.sundae_v3_dao();
+        match table {
+            "reserves" => {
+                let mut rows = dao
+                    .export_reserve_snapshots(query.from_slot, query.to_slot)
+                    .await?;
+                rows.retain(|row| key.can_see_pool(&row.ident));
+                export::to_csv(&rows, columns).map_err(ScooperError::bad_request)
+            }
+            // Raw txos aren't split up by pool, so only unscoped keys can export them
+            "orders" | "pools" if !key.sees_all_pools() => Err(ScooperError::Forbidden),
+            "orders" | "pools" => {
+                let txo_type = if table == "orders" { "order" } else { "pool" };
+                let rows = dao
+                    .export_txos(txo_type, query.from_slot, query.to_slot)
+                    .await?;
+                export::to_csv(&rows, columns).map_err(ScooperError::bad_request)
+            }
+            other => Err(ScooperError::not_found(format!(
+                "no such export \"{other}\""
+            ))),
+        }
+    }
+
+    async fn api_keys(&self, req: &Request<IncomingBody>) -> Result<String, ScooperError> {
+        let dao = self.persistence.api_key_dao();
+        if let Some(name) = req.uri().path().strip_prefix("/api-keys/") {
+            if req.method() != Method::DELETE {
+                return Err(ScooperError::bad_request(anyhow!(
+                    "keys can only be deleted"
+                )));
+            }
+            if !dao.delete_api_key(name).await? {
+                return Err(ScooperError::not_found("No such key"));
+            }
+            return Ok("deleted".into());
+        }
+        if req.method() == Method::POST {
+            let key = auth::parse_new_key(req.uri().query()).map_err(ScooperError::bad_request)?;
+            let secret = auth::generate_secret();
+            dao.save_api_key(&key, &auth::hash_secret(&secret)).await?;
+            return Ok(serde_json::to_string_pretty(&NewApiKey { key, secret }).unwrap());
+        }
+        Ok(serde_json::to_string_pretty(&dao.list_api_keys().await?).unwrap())
+    }
+
+    async fn do_call(
+        &self,
+        req: Request<IncomingBody>,
+        key: &ApiKey,
+    ) -> Result<String, ScooperError> {
+        let path = req.uri().path();
+        if auth::is_admin_only(path) && !key.admin {
+            return Err(ScooperError::Forbidden);
+        }
+
+        if path == "/api-keys" || path.starts_with("/api-keys/") {
+            return self.api_keys(&req).await;
+        }
+
+        if path == "/pools/batch" {
+            return self.pools_batch(req, key).await;
+        }
+
+        if let Some(tx_hash) = path.strip_prefix("/tx/") {
+            let tx_hash = hex::decode(tx_hash).map_err(ScooperError::bad_request)?;
+            let Some(pool_tx) = self
+                .persistence
+                .sundae_v3_dao()
+                .load_pool_tx(&tx_hash)
+                .await?
+            else {
+                return Err(ScooperError::not_found("No such tx"));
+            };
+            let summary = match TxSummary::decode(&pool_tx.tx) {
+                Ok(summary) => Some(summary),
+                Err(e) => {
+                    warn!("Failed to decode stored tx: {e:#}");
+                    None
+                }
+            };
+            let report = PoolTxReport {
+                slot: pool_tx.slot,
+                tx: hex::encode(&pool_tx.tx),
+                summary,
+            };
+            return Ok(serde_json::to_string_pretty(&report).unwrap());
+        }
+
+        if let Some(pool_id) = req
+            .uri()
+            .path()
+            .strip_prefix("/pool/")
+            .and_then(|p| p.strip_suffix("/trades"))
+        {
+            let ident = self
+                .aliases
+                .resolve(pool_id)
+                .map_err(ScooperError::bad_request)?;
+            if !key.can_see_pool(&ident) {
+                return Err(ScooperError::not_found("No such pool"));
+            }
+            let (from_slot, limit) =
+                parse_trades_query(req.uri().query()).map_err(ScooperError::bad_request)?;
+            let dao = self.persistence.sundae_v3_dao();
+            let mut trades = dao.load_trades(&ident, from_slot, limit + 1).await?;
+            let mut next_from_slot = None;
+            if trades.len() > limit as usize {
+                // Pages end on a slot boundary, so the next one can start at a slot
+                let next = trades[limit as usize].slot;
+                trades.retain(|t| t.slot < next);
+                if trades.is_empty() {
+                    trades = dao.load_trades(&ident, next, u32::MAX).await?;
+                    trades.retain(|t| t.slot == next);
+                    next_from_slot = Some(next + 1);
+                } else {
+                    next_from_slot = Some(next);
+                }
+            }
+            let response = TradesResponse {
+                trades: trades
+                    .iter()
+                    .map(|trade| TradeEntry {
+                        trade,
+                        price: trade.price(),
+                    })
+                    .collect(),
+                next_from_slot,
+            };
+            return Ok(serde_json::to_string_pretty(&response).unwrap());
+        }
+
+        if let Some(pool_id) = req
+            .uri()
+            .path()
+            .strip_prefix("/pool/")
+            .and_then(|p| p.strip_suffix("/reserves-history"))
+        {
+            let ident = self
+                .aliases
+                .resolve(pool_id)
+                .map_err(ScooperError::bad_request)?;
+            if !key.can_see_pool(&ident) {
+                return Err(ScooperError::not_found("No such pool"));
+            }
+            let history = self
+                .persistence
+                .sundae_v3_dao()
+                .load_reserve_history(&ident)
+                .await?;
+            return Ok(serde_json::to_string_pretty(&history).unwrap());
+        }
+
+        if let Some(pool_id) = req
+            .uri()
+            .path()
+            .strip_prefix("/pool/")
+            .and_then(|p| p.strip_suffix("/scoops"))
+        {
+            let ident = self
+                .aliases
+                .resolve(pool_id)
+                .map_err(ScooperError::bad_request)?;
+            if !key.can_see_pool(&ident) {
+                return Err(ScooperError::not_found("No such pool"));
+            }
+            let scoops = self
+                .persistence
+                .sundae_v3_dao()
+                .load_scoops(&ident, SCOOP_HISTORY_LIMIT)
+                .await?;
+            return Ok(serde_json::to_string_pretty(&scoops).unwrap());
+        }
+
+        if let Some(pool_id) = req
+            .uri()
+            .path()
+            .strip_prefix("/pool/")
+            .and_then(|p| p.strip_suffix("/cadence"))
+        {
+            let ident = self
+                .aliases
+                .resolve(pool_id)
+                .map_err(ScooperError::bad_request)?;
+            if !key.can_see_pool(&ident) {
+                return Err(ScooperError::not_found("No such pool"));
+            }
+            let scoops = self
+                .persistence
+                .sundae_v3_dao()
+                .load_scoops(&ident, CADENCE_SCOOP_LIMIT)
+                .await?;
+            let cadence = ScoopCadence::new(&scoops);
+            return Ok(serde_json::to_string_pretty(&cadence).unwrap());
+        }
+
+        if let Some(pool_id) = req.uri().path().strip_prefix("/pool/") {
+            let ident = self
+                .aliases
+                .resolve(pool_id)
+                .map_err(ScooperError::bad_request)?;
+            let at_slot =
+                parse_at_slot_query(req.uri().query()).map_err(ScooperError::bad_request)?;
+            let (slot, shard, settings) = match at_slot {
+                Some(at_slot) => {
+                    let (shard, settings) = self
+                        .read_at(at_slot, |state| {
+                            (PoolShard::from_state(state, &ident), state.settings.clone())
+                        })
+                        .await?;
+                    (at_slot, shard, settings)
+                }
+                None => (
+                    self.pools.slot(),
+                    self.pools.get(&ident),
+                    self.pools.settings(),
+                ),
+            };
+            let PoolShard { pool, orders } = match shard {
+                Some(shard) if key.can_see_pool(&ident) => shard,
+                _ => {
+                    return Err(ScooperError::not_found("No such pool"));
+                }
+            };
+            let response = self.query_pool(
+                &ident,
+                &pool,
+                &orders,
+                slot,
+                settings.as_ref().map(|settings| &settings.datum),
+            );
+            return Ok(serde_json::to_string(&response).unwrap());
+        }
+
+        if let Some(id) = req
+            .uri()
+            .path()
+            .strip_prefix("/cursors/")
+            .and_then(|p| p.strip_suffix("/unhalt"))
+        {
+            if req.method() != Method::POST {
+                return Err(ScooperError::bad_request(anyhow!("unhalt must be a POST")));
+            }
+            let rewind_to =
+                parse_rewind_point(req.uri().query()).map_err(ScooperError::bad_request)?;
+            if let Some(point) = &rewind_to {
+                let oldest = self
+                    .index
+                    .lock()
+                    .await
+                    .slot_range()
+                    .map(|(oldest, _)| oldest);
+                if id != SUNDAE_V3_INDEX_NAME {
+                    return Err(ScooperError::bad_request(anyhow!(
+                        "cannot rewind index \"{id}\""
+                    )));
+                }
+                if oldest.is_none_or(|oldest| point.slot() < oldest) {
+                    return Err(ScooperError::bad_request(anyhow!(
+                        "rewind point is outside the rollback window"
+                    )));
+                }
             }
-            return serde_json::to_string(&response).unwrap();
+            let _ = self.restart_tx.send(RestartRequest::Unhalt {
+                index: id.to_string(),
+                rewind_to,
+            });
+            return Ok("unhalt".into());
         }
 
-        match req.uri().path() {
+        if let Some(table) = req
+            .uri()
+            .path()
+            .strip_prefix("/export/")
+            .and_then(|p| p.strip_suffix(".csv"))
+        {
+            return self.export(key, table, req.uri().query()).await;
+        }
+
+        let response = match req.uri().path() {
             "/resync-from-acropolis" => {
-                let _ = self.resync_tx.send(());
+                let _ = self.restart_tx.send(RestartRequest::Resync);
                 "resync".into()
             }
-            "/health" => "health".into(),
+            "/soft-resync" => {
+                let _ = self.restart_tx.send(RestartRequest::SoftResync);
+                "soft resync".into()
+            }
+            "/metrics" => metrics::render(),
+            "/webhooks" => {
+                let statuses = self.webhooks.lock().unwrap().clone();
+                serde_json::to_string_pretty(&statuses).unwrap()
+            }
+            "/cursors" => {
+                let stored = self.persistence.cursor_store().entries().await?;
+                let slot_range = self.index.lock().await.slot_range();
+                let in_memory = HashMap::from([(
+                    SUNDAE_V3_INDEX_NAME.to_string(),
+                    InMemoryCursor {
+                        oldest_slot: slot_range.map(|(oldest, _)| oldest),
+                        latest_slot: slot_range.map(|(_, latest)| latest),
+                    },
+                )]);
+                serde_json::to_string_pretty(&CursorsResponse { stored, in_memory }).unwrap()
+            }
+            "/quarantine" => {
+                let txos = self
+                    .persistence
+                    .sundae_v3_dao()
+                    .load_quarantined_txos(QUARANTINE_LIMIT)
+                    .await?;
+                let report: Vec<QuarantineReport> =
+                    txos.iter().map(QuarantineReport::new).collect();
+                serde_json::to_string_pretty(&report).unwrap()
+            }
+            "/settings" => {
+                let Some(settings) = self.pools.settings() else {
+                    return Err(ScooperError::not_found("No settings indexed"));
+                };
+                serde_json::to_string_pretty(&settings).unwrap()
+            }
+            "/settings/history" => {
+                // One extra, so the oldest shown still has something to diff against
+                let records = self
+                    .persistence
+                    .sundae_v3_dao()
+                    .load_settings_history(SETTINGS_HISTORY_LIMIT + 1)
+                    .await?;
+                let mut datums = vec![];
+                for record in &records {
+                    datums.push(SettingsDatum::from_cbor(&record.datum)?);
+                }
+                let history: Vec<SettingsHistoryEntry> = records
+                    .iter()
+                    .zip(&datums)
+                    .enumerate()
+                    .take(SETTINGS_HISTORY_LIMIT as usize)
+                    .map(|(ix, (record, settings))| SettingsHistoryEntry {
+                        txo: &record.txo_id,
+                        slot: record.slot,
+                        settings,
+                        changes: datums.get(ix + 1).map(|prev| settings.changes_since(prev)),
+                    })
+                    .collect();
+                serde_json::to_string_pretty(&history).unwrap()
+            }
+            "/sla" => {
+                let days = parse_sla_query(req.uri().query()).map_err(ScooperError::bad_request)?;
+                let dao = self.persistence.sundae_v3_dao();
+                let latest = dao.latest_slot().await?.map_or(0, |slot| slot.0);
+                let mut latencies = dao
+                    .load_order_latencies(latest.saturating_sub(days * SLOTS_PER_DAY))
+                    .await?;
+                latencies.retain(|latency| key.can_see_pool(&latency.ident));
+                serde_json::to_string_pretty(&SlaReport::new(&latencies)).unwrap()
+            }
+            "/analytics/sources" => {
+                let from_slot =
+                    parse_sources_query(req.uri().query()).map_err(ScooperError::bad_request)?;
+                let flow = self
+                    .persistence
+                    .sundae_v3_dao()
+                    .load_order_flow(from_slot)
+                    .await?;
+                let visible = flow.iter().filter(|order| key.can_see_pool(&order.ident));
+                serde_json::to_string_pretty(&self.attribution.aggregate(visible)).unwrap()
+            }
+            "/mev-report" => {
+                let from_slot =
+                    parse_sources_query(req.uri().query()).map_err(ScooperError::bad_request)?;
+                let mut flow = self
+                    .persistence
+                    .sundae_v3_dao()
+                    .load_order_flow(from_slot)
+                    .await?;
+                flow.retain(|order| key.can_see_pool(&order.ident));
+                serde_json::to_string_pretty(&MevReport::new(&flow)).unwrap()
+            }
+            "/attestations/latest" => {
+                let Some(attestation) = &self.attestation else {
+                    return Err(ScooperError::not_found("attestation is disabled"));
+                };
+                let Some(latest) = attestation.read().unwrap().clone() else {
+                    return Err(ScooperError::not_found("nothing attested yet"));
+                };
+                serde_json::to_string_pretty(&latest).unwrap()
+            }
+            "/competition" => {
+                let report = self
+                    .competition
+                    .lock()
+                    .unwrap()
+                    .report(|ident| key.can_see_orders_for(ident));
+                serde_json::to_string_pretty(&report).unwrap()
+            }
             "/pools" => {
-                let state = self.index.lock().await.latest().into_owned();
+                let at_slot =
+                    parse_at_slot_query(req.uri().query()).map_err(ScooperError::bad_request)?;
+                let pools = match at_slot {
+                    Some(at_slot) => self.read_at(at_slot, |state| state.pools.clone()).await?,
+                    None => self
+                        .pools
+                        .idents()
+                        .into_iter()
+                        .filter_map(|ident| {
+                            let PoolShard { pool, .. } = self.pools.get(&ident)?;
+                            Some((ident, pool))
+                        })
+                        .collect(),
+                };
                 let mut json_map = serde_json::Map::new();
 
-                for (ident, pool) in state.pools {
-                    json_map.insert(
-                        hex::encode(ident.to_bytes()),
-                        serde_json::to_value(pool).unwrap(),
-                    );
+                for (ident, pool) in pools {
+                    if !key.can_see_pool(&ident) {
+                        continue;
+                    }
+                    let mut json = serde_json::to_value(&pool).unwrap();
+                    json["fees"] = serde_json::to_value(pool.pool_datum.fees()).unwrap();
+                    if let Some(alias) = self.aliases.alias_of(&ident) {
+                        json["alias"] = alias.into();
+                    }
+                    json_map.insert(hex::encode(ident.to_bytes()), json);
                 }
 
                 serde_json::to_string_pretty(&json_map).unwrap()
             }
             "/orders" => {
-                let state = self.index.lock().await.latest().into_owned();
+                let include_closed =
+                    parse_orders_query(req.uri().query()).map_err(ScooperError::bad_request)?;
+                let mut orders = vec![];
+                for ident in self.pools.idents() {
+                    if key.can_see_pool(&ident)
+                        && let Some(shard) = self.pools.get(&ident)
+                    {
+                        orders.extend(shard.orders);
+                    }
+                }
+                for order in self.pools.unpooled_orders() {
+                    let visible = match &order.datum.ident {
+                        Some(ident) => key.can_see_pool(ident),
+                        None => key.sees_all_pools(),
+                    };
+                    if visible {
+                        orders.push(order);
+                    }
+                }
 
                 let mut json_map = serde_json::Map::new();
-                for order in &state.orders {
+                for order in &orders {
                     let hex = match order.datum.ident.as_ref() {
                         Some(id) => hex::encode(id.to_bytes()),
                         None => "null".to_string(),
                     };
 
                     match serde_json::to_value(order) {
-                        Ok(val) => {
+                        Ok(mut val) => {
+                            if let Some(reason) = self.screen(&order.datum) {
+                                val["screening"] = reason.into();
+                            }
                             json_map.insert(hex, val);
                         }
                         Err(e) => {
@@ -229,66 +1062,320 @@ impl AdminServer {
                     }
                 }
 
-                serde_json::to_string_pretty(&json_map).unwrap()
+                if !include_closed {
+                    return Ok(serde_json::to_string_pretty(&json_map).unwrap());
+                }
+                let closed_orders = self.pools.closed_orders();
+                let closed: Vec<_> = closed_orders
+                    .iter()
+                    .filter(|closed| match &closed.order.datum.ident {
+                        Some(ident) => key.can_see_pool(ident),
+                        None => key.sees_all_pools(),
+                    })
+                    .collect();
+                let response = serde_json::json!({ "open": json_map, "closed": closed });
+                serde_json::to_string_pretty(&response).unwrap()
             }
-            _ => "unknown".into(),
+            _ => return Err(ScooperError::not_found("unknown")),
+        };
+        Ok(response)
+    }
+}
+
+/// Reads an optional `slot` and `hash` pair out of a query string.
+fn parse_rewind_point(query: Option<&str>) -> Result<Option<Point>> {
+    let mut slot = None;
+    let mut hash = None;
+    for (key, value) in query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|kv| kv.split_once('='))
+    {
+        match key {
+            "slot" => slot = Some(value.parse::<u64>()?),
+            "hash" => hash = Some(parse_block_hash(value)?),
+            other => bail!("unrecognized parameter \"{other}\""),
+        }
+    }
+    match (slot, hash) {
+        (Some(slot), Some(hash)) => Ok(Some(Point::Specific { slot, hash })),
+        (None, None) => Ok(None),
+        _ => bail!("slot and hash must be passed together"),
+    }
+}
+
+fn parse_trades_query(query: Option<&str>) -> Result<(u64, u32)> {
+    let mut from_slot = 0;
+    let mut limit = DEFAULT_TRADES_LIMIT;
+    for (key, value) in query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|kv| kv.split_once('='))
+    {
+        match key {
+            "from_slot" => from_slot = value.parse::<u64>()?,
+            "limit" => limit = value.parse::<u32>()?,
+            other => bail!("unrecognized parameter \"{other}\""),
+        }
+    }
+    if limit == 0 || limit > MAX_TRADES_LIMIT {
+        bail!("limit must be between 1 and {MAX_TRADES_LIMIT}");
+    }
+    Ok((from_slot, limit))
+}
+
+/// Reads the optional `from_slot` out of an `/analytics/sources` or
+/// `/mev-report` query string.
+/// Without one, every order recorded is counted.
+fn parse_sources_query(query: Option<&str>) -> Result<u64> {
+    let mut from_slot = 0;
+    for (key, value) in query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|kv| kv.split_once('='))
+    {
+        match key {
+            "from_slot" => from_slot = value.parse::<u64>()?,
+            other => bail!("unrecognized parameter \"{other}\""),
+        }
+    }
+    Ok(from_slot)
+}
+
+/// Reads the optional `days` window out of an `/sla` query string.
+fn parse_sla_query(query: Option<&str>) -> Result<u64> {
+    let mut days = DEFAULT_SLA_DAYS;
+    for (key, value) in query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|kv| kv.split_once('='))
+    {
+        match key {
+            "days" => days = value.parse::<u64>()?,
+            other => bail!("unrecognized parameter \"{other}\""),
+        }
+    }
+    if days == 0 || days > MAX_SLA_DAYS {
+        bail!("days must be between 1 and {MAX_SLA_DAYS}");
+    }
+    Ok(days)
+}
+
+/// Reads the idents or aliases out of a `/pools/batch` body, a JSON array of
+/// strings. Duplicates are dropped.
+fn parse_batch_request(body: &[u8]) -> Result<Vec<String>> {
+    let mut pool_ids: Vec<String> = serde_json::from_slice(body)?;
+    let mut seen = std::collections::HashSet::new();
+    pool_ids.retain(|pool_id| seen.insert(pool_id.clone()));
+    if pool_ids.is_empty() || pool_ids.len() > BATCH_POOL_LIMIT {
+        bail!("a batch must ask for between 1 and {BATCH_POOL_LIMIT} pools");
+    }
+    Ok(pool_ids)
+}
+
+/// Reads the optional `at_slot` out of a `/pools` or `/pool/<ident>` query
+/// string. Without one, the latest state is read.
+fn parse_at_slot_query(query: Option<&str>) -> Result<Option<u64>> {
+    let mut at_slot = None;
+    for (key, value) in query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|kv| kv.split_once('='))
+    {
+        match key {
+            "at_slot" => at_slot = Some(value.parse::<u64>()?),
+            other => bail!("unrecognized parameter \"{other}\""),
+        }
+    }
+    Ok(at_slot)
+}
+
+/// Reads the optional `include_closed` flag out of an `/orders` query string.
+fn parse_orders_query(query: Option<&str>) -> Result<bool> {
+    let mut include_closed = false;
+    for (key, value) in query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|kv| kv.split_once('='))
+    {
+        match key {
+            "include_closed" => include_closed = value.parse::<bool>()?,
+            other => bail!("unrecognized parameter \"{other}\""),
         }
     }
+    Ok(include_closed)
 }
 
 #[tokio::main]
 #[allow(unreachable_code)]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt().with_env_filter("info").init();
-    event!(Level::INFO, "Started scooper");
     let args = Args::parse();
     let scooper_config_file = args.config;
 
-    let config = config::load_config(&scooper_config_file)?;
+    let config = config::load_config(&scooper_config_file, &args.overrides)?;
     let app_config = config.clone().try_deserialize::<AppConfig>()?;
+    let _telemetry = telemetry::init(&app_config.telemetry)?;
+    event!(Level::INFO, "Started scooper");
 
-    let protocol_config_file = args.protocol;
+    let load_protocol = || match (&args.protocol, &app_config.protocol) {
+        (Some(name_or_path), _) => SundaeV3Protocol::load(name_or_path),
+        (None, Some(protocol)) => protocol.resolve(config::config_dir(&scooper_config_file)),
+        (None, None) => bail!("no protocol: pass --protocol or set protocol in the config"),
+    };
     let default_start = match args.command {
         Commands::SyncFromOrigin => Point::Origin,
         Commands::SyncFromPoint { slot, block_hash } => Point::Specific {
             slot,
             hash: block_hash,
         },
+        Commands::Unhalt {
+            index,
+            slot,
+            block_hash,
+        } => {
+            let persistence = persistence::connect(&app_config.persistence).await?;
+            let rewind_to = slot
+                .zip(block_hash)
+                .map(|(slot, hash)| Point::Specific { slot, hash });
+            let history = Mutex::new(SundaeV3HistoricalState::new());
+            unhalt_index(persistence.as_ref(), &history, &index, rewind_to).await?;
+            info!("unhalted {index}");
+            return Ok(());
+        }
+        Commands::Verify {
+            against: VerifySource::Blockfrost,
+            url,
+            project_id,
+        } => {
+            let protocol = load_protocol()?;
+            let persistence = persistence::connect(&app_config.persistence).await?;
+            let source = Blockfrost::new(&url, project_id);
+            return verify_state(persistence, protocol, &source).await;
+        }
+        Commands::Db {
+            command: DbCommands::Status,
+        } => {
+            let status = persistence::schema_status(&app_config.persistence).await?;
+            print_schema_status(&status);
+            return Ok(());
+        }
+        Commands::Db {
+            command: DbCommands::ExportSnapshot { path },
+        } => {
+            let persistence = persistence::connect(&app_config.persistence).await?;
+            let snapshot = Snapshot::new(persistence.sundae_v3_dao().load_txos().await?);
+            std::fs::write(&path, snapshot.encode()?)?;
+            info!(
+                txos = snapshot.txos.len(),
+                slot = snapshot.slot,
+                "wrote snapshot to {}",
+                path.display()
+            );
+            return Ok(());
+        }
+        Commands::Db {
+            command: DbCommands::Copy { to },
+        } => {
+            let target_config = config::load_config(&to, &[])?
+                .get::<persistence::PersistenceConfig>("persistence")?;
+            let from = persistence::connect(&app_config.persistence).await?;
+            let to = persistence::connect(&target_config).await?;
+            let report = persistence::copy(from.as_ref(), to.as_ref()).await?;
+            info!(?report, "copy complete");
+            return Ok(());
+        }
     };
 
-    let (resync_tx, _) = tokio::sync::broadcast::channel(1);
-    let shutdown = CancellationToken::new();
+    let protocol = load_protocol()?;
 
-    let protocol: SundaeV3Protocol = {
-        let f = std::fs::File::open(protocol_config_file)?;
-        serde_json::from_reader(f)?
+    let persistence: Arc<dyn Persistence> = Arc::new(InstrumentedPersistence::new(
+        persistence::connect(&app_config.persistence).await?,
+        &app_config.instrumentation,
+    ));
+    let components = &app_config.components;
+    if !components.indexer && !components.scooper && !components.admin {
+        bail!("every component is disabled");
+    }
+    let runtime = if components.indexer {
+        ScooperRuntime::start(
+            Arc::new(config),
+            &app_config,
+            protocol.clone(),
+            persistence.clone(),
+            default_start,
+        )
+        .await?
+    } else {
+        info!("indexer disabled, following the database");
+        if !app_config.webhooks.is_empty() {
+            warn!("webhooks are only sent by the indexer, and it is disabled");
+        }
+        ScooperRuntime::follow(&app_config, protocol.clone(), persistence.clone()).await?
     };
+    let shutdown = runtime.shutdown_token();
+    let webhooks = WebhookDispatcher::new(&app_config.webhooks)?;
+    let webhook_statuses = webhooks.statuses();
 
-    let persistence = persistence::connect(&app_config.persistence).await?;
-
-    let index = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
-    let broadcaster = tokio::sync::watch::Sender::default();
-
-    let manager_handle = tokio::spawn(manager_loop(
-        index.clone(),
-        resync_tx.clone(),
-        broadcaster.clone(),
-        Arc::new(config),
-        protocol.clone(),
-        persistence.clone(),
-        default_start,
-        shutdown.child_token(),
-    ));
-    let scooper_handle = tokio::spawn(
-        Scooper::new(broadcaster.subscribe(), &protocol.pool_script_hash)?
-            .run(shutdown.child_token()),
-    );
-    let admin_handle = tokio::spawn(admin_server(
-        index.clone(),
-        resync_tx,
-        protocol,
+    let mut competition = CompetitionStats::default();
+    let mut screening = None;
+    let mut scooper_handle = None;
+    if components.scooper {
+        let mut scooper = Scooper::new(
+            runtime.subscribe(),
+            &protocol.pool_script_hash,
+            protocol.ada_rider,
+            &app_config.scooper,
+            Arc::new(SystemClock),
+        )?;
+        if let Some(slot_config) = protocol.slot_config {
+            scooper = scooper.with_slot_config(slot_config);
+        }
+        if let Some(screening_config) = &app_config.screening {
+            let filter = Screening::new(screening_config).await?;
+            scooper = scooper.with_order_filter(filter.clone());
+            tokio::spawn(filter.clone().run(shutdown.child_token()));
+            screening = Some(filter);
+        }
+        competition = scooper.competition();
+        scooper_handle = Some(tokio::spawn(
+            scooper.run(runtime.subscribe_order_events(), shutdown.child_token()),
+        ));
+    }
+    let webhook_handle =
+        tokio::spawn(webhooks.run(runtime.subscribe_order_events(), shutdown.child_token()));
+    let notifier_handle = tokio::spawn(Notifier::new(app_config.notifications.clone()).run(
+        runtime.subscribe_order_events(),
+        runtime.subscribe(),
         shutdown.child_token(),
     ));
+    let attestor = Attestor::new(&app_config.attestation)?;
+    let attestation = attestor.as_ref().map(Attestor::latest);
+    if let Some(attestor) = attestor {
+        tokio::spawn(attestor.run(runtime.subscribe(), shutdown.child_token()));
+    }
+    let admin_handle = components.admin.then(|| {
+        let server = AdminServer {
+            index: runtime.index(),
+            pools: runtime.pools(),
+            restart_tx: runtime.restarts(),
+            protocol,
+            persistence,
+            webhooks: webhook_statuses,
+            competition,
+            validation_profile: app_config.scooper.validation_profile,
+            screening,
+            auth: app_config.auth.clone(),
+            aliases: app_config.pool_aliases.clone(),
+            attestation,
+            attribution: Attribution::new(&app_config.attribution),
+            pause_rules: app_config.scooper.pause_rules.clone(),
+        };
+        tokio::spawn(admin_server(
+            Arc::new(server),
+            app_config.admin.listen_address,
+            shutdown.child_token(),
+        ))
+    });
 
     tokio::spawn(async move {
         let _ = ctrl_c().await;
@@ -299,90 +1386,95 @@ async fn main() -> Result<()> {
         process::exit(0);
     });
 
-    tokio::try_join!(manager_handle, scooper_handle, admin_handle)?;
+    tokio::try_join!(
+        runtime.join(),
+        join_component(scooper_handle),
+        webhook_handle,
+        notifier_handle,
+        join_component(admin_handle)
+    )?;
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn manager_loop(
-    index: Arc<Mutex<SundaeV3HistoricalState>>,
-    resync_tx: tokio::sync::broadcast::Sender<()>,
-    broadcaster: tokio::sync::watch::Sender<SundaeV3Update>,
-    config: Arc<::config::Config>,
-    protocol: SundaeV3Protocol,
-    persistence: Arc<dyn Persistence>,
-    default_start: Point,
-    shutdown: CancellationToken,
-) {
-    let mut force_restart = false;
-    loop {
-        let index = index.clone();
-        let mut resync_tx = resync_tx.subscribe();
-        let config = config.clone();
-        let protocol = protocol.clone();
-        let default_start = default_start.clone();
-        let broadcaster = broadcaster.clone();
-        let enable_mithril = config::use_mithril(&config);
-
-        let mut process = Process::<Message>::create(config).await;
-        GenesisBootstrapper::register(&mut process);
-        if enable_mithril {
-            MithrilSnapshotFetcher::register(&mut process);
-        }
-        BlockUnpacker::register(&mut process);
-        PeerNetworkInterface::register(&mut process);
-
-        let indexer = Arc::new(CustomIndexer::new(persistence.cursor_store()));
-        process.register(indexer.clone());
-
-        let mut v3_index = SundaeV3Indexer::new(
-            index,
-            broadcaster,
-            protocol,
-            config::ROLLBACK_LIMIT,
-            persistence.sundae_v3_dao(),
-        );
-        v3_index.load().await.unwrap();
+/// Waits for a component's task, if it was started.
+async fn join_component<T>(handle: Option<JoinHandle<T>>) -> Result<(), JoinError> {
+    match handle {
+        Some(handle) => handle.await.map(|_| ()),
+        None => Ok(()),
+    }
+}
 
-        indexer
-            .add_index(v3_index, default_start, force_restart)
-            .await
-            .unwrap();
+async fn verify_state(
+    persistence: Arc<dyn Persistence>,
+    protocol: SundaeV3Protocol,
+    source: &Blockfrost,
+) -> Result<()> {
+    let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+    let mut indexer = SundaeV3Indexer::new(
+        state.clone(),
+        tokio::sync::watch::Sender::default(),
+        tokio::sync::broadcast::channel(1).0,
+        protocol.clone(),
+        0,
+        persistence.sundae_v3_dao(),
+    );
+    indexer.load().await?;
+    let (ours, slot) = {
+        let state = state.lock().await;
+        let slot = state
+            .slot_range()
+            .map(|(_, latest)| latest)
+            .unwrap_or_default();
+        (verify::state_utxos(&state.latest()), slot)
+    };
+    let tip = source.tip_slot().await?;
+    let theirs = source.tracked_utxos(&protocol).await?;
+    println!("our state is as of slot {slot}, the source's tip is slot {tip}");
 
-        match process.start().await {
-            Ok(running_process) => {
-                let shutting_down = select! {
-                    res = resync_tx.recv() => res.is_err(),
-                    _ = shutdown.cancelled() => true,
-                };
-                force_restart = true;
+    let drift = verify::diff(&ours, &theirs);
+    for input in &drift.missing {
+        println!("missing    {input}");
+    }
+    for input in &drift.extra {
+        println!("extra      {input}");
+    }
+    for (input, deltas) in &drift.mismatched {
+        let deltas: Vec<String> = deltas
+            .iter()
+            .map(|(asset, delta)| format!("{asset} {delta}"))
+            .collect();
+        println!("mismatched {input} {}", deltas.join(", "));
+    }
+    if !drift.is_empty() {
+        bail!(
+            "{} missing, {} extra and {} mismatched utxos",
+            drift.missing.len(),
+            drift.extra.len(),
+            drift.mismatched.len()
+        );
+    }
+    println!("{} utxos match", ours.len());
+    Ok(())
+}
 
-                info!("terminating acropolis process");
-                match running_process.stop().await {
-                    Ok(()) => info!("terminated acropolis process"),
-                    Err(err) => warn!("could not terminate acropolis process: {err:#}"),
-                }
-                if shutting_down {
-                    break;
-                }
-            }
-            Err(err) => {
-                warn!("could not start acropolis process: {err:#}");
-                tokio::time::sleep(Duration::from_secs(5)).await;
-            }
+fn print_schema_status(status: &SchemaStatus) {
+    for migration in &status.migrations {
+        let state = if migration.applied {
+            "applied"
+        } else {
+            "pending"
         };
-
-        warn!("Restarting Scooper indexer");
+        println!(
+            "{:08} {state:<8} {}",
+            migration.version, migration.description
+        );
+    }
+    for version in &status.unknown_versions {
+        println!("{version:08} unknown  applied by a newer build");
     }
 }
 
-async fn admin_server(
-    index: Arc<Mutex<SundaeV3HistoricalState>>,
-    resync_tx: tokio::sync::broadcast::Sender<()>,
-    protocol: SundaeV3Protocol,
-    shutdown: CancellationToken,
-) {
-    let addr = SocketAddr::from(([127, 0, 0, 1], 9999));
+async fn admin_server(server: Arc<AdminServer>, addr: SocketAddr, shutdown: CancellationToken) {
     let listener = TcpListener::bind(addr).await.unwrap();
 
     loop {
@@ -391,37 +1483,20 @@ async fn admin_server(
             _ = shutdown.cancelled() => { break; }
         };
 
-        let resync_tx = resync_tx.clone();
-        let index = index.clone();
-        let protocol = protocol.clone();
-
+        let service = AdminService(server.clone());
         let child = shutdown.child_token();
         tokio::task::spawn(async move {
             select! {
                 _ = child.cancelled() => {},
-                _ = handle_request(stream, index, resync_tx, protocol) => {}
+                _ = handle_request(stream, service) => {}
             }
         });
     }
 }
 
-async fn handle_request(
-    stream: TcpStream,
-    index: Arc<Mutex<SundaeV3HistoricalState>>,
-    resync_tx: tokio::sync::broadcast::Sender<()>,
-    protocol: SundaeV3Protocol,
-) {
+async fn handle_request(stream: TcpStream, service: AdminService) {
     let io = TokioIo::new(stream);
-
-    let admin_server = AdminServer {
-        index,
-        resync_tx,
-        protocol,
-    };
-    if let Err(err) = http1::Builder::new()
-        .serve_connection(io, admin_server)
-        .await
-    {
+    if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
         event!(Level::DEBUG, "Failed to serve connection: {:?}", err);
     }
 }
@@ -0,0 +1,334 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// A single process-wide metric, rendered in the Prometheus text format.
+pub struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    value: AtomicU64,
+}
+
+impl Metric {
+    pub const fn counter(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind: "counter",
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub const fn gauge(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind: "gauge",
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.value.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, value: u64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    /// Raises a gauge to `value`, if it's lower.
+    pub fn set_max(&self, value: u64) {
+        self.value.fetch_max(value, Ordering::Relaxed);
+    }
+}
+
+/// Counts observations into fixed buckets, rendered as a Prometheus histogram.
+pub struct Histogram<const N: usize> {
+    name: &'static str,
+    help: &'static str,
+    /// Each bucket's inclusive upper bound
+    bounds: [u64; N],
+    buckets: [AtomicU64; N],
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N> {
+    pub const fn new(name: &'static str, help: &'static str, bounds: [u64; N]) -> Self {
+        Self {
+            name,
+            help,
+            bounds,
+            buckets: [const { AtomicU64::new(0) }; N],
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: u64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} histogram", self.name);
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{bound}\"}} {cumulative}", self.name);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {count}", self.name);
+        let _ = writeln!(
+            out,
+            "{}_sum {}",
+            self.name,
+            self.sum.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(out, "{}_count {count}", self.name);
+    }
+}
+
+pub static INDEXER_START_FAILURES: Metric = Metric::counter(
+    "scooper_indexer_start_failures_total",
+    "Times the acropolis process failed to start",
+);
+pub static INDEXER_RESTARTS: Metric = Metric::counter(
+    "scooper_indexer_restarts_total",
+    "Times the acropolis process was restarted on request",
+);
+pub static INDEXER_CIRCUIT_OPEN: Metric = Metric::gauge(
+    "scooper_indexer_circuit_open",
+    "1 if the manager loop has given up on starting acropolis",
+);
+
+pub static INDEXES_HALTED: Metric = Metric::gauge(
+    "scooper_indexes_halted",
+    "Chain indexes halted after an error, while the others keep running",
+);
+pub static INDEXER_TXS_SKIPPED: Metric = Metric::counter(
+    "scooper_indexer_txs_skipped_total",
+    "Transactions the indexer could not decode, and so skipped",
+);
+pub static INDEXER_TXS_FILTERED: Metric = Metric::counter(
+    "scooper_indexer_txs_filtered_total",
+    "Transactions skipped without decoding, since they can't concern the protocol",
+);
+pub static POOL_FEE_CHANGES: Metric = Metric::counter(
+    "scooper_pool_fee_changes_total",
+    "Times a pool was recreated with different bid or ask fees",
+);
+pub static SCOOP_FEE_MISMATCHES: Metric = Metric::counter(
+    "scooper_scoop_fee_mismatches_total",
+    "Scoops which added something other than the settings' fees to a pool's protocol fees",
+);
+pub static POOL_STAKE_UNAUTHORIZED: Metric = Metric::counter(
+    "scooper_pool_stake_unauthorized_total",
+    "Times a pool was recreated with a stake credential the settings don't authorize",
+);
+pub static SCOOPER_DEAUTHORIZED: Metric = Metric::gauge(
+    "scooper_keys_deauthorized",
+    "Configured scooper keys missing from the settings' authorized scoopers",
+);
+pub static ORDERS_DEFERRED: Metric = Metric::gauge(
+    "scooper_orders_deferred",
+    "Valid orders the owner policy is holding back from at least one pool's scoop",
+);
+pub static ORDERS_SHED: Metric = Metric::counter(
+    "scooper_orders_shed_total",
+    "Orders dropped from memory to stay within the order limits",
+);
+pub static SCREENING_DENYLIST_ENTRIES: Metric = Metric::gauge(
+    "scooper_screening_denylist_entries",
+    "Hashes in the screening denylist last loaded",
+);
+pub static SCREENING_REFRESH_FAILURES: Metric = Metric::counter(
+    "scooper_screening_refresh_failures_total",
+    "Times the screening denylist could not be reloaded",
+);
+pub static ROLLBACK_MAX_DEPTH: Metric = Metric::gauge(
+    "scooper_rollback_max_depth_blocks",
+    "The deepest rollback ever handled, in blocks, kept across restarts",
+);
+
+pub static ROLLBACK_DEPTH: Histogram<10> = Histogram::new(
+    "scooper_rollback_depth_blocks",
+    "Blocks undone by each rollback the indexer handled",
+    [1, 2, 3, 5, 10, 20, 50, 100, 500, 2160],
+);
+
+static ALL: &[&Metric] = &[
+    &INDEXER_START_FAILURES,
+    &INDEXER_RESTARTS,
+    &INDEXER_CIRCUIT_OPEN,
+    &INDEXES_HALTED,
+    &INDEXER_TXS_SKIPPED,
+    &INDEXER_TXS_FILTERED,
+    &POOL_FEE_CHANGES,
+    &SCOOP_FEE_MISMATCHES,
+    &POOL_STAKE_UNAUTHORIZED,
+    &SCOOPER_DEAUTHORIZED,
+    &ORDERS_DEFERRED,
+    &ORDERS_SHED,
+    &SCREENING_DENYLIST_ENTRIES,
+    &SCREENING_REFRESH_FAILURES,
+    &ROLLBACK_MAX_DEPTH,
+];
+
+/// Calls, errors, rows and time spent in one persistence method.
+pub struct DaoMethodMetrics {
+    method: &'static str,
+    calls: AtomicU64,
+    errors: AtomicU64,
+    rows: AtomicU64,
+    micros: AtomicU64,
+}
+
+impl DaoMethodMetrics {
+    const fn new(method: &'static str) -> Self {
+        Self {
+            method,
+            calls: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            rows: AtomicU64::new(0),
+            micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a call, with the rows it read or wrote, or None if it failed.
+    pub fn record(&self, elapsed: Duration, rows: Option<u64>) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        match rows {
+            Some(rows) => self.rows.fetch_add(rows, Ordering::Relaxed),
+            None => self.errors.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.rows.load(Ordering::Relaxed)
+    }
+}
+
+static DAO_METHODS: &[DaoMethodMetrics] = &[
+    DaoMethodMetrics::new("apply_tx_changes"),
+    DaoMethodMetrics::new("rollback"),
+    DaoMethodMetrics::new("load_txos"),
+    DaoMethodMetrics::new("prune_txos"),
+    DaoMethodMetrics::new("load_quarantined_txos"),
+    DaoMethodMetrics::new("latest_slot"),
+    DaoMethodMetrics::new("load_reserve_history"),
+    DaoMethodMetrics::new("export_txos"),
+    DaoMethodMetrics::new("load_spent_txos"),
+    DaoMethodMetrics::new("load_pool_tx"),
+    DaoMethodMetrics::new("load_settings_history"),
+    DaoMethodMetrics::new("load_trades"),
+    DaoMethodMetrics::new("load_scoops"),
+    DaoMethodMetrics::new("load_order_latencies"),
+    DaoMethodMetrics::new("load_order_flow"),
+    DaoMethodMetrics::new("export_reserve_snapshots"),
+    DaoMethodMetrics::new("record_rollback"),
+    DaoMethodMetrics::new("load_deepest_rollback"),
+];
+
+/// The metrics for a `SundaeV3Dao` method, by name.
+pub fn dao_method(method: &str) -> &'static DaoMethodMetrics {
+    DAO_METHODS
+        .iter()
+        .find(|m| m.method == method)
+        .unwrap_or_else(|| panic!("no metrics for dao method {method}"))
+}
+
+pub fn render() -> String {
+    let mut out = String::new();
+    for metric in ALL {
+        let _ = writeln!(out, "# HELP {} {}", metric.name, metric.help);
+        let _ = writeln!(out, "# TYPE {} {}", metric.name, metric.kind);
+        let _ = writeln!(out, "{} {}", metric.name, metric.get());
+    }
+    ROLLBACK_DEPTH.render(&mut out);
+    let dao_metrics: [(&str, &str, fn(&DaoMethodMetrics) -> u64); 4] = [
+        (
+            "scooper_dao_calls_total",
+            "Calls to each persistence method",
+            DaoMethodMetrics::calls,
+        ),
+        (
+            "scooper_dao_errors_total",
+            "Persistence calls which returned an error",
+            DaoMethodMetrics::errors,
+        ),
+        (
+            "scooper_dao_rows_total",
+            "Rows read or written by each persistence method",
+            DaoMethodMetrics::rows,
+        ),
+        (
+            "scooper_dao_latency_microseconds_total",
+            "Time spent in each persistence method",
+            |m| m.micros.load(Ordering::Relaxed),
+        ),
+    ];
+    for (name, help, value) in dao_metrics {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} counter");
+        for method in DAO_METHODS {
+            let _ = writeln!(
+                out,
+                "{name}{{method=\"{}\"}} {}",
+                method.method,
+                value(method)
+            );
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_render_cumulative_buckets() {
+        let histogram = Histogram::new("test_depth", "Depths", [1, 5]);
+        for value in [1, 3, 4, 9] {
+            histogram.observe(value);
+        }
+        let mut out = String::new();
+        histogram.render(&mut out);
+        assert_eq!(
+            out,
+            "# HELP test_depth Depths\n\
+             # TYPE test_depth histogram\n\
+             test_depth_bucket{le=\"1\"} 1\n\
+             test_depth_bucket{le=\"5\"} 3\n\
+             test_depth_bucket{le=\"+Inf\"} 4\n\
+             test_depth_sum 17\n\
+             test_depth_count 4\n"
+        );
+    }
+}
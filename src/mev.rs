@@ -0,0 +1,234 @@
+//! Looks through the scoops we've seen for orderings which favour some owners
+//! over others: orders executed ahead of older ones, and one owner's swaps
+//! placed either side of someone else's.
+
+use std::collections::BTreeMap;
+
+use pallas_primitives::Fragment;
+use plutus_parser::AsPlutus;
+use serde::Serialize;
+
+use crate::{
+    cardano_types::TransactionInput,
+    multisig::Multisig,
+    persistence::OrderFlow,
+    sundaev3::{Ident, Order, OrderDatum},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MevReport {
+    pub scoops: u64,
+    pub orders: u64,
+    /// Pairs of orders in the same scoop executed in the opposite order to
+    /// the slots they were placed in
+    pub fifo_inversions: u64,
+    pub sandwiches: Vec<Sandwich>,
+    /// Owners whose orders went ahead of older ones, most often first
+    pub beneficiaries: Vec<Beneficiary>,
+}
+
+/// One owner swapping either side of other owners' swaps in the same
+/// direction as the first, within one scoop.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Sandwich {
+    pub ident: Ident,
+    pub slot: u64,
+    pub owner: String,
+    pub front: TransactionInput,
+    pub back: TransactionInput,
+    pub victims: Vec<TransactionInput>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Beneficiary {
+    pub owner: String,
+    /// How many older orders theirs went ahead of
+    pub jumps: u64,
+    /// How many of their orders did
+    pub orders: u64,
+}
+
+struct Executed<'a> {
+    flow: &'a OrderFlow,
+    owner: Option<String>,
+    /// The policy and token a swap gives
+    gives: Option<(&'a [u8], &'a [u8])>,
+}
+
+/// The owner as reported: the key or script hash when it's a single one.
+fn owner_key(owner: &Multisig) -> String {
+    match owner {
+        Multisig::Signature(hash) | Multisig::Script(hash) => hex::encode(hash),
+        other => hex::encode(
+            other
+                .clone()
+                .to_plutus()
+                .encode_fragment()
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+impl MevReport {
+    /// Scoops are told apart by pool and slot, and ordered by where they
+    /// executed each order.
+    pub fn new(flow: &[OrderFlow]) -> Self {
+        let datums: Vec<Option<OrderDatum>> =
+            flow.iter().map(|order| order.decode_datum().ok()).collect();
+        let mut scoops = BTreeMap::<(&Ident, u64), Vec<Executed>>::new();
+        for (order, datum) in flow.iter().zip(&datums) {
+            let gives = datum.as_ref().and_then(|datum| match &datum.action {
+                Order::Swap(gives, _) => Some((gives.policy.as_slice(), gives.token.as_slice())),
+                _ => None,
+            });
+            scoops
+                .entry((&order.ident, order.slot))
+                .or_default()
+                .push(Executed {
+                    flow: order,
+                    owner: datum.as_ref().map(|datum| owner_key(&datum.owner)),
+                    gives,
+                });
+        }
+
+        let mut report = MevReport {
+            scoops: scoops.len() as u64,
+            orders: flow.len() as u64,
+            ..Default::default()
+        };
+        let mut beneficiaries = BTreeMap::<String, (u64, u64)>::new();
+        for ((ident, slot), mut orders) in scoops {
+            orders.sort_by_key(|order| order.flow.position);
+            for (i, order) in orders.iter().enumerate() {
+                let jumps = orders[i + 1..]
+                    .iter()
+                    .filter(|later| later.flow.created_slot < order.flow.created_slot)
+                    .count() as u64;
+                report.fifo_inversions += jumps;
+                if let Some(owner) = &order.owner
+                    && jumps > 0
+                {
+                    let entry = beneficiaries.entry(owner.clone()).or_default();
+                    entry.0 += jumps;
+                    entry.1 += 1;
+                }
+            }
+            report.sandwiches.extend(sandwiches(ident, slot, &orders));
+        }
+        report.beneficiaries = beneficiaries
+            .into_iter()
+            .map(|(owner, (jumps, orders))| Beneficiary {
+                owner,
+                jumps,
+                orders,
+            })
+            .collect();
+        report
+            .beneficiaries
+            .sort_by(|a, b| b.jumps.cmp(&a.jumps).then_with(|| a.owner.cmp(&b.owner)));
+        report
+    }
+}
+
+fn sandwiches(ident: &Ident, slot: u64, orders: &[Executed]) -> Vec<Sandwich> {
+    let mut found = vec![];
+    for (i, front) in orders.iter().enumerate() {
+        let (Some(owner), Some(direction)) = (&front.owner, front.gives) else {
+            continue;
+        };
+        for (k, back) in orders.iter().enumerate().skip(i + 2) {
+            let closes = back.owner.as_ref() == Some(owner)
+                && back.gives.is_some_and(|gives| gives != direction);
+            if !closes {
+                continue;
+            }
+            let victims: Vec<_> = orders[i + 1..k]
+                .iter()
+                .filter(|victim| victim.owner.as_ref() != Some(owner))
+                .filter(|victim| victim.gives == Some(direction))
+                .map(|victim| victim.flow.order.clone())
+                .collect();
+            if !victims.is_empty() {
+                found.push(Sandwich {
+                    ident: ident.clone(),
+                    slot,
+                    owner: owner.clone(),
+                    front: front.flow.order.clone(),
+                    back: back.flow.order.clone(),
+                    victims,
+                });
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bigint::BigInt,
+        sundaev3::{SingletonValue, test_utils::datum},
+    };
+
+    fn asset(token: u8) -> SingletonValue {
+        SingletonValue {
+            policy: vec![],
+            token: vec![token],
+            amount: BigInt::from(100),
+        }
+    }
+
+    /// A swap by `owner` placed at `created_slot`, giving token `gives`.
+    fn swap(position: u32, created_slot: u64, owner: u8, gives: u8) -> OrderFlow {
+        let datum = OrderDatum {
+            ident: Some(Ident::new(&[1; 28])),
+            owner: Multisig::Signature(vec![owner]),
+            ..datum(Order::Swap(asset(gives), asset(1 - gives)))
+        };
+        OrderFlow {
+            ident: Ident::new(&[1; 28]),
+            order: TransactionInput::new([position as u8; 32].into(), 0),
+            slot: 100,
+            created_slot,
+            position,
+            datum: datum.to_plutus().encode_fragment().unwrap(),
+            lovelace: 0,
+        }
+    }
+
+    #[test]
+    fn should_report_jumps_and_sandwiches() {
+        // Owner 9 goes first with the newest order, then closes around owner 1
+        let report = MevReport::new(&[
+            swap(2, 11, 9, 1),
+            swap(0, 12, 9, 0),
+            swap(1, 10, 1, 0),
+            swap(3, 13, 2, 1),
+        ]);
+        assert_eq!(report.scoops, 1);
+        assert_eq!(report.orders, 4);
+        // 12 before 10 and 11, 10 before nothing older, 11 before nothing older
+        assert_eq!(report.fifo_inversions, 2);
+        assert_eq!(
+            report.beneficiaries,
+            vec![Beneficiary {
+                owner: "09".to_string(),
+                jumps: 2,
+                orders: 1,
+            }]
+        );
+        assert_eq!(report.sandwiches.len(), 1);
+        let sandwich = &report.sandwiches[0];
+        assert_eq!(sandwich.owner, "09");
+        assert_eq!(sandwich.front, swap(0, 12, 9, 0).order);
+        assert_eq!(sandwich.back, swap(2, 11, 9, 1).order);
+        assert_eq!(sandwich.victims, vec![swap(1, 10, 1, 0).order]);
+
+        // In FIFO order, with no owner on both sides, nothing stands out
+        let fair = MevReport::new(&[swap(0, 10, 1, 0), swap(1, 11, 2, 0), swap(2, 12, 1, 0)]);
+        assert_eq!(fair.fifo_inversions, 0);
+        assert!(fair.beneficiaries.is_empty());
+        assert!(fair.sandwiches.is_empty());
+    }
+}
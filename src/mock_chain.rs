@@ -0,0 +1,716 @@
+use acropolis_common::{BlockHash, BlockInfo, BlockIntent, BlockStatus, Era, Point};
+use acropolis_module_custom_indexer::chain_index::ChainIndex;
+use anyhow::Result;
+use pallas_traverse::MultiEraBlock;
+
+enum ChainStep {
+    RollForward(Vec<u8>),
+    RollBackward(Point),
+}
+
+/// A scripted chain which feeds blocks and rollbacks into a [`ChainIndex`] the
+/// same way the custom indexer would, without a network or acropolis runtime.
+#[derive(Default)]
+pub struct MockChainSource {
+    steps: Vec<ChainStep>,
+}
+
+impl MockChainSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn roll_forward(mut self, block: &[u8]) -> Self {
+        self.steps.push(ChainStep::RollForward(block.to_vec()));
+        self
+    }
+
+    pub fn roll_backward(mut self, point: Point) -> Self {
+        self.steps.push(ChainStep::RollBackward(point));
+        self
+    }
+
+    pub async fn run<I: ChainIndex>(self, index: &mut I) -> Result<()> {
+        // Heights are assigned sequentially, and rewound along with rollbacks
+        let mut applied_slots: Vec<u64> = vec![];
+        for step in self.steps {
+            match step {
+                ChainStep::RollForward(bytes) => {
+                    let block = MultiEraBlock::decode(&bytes)?;
+                    let info = block_info(&block, applied_slots.len() as u64);
+                    for tx in block.txs() {
+                        index.handle_onchain_tx_bytes(&info, &tx.encode()).await?;
+                    }
+                    applied_slots.push(block.slot());
+                }
+                ChainStep::RollBackward(point) => {
+                    applied_slots.retain(|s| *s <= point.slot());
+                    index.handle_rollback(&point).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn block_info(block: &MultiEraBlock, number: u64) -> BlockInfo {
+    BlockInfo {
+        status: BlockStatus::Volatile,
+        intent: BlockIntent::none(),
+        slot: block.slot(),
+        number,
+        hash: BlockHash::new(*block.hash()),
+        epoch: 0,
+        epoch_slot: 0,
+        new_epoch: false,
+        tip_slot: None,
+        timestamp: 0,
+        era: Era::Conway,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeSet, HashMap},
+        fs,
+        sync::{Arc, Mutex as StdMutex},
+        time::Duration,
+    };
+
+    use acropolis_module_custom_indexer::cursor_store::{CursorEntry, CursorStore};
+    use pallas_primitives::Hash;
+    use tokio::sync::{Mutex, broadcast, watch};
+
+    use super::*;
+    use crate::{
+        cardano_types::{BlockHeight, Slot, TransactionInput},
+        persistence::{
+            self, ChaosDao, Fault, FaultSchedule, PersistedTxo, Persistence, PersistenceConfig,
+            SundaeV3TxChanges,
+        },
+        sundaev3::{
+            Ident, OrderLimits, PoolIndex, SUNDAE_V3_INDEX_NAME, StartupRepair,
+            SundaeV3HistoricalState, SundaeV3Indexer, SundaeV3State, SundaeV3Update,
+            repair_ahead_of_cursor,
+        },
+    };
+
+    /// How many times a failing call is retried before the test gives up.
+    const MAX_ATTEMPTS: usize = 20;
+
+    fn scooped_pool() -> Ident {
+        Ident::new(
+            &hex::decode("32c43f096fa05626da1ead9383793ccd7bba6a1b259e77597766aee8").unwrap(),
+        )
+    }
+
+    fn new_indexer(
+        persistence: &dyn Persistence,
+        state: Arc<Mutex<SundaeV3HistoricalState>>,
+        broadcaster: watch::Sender<SundaeV3Update>,
+    ) -> Result<SundaeV3Indexer> {
+        let protocol = serde_json::from_reader(fs::File::open("testdata/protocol")?)?;
+        Ok(SundaeV3Indexer::new(
+            state,
+            broadcaster,
+            broadcast::channel(16).0,
+            protocol,
+            2160,
+            persistence.sundae_v3_dao(),
+        ))
+    }
+
+    fn chaos_indexer(
+        persistence: &dyn Persistence,
+        state: Arc<Mutex<SundaeV3HistoricalState>>,
+        schedule: Arc<StdMutex<FaultSchedule>>,
+    ) -> Result<SundaeV3Indexer> {
+        let protocol = serde_json::from_reader(fs::File::open("testdata/protocol")?)?;
+        Ok(SundaeV3Indexer::new(
+            state,
+            watch::Sender::default(),
+            broadcast::channel(16).0,
+            protocol,
+            2160,
+            Box::new(ChaosDao::new(persistence.sundae_v3_dao(), schedule)),
+        ))
+    }
+
+    /// An order from the preview network, for a pool we never index.
+    fn open_order(txo_id: TransactionInput, slot: u64) -> PersistedTxo {
+        let order = "
+            a300583910cfad1914b599d18bffd14d
+            2bbd696019c2899cbdd6a03325cdf680
+            bc121fd22e0b57ac206fefc763f8bfa0
+            771919f5218b40691eea4514d0011a00
+            c65d40028201d81858e1d8799fd8799f
+            581c2baab4c73a1cd60176f903a29a9c
+            92ed4237c88622da51e9179121a3ffd8
+            799f581c121fd22e0b57ac206fefc763
+            f8bfa0771919f5218b40691eea4514d0
+            ff1a000f4240d8799fd8799fd8799f58
+            1cc279a3fb3b4e62bbc78e288783b580
+            45d4ae82a18867d8352d02775affd879
+            9fd8799fd8799f581c121fd22e0b57ac
+            206fefc763f8bfa0771919f5218b4069
+            1eea4514d0ffffffffd87980ffd87a9f
+            9f40401a00989680ff9f581c99b071ce
+            8580d6a3a11b4902145adb8bfd0d2a03
+            935af8cf66403e15465342455252591a
+            00f65febffff43d87980ff
+        "
+        .split_whitespace()
+        .collect::<String>();
+        PersistedTxo {
+            txo_id,
+            txo_type: "order".to_string(),
+            created_slot: slot,
+            created_block_hash: None,
+            era: 7,
+            txo: hex::decode(order).unwrap(),
+        }
+    }
+
+    /// Fails unless memory holds what a restart would load from the database.
+    async fn assert_matches_database(
+        persistence: &dyn Persistence,
+        state: &Mutex<SundaeV3HistoricalState>,
+    ) -> Result<()> {
+        let loaded = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        new_indexer(persistence, loaded.clone(), watch::Sender::default())?
+            .load()
+            .await?;
+        let loaded = loaded.lock().await.latest().into_owned();
+        let ours = state.lock().await.latest().into_owned();
+        let orders = |state: &SundaeV3State| {
+            state
+                .orders
+                .iter()
+                .map(|order| order.input.clone())
+                .collect::<BTreeSet<_>>()
+        };
+        assert_eq!(ours.pools, loaded.pools);
+        assert_eq!(orders(&ours), orders(&loaded));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_not_diverge_from_the_database_under_intermittent_errors() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let schedule = Arc::new(StdMutex::new(
+            FaultSchedule::new(3484)
+                .with_fault(Fault::Error, 0.4)
+                .with_fault(Fault::Slow, 0.2)
+                .with_delay(Duration::from_millis(1)),
+        ));
+        let mut indexer = chaos_indexer(persistence.as_ref(), state.clone(), schedule.clone())?;
+
+        let bytes = fs::read("testdata/scoop-pool.block")?;
+        let block = MultiEraBlock::decode(&bytes)?;
+        let info = block_info(&block, 0);
+        let before_block = Point::Specific {
+            slot: block.slot() - 1,
+            hash: BlockHash::new([0; 32]),
+        };
+
+        // The custom indexer halts an index when a call fails, and it picks up
+        // from the same tx once it's unhalted
+        for _ in 0..10 {
+            for tx in block.txs() {
+                let raw_tx = tx.encode();
+                let mut attempts = 0;
+                while let Err(err) = indexer.handle_onchain_tx_bytes(&info, &raw_tx).await {
+                    attempts += 1;
+                    assert!(attempts < MAX_ATTEMPTS, "never recovered: {err:#}");
+                    assert_matches_database(persistence.as_ref(), &state).await?;
+                }
+                assert_matches_database(persistence.as_ref(), &state).await?;
+            }
+            assert!(
+                state
+                    .lock()
+                    .await
+                    .latest()
+                    .pools
+                    .contains_key(&scooped_pool())
+            );
+
+            // A rollback which fails part way is finished by its retry
+            let mut attempts = 0;
+            while let Err(err) = indexer.handle_rollback(&before_block).await {
+                attempts += 1;
+                assert!(attempts < MAX_ATTEMPTS, "never recovered: {err:#}");
+            }
+            assert_matches_database(persistence.as_ref(), &state).await?;
+            assert!(state.lock().await.latest().pools.is_empty());
+        }
+
+        let injected = &schedule.lock().unwrap().injected;
+        assert!(injected.iter().any(|(_, fault)| *fault == Fault::Error));
+        assert!(injected.iter().any(|(_, fault)| *fault == Fault::Slow));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_stay_halted_until_restarted_when_a_write_is_lost() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let schedule = Arc::new(StdMutex::new(
+            FaultSchedule::new(3484)
+                .with_fault(Fault::Timeout, 1.0)
+                .with_delay(Duration::from_millis(1)),
+        ));
+        let mut indexer = chaos_indexer(persistence.as_ref(), state.clone(), schedule.clone())?;
+
+        let bytes = fs::read("testdata/scoop-pool.block")?;
+        let block = MultiEraBlock::decode(&bytes)?;
+        let info = block_info(&block, 0);
+
+        // The first tx we write lands, but we never hear that it did
+        let mut lost = None;
+        for tx in block.txs() {
+            let raw_tx = tx.encode();
+            let before = state.lock().await.latest().into_owned();
+            if indexer
+                .handle_onchain_tx_bytes(&info, &raw_tx)
+                .await
+                .is_err()
+            {
+                let after = state.lock().await.latest().into_owned();
+                assert_eq!(after.pools, before.pools);
+                assert_eq!(after.orders.len(), before.orders.len());
+                lost = Some(raw_tx);
+                break;
+            }
+        }
+        let lost = lost.expect("the block has txs to write");
+        assert!(!persistence.sundae_v3_dao().load_txos().await?.is_empty());
+
+        // Writing it again either fails, leaving the index halted, or catches
+        // memory up with the database; it never leaves memory ahead
+        schedule.lock().unwrap().stop();
+        if indexer.handle_onchain_tx_bytes(&info, &lost).await.is_ok() {
+            assert_matches_database(persistence.as_ref(), &state).await?;
+        }
+
+        // A restart rolls the database back to the cursor, and syncs again
+        repair_ahead_of_cursor(persistence.as_ref()).await?;
+        indexer.load().await?;
+        MockChainSource::new()
+            .roll_forward(&bytes)
+            .run(&mut indexer)
+            .await?;
+        assert_matches_database(persistence.as_ref(), &state).await?;
+        assert!(
+            state
+                .lock()
+                .await
+                .latest()
+                .pools
+                .contains_key(&scooped_pool())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_reapply_rolled_back_scoop() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let broadcaster = watch::Sender::default();
+        let mut updates = broadcaster.subscribe();
+        let mut indexer = new_indexer(persistence.as_ref(), state.clone(), broadcaster)?;
+
+        let block = fs::read("testdata/scoop-pool.block")?;
+        let slot = MultiEraBlock::decode(&block)?.slot();
+
+        // Pool scooped, rolled back, then scooped again on the new fork
+        MockChainSource::new()
+            .roll_forward(&block)
+            .roll_backward(Point::Specific {
+                slot: slot - 1,
+                hash: BlockHash::new([0; 32]),
+            })
+            .roll_forward(&block)
+            .run(&mut indexer)
+            .await?;
+
+        let latest = state.lock().await.latest().into_owned();
+        assert!(latest.pools.contains_key(&scooped_pool()));
+
+        let update = updates.borrow_and_update().clone();
+        assert_eq!(update.slot, slot);
+        assert!(update.state.pools.contains_key(&scooped_pool()));
+
+        let txos = persistence.sundae_v3_dao().load_txos().await?;
+        assert_eq!(txos.len(), 1);
+        assert_eq!(txos[0].txo_type, "pool");
+        assert_eq!(txos[0].created_slot, slot);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_keep_the_pool_index_in_step() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let pools = PoolIndex::new();
+        let mut indexer = new_indexer(
+            persistence.as_ref(),
+            state.clone(),
+            watch::Sender::default(),
+        )?
+        .with_pool_index(pools.clone());
+
+        let block = fs::read("testdata/scoop-pool.block")?;
+        let slot = MultiEraBlock::decode(&block)?.slot();
+        MockChainSource::new()
+            .roll_forward(&block)
+            .run(&mut indexer)
+            .await?;
+        let latest = state.lock().await.latest().into_owned();
+        let shard = pools
+            .get(&scooped_pool())
+            .expect("the scooped pool is indexed");
+        assert_eq!(shard.pool, latest.pools[&scooped_pool()]);
+        assert_eq!(
+            pools.idents(),
+            latest.pools.keys().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(pools.slot(), slot);
+
+        MockChainSource::new()
+            .roll_backward(Point::Specific {
+                slot: slot - 1,
+                hash: BlockHash::new([0; 32]),
+            })
+            .run(&mut indexer)
+            .await?;
+        assert!(pools.get(&scooped_pool()).is_none());
+        assert_eq!(pools.slot(), slot - 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_serve_the_pool_index_while_the_history_is_locked() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let mut changes = SundaeV3TxChanges::new(Slot(10), BlockHeight(0));
+        let txo_id = TransactionInput::new(Hash::new([1; 32]), 0);
+        changes.created_txos.push(open_order(txo_id.clone(), 10));
+        persistence
+            .sundae_v3_dao()
+            .apply_tx_changes(changes)
+            .await?;
+
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let pools = PoolIndex::new();
+        new_indexer(
+            persistence.as_ref(),
+            state.clone(),
+            watch::Sender::default(),
+        )?
+        .with_pool_index(pools.clone())
+        .load()
+        .await?;
+
+        // As the indexer holds it while it writes a block
+        let history = state.lock().await;
+        assert!(state.try_lock().is_err());
+        let orders: Vec<_> = pools
+            .unpooled_orders()
+            .iter()
+            .map(|order| order.input.clone())
+            .collect();
+        assert_eq!(orders, vec![txo_id]);
+        assert!(pools.idents().is_empty());
+        assert!(pools.closed_orders().is_empty());
+        assert!(pools.settings().is_none());
+        drop(history);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_forget_rolled_back_scoop() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let broadcaster = watch::Sender::default();
+        let mut updates = broadcaster.subscribe();
+        let mut indexer = new_indexer(persistence.as_ref(), state.clone(), broadcaster)?;
+
+        let block = fs::read("testdata/scoop-pool.block")?;
+        let slot = MultiEraBlock::decode(&block)?.slot();
+
+        MockChainSource::new()
+            .roll_forward(&block)
+            .roll_backward(Point::Specific {
+                slot: slot - 1,
+                hash: BlockHash::new([0; 32]),
+            })
+            .run(&mut indexer)
+            .await?;
+
+        let latest = state.lock().await.latest().into_owned();
+        assert!(latest.pools.is_empty());
+
+        let update = updates.borrow_and_update().clone();
+        assert_eq!(update.slot, slot - 1);
+        assert!(update.state.pools.is_empty());
+
+        assert!(persistence.sundae_v3_dao().load_txos().await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_restore_indexed_state_after_restart() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let mut indexer = new_indexer(
+            persistence.as_ref(),
+            state.clone(),
+            watch::Sender::default(),
+        )?;
+
+        let block = fs::read("testdata/scoop-pool.block")?;
+        MockChainSource::new()
+            .roll_forward(&block)
+            .run(&mut indexer)
+            .await?;
+        let before = state.lock().await.latest().into_owned();
+
+        // A fresh indexer loads the same state back out of the database
+        let restored_state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let mut restored = new_indexer(
+            persistence.as_ref(),
+            restored_state.clone(),
+            watch::Sender::default(),
+        )?;
+        restored.load().await?;
+        let after = restored_state.lock().await.latest().into_owned();
+
+        assert_eq!(after.pools, before.pools);
+        assert_eq!(after.orders.len(), before.orders.len());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_reload_state_for_rollback_past_history() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let mut indexer = new_indexer(
+            persistence.as_ref(),
+            state.clone(),
+            watch::Sender::default(),
+        )?;
+
+        let block = fs::read("testdata/scoop-pool.block")?;
+        let slot = MultiEraBlock::decode(&block)?.slot();
+        MockChainSource::new()
+            .roll_forward(&block)
+            .run(&mut indexer)
+            .await?;
+
+        // Something else lands later, and then we restart, leaving only the
+        // latest state in memory
+        let dao = persistence.sundae_v3_dao();
+        let scooped = dao.load_txos().await?.remove(0);
+        let mut later = scooped.clone();
+        later.txo_id = TransactionInput::new(later.txo_id.0.transaction_id, 99);
+        later.created_slot = slot + 10;
+        dao.apply_tx_changes(SundaeV3TxChanges {
+            slot: Slot(slot + 10),
+            height: BlockHeight(1),
+            tx_hash: None,
+            block_hash: None,
+            created_txos: vec![later],
+            spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
+        })
+        .await?;
+        indexer.load().await?;
+
+        // Rolling back between the two only keeps the first
+        MockChainSource::new()
+            .roll_backward(Point::Specific {
+                slot: slot + 5,
+                hash: BlockHash::new([0; 32]),
+            })
+            .run(&mut indexer)
+            .await?;
+
+        let latest = state.lock().await.latest().into_owned();
+        assert_eq!(latest.pools[&scooped_pool()].input, scooped.txo_id);
+        assert_eq!(dao.load_txos().await?, vec![scooped]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_roll_back_database_ahead_of_cursor() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let mut indexer = new_indexer(
+            persistence.as_ref(),
+            state.clone(),
+            watch::Sender::default(),
+        )?;
+
+        let block = fs::read("testdata/scoop-pool.block")?;
+        let slot = MultiEraBlock::decode(&block)?.slot();
+        MockChainSource::new()
+            .roll_forward(&block)
+            .run(&mut indexer)
+            .await?;
+
+        // The mock chain never saves a cursor, as if we crashed before it was written
+        let repair = repair_ahead_of_cursor(persistence.as_ref()).await?;
+        assert_eq!(
+            repair,
+            Some(StartupRepair {
+                db_slot: slot,
+                cursor: None
+            })
+        );
+        assert!(persistence.sundae_v3_dao().load_txos().await?.is_empty());
+        assert_eq!(repair_ahead_of_cursor(persistence.as_ref()).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_record_spends_of_shed_orders() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let block = fs::read("testdata/scoop-pool.block")?;
+        let decoded = MultiEraBlock::decode(&block)?;
+        let slot = decoded.slot();
+        let txs = decoded.txs();
+        let scoop = txs
+            .iter()
+            .find(|tx| !tx.redeemers().is_empty())
+            .expect("the block has a scoop");
+        let input = scoop.consumes().remove(0);
+        let spent = TransactionInput::new(*input.hash(), input.index());
+
+        // An open order which the scoop spends, from before we started indexing
+        let mut changes = SundaeV3TxChanges::new(Slot(slot - 10), BlockHeight(0));
+        changes.created_txos.push(open_order(spent, slot - 10));
+        persistence
+            .sundae_v3_dao()
+            .apply_tx_changes(changes)
+            .await?;
+
+        // With no room for orders, it's shed from memory as soon as it's loaded
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let mut indexer = new_indexer(
+            persistence.as_ref(),
+            state.clone(),
+            watch::Sender::default(),
+        )?
+        .with_order_limits(OrderLimits {
+            max_orders: 0,
+            ..OrderLimits::default()
+        });
+        indexer.load().await?;
+        assert!(state.lock().await.latest().orders.is_empty());
+        MockChainSource::new()
+            .roll_forward(&block)
+            .run(&mut indexer)
+            .await?;
+
+        // but its spend is still recorded, so it doesn't come back on reload
+        let reloaded = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        new_indexer(
+            persistence.as_ref(),
+            reloaded.clone(),
+            watch::Sender::default(),
+        )?
+        .load()
+        .await?;
+        assert!(reloaded.lock().await.latest().orders.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_flush_a_debounced_cursor_before_restarting() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let mut indexer = new_indexer(
+            persistence.as_ref(),
+            state.clone(),
+            watch::Sender::default(),
+        )?;
+        let cursors = persistence
+            .cursor_store()
+            .with_save_interval(Duration::from_secs(3600));
+        let cursor = |slot, hash| {
+            HashMap::from([(
+                SUNDAE_V3_INDEX_NAME.to_string(),
+                CursorEntry {
+                    tip: Point::Specific { slot, hash },
+                    halted: false,
+                },
+            )])
+        };
+
+        let block = fs::read("testdata/scoop-pool.block")?;
+        let decoded = MultiEraBlock::decode(&block)?;
+        let slot = decoded.slot();
+        let hash = BlockHash::new(*decoded.hash());
+        cursors
+            .save(&cursor(slot - 1, BlockHash::new([0; 32])))
+            .await?;
+        MockChainSource::new()
+            .roll_forward(&block)
+            .run(&mut indexer)
+            .await?;
+
+        // The tip moved again too soon to be written, leaving the database ahead
+        cursors.save(&cursor(slot, hash)).await?;
+        let stored = persistence.cursor_store().entries().await?;
+        assert_eq!(stored[SUNDAE_V3_INDEX_NAME].tip.slot(), slot - 1);
+
+        // Flushing as the pipeline stops catches the cursor up, so a restart
+        // reloads the state it resumes from
+        cursors.flush().await?;
+        let stored = persistence.cursor_store().entries().await?;
+        assert_eq!(
+            stored[SUNDAE_V3_INDEX_NAME].tip,
+            Point::Specific { slot, hash }
+        );
+        assert_eq!(repair_ahead_of_cursor(persistence.as_ref()).await?, None);
+        indexer.load().await?;
+        assert!(
+            state
+                .lock()
+                .await
+                .latest()
+                .pools
+                .contains_key(&scooped_pool())
+        );
+
+        // Without the flush, the database is rolled back to the cursor first
+        persistence
+            .cursor_store()
+            .save(&cursor(slot - 1, BlockHash::new([0; 32])))
+            .await?;
+        assert!(
+            repair_ahead_of_cursor(persistence.as_ref())
+                .await?
+                .is_some()
+        );
+        indexer.load().await?;
+        assert!(state.lock().await.latest().pools.is_empty());
+
+        Ok(())
+    }
+}
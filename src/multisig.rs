@@ -1,6 +1,6 @@
 use plutus_parser::AsPlutus;
 use serde::{
-    Serializer,
+    Serialize, Serializer,
     ser::{SerializeMap, SerializeSeq},
 };
 
@@ -17,6 +17,116 @@ pub enum Multisig {
     Script(Vec<u8>),
 }
 
+impl Multisig {
+    /// Whether a key hash or script hash appears anywhere in this multisig.
+    pub fn mentions(&self, credential: &[u8]) -> bool {
+        match self {
+            Multisig::Signature(hash) | Multisig::Script(hash) => hash == credential,
+            Multisig::AllOf(list) | Multisig::AnyOf(list) | Multisig::AtLeast(_, list) => {
+                list.iter().any(|m| m.mentions(credential))
+            }
+            Multisig::Before(_) | Multisig::After(_) => false,
+        }
+    }
+
+    /// Every key hash and script hash in this multisig, in the order they appear.
+    pub fn hashes(&self) -> Vec<&[u8]> {
+        let mut hashes = vec![];
+        self.collect_hashes(&mut hashes);
+        hashes
+    }
+
+    fn collect_hashes<'a>(&'a self, hashes: &mut Vec<&'a [u8]>) {
+        match self {
+            Multisig::Signature(hash) | Multisig::Script(hash) => hashes.push(hash),
+            Multisig::AllOf(list) | Multisig::AnyOf(list) | Multisig::AtLeast(_, list) => {
+                for m in list {
+                    m.collect_hashes(hashes);
+                }
+            }
+            Multisig::Before(_) | Multisig::After(_) => {}
+        }
+    }
+
+    /// Whether the `Before` and `After` conditions let this be satisfied at
+    /// `now`, a POSIX time in milliseconds, assuming every signature is given.
+    pub fn time_window(&self, now: &BigInt) -> TimeWindow {
+        match self.bounds(now) {
+            None => TimeWindow::Closed,
+            Some((from, _)) if from <= *now => TimeWindow::Open,
+            Some((from, _)) => TimeWindow::From(from),
+        }
+    }
+
+    /// The earliest time from `now` on this can be satisfied, and the time it
+    /// stops being satisfiable, if it ever does. None if it never can be.
+    /// Thresholds are taken from the members which open soonest, which is
+    /// exact unless they also close at different times.
+    fn bounds(&self, now: &BigInt) -> Option<(BigInt, Option<BigInt>)> {
+        let bounds = match self {
+            Multisig::Signature(_) | Multisig::Script(_) => (now.clone(), None),
+            Multisig::Before(time) => (now.clone(), Some(time.clone())),
+            Multisig::After(time) => (now.clone().max(time.clone()), None),
+            Multisig::AllOf(list) => {
+                let mut bounds = (now.clone(), None);
+                for member in list {
+                    bounds = intersect(bounds, member.bounds(now)?);
+                }
+                bounds
+            }
+            Multisig::AnyOf(list) => return at_least(1, list, now),
+            Multisig::AtLeast(required, list) => {
+                let required = required.to_u64().unwrap_or_default() as usize;
+                return at_least(required, list, now);
+            }
+        };
+        let (from, until) = &bounds;
+        until
+            .as_ref()
+            .is_none_or(|until| from < until)
+            .then_some(bounds)
+    }
+}
+
+fn intersect(
+    (from_a, until_a): (BigInt, Option<BigInt>),
+    (from_b, until_b): (BigInt, Option<BigInt>),
+) -> (BigInt, Option<BigInt>) {
+    let until = match (until_a, until_b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    (from_a.max(from_b), until)
+}
+
+fn at_least(required: usize, list: &[Multisig], now: &BigInt) -> Option<(BigInt, Option<BigInt>)> {
+    let mut members: Vec<_> = list.iter().filter_map(|m| m.bounds(now)).collect();
+    if members.len() < required {
+        return None;
+    }
+    members.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut bounds = (now.clone(), None);
+    for member in members.into_iter().take(required) {
+        bounds = intersect(bounds, member);
+    }
+    let (from, until) = &bounds;
+    until
+        .as_ref()
+        .is_none_or(|until| from < until)
+        .then_some(bounds)
+}
+
+/// When an owner's time conditions allow it to act.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeWindow {
+    Open,
+    /// Not until this POSIX time in milliseconds
+    From(BigInt),
+    /// Never again
+    Closed,
+}
+
 impl serde::Serialize for Multisig {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -56,3 +166,53 @@ impl serde::Serialize for Multisig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(ms: u64) -> BigInt {
+        BigInt::from(ms)
+    }
+
+    #[test]
+    fn should_find_time_windows() {
+        let key = Multisig::Signature(vec![1]);
+        let now = time(100);
+        assert_eq!(key.time_window(&now), TimeWindow::Open);
+
+        let until = |t| Multisig::AllOf(vec![key.clone(), Multisig::Before(time(t))]);
+        assert_eq!(until(150).time_window(&now), TimeWindow::Open);
+        assert_eq!(until(100).time_window(&now), TimeWindow::Closed);
+
+        let from = |t| Multisig::AllOf(vec![key.clone(), Multisig::After(time(t))]);
+        assert_eq!(from(50).time_window(&now), TimeWindow::Open);
+        assert_eq!(from(200).time_window(&now), TimeWindow::From(time(200)));
+
+        // Opens after it has already closed
+        let never = Multisig::AllOf(vec![
+            Multisig::After(time(200)),
+            Multisig::Before(time(150)),
+        ]);
+        assert_eq!(never.time_window(&now), TimeWindow::Closed);
+
+        // Either path will do, so the sooner one counts
+        let either = Multisig::AnyOf(vec![until(50), from(300), from(200)]);
+        assert_eq!(either.time_window(&now), TimeWindow::From(time(200)));
+        let both = Multisig::AtLeast(BigInt::from(2), vec![until(50), from(300), from(200)]);
+        assert_eq!(both.time_window(&now), TimeWindow::From(time(300)));
+        let three = Multisig::AtLeast(BigInt::from(3), vec![until(50), from(300), from(200)]);
+        assert_eq!(three.time_window(&now), TimeWindow::Closed);
+    }
+
+    #[test]
+    fn should_list_every_hash() {
+        let owner = Multisig::AnyOf(vec![
+            Multisig::Signature(vec![1]),
+            Multisig::AllOf(vec![Multisig::Script(vec![2]), Multisig::After(time(5))]),
+            Multisig::AtLeast(BigInt::from(1), vec![Multisig::Signature(vec![3])]),
+        ]);
+        assert_eq!(owner.hashes(), [vec![1], vec![2], vec![3]]);
+        assert!(Multisig::Before(time(5)).hashes().is_empty());
+    }
+}
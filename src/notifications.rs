@@ -0,0 +1,304 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    select,
+    sync::{broadcast, watch},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::{
+    cardano_types::ADA_ASSET_CLASS,
+    metrics,
+    sundaev3::{OrderEvent, OrderOutcome, SundaeV3Update},
+};
+
+/// Chat channels to post operational events to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct NotificationsConfig {
+    pub discord_webhook_url: Option<String>,
+    pub telegram: Option<TelegramConfig>,
+    /// Report scooped orders holding at least this much ADA
+    pub large_trade_lovelace: Option<u64>,
+    /// Report when no new blocks have been indexed for this long
+    pub stall_secs: u64,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            discord_webhook_url: None,
+            telegram: None,
+            large_trade_lovelace: None,
+            stall_secs: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Notification {
+    LargeTrade { event: String, lovelace: i128 },
+    IndexerDown { failures: u64 },
+    IndexerRecovered,
+    IndexesHalted { count: u64 },
+    IndexesResumed,
+    SyncStalled { slot: u64, secs: u64 },
+    SyncResumed { slot: u64 },
+    ScooperDeauthorized { keys: u64 },
+    ScooperReauthorized,
+    OrdersShed { count: u64 },
+}
+
+impl fmt::Display for Notification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Notification::LargeTrade { event, lovelace } => {
+                write!(f, "Large trade: {event} ({} ADA)", lovelace / 1_000_000)
+            }
+            Notification::IndexerDown { failures } => write!(
+                f,
+                "Indexer gave up after {failures} failed starts, waiting for a restart request"
+            ),
+            Notification::IndexerRecovered => write!(f, "Indexer is starting again"),
+            Notification::IndexesHalted { count } => write!(
+                f,
+                "{count} chain indexes halted after errors, the others are still running"
+            ),
+            Notification::IndexesResumed => write!(f, "No chain indexes are halted"),
+            Notification::SyncStalled { slot, secs } => {
+                write!(f, "No new blocks indexed for {secs}s, stuck at slot {slot}")
+            }
+            Notification::SyncResumed { slot } => write!(f, "Indexing resumed at slot {slot}"),
+            Notification::ScooperDeauthorized { keys } => write!(
+                f,
+                "URGENT: {keys} of our scooper keys are no longer authorized to scoop"
+            ),
+            Notification::ScooperReauthorized => {
+                write!(f, "All of our scooper keys are authorized again")
+            }
+            Notification::OrdersShed { count } => write!(
+                f,
+                "{count} orders were shed to stay within the order limits, is the order address being spammed?"
+            ),
+        }
+    }
+}
+
+/// A scooped order worth reporting, if it holds enough ADA.
+pub fn large_trade(event: &OrderEvent, threshold: u64) -> Option<Notification> {
+    if event.outcome != OrderOutcome::Scooped {
+        return None;
+    }
+    let lovelace = event.value.get_asset_class(&ADA_ASSET_CLASS);
+    if lovelace < threshold as i128 {
+        return None;
+    }
+    let pool = event
+        .datum
+        .ident
+        .as_ref()
+        .map_or("any pool".to_string(), |ident| format!("pool {ident}"));
+    Some(Notification::LargeTrade {
+        event: format!("order {} scooped in {pool}", event.order),
+        lovelace,
+    })
+}
+
+pub struct Notifier {
+    client: reqwest::Client,
+    config: NotificationsConfig,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationsConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.config.discord_webhook_url.is_some() || self.config.telegram.is_some()
+    }
+
+    pub async fn run(
+        self,
+        mut order_events: broadcast::Receiver<OrderEvent>,
+        mut updates: watch::Receiver<SundaeV3Update>,
+        shutdown: CancellationToken,
+    ) {
+        if !self.enabled() {
+            return;
+        }
+        let stall_after = Duration::from_secs(self.config.stall_secs);
+        let mut last_slot = updates.borrow().slot;
+        let mut last_progress = Instant::now();
+        let mut stalled = false;
+        let mut indexer_down = false;
+        let mut indexes_halted = 0;
+        let mut deauthorized = 0;
+        let mut orders_shed = metrics::ORDERS_SHED.get();
+        let mut ticker = tokio::time::interval(Duration::from_secs(10));
+        loop {
+            select! {
+                _ = shutdown.cancelled() => break,
+                res = order_events.recv() => match res {
+                    Ok(event) => {
+                        if let Some(notification) = self
+                            .config
+                            .large_trade_lovelace
+                            .and_then(|threshold| large_trade(&event, threshold))
+                        {
+                            self.send(&notification).await;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                res = updates.changed() => {
+                    if res.is_err() {
+                        break;
+                    }
+                    let slot = updates.borrow_and_update().slot;
+                    if slot != last_slot {
+                        last_slot = slot;
+                        last_progress = Instant::now();
+                        if stalled {
+                            stalled = false;
+                            self.send(&Notification::SyncResumed { slot }).await;
+                        }
+                    }
+                }
+                _ = ticker.tick() => {
+                    let circuit_open = metrics::INDEXER_CIRCUIT_OPEN.get() > 0;
+                    if circuit_open != indexer_down {
+                        indexer_down = circuit_open;
+                        let notification = if circuit_open {
+                            Notification::IndexerDown {
+                                failures: metrics::INDEXER_START_FAILURES.get(),
+                            }
+                        } else {
+                            Notification::IndexerRecovered
+                        };
+                        self.send(&notification).await;
+                    }
+                    let halted = metrics::INDEXES_HALTED.get();
+                    if halted != indexes_halted {
+                        let notification = if halted > 0 {
+                            Notification::IndexesHalted { count: halted }
+                        } else {
+                            Notification::IndexesResumed
+                        };
+                        indexes_halted = halted;
+                        self.send(&notification).await;
+                    }
+                    let missing_keys = metrics::SCOOPER_DEAUTHORIZED.get();
+                    if missing_keys != deauthorized {
+                        let notification = if missing_keys > 0 {
+                            Notification::ScooperDeauthorized { keys: missing_keys }
+                        } else {
+                            Notification::ScooperReauthorized
+                        };
+                        deauthorized = missing_keys;
+                        self.send(&notification).await;
+                    }
+                    let shed = metrics::ORDERS_SHED.get();
+                    if shed > orders_shed {
+                        let count = shed - orders_shed;
+                        orders_shed = shed;
+                        self.send(&Notification::OrdersShed { count }).await;
+                    }
+                    let stall_enabled = self.config.stall_secs > 0;
+                    if !stalled && stall_enabled && last_progress.elapsed() >= stall_after {
+                        stalled = true;
+                        let secs = last_progress.elapsed().as_secs();
+                        self.send(&Notification::SyncStalled { slot: last_slot, secs }).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn send(&self, notification: &Notification) {
+        let text = notification.to_string();
+        if let Some(url) = &self.config.discord_webhook_url
+            && let Err(err) = self.post(url, json!({ "content": text })).await
+        {
+            warn!("could not notify discord: {err:#}");
+        }
+        if let Some(telegram) = &self.config.telegram {
+            let url = format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                telegram.bot_token
+            );
+            let body = json!({ "chat_id": telegram.chat_id, "text": text });
+            if let Err(err) = self.post(&url, body).await {
+                warn!("could not notify telegram: {err:#}");
+            }
+        }
+    }
+
+    async fn post(&self, url: &str, body: serde_json::Value) -> Result<()> {
+        self.client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cardano_types::{TransactionInput, Value},
+        sundaev3::{
+            Ident, Order, OrderDatum,
+            test_utils::{self, datum},
+        },
+    };
+
+    fn event(outcome: OrderOutcome, lovelace: i128) -> OrderEvent {
+        let mut value = Value::new();
+        value.insert(&ADA_ASSET_CLASS, lovelace);
+        let datum = OrderDatum {
+            ident: Some(Ident::new(&[0xab])),
+            ..datum(Order::Record(ADA_ASSET_CLASS))
+        };
+        OrderEvent {
+            order: TransactionInput::new([0; 32].as_slice().into(), 1),
+            value,
+            ..test_utils::event(outcome, datum)
+        }
+    }
+
+    #[test]
+    fn should_only_report_large_scoops() {
+        let threshold = 1_000_000_000;
+        assert_eq!(
+            large_trade(&event(OrderOutcome::Scooped, 2_000_000_000), threshold)
+                .unwrap()
+                .to_string(),
+            "Large trade: order 0000000000000000000000000000000000000000000000000000000000000000#1 scooped in pool ab (2000 ADA)"
+        );
+        assert!(large_trade(&event(OrderOutcome::Scooped, 999_999_999), threshold).is_none());
+        assert!(large_trade(&event(OrderOutcome::Cancelled, 2_000_000_000), threshold).is_none());
+    }
+}
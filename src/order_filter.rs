@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::sundaev3::{Ident, SundaeV3Order, SundaeV3Pool};
+
+/// A check an embedder adds on top of protocol validation, e.g. screening
+/// destination addresses. Filters see each order once per pool it could be
+/// scooped against, after it has passed validation for that pool.
+pub trait OrderFilter: Send + Sync {
+    /// Identifies the filter in the notes and vetoes it gives.
+    fn name(&self) -> &str;
+
+    fn check(&self, order: &SundaeV3Order, pool: &SundaeV3Pool) -> Verdict;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// Scoop the order, but report the note alongside it
+    Annotate(String),
+    /// Keep the order out of scoops against this pool
+    Veto(String),
+}
+
+/// What a filter said about an order against one pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FilterNote {
+    pub filter: String,
+    pub pool: Ident,
+    pub note: String,
+}
+
+/// Runs every filter over `order` as scooped against `pool`. The first veto
+/// stops the rest; otherwise any annotations are returned.
+pub fn check_order(
+    filters: &[Arc<dyn OrderFilter>],
+    order: &SundaeV3Order,
+    pool: &SundaeV3Pool,
+) -> Result<Vec<FilterNote>, FilterNote> {
+    let mut notes = vec![];
+    for filter in filters {
+        let note = |note: String| FilterNote {
+            filter: filter.name().to_string(),
+            pool: pool.pool_datum.ident.clone(),
+            note,
+        };
+        match filter.check(order, pool) {
+            Verdict::Allow => {}
+            Verdict::Annotate(text) => notes.push(note(text)),
+            Verdict::Veto(reason) => return Err(note(reason)),
+        }
+    }
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bigint::BigInt,
+        cardano_types::{ADA_ASSET_CLASS, Value},
+        sundaev3::{
+            Order, OrderDatum,
+            test_utils::{self, datum, pool},
+        },
+    };
+
+    /// Vetoes orders with a large scoop fee, and annotates the rest.
+    struct FeeFilter;
+
+    impl OrderFilter for FeeFilter {
+        fn name(&self) -> &str {
+            "fees"
+        }
+
+        fn check(&self, order: &SundaeV3Order, _pool: &SundaeV3Pool) -> Verdict {
+            if order.datum.scoop_fee > BigInt::from(1_000_000) {
+                Verdict::Veto("scoop fee too high".to_string())
+            } else {
+                Verdict::Annotate("scoop fee ok".to_string())
+            }
+        }
+    }
+
+    struct AllowAll;
+
+    impl OrderFilter for AllowAll {
+        fn name(&self) -> &str {
+            "allow"
+        }
+
+        fn check(&self, _order: &SundaeV3Order, _pool: &SundaeV3Pool) -> Verdict {
+            Verdict::Allow
+        }
+    }
+
+    fn order(scoop_fee: i64) -> SundaeV3Order {
+        let datum = OrderDatum {
+            scoop_fee: BigInt::from(scoop_fee),
+            ..datum(Order::Record(ADA_ASSET_CLASS))
+        };
+        test_utils::order(0, 0, datum)
+    }
+
+    #[test]
+    fn should_collect_notes_until_a_veto() {
+        let filters: Vec<Arc<dyn OrderFilter>> = vec![Arc::new(AllowAll), Arc::new(FeeFilter)];
+        let pool = pool(Value::default(), 0);
+        assert_eq!(
+            check_order(&filters, &order(1_000_000), &pool),
+            Ok(vec![FilterNote {
+                filter: "fees".to_string(),
+                pool: Ident::new(&[3; 28]),
+                note: "scoop fee ok".to_string(),
+            }])
+        );
+        assert_eq!(
+            check_order(&filters, &order(2_000_000), &pool),
+            Err(FilterNote {
+                filter: "fees".to_string(),
+                pool: Ident::new(&[3; 28]),
+                note: "scoop fee too high".to_string(),
+            })
+        );
+        assert_eq!(check_order(&[], &order(2_000_000), &pool), Ok(vec![]));
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cardano_types::TransactionInput,
+    multisig::Multisig,
+    sundaev3::{Ident, SundaeV3Order},
+};
+
+/// Limits how much of one scoop a single owner can take up, so a flood of
+/// orders from one credential can't crowd everyone else out.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct OwnerPolicy {
+    /// How many of one owner's orders can go into a single scoop of a pool.
+    pub max_orders_per_owner: Option<usize>,
+    pub stale_duplicates: StaleDuplicates,
+}
+
+/// What happens to an order when the same owner has a newer order with the
+/// same action and destination against the same pool. Users who think an
+/// order got stuck tend to place it again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StaleDuplicates {
+    /// Treat it like any other order
+    #[default]
+    Ignore,
+    /// Rank it behind the owner's other orders when the owner is over the limit
+    Deprioritize,
+    /// Keep it out of scoops until the newer order is gone
+    Defer,
+}
+
+/// Why an order was held back from one pool's scoop.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Deferral {
+    pub pool: Ident,
+    pub reason: DeferReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DeferReason {
+    /// The owner already fills their share of the scoop
+    OwnerLimit,
+    /// The owner has a newer copy of the order
+    StaleDuplicate,
+}
+
+impl OwnerPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.max_orders_per_owner.is_some() || self.stale_duplicates != StaleDuplicates::Ignore
+    }
+
+    /// Works out which of the orders valid against each pool the policy holds
+    /// back from that pool's next scoop. Owners' orders otherwise go oldest first.
+    pub fn defer<'a>(
+        &self,
+        candidates: impl IntoIterator<Item = (&'a SundaeV3Order, &'a [Ident])>,
+    ) -> BTreeMap<TransactionInput, Vec<Deferral>> {
+        let mut by_pool: BTreeMap<&Ident, Vec<(&Multisig, Vec<&SundaeV3Order>)>> = BTreeMap::new();
+        for (order, pools) in candidates {
+            for pool in pools {
+                let owners = by_pool.entry(pool).or_default();
+                match owners
+                    .iter_mut()
+                    .find(|(owner, _)| **owner == order.datum.owner)
+                {
+                    Some((_, orders)) => orders.push(order),
+                    None => owners.push((&order.datum.owner, vec![order])),
+                }
+            }
+        }
+
+        let mut deferred: BTreeMap<TransactionInput, Vec<Deferral>> = BTreeMap::new();
+        for (pool, owners) in by_pool {
+            for (_, orders) in owners {
+                let mut ranked: Vec<(bool, &SundaeV3Order)> = orders
+                    .iter()
+                    .map(|order| (is_stale_duplicate(order, &orders), *order))
+                    .collect();
+                if self.stale_duplicates == StaleDuplicates::Defer {
+                    ranked.retain(|(stale, order)| {
+                        if *stale {
+                            deferred
+                                .entry(order.input.clone())
+                                .or_default()
+                                .push(Deferral {
+                                    pool: pool.clone(),
+                                    reason: DeferReason::StaleDuplicate,
+                                });
+                        }
+                        !stale
+                    });
+                }
+                let Some(limit) = self.max_orders_per_owner else {
+                    continue;
+                };
+                let deprioritize = self.stale_duplicates == StaleDuplicates::Deprioritize;
+                ranked.sort_by_key(|(stale, order)| {
+                    (deprioritize && *stale, order.slot, order.input.clone())
+                });
+                for (_, order) in ranked.into_iter().skip(limit) {
+                    deferred
+                        .entry(order.input.clone())
+                        .or_default()
+                        .push(Deferral {
+                            pool: pool.clone(),
+                            reason: DeferReason::OwnerLimit,
+                        });
+                }
+            }
+        }
+        deferred
+    }
+}
+
+/// Whether one of the owner's other orders repeats this one and is newer.
+fn is_stale_duplicate(order: &SundaeV3Order, owners_orders: &[&SundaeV3Order]) -> bool {
+    owners_orders.iter().any(|other| {
+        (other.slot, &other.input) > (order.slot, &order.input)
+            && other.datum.action == order.datum.action
+            && other.datum.destination == order.datum.destination
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cardano_types::AssetClass,
+        sundaev3::{Order, OrderDatum, test_utils},
+    };
+
+    fn order(index: u64, slot: u64, owner: u8, action: Order) -> SundaeV3Order {
+        let datum = OrderDatum {
+            owner: Multisig::Signature(vec![owner]),
+            ..test_utils::datum(action)
+        };
+        test_utils::order(index, slot, datum)
+    }
+
+    fn record(policy: u8) -> Order {
+        Order::Record(AssetClass::from_pair((vec![policy; 28], vec![])))
+    }
+
+    fn deferred(policy: &OwnerPolicy, orders: &[SundaeV3Order]) -> Vec<(u64, Vec<Deferral>)> {
+        let pools = [Ident::new(&[9; 28])];
+        policy
+            .defer(orders.iter().map(|order| (order, pools.as_slice())))
+            .into_iter()
+            .map(|(input, pools)| (input.0.index, pools))
+            .collect()
+    }
+
+    #[test]
+    fn should_cap_each_owner_oldest_first() {
+        let orders = [
+            order(0, 30, 1, record(1)),
+            order(1, 10, 1, record(2)),
+            order(2, 20, 1, record(3)),
+            order(3, 40, 2, record(4)),
+        ];
+        let policy = OwnerPolicy {
+            max_orders_per_owner: Some(2),
+            ..Default::default()
+        };
+        let pool = Ident::new(&[9; 28]);
+        assert_eq!(
+            deferred(&policy, &orders),
+            vec![(
+                0,
+                vec![Deferral {
+                    pool,
+                    reason: DeferReason::OwnerLimit
+                }]
+            )]
+        );
+        assert!(deferred(&OwnerPolicy::default(), &orders).is_empty());
+    }
+
+    #[test]
+    fn should_handle_stale_duplicates() {
+        let orders = [
+            order(0, 10, 1, record(1)),
+            order(1, 20, 1, record(2)),
+            order(2, 30, 1, record(1)),
+        ];
+        let pool = Ident::new(&[9; 28]);
+
+        let mut policy = OwnerPolicy {
+            max_orders_per_owner: Some(2),
+            stale_duplicates: StaleDuplicates::Deprioritize,
+        };
+        assert_eq!(
+            deferred(&policy, &orders),
+            vec![(
+                0,
+                vec![Deferral {
+                    pool: pool.clone(),
+                    reason: DeferReason::OwnerLimit
+                }]
+            )]
+        );
+
+        policy.max_orders_per_owner = None;
+        policy.stale_duplicates = StaleDuplicates::Defer;
+        assert_eq!(
+            deferred(&policy, &orders),
+            vec![(
+                0,
+                vec![Deferral {
+                    pool,
+                    reason: DeferReason::StaleDuplicate
+                }]
+            )]
+        );
+    }
+}
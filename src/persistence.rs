@@ -1,21 +1,50 @@
+#[cfg(test)]
+mod chaos;
+mod copy;
+mod dual_write;
+mod instrumented;
+mod none;
+#[cfg(feature = "rocksdb")]
+mod rocksdb;
 mod sqlite;
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use acropolis_common::Point;
 use acropolis_module_custom_indexer::cursor_store::{CursorEntry, CursorSaveError, CursorStore};
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use async_trait::async_trait;
-use serde::Deserialize;
+use chrono::DateTime;
+use pallas_primitives::{Fragment, PlutusData};
+use plutus_parser::AsPlutus;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::warn;
 
 use crate::{
-    cardano_types::TransactionInput,
-    persistence::sqlite::{SqliteConfig, SqlitePersistence},
+    bigint::BigInt,
+    cardano_types::{BlockHeight, Slot, TransactionInput},
+    persistence::{
+        none::NoPersistence,
+        sqlite::{SqliteConfig, SqlitePersistence},
+    },
+    sundaev3::{Ident, OrderDatum},
 };
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum PersistenceConfig {
     Sqlite(SqliteConfig),
+    #[cfg(feature = "rocksdb")]
+    Rocksdb(rocksdb::RocksdbConfig),
+    /// Keep nothing, for throwaway runs from a recent sync point
+    None,
+    /// Write to two backends while migrating from one to the other
+    DualWrite(DualWriteConfig),
 }
 
 impl Default for PersistenceConfig {
@@ -24,43 +53,171 @@ impl Default for PersistenceConfig {
     }
 }
 
+/// Reports which migrations have been applied, without applying any.
+pub async fn schema_status(config: &PersistenceConfig) -> Result<SchemaStatus> {
+    match config {
+        PersistenceConfig::Sqlite(sqlite) => sqlite::schema_status(sqlite).await,
+        // Rocksdb has no migrations, and checks its format version on open
+        #[cfg(feature = "rocksdb")]
+        PersistenceConfig::Rocksdb(_) => Ok(SchemaStatus {
+            migrations: vec![],
+            unknown_versions: vec![],
+        }),
+        PersistenceConfig::None => Ok(SchemaStatus {
+            migrations: vec![],
+            unknown_versions: vec![],
+        }),
+        PersistenceConfig::DualWrite(dual) => Box::pin(schema_status(&dual.primary)).await,
+    }
+}
+
+/// A migration this build knows about, and whether the database has it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaStatus {
+    pub migrations: Vec<MigrationStatus>,
+    /// Applied migrations this build has never heard of, from a newer release.
+    pub unknown_versions: Vec<i64>,
+}
+
 pub trait Persistence: Send + Sync {
     fn sundae_v3_dao(&self) -> Box<dyn SundaeV3Dao>;
     fn cursor_store(&self) -> CursorDao;
+    fn api_key_dao(&self) -> Box<dyn ApiKeyDao>;
+    /// Storage for an extension index, kept apart from every other namespace.
+    fn extension_dao(&self, namespace: &str) -> Box<dyn ExtensionDao>;
 }
 
 pub async fn connect(config: &PersistenceConfig) -> Result<Arc<dyn Persistence>> {
     Ok(match config {
         PersistenceConfig::Sqlite(sqlite) => Arc::new(SqlitePersistence::new(sqlite).await?),
+        #[cfg(feature = "rocksdb")]
+        PersistenceConfig::Rocksdb(config) => Arc::new(rocksdb::RocksdbPersistence::new(config)?),
+        PersistenceConfig::None => Arc::new(NoPersistence),
+        PersistenceConfig::DualWrite(dual) => Arc::new(DualWritePersistence::new(
+            Box::pin(connect(&dual.primary)).await?,
+            Box::pin(connect(&dual.secondary)).await?,
+        )),
     })
 }
 
+#[cfg(test)]
+pub use chaos::{ChaosDao, Fault, FaultSchedule};
+pub use copy::{CopyReport, copy};
+pub use dual_write::{DualWriteConfig, DualWritePersistence};
+pub use instrumented::{InstrumentationConfig, InstrumentedPersistence};
+pub use none::NoOpSundaeV3Dao;
+
+#[derive(Clone)]
 pub struct SundaeV3TxChanges {
-    pub slot: u64,
-    pub height: u64,
+    pub slot: Slot,
+    pub height: BlockHeight,
+    /// The tx and block which made these changes, recorded against spent txos.
+    /// Created txos carry their own block hash, and their id is the tx hash.
+    pub tx_hash: Option<Vec<u8>>,
+    pub block_hash: Option<Vec<u8>>,
     pub created_txos: Vec<PersistedTxo>,
     pub spent_txos: Vec<TransactionInput>,
+    pub quarantined_txos: Vec<QuarantinedTxo>,
+    pub reserve_snapshots: Vec<PoolReserveSnapshot>,
+    pub pool_txs: Vec<PoolTx>,
+    pub settings_history: Vec<SettingsRecord>,
+    pub trades: Vec<Trade>,
+    pub scoops: Vec<Scoop>,
+    pub order_latencies: Vec<OrderLatency>,
+    pub order_flow: Vec<OrderFlow>,
 }
 impl SundaeV3TxChanges {
-    pub fn new(slot: u64, height: u64) -> Self {
+    pub fn new(slot: Slot, height: BlockHeight) -> Self {
         Self {
             slot,
             height,
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         }
     }
     pub fn is_empty(&self) -> bool {
-        self.created_txos.is_empty() && self.spent_txos.is_empty()
+        self.created_txos.is_empty()
+            && self.spent_txos.is_empty()
+            && self.quarantined_txos.is_empty()
+            && self.reserve_snapshots.is_empty()
+            && self.pool_txs.is_empty()
+            && self.settings_history.is_empty()
+            && self.trades.is_empty()
+            && self.scoops.is_empty()
+            && self.order_latencies.is_empty()
+            && self.order_flow.is_empty()
     }
 }
 
 #[async_trait]
 pub trait SundaeV3Dao: Send + Sync + 'static {
     async fn apply_tx_changes(&self, changes: SundaeV3TxChanges) -> Result<()>;
-    async fn rollback(&self, slot: u64) -> Result<()>;
+    async fn rollback(&self, slot: Slot) -> Result<()>;
     async fn load_txos(&self) -> Result<Vec<PersistedTxo>>;
-    async fn prune_txos(&self, min_height: u64) -> Result<()>;
+    async fn prune_txos(&self, min_height: BlockHeight) -> Result<()>;
+    async fn load_quarantined_txos(&self, limit: u32) -> Result<Vec<QuarantinedTxo>>;
+    /// The latest slot any stored change was made in.
+    async fn latest_slot(&self) -> Result<Option<Slot>>;
+    async fn load_reserve_history(&self, ident: &Ident) -> Result<Vec<PoolReserveSnapshot>>;
+    /// Every stored txo of the given type created within the slot range,
+    /// including spent ones which haven't been pruned yet.
+    async fn export_txos(
+        &self,
+        txo_type: &str,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<ExportedTxo>>;
+    /// Every stored txo of the given type spent from the given slot on, which
+    /// hasn't been pruned yet, oldest spend first.
+    async fn load_spent_txos(&self, txo_type: &str, from_slot: u64) -> Result<Vec<ExportedTxo>>;
+    async fn load_pool_tx(&self, tx_hash: &[u8]) -> Result<Option<PoolTx>>;
+    /// Every version of the settings datum, newest first.
+    async fn load_settings_history(&self, limit: u32) -> Result<Vec<SettingsRecord>>;
+    /// A pool's trades from the given slot on, oldest first.
+    async fn load_trades(&self, ident: &Ident, from_slot: u64, limit: u32) -> Result<Vec<Trade>>;
+    /// A pool's most recent scoops, newest first.
+    async fn load_scoops(&self, ident: &Ident, limit: u32) -> Result<Vec<Scoop>>;
+    /// Every order scooped from the given slot on, oldest first.
+    async fn load_order_latencies(&self, from_slot: u64) -> Result<Vec<OrderLatency>>;
+    /// The datum and volume of every order scooped from the given slot on,
+    /// oldest first.
+    async fn load_order_flow(&self, from_slot: u64) -> Result<Vec<OrderFlow>>;
+    async fn export_reserve_snapshots(
+        &self,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<PoolReserveSnapshot>>;
+    /// Keeps the rollback if it's deeper than the deepest one stored. Rolling
+    /// back doesn't undo this.
+    async fn record_rollback(&self, rollback: &RollbackRecord) -> Result<()>;
+    async fn load_deepest_rollback(&self) -> Result<Option<RollbackRecord>>;
+}
+
+/// A rollback the indexer handled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RollbackRecord {
+    /// Applied blocks which were undone
+    pub depth: u64,
+    /// The tip before the rollback
+    pub from_slot: u64,
+    pub to_slot: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -68,24 +225,537 @@ pub struct PersistedTxo {
     pub txo_id: TransactionInput,
     pub txo_type: String,
     pub created_slot: u64,
+    pub created_block_hash: Option<Vec<u8>>,
     pub era: u16,
     pub txo: Vec<u8>,
 }
 
-pub struct CursorDao(Box<dyn CursorDaoImpl>);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedTxo {
+    pub txo: PersistedTxo,
+    pub spent_slot: Option<u64>,
+    pub spent_height: Option<u64>,
+    pub spent_tx_hash: Option<Vec<u8>>,
+    pub spent_block_hash: Option<Vec<u8>>,
+}
+
+/// An output at one of our script addresses which we could not make sense of.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QuarantinedTxo {
+    pub txo_id: TransactionInput,
+    pub txo_type: String,
+    pub slot: u64,
+    pub era: u16,
+    pub reason: String,
+    #[serde(serialize_with = "hex::serialize")]
+    pub txo: Vec<u8>,
+}
+
+/// A pool's reserves as first seen in an epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PoolReserveSnapshot {
+    pub ident: Ident,
+    pub epoch: u64,
+    pub slot: u64,
+    pub reserve_a: BigInt,
+    pub reserve_b: BigInt,
+    pub circulating_lp: BigInt,
+}
+
+/// The full CBOR of a transaction which spent one of our pools.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolTx {
+    pub tx_hash: Vec<u8>,
+    pub slot: u64,
+    pub tx: Vec<u8>,
+}
+
+/// A settings datum as it was created on chain, kept after it is spent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsRecord {
+    pub txo_id: TransactionInput,
+    pub slot: u64,
+    /// The datum's CBOR
+    pub datum: Vec<u8>,
+}
+
+/// A swap executed against a pool by a scoop. The amounts are simulated from
+/// the pool's reserves before the scoop, in the order the pool processed them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Trade {
+    pub ident: Ident,
+    pub slot: u64,
+    #[serde(serialize_with = "hex::serialize")]
+    pub tx_hash: Vec<u8>,
+    pub order: TransactionInput,
+    /// Whether the order gave asset A to take asset B
+    pub a_to_b: bool,
+    pub gives: BigInt,
+    pub takes: BigInt,
+}
+
+impl Trade {
+    /// The execution price, in units of B per unit of A.
+    pub fn price(&self) -> Option<f64> {
+        let (a, b) = if self.a_to_b {
+            (&self.gives, &self.takes)
+        } else {
+            (&self.takes, &self.gives)
+        };
+        let a = a.to_f64()?;
+        if a == 0.0 {
+            return None;
+        }
+        Some(b.to_f64()? / a)
+    }
+}
+
+/// A scoop of one pool, and how many orders it processed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Scoop {
+    pub ident: Ident,
+    pub slot: u64,
+    #[serde(serialize_with = "hex::serialize")]
+    pub tx_hash: Vec<u8>,
+    pub orders: u32,
+    /// Missing for scoops recorded before blocks were
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<ScoopBlock>,
+}
+
+/// Where a scoop landed, for telling whether some block producers leave
+/// scoops out or put them last.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScoopBlock {
+    #[serde(serialize_with = "hex::serialize")]
+    pub hash: Vec<u8>,
+    /// The scoop's position among the block's transactions, from 0
+    pub tx_index: u32,
+}
+
+/// How often a pool gets scooped, over some run of its scoops.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScoopCadence {
+    pub scoops: usize,
+    pub last_scoop_slot: Option<u64>,
+    /// Mean slots between consecutive scoops
+    pub average_interval_slots: Option<f64>,
+    /// How many scoops processed each number of orders
+    pub orders_per_scoop: BTreeMap<u32, usize>,
+}
+
+impl ScoopCadence {
+    pub fn new(scoops: &[Scoop]) -> Self {
+        let first = scoops.iter().map(|s| s.slot).min();
+        let last = scoops.iter().map(|s| s.slot).max();
+        let average_interval_slots = match (first, last) {
+            (Some(first), Some(last)) if scoops.len() > 1 => {
+                Some((last - first) as f64 / (scoops.len() - 1) as f64)
+            }
+            _ => None,
+        };
+        let mut orders_per_scoop = BTreeMap::new();
+        for scoop in scoops {
+            *orders_per_scoop.entry(scoop.orders).or_default() += 1;
+        }
+        Self {
+            scoops: scoops.len(),
+            last_scoop_slot: last,
+            average_interval_slots,
+            orders_per_scoop,
+        }
+    }
+}
+
+/// How long an order waited between being created and being scooped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrderLatency {
+    pub ident: Ident,
+    pub order: TransactionInput,
+    pub created_slot: u64,
+    pub scooped_slot: u64,
+    /// Unix time of the scooping block, in seconds
+    pub scooped_at: u64,
+}
+
+impl OrderLatency {
+    pub fn delay_slots(&self) -> u64 {
+        self.scooped_slot.saturating_sub(self.created_slot)
+    }
+}
+
+/// A scooped order's datum, kept whole so its source can be attributed by
+/// whatever matchers are configured when it's asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderFlow {
+    pub ident: Ident,
+    pub order: TransactionInput,
+    pub slot: u64,
+    /// The slot the order was placed in
+    pub created_slot: u64,
+    /// Where the scoop executed the order, from 0
+    pub position: u32,
+    /// The CBOR of the order's datum
+    pub datum: Vec<u8>,
+    /// ADA the order traded, for swaps against ADA
+    pub lovelace: u64,
+}
+
+impl OrderFlow {
+    pub fn decode_datum(&self) -> Result<OrderDatum> {
+        let data = PlutusData::decode_fragment(&self.datum)?;
+        Ok(OrderDatum::from_plutus(data)?)
+    }
+}
+
+/// Nearest-rank percentiles of some orders' delays, in slots.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LatencyPercentiles {
+    pub orders: usize,
+    pub p50_slots: u64,
+    pub p90_slots: u64,
+    pub p99_slots: u64,
+    pub max_slots: u64,
+}
+
+impl LatencyPercentiles {
+    pub fn new(mut delays: Vec<u64>) -> Option<Self> {
+        delays.sort_unstable();
+        let max_slots = *delays.last()?;
+        let percentile = |p: usize| delays[(delays.len() * p).div_ceil(100) - 1];
+        Some(Self {
+            orders: delays.len(),
+            p50_slots: percentile(50),
+            p90_slots: percentile(90),
+            p99_slots: percentile(99),
+            max_slots,
+        })
+    }
+}
+
+/// A pool's order latencies, overall and per UTC day of the scoop.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PoolSla {
+    pub overall: LatencyPercentiles,
+    pub days: BTreeMap<String, LatencyPercentiles>,
+}
+
+/// Order latencies across every pool, as served by `/sla`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SlaReport {
+    pub overall: Option<LatencyPercentiles>,
+    pub pools: BTreeMap<Ident, PoolSla>,
+}
+
+impl SlaReport {
+    pub fn new(latencies: &[OrderLatency]) -> Self {
+        let mut by_pool: BTreeMap<&Ident, BTreeMap<String, Vec<u64>>> = BTreeMap::new();
+        for latency in latencies {
+            let day = DateTime::from_timestamp(latency.scooped_at as i64, 0)
+                .unwrap_or_default()
+                .date_naive()
+                .to_string();
+            by_pool
+                .entry(&latency.ident)
+                .or_default()
+                .entry(day)
+                .or_default()
+                .push(latency.delay_slots());
+        }
+        let pools = by_pool
+            .into_iter()
+            .filter_map(|(ident, days)| {
+                let overall = LatencyPercentiles::new(days.values().flatten().copied().collect())?;
+                let days = days
+                    .into_iter()
+                    .filter_map(|(day, delays)| Some((day, LatencyPercentiles::new(delays)?)))
+                    .collect();
+                Some((ident.clone(), PoolSla { overall, days }))
+            })
+            .collect();
+        Self {
+            overall: LatencyPercentiles::new(latencies.iter().map(|l| l.delay_slots()).collect()),
+            pools,
+        }
+    }
+}
+
+/// What a caller of the admin server may see and do.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiKey {
+    pub name: String,
+    /// Pools the key may query. Empty means all pools.
+    pub pools: Vec<Ident>,
+    /// Whether the key may restart the indexer and manage other keys
+    pub admin: bool,
+}
+
+#[async_trait]
+pub trait ApiKeyDao: Send + Sync + 'static {
+    /// Stores a key by the hash of its secret, replacing any key with the same name.
+    async fn save_api_key(&self, key: &ApiKey, secret_hash: &[u8]) -> Result<()>;
+    async fn find_api_key(&self, secret_hash: &[u8]) -> Result<Option<ApiKey>>;
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>>;
+    /// Returns whether there was a key to delete.
+    async fn delete_api_key(&self, name: &str) -> Result<bool>;
+}
+
+/// A key-value store for an index other than SundaeV3, within one namespace.
+/// Every write is kept against the slot it was made at, so that the index can
+/// undo writes along with the blocks that made them.
+#[async_trait]
+pub trait ExtensionDao: Send + Sync + 'static {
+    /// Sets `key` as of `slot`, or deletes it if `value` is `None`. Writing the
+    /// same key at the same slot again replaces the earlier write, so replaying
+    /// a block is harmless.
+    async fn put(&self, slot: Slot, key: &[u8], value: Option<&[u8]>) -> Result<()>;
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+    /// Every key which has a value, in key order.
+    async fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+    /// Undoes every write made after `slot`.
+    async fn rollback(&self, slot: Slot) -> Result<()>;
+}
+
+/// How often the indexer's cursors are written.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CursorConfig {
+    /// Skip saves which only move tips within this long of the last save.
+    /// Halting or unhalting an index always saves, and so does stopping the
+    /// pipeline. 0 saves every time.
+    pub save_interval_ms: u64,
+}
+
+/// Clones share what's been saved, so one kept aside can flush the saves
+/// skipped by another handed to the custom indexer.
+#[derive(Clone)]
+pub struct CursorDao {
+    inner: Arc<dyn CursorDaoImpl>,
+    save_interval: Duration,
+    saved: Arc<Mutex<SavedCursors>>,
+}
+
+/// What's known to be stored, so that saves only write what changed.
+#[derive(Default)]
+struct SavedCursors {
+    /// Each cursor's JSON, and whether it was halted
+    entries: HashMap<String, (Vec<u8>, bool)>,
+    at: Option<Instant>,
+    /// The latest cursors a save skipped, until they're written
+    pending: Option<HashMap<String, CursorEntry>>,
+}
 
 #[async_trait]
 trait CursorDaoImpl: Send + Sync + 'static {
     async fn load(&self) -> Result<HashMap<String, CursorEntry>>;
-    async fn save(&self, entries: &HashMap<String, CursorEntry>) -> Result<(), CursorSaveError>;
+    /// Upserts the given serialized cursors, and deletes the removed ones.
+    async fn save(
+        &self,
+        changed: &HashMap<String, Vec<u8>>,
+        removed: &[String],
+    ) -> Result<(), CursorSaveError>;
+}
+
+impl CursorDao {
+    fn new(inner: Box<dyn CursorDaoImpl>) -> Self {
+        Self {
+            inner: inner.into(),
+            save_interval: Duration::ZERO,
+            saved: Arc::new(Mutex::new(SavedCursors::default())),
+        }
+    }
+
+    pub fn with_save_interval(mut self, save_interval: Duration) -> Self {
+        self.save_interval = save_interval;
+        self
+    }
+
+    /// Reads the stored cursors directly, outside of the custom indexer.
+    pub async fn entries(&self) -> Result<HashMap<String, CursorEntry>> {
+        self.inner.load().await
+    }
+
+    /// Clears an index's halted flag, and optionally moves its tip. Only safe
+    /// while the custom indexer isn't running, since it saves over the cursors.
+    pub async fn unhalt(&self, id: &str, rewind_to: Option<Point>) -> Result<()> {
+        let mut entries = self.inner.load().await?;
+        let Some(entry) = entries.get_mut(id) else {
+            bail!("no cursor stored for index \"{id}\"");
+        };
+        entry.halted = false;
+        if let Some(point) = rewind_to {
+            entry.tip = point;
+        }
+        let changed = HashMap::from([(id.to_string(), serde_json::to_vec(entry)?)]);
+        self.inner
+            .save(&changed, &[])
+            .await
+            .map_err(|err| anyhow!("could not save cursors for {:?}", err.failed))
+    }
+
+    /// Writes the cursors the save interval skipped, so the stored cursors
+    /// catch up with the database. Only safe once the custom indexer has
+    /// stopped, or it may save over them with older ones.
+    pub async fn flush(&self) -> Result<()> {
+        let pending = self.saved.lock().await.pending.take();
+        let Some(entries) = pending else {
+            return Ok(());
+        };
+        self.write(&entries, false)
+            .await
+            .map_err(|err| anyhow!("could not save cursors for {:?}", err.failed))
+    }
+
+    /// Saves what changed since the last save, unless `debounce` and that
+    /// was too recent.
+    async fn write(
+        &self,
+        entries: &HashMap<String, CursorEntry>,
+        debounce: bool,
+    ) -> Result<(), CursorSaveError> {
+        let mut saved = self.saved.lock().await;
+        let mut changed = HashMap::new();
+        let mut halts_changed = false;
+        for (id, entry) in entries {
+            let bytes = serde_json::to_vec(entry).map_err(|err| {
+                warn!("could not serialize cursor for {id}: {err:#}");
+                CursorSaveError {
+                    failed: vec![id.clone()],
+                }
+            })?;
+            let stored = saved.entries.get(id);
+            if stored.is_some_and(|(stored, _)| *stored == bytes) {
+                continue;
+            }
+            halts_changed |= stored.is_none_or(|(_, halted)| *halted != entry.halted);
+            changed.insert(id.clone(), bytes);
+        }
+        let removed: Vec<String> = saved
+            .entries
+            .keys()
+            .filter(|id| !entries.contains_key(*id))
+            .cloned()
+            .collect();
+        if changed.is_empty() && removed.is_empty() {
+            saved.pending = None;
+            return Ok(());
+        }
+        let recently_saved = saved.at.is_some_and(|at| at.elapsed() < self.save_interval);
+        if debounce && recently_saved && !halts_changed && removed.is_empty() {
+            saved.pending = Some(entries.clone());
+            return Ok(());
+        }
+
+        let result = self.inner.save(&changed, &removed).await;
+        let failed = result.as_ref().err().map_or(&[][..], |err| &err.failed);
+        for (id, bytes) in changed {
+            if !failed.contains(&id) {
+                let halted = entries[&id].halted;
+                saved.entries.insert(id, (bytes, halted));
+            }
+        }
+        for id in removed {
+            if !failed.contains(&id) {
+                saved.entries.remove(&id);
+            }
+        }
+        saved.at = Some(Instant::now());
+        saved.pending = None;
+        result
+    }
 }
 
 impl CursorStore for CursorDao {
     async fn load(&self) -> Result<HashMap<String, CursorEntry>> {
-        self.0.load().await
+        let entries = self.inner.load().await?;
+        let mut saved = self.saved.lock().await;
+        saved.entries.clear();
+        for (id, entry) in &entries {
+            saved
+                .entries
+                .insert(id.clone(), (serde_json::to_vec(entry)?, entry.halted));
+        }
+        Ok(entries)
     }
 
     async fn save(&self, entries: &HashMap<String, CursorEntry>) -> Result<(), CursorSaveError> {
-        self.0.save(entries).await
+        self.write(entries, true).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scoop(slot: u64, orders: u32) -> Scoop {
+        Scoop {
+            ident: Ident::new(&[1; 28]),
+            slot,
+            tx_hash: vec![slot as u8; 32],
+            orders,
+            block: None,
+        }
+    }
+
+    fn latency(ident: u8, created_slot: u64, scooped_slot: u64, scooped_at: u64) -> OrderLatency {
+        OrderLatency {
+            ident: Ident::new(&[ident; 28]),
+            order: TransactionInput::new([created_slot as u8; 32].into(), 0),
+            created_slot,
+            scooped_slot,
+            scooped_at,
+        }
+    }
+
+    #[test]
+    fn should_compute_nearest_rank_percentiles() {
+        let percentiles = LatencyPercentiles::new((1..=100).rev().collect()).unwrap();
+        assert_eq!(percentiles.orders, 100);
+        assert_eq!(percentiles.p50_slots, 50);
+        assert_eq!(percentiles.p90_slots, 90);
+        assert_eq!(percentiles.p99_slots, 99);
+        assert_eq!(percentiles.max_slots, 100);
+
+        let single = LatencyPercentiles::new(vec![7]).unwrap();
+        assert_eq!((single.p50_slots, single.p99_slots), (7, 7));
+        assert_eq!(LatencyPercentiles::new(vec![]), None);
+    }
+
+    #[test]
+    fn should_split_sla_by_pool_and_day() {
+        let day = 86_400;
+        let report = SlaReport::new(&[
+            latency(1, 100, 120, day),
+            latency(1, 100, 160, day + 60),
+            latency(1, 200, 210, 2 * day),
+            latency(2, 300, 400, 2 * day),
+        ]);
+        assert_eq!(report.overall.as_ref().unwrap().orders, 4);
+        assert_eq!(report.overall.as_ref().unwrap().max_slots, 100);
+
+        let pool = &report.pools[&Ident::new(&[1; 28])];
+        assert_eq!(pool.overall.orders, 3);
+        assert_eq!(
+            pool.days.keys().collect::<Vec<_>>(),
+            vec!["1970-01-02", "1970-01-03"]
+        );
+        assert_eq!(pool.days["1970-01-02"].p50_slots, 20);
+        assert_eq!(pool.days["1970-01-02"].max_slots, 60);
+        assert_eq!(report.pools[&Ident::new(&[2; 28])].overall.p50_slots, 100);
+    }
+
+    #[test]
+    fn should_summarize_scoop_cadence() {
+        let cadence = ScoopCadence::new(&[scoop(400, 3), scoop(250, 1), scoop(100, 3)]);
+        assert_eq!(cadence.scoops, 3);
+        assert_eq!(cadence.last_scoop_slot, Some(400));
+        assert_eq!(cadence.average_interval_slots, Some(150.0));
+        assert_eq!(cadence.orders_per_scoop, BTreeMap::from([(1, 1), (3, 2)]));
+
+        let single = ScoopCadence::new(&[scoop(100, 2)]);
+        assert_eq!(single.average_interval_slots, None);
+        let none = ScoopCadence::new(&[]);
+        assert_eq!(none.last_scoop_slot, None);
+        assert!(none.orders_per_scoop.is_empty());
     }
 }
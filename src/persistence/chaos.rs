@@ -0,0 +1,244 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    cardano_types::{BlockHeight, Slot},
+    persistence::{
+        ExportedTxo, OrderFlow, OrderLatency, PersistedTxo, PoolReserveSnapshot, PoolTx,
+        QuarantinedTxo, RollbackRecord, Scoop, SettingsRecord, SundaeV3Dao, SundaeV3TxChanges,
+        Trade,
+    },
+    sundaev3::Ident,
+};
+
+/// What goes wrong with a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Fails without reaching the database
+    Error,
+    /// Reaches the database, but the caller only hears back that it failed, as
+    /// when a write commits and its acknowledgement is lost
+    Timeout,
+    /// Succeeds, after a delay
+    Slow,
+}
+
+/// Picks which calls fail from a seed, so a failing run can be replayed.
+pub struct FaultSchedule {
+    rng: StdRng,
+    /// Each fault, and the chance of any one call getting it
+    odds: Vec<(Fault, f64)>,
+    delay: Duration,
+    /// Every fault injected so far, with the method it was injected into
+    pub injected: Vec<(&'static str, Fault)>,
+}
+
+impl FaultSchedule {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            odds: vec![],
+            delay: Duration::from_millis(5),
+            injected: vec![],
+        }
+    }
+
+    pub fn with_fault(mut self, fault: Fault, chance: f64) -> Self {
+        self.odds.push((fault, chance));
+        self
+    }
+
+    /// How long slow calls and timeouts take
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Stops injecting faults, e.g. once the database has "recovered".
+    pub fn stop(&mut self) {
+        self.odds.clear();
+    }
+
+    fn next(&mut self, method: &'static str) -> Option<Fault> {
+        let roll: f64 = self.rng.random();
+        let mut threshold = 0.0;
+        let fault = self.odds.iter().find_map(|(fault, chance)| {
+            threshold += chance;
+            (roll < threshold).then_some(*fault)
+        })?;
+        self.injected.push((method, fault));
+        Some(fault)
+    }
+}
+
+/// Wraps a `SundaeV3Dao`, injecting faults into its calls as scheduled. Only
+/// for tests.
+pub struct ChaosDao {
+    inner: Box<dyn SundaeV3Dao>,
+    schedule: Arc<Mutex<FaultSchedule>>,
+}
+
+impl ChaosDao {
+    pub fn new(inner: Box<dyn SundaeV3Dao>, schedule: Arc<Mutex<FaultSchedule>>) -> Self {
+        Self { inner, schedule }
+    }
+
+    async fn inject<T>(
+        &self,
+        method: &'static str,
+        call: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let (fault, delay) = {
+            let mut schedule = self.schedule.lock().unwrap();
+            (schedule.next(method), schedule.delay)
+        };
+        match fault {
+            None => call.await,
+            Some(Fault::Error) => bail!("injected error in {method}"),
+            Some(Fault::Timeout) => {
+                let _ = call.await;
+                tokio::time::sleep(delay).await;
+                bail!("injected timeout in {method}")
+            }
+            Some(Fault::Slow) => {
+                tokio::time::sleep(delay).await;
+                call.await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SundaeV3Dao for ChaosDao {
+    async fn apply_tx_changes(&self, changes: SundaeV3TxChanges) -> Result<()> {
+        self.inject("apply_tx_changes", self.inner.apply_tx_changes(changes))
+            .await
+    }
+    async fn rollback(&self, slot: Slot) -> Result<()> {
+        self.inject("rollback", self.inner.rollback(slot)).await
+    }
+    async fn load_txos(&self) -> Result<Vec<PersistedTxo>> {
+        self.inject("load_txos", self.inner.load_txos()).await
+    }
+    async fn prune_txos(&self, min_height: BlockHeight) -> Result<()> {
+        self.inject("prune_txos", self.inner.prune_txos(min_height))
+            .await
+    }
+    async fn load_quarantined_txos(&self, limit: u32) -> Result<Vec<QuarantinedTxo>> {
+        self.inject(
+            "load_quarantined_txos",
+            self.inner.load_quarantined_txos(limit),
+        )
+        .await
+    }
+    async fn latest_slot(&self) -> Result<Option<Slot>> {
+        self.inject("latest_slot", self.inner.latest_slot()).await
+    }
+    async fn load_reserve_history(&self, ident: &Ident) -> Result<Vec<PoolReserveSnapshot>> {
+        self.inject(
+            "load_reserve_history",
+            self.inner.load_reserve_history(ident),
+        )
+        .await
+    }
+    async fn export_txos(
+        &self,
+        txo_type: &str,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<ExportedTxo>> {
+        self.inject(
+            "export_txos",
+            self.inner.export_txos(txo_type, from_slot, to_slot),
+        )
+        .await
+    }
+    async fn load_spent_txos(&self, txo_type: &str, from_slot: u64) -> Result<Vec<ExportedTxo>> {
+        self.inject(
+            "load_spent_txos",
+            self.inner.load_spent_txos(txo_type, from_slot),
+        )
+        .await
+    }
+    async fn load_pool_tx(&self, tx_hash: &[u8]) -> Result<Option<PoolTx>> {
+        self.inject("load_pool_tx", self.inner.load_pool_tx(tx_hash))
+            .await
+    }
+    async fn load_settings_history(&self, limit: u32) -> Result<Vec<SettingsRecord>> {
+        self.inject(
+            "load_settings_history",
+            self.inner.load_settings_history(limit),
+        )
+        .await
+    }
+    async fn load_trades(&self, ident: &Ident, from_slot: u64, limit: u32) -> Result<Vec<Trade>> {
+        self.inject(
+            "load_trades",
+            self.inner.load_trades(ident, from_slot, limit),
+        )
+        .await
+    }
+    async fn load_scoops(&self, ident: &Ident, limit: u32) -> Result<Vec<Scoop>> {
+        self.inject("load_scoops", self.inner.load_scoops(ident, limit))
+            .await
+    }
+    async fn load_order_latencies(&self, from_slot: u64) -> Result<Vec<OrderLatency>> {
+        self.inject(
+            "load_order_latencies",
+            self.inner.load_order_latencies(from_slot),
+        )
+        .await
+    }
+    async fn load_order_flow(&self, from_slot: u64) -> Result<Vec<OrderFlow>> {
+        self.inject("load_order_flow", self.inner.load_order_flow(from_slot))
+            .await
+    }
+    async fn export_reserve_snapshots(
+        &self,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<PoolReserveSnapshot>> {
+        self.inject(
+            "export_reserve_snapshots",
+            self.inner.export_reserve_snapshots(from_slot, to_slot),
+        )
+        .await
+    }
+    async fn record_rollback(&self, rollback: &RollbackRecord) -> Result<()> {
+        self.inject("record_rollback", self.inner.record_rollback(rollback))
+            .await
+    }
+    async fn load_deepest_rollback(&self) -> Result<Option<RollbackRecord>> {
+        self.inject("load_deepest_rollback", self.inner.load_deepest_rollback())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_replay_the_same_faults_from_a_seed() {
+        let run = |seed| {
+            let mut schedule = FaultSchedule::new(seed)
+                .with_fault(Fault::Error, 0.2)
+                .with_fault(Fault::Timeout, 0.1);
+            (0..100)
+                .map(|_| schedule.next("load_txos"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        let faults = run(7);
+        assert!(faults.contains(&Some(Fault::Error)));
+        assert!(faults.contains(&Some(Fault::Timeout)));
+        assert!(faults.contains(&None));
+    }
+}
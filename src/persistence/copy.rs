@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use anyhow::{Result, anyhow};
+use tracing::{info, warn};
+
+use crate::{
+    cardano_types::{BlockHeight, Slot},
+    extensions::EXTENSION_INDEX_PREFIX,
+    persistence::{CursorDaoImpl, Persistence, SundaeV3TxChanges},
+    sundaev3::SUNDAE_V3_INDEX_NAME,
+};
+
+const TXO_TYPES: [&str; 3] = ["pool", "order", "settings"];
+
+/// How many change sets are written between progress logs.
+const PROGRESS_INTERVAL: usize = 10_000;
+
+type ChangeSets = BTreeMap<(u64, Option<Vec<u8>>), SundaeV3TxChanges>;
+
+fn at(changes: &mut ChangeSets, slot: u64, spent_by: Option<Vec<u8>>) -> &mut SundaeV3TxChanges {
+    changes.entry((slot, spent_by.clone())).or_insert_with(|| {
+        let mut changes = SundaeV3TxChanges::new(Slot(slot), BlockHeight(0));
+        changes.tx_hash = spent_by;
+        changes
+    })
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CopyReport {
+    /// The slot the copy picked up from, if the target already had data
+    pub resumed_from: Option<u64>,
+    /// How many change sets were written to the target
+    pub changes: usize,
+}
+
+/// Replays everything one backend has stored into another, slot by slot, the
+/// same way the indexer wrote it. A copy which is interrupted can be run again:
+/// the target is rolled back to before the last slot it has, which may only
+/// have been partly written, and the copy picks up from there. Cursors are
+/// copied last, so an index won't start from the target until it's complete.
+///
+/// API keys are only stored hashed and can't be listed with their hashes, so
+/// they have to be issued again against the target. Extension indexes' records
+/// aren't copied either, so their cursors are left behind and they sync again.
+pub async fn copy(from: &dyn Persistence, to: &dyn Persistence) -> Result<CopyReport> {
+    let source = from.sundae_v3_dao();
+    let target = to.sundae_v3_dao();
+    let mut report = CopyReport::default();
+
+    let mut resume_at = 0;
+    if let Some(Slot(latest)) = target.latest_slot().await? {
+        info!(
+            slot = latest,
+            "target already has data, resuming from its latest slot"
+        );
+        target.rollback(Slot(latest.saturating_sub(1))).await?;
+        resume_at = latest;
+        report.resumed_from = Some(latest);
+    }
+
+    // Spends are kept apart by tx, since each records the tx which made it.
+    // Everything else in a slot goes in with the txos created in it.
+    let mut changes = ChangeSets::new();
+
+    let mut pool_spends = BTreeSet::new();
+    for txo_type in TXO_TYPES {
+        for txo in source.export_txos(txo_type, None, None).await? {
+            if let Some(slot) = txo.spent_slot {
+                let spend = at(&mut changes, slot, txo.spent_tx_hash.clone());
+                spend.height = BlockHeight(txo.spent_height.unwrap_or_default());
+                spend.block_hash = txo.spent_block_hash.clone();
+                spend.spent_txos.push(txo.txo.txo_id.clone());
+                if txo_type == "pool"
+                    && let Some(tx_hash) = txo.spent_tx_hash
+                {
+                    pool_spends.insert(tx_hash);
+                }
+            }
+            at(&mut changes, txo.txo.created_slot, None)
+                .created_txos
+                .push(txo.txo);
+        }
+    }
+    for txo in source.load_quarantined_txos(u32::MAX).await? {
+        at(&mut changes, txo.slot, None).quarantined_txos.push(txo);
+    }
+    for tx_hash in pool_spends {
+        if let Some(pool_tx) = source.load_pool_tx(&tx_hash).await? {
+            at(&mut changes, pool_tx.slot, None).pool_txs.push(pool_tx);
+        }
+    }
+    for record in source.load_settings_history(u32::MAX).await? {
+        at(&mut changes, record.slot, None)
+            .settings_history
+            .push(record);
+    }
+
+    // Every pool gets a reserve snapshot when it's created, so between them
+    // the snapshots and latencies name every pool with trades or scoops
+    let mut idents = BTreeSet::new();
+    for snapshot in source.export_reserve_snapshots(None, None).await? {
+        idents.insert(snapshot.ident.clone());
+        at(&mut changes, snapshot.slot, None)
+            .reserve_snapshots
+            .push(snapshot);
+    }
+    for latency in source.load_order_latencies(0).await? {
+        idents.insert(latency.ident.clone());
+        at(&mut changes, latency.scooped_slot, None)
+            .order_latencies
+            .push(latency);
+    }
+    for flow in source.load_order_flow(0).await? {
+        at(&mut changes, flow.slot, None).order_flow.push(flow);
+    }
+    for ident in &idents {
+        for trade in source.load_trades(ident, 0, u32::MAX).await? {
+            at(&mut changes, trade.slot, None).trades.push(trade);
+        }
+        for scoop in source.load_scoops(ident, u32::MAX).await? {
+            at(&mut changes, scoop.slot, None).scoops.push(scoop);
+        }
+    }
+
+    let total = changes.len();
+    for ((slot, _), changes) in changes {
+        if slot < resume_at {
+            continue;
+        }
+        target.apply_tx_changes(changes).await?;
+        report.changes += 1;
+        if report.changes % PROGRESS_INTERVAL == 0 {
+            info!(slot, written = report.changes, total, "copying");
+        }
+    }
+
+    if let Some(rollback) = source.load_deepest_rollback().await? {
+        target.record_rollback(&rollback).await?;
+    }
+
+    let mut cursors = from.cursor_store().entries().await?;
+    let before = cursors.len();
+    cursors.retain(|id, _| !id.starts_with(EXTENSION_INDEX_PREFIX));
+    let extensions = before - cursors.len();
+    if extensions > 0 {
+        warn!(
+            extensions,
+            "extension indexes aren't copied, and will sync again from their start points"
+        );
+    }
+    let serialized = cursors
+        .iter()
+        .map(|(id, entry)| Ok((id.clone(), serde_json::to_vec(entry)?)))
+        .collect::<Result<HashMap<_, _>>>()?;
+    to.cursor_store()
+        .inner
+        .save(&serialized, &[])
+        .await
+        .map_err(|err| anyhow!("could not save cursors for {:?}", err.failed))?;
+    if !cursors.contains_key(SUNDAE_V3_INDEX_NAME) {
+        warn!(
+            "the source has no cursor for {SUNDAE_V3_INDEX_NAME}, so the target will sync from the start"
+        );
+    }
+
+    let keys = from.api_key_dao().list_api_keys().await?;
+    if !keys.is_empty() {
+        warn!(
+            keys = keys.len(),
+            "API keys can't be copied, and need to be issued again"
+        );
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bigint::BigInt,
+        cardano_types::TransactionInput,
+        persistence::{PersistedTxo, PersistenceConfig, PoolReserveSnapshot, connect},
+        sundaev3::Ident,
+    };
+
+    fn txo(index: u64, txo_type: &str, slot: u64) -> PersistedTxo {
+        PersistedTxo {
+            txo_id: TransactionInput::new([index as u8; 32].into(), index),
+            txo_type: txo_type.to_string(),
+            created_slot: slot,
+            created_block_hash: Some(vec![slot as u8; 32]),
+            era: 6,
+            txo: vec![index as u8],
+        }
+    }
+
+    #[tokio::test]
+    async fn should_copy_and_resume() -> Result<()> {
+        let from = connect(&PersistenceConfig::default()).await?;
+        let to = connect(&PersistenceConfig::default()).await?;
+        let source = from.sundae_v3_dao();
+
+        let mut changes = SundaeV3TxChanges::new(Slot(10), BlockHeight(1));
+        changes.created_txos = vec![txo(0, "pool", 10), txo(1, "order", 10)];
+        changes.reserve_snapshots.push(PoolReserveSnapshot {
+            ident: Ident::new(&[1]),
+            epoch: 0,
+            slot: 10,
+            reserve_a: BigInt::from(1),
+            reserve_b: BigInt::from(2),
+            circulating_lp: BigInt::from(3),
+        });
+        source.apply_tx_changes(changes).await?;
+        let mut changes = SundaeV3TxChanges::new(Slot(20), BlockHeight(2));
+        changes.tx_hash = Some(vec![9; 32]);
+        changes.block_hash = Some(vec![8; 32]);
+        changes.spent_txos.push(txo(1, "order", 10).txo_id);
+        changes.created_txos.push(txo(2, "order", 20));
+        source.apply_tx_changes(changes).await?;
+
+        let report = copy(from.as_ref(), to.as_ref()).await?;
+        assert_eq!(report.resumed_from, None);
+        let target = to.sundae_v3_dao();
+        assert_eq!(target.load_txos().await?, source.load_txos().await?);
+        assert_eq!(
+            target.export_txos("order", None, None).await?,
+            source.export_txos("order", None, None).await?
+        );
+        assert_eq!(
+            target.export_reserve_snapshots(None, None).await?,
+            source.export_reserve_snapshots(None, None).await?
+        );
+
+        // Copying again picks up from the last slot without duplicating it
+        let report = copy(from.as_ref(), to.as_ref()).await?;
+        assert_eq!(report.resumed_from, Some(20));
+        assert_eq!(
+            target.export_txos("order", None, None).await?,
+            source.export_txos("order", None, None).await?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use std::{collections::HashMap, sync::Arc};
+
+use acropolis_module_custom_indexer::cursor_store::{CursorEntry, CursorSaveError};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::{
+    cardano_types::{BlockHeight, Slot},
+    persistence::{
+        ApiKey, ApiKeyDao, CursorDao, CursorDaoImpl, ExportedTxo, ExtensionDao, OrderFlow,
+        OrderLatency, PersistedTxo, Persistence, PersistenceConfig, PoolReserveSnapshot, PoolTx,
+        QuarantinedTxo, RollbackRecord, Scoop, SettingsRecord, SundaeV3Dao, SundaeV3TxChanges,
+        Trade,
+    },
+    sundaev3::Ident,
+};
+
+/// Writes to two backends at once while moving from one to the other. Reads
+/// only go to the primary.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DualWriteConfig {
+    pub primary: Box<PersistenceConfig>,
+    pub secondary: Box<PersistenceConfig>,
+}
+
+/// Every write goes to the primary, then the secondary. If the secondary
+/// fails, so does the write, so the two can't silently drift apart.
+pub struct DualWritePersistence {
+    primary: Arc<dyn Persistence>,
+    secondary: Arc<dyn Persistence>,
+}
+
+impl DualWritePersistence {
+    pub fn new(primary: Arc<dyn Persistence>, secondary: Arc<dyn Persistence>) -> Self {
+        Self { primary, secondary }
+    }
+}
+
+impl Persistence for DualWritePersistence {
+    fn sundae_v3_dao(&self) -> Box<dyn SundaeV3Dao> {
+        Box::new(DualWriteSundaeV3Dao {
+            primary: self.primary.sundae_v3_dao(),
+            secondary: self.secondary.sundae_v3_dao(),
+        })
+    }
+
+    fn cursor_store(&self) -> CursorDao {
+        CursorDao::new(Box::new(DualWriteCursors {
+            primary: self.primary.cursor_store().inner,
+            secondary: self.secondary.cursor_store().inner,
+        }))
+    }
+
+    fn api_key_dao(&self) -> Box<dyn ApiKeyDao> {
+        Box::new(DualWriteApiKeys {
+            primary: self.primary.api_key_dao(),
+            secondary: self.secondary.api_key_dao(),
+        })
+    }
+
+    fn extension_dao(&self, namespace: &str) -> Box<dyn ExtensionDao> {
+        Box::new(DualWriteExtensions {
+            primary: self.primary.extension_dao(namespace),
+            secondary: self.secondary.extension_dao(namespace),
+        })
+    }
+}
+
+struct DualWriteSundaeV3Dao {
+    primary: Box<dyn SundaeV3Dao>,
+    secondary: Box<dyn SundaeV3Dao>,
+}
+
+const SECONDARY_FAILED: &str = "write to the secondary persistence failed";
+
+#[async_trait]
+impl SundaeV3Dao for DualWriteSundaeV3Dao {
+    async fn apply_tx_changes(&self, changes: SundaeV3TxChanges) -> Result<()> {
+        self.primary.apply_tx_changes(changes.clone()).await?;
+        self.secondary
+            .apply_tx_changes(changes)
+            .await
+            .context(SECONDARY_FAILED)
+    }
+    async fn rollback(&self, slot: Slot) -> Result<()> {
+        self.primary.rollback(slot).await?;
+        self.secondary
+            .rollback(slot)
+            .await
+            .context(SECONDARY_FAILED)
+    }
+    async fn load_txos(&self) -> Result<Vec<PersistedTxo>> {
+        self.primary.load_txos().await
+    }
+    async fn prune_txos(&self, min_height: BlockHeight) -> Result<()> {
+        self.primary.prune_txos(min_height).await?;
+        self.secondary
+            .prune_txos(min_height)
+            .await
+            .context(SECONDARY_FAILED)
+    }
+    async fn load_quarantined_txos(&self, limit: u32) -> Result<Vec<QuarantinedTxo>> {
+        self.primary.load_quarantined_txos(limit).await
+    }
+    async fn latest_slot(&self) -> Result<Option<Slot>> {
+        self.primary.latest_slot().await
+    }
+    async fn load_reserve_history(&self, ident: &Ident) -> Result<Vec<PoolReserveSnapshot>> {
+        self.primary.load_reserve_history(ident).await
+    }
+    async fn export_txos(
+        &self,
+        txo_type: &str,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<ExportedTxo>> {
+        self.primary.export_txos(txo_type, from_slot, to_slot).await
+    }
+    async fn load_spent_txos(&self, txo_type: &str, from_slot: u64) -> Result<Vec<ExportedTxo>> {
+        self.primary.load_spent_txos(txo_type, from_slot).await
+    }
+    async fn load_pool_tx(&self, tx_hash: &[u8]) -> Result<Option<PoolTx>> {
+        self.primary.load_pool_tx(tx_hash).await
+    }
+    async fn load_settings_history(&self, limit: u32) -> Result<Vec<SettingsRecord>> {
+        self.primary.load_settings_history(limit).await
+    }
+    async fn load_trades(&self, ident: &Ident, from_slot: u64, limit: u32) -> Result<Vec<Trade>> {
+        self.primary.load_trades(ident, from_slot, limit).await
+    }
+    async fn load_scoops(&self, ident: &Ident, limit: u32) -> Result<Vec<Scoop>> {
+        self.primary.load_scoops(ident, limit).await
+    }
+    async fn load_order_latencies(&self, from_slot: u64) -> Result<Vec<OrderLatency>> {
+        self.primary.load_order_latencies(from_slot).await
+    }
+    async fn load_order_flow(&self, from_slot: u64) -> Result<Vec<OrderFlow>> {
+        self.primary.load_order_flow(from_slot).await
+    }
+    async fn export_reserve_snapshots(
+        &self,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<PoolReserveSnapshot>> {
+        self.primary
+            .export_reserve_snapshots(from_slot, to_slot)
+            .await
+    }
+    async fn record_rollback(&self, rollback: &RollbackRecord) -> Result<()> {
+        self.primary.record_rollback(rollback).await?;
+        self.secondary
+            .record_rollback(rollback)
+            .await
+            .context(SECONDARY_FAILED)
+    }
+    async fn load_deepest_rollback(&self) -> Result<Option<RollbackRecord>> {
+        self.primary.load_deepest_rollback().await
+    }
+}
+
+struct DualWriteCursors {
+    primary: Arc<dyn CursorDaoImpl>,
+    secondary: Arc<dyn CursorDaoImpl>,
+}
+
+#[async_trait]
+impl CursorDaoImpl for DualWriteCursors {
+    async fn load(&self) -> Result<HashMap<String, CursorEntry>> {
+        self.primary.load().await
+    }
+
+    async fn save(
+        &self,
+        changed: &HashMap<String, Vec<u8>>,
+        removed: &[String],
+    ) -> Result<(), CursorSaveError> {
+        self.primary.save(changed, removed).await?;
+        self.secondary.save(changed, removed).await
+    }
+}
+
+struct DualWriteApiKeys {
+    primary: Box<dyn ApiKeyDao>,
+    secondary: Box<dyn ApiKeyDao>,
+}
+
+#[async_trait]
+impl ApiKeyDao for DualWriteApiKeys {
+    async fn save_api_key(&self, key: &ApiKey, secret_hash: &[u8]) -> Result<()> {
+        self.primary.save_api_key(key, secret_hash).await?;
+        self.secondary
+            .save_api_key(key, secret_hash)
+            .await
+            .context(SECONDARY_FAILED)
+    }
+    async fn find_api_key(&self, secret_hash: &[u8]) -> Result<Option<ApiKey>> {
+        self.primary.find_api_key(secret_hash).await
+    }
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        self.primary.list_api_keys().await
+    }
+    async fn delete_api_key(&self, name: &str) -> Result<bool> {
+        let deleted = self.primary.delete_api_key(name).await?;
+        self.secondary
+            .delete_api_key(name)
+            .await
+            .context(SECONDARY_FAILED)?;
+        Ok(deleted)
+    }
+}
+
+struct DualWriteExtensions {
+    primary: Box<dyn ExtensionDao>,
+    secondary: Box<dyn ExtensionDao>,
+}
+
+#[async_trait]
+impl ExtensionDao for DualWriteExtensions {
+    async fn put(&self, slot: Slot, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        self.primary.put(slot, key, value).await?;
+        self.secondary
+            .put(slot, key, value)
+            .await
+            .context(SECONDARY_FAILED)
+    }
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.primary.get(key).await
+    }
+    async fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.primary.entries().await
+    }
+    async fn rollback(&self, slot: Slot) -> Result<()> {
+        self.primary.rollback(slot).await?;
+        self.secondary
+            .rollback(slot)
+            .await
+            .context(SECONDARY_FAILED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::connect;
+
+    #[tokio::test]
+    async fn should_write_to_both() -> Result<()> {
+        let primary = connect(&PersistenceConfig::default()).await?;
+        let secondary = connect(&PersistenceConfig::default()).await?;
+        let dual = DualWritePersistence::new(primary.clone(), secondary.clone());
+
+        let mut changes = SundaeV3TxChanges::new(Slot(5), BlockHeight(1));
+        changes.settings_history.push(SettingsRecord {
+            txo_id: crate::cardano_types::TransactionInput::new([1; 32].into(), 0),
+            slot: 5,
+            datum: vec![0],
+        });
+        dual.sundae_v3_dao().apply_tx_changes(changes).await?;
+        for persistence in [&primary, &secondary] {
+            let history = persistence
+                .sundae_v3_dao()
+                .load_settings_history(10)
+                .await?;
+            assert_eq!(history.len(), 1);
+        }
+
+        dual.sundae_v3_dao().rollback(Slot(0)).await?;
+        for persistence in [&primary, &secondary] {
+            let history = persistence
+                .sundae_v3_dao()
+                .load_settings_history(10)
+                .await?;
+            assert!(history.is_empty());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use tracing::{Instrument, info_span, warn};
+
+use crate::{
+    cardano_types::{BlockHeight, Slot},
+    metrics,
+    persistence::{
+        ApiKeyDao, CursorDao, ExportedTxo, ExtensionDao, OrderFlow, OrderLatency, PersistedTxo,
+        Persistence, PoolReserveSnapshot, PoolTx, QuarantinedTxo, RollbackRecord, Scoop,
+        SettingsRecord, SundaeV3Dao, SundaeV3TxChanges, Trade,
+    },
+    sundaev3::Ident,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct InstrumentationConfig {
+    /// Persistence calls slower than this are logged. 0 logs nothing.
+    pub slow_query_ms: u64,
+}
+
+impl Default for InstrumentationConfig {
+    fn default() -> Self {
+        Self { slow_query_ms: 500 }
+    }
+}
+
+/// Records metrics for every `SundaeV3Dao` call made through it.
+pub struct InstrumentedPersistence {
+    inner: Arc<dyn Persistence>,
+    slow_query: Option<Duration>,
+}
+
+impl InstrumentedPersistence {
+    pub fn new(inner: Arc<dyn Persistence>, config: &InstrumentationConfig) -> Self {
+        let slow_query =
+            (config.slow_query_ms > 0).then_some(Duration::from_millis(config.slow_query_ms));
+        Self { inner, slow_query }
+    }
+}
+
+impl Persistence for InstrumentedPersistence {
+    fn sundae_v3_dao(&self) -> Box<dyn SundaeV3Dao> {
+        Box::new(InstrumentedSundaeV3Dao {
+            inner: self.inner.sundae_v3_dao(),
+            slow_query: self.slow_query,
+        })
+    }
+
+    fn cursor_store(&self) -> CursorDao {
+        self.inner.cursor_store()
+    }
+
+    fn api_key_dao(&self) -> Box<dyn ApiKeyDao> {
+        self.inner.api_key_dao()
+    }
+
+    fn extension_dao(&self, namespace: &str) -> Box<dyn ExtensionDao> {
+        self.inner.extension_dao(namespace)
+    }
+}
+
+/// How many rows a call returned, for the metrics.
+trait Rows {
+    fn rows(&self) -> u64;
+}
+
+impl Rows for () {
+    fn rows(&self) -> u64 {
+        0
+    }
+}
+
+impl<T> Rows for Vec<T> {
+    fn rows(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<T> Rows for Option<T> {
+    fn rows(&self) -> u64 {
+        self.is_some() as u64
+    }
+}
+
+struct InstrumentedSundaeV3Dao {
+    inner: Box<dyn SundaeV3Dao>,
+    slow_query: Option<Duration>,
+}
+
+impl InstrumentedSundaeV3Dao {
+    async fn observe<T: Rows>(
+        &self,
+        method: &'static str,
+        rows_written: u64,
+        call: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let start = Instant::now();
+        let result = call.instrument(info_span!("persistence", method)).await;
+        let elapsed = start.elapsed();
+        let rows = result.as_ref().ok().map(|r| r.rows() + rows_written);
+        metrics::dao_method(method).record(elapsed, rows);
+        if self
+            .slow_query
+            .is_some_and(|threshold| elapsed >= threshold)
+        {
+            warn!(
+                method,
+                elapsed_ms = elapsed.as_millis() as u64,
+                rows,
+                "slow persistence call"
+            );
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl SundaeV3Dao for InstrumentedSundaeV3Dao {
+    async fn apply_tx_changes(&self, changes: SundaeV3TxChanges) -> Result<()> {
+        let rows = changes.created_txos.len()
+            + changes.spent_txos.len()
+            + changes.quarantined_txos.len()
+            + changes.reserve_snapshots.len()
+            + changes.pool_txs.len()
+            + changes.settings_history.len()
+            + changes.trades.len()
+            + changes.scoops.len()
+            + changes.order_latencies.len()
+            + changes.order_flow.len();
+        self.observe(
+            "apply_tx_changes",
+            rows as u64,
+            self.inner.apply_tx_changes(changes),
+        )
+        .await
+    }
+    async fn rollback(&self, slot: Slot) -> Result<()> {
+        self.observe("rollback", 0, self.inner.rollback(slot)).await
+    }
+    async fn load_txos(&self) -> Result<Vec<PersistedTxo>> {
+        self.observe("load_txos", 0, self.inner.load_txos()).await
+    }
+    async fn prune_txos(&self, min_height: BlockHeight) -> Result<()> {
+        self.observe("prune_txos", 0, self.inner.prune_txos(min_height))
+            .await
+    }
+    async fn load_quarantined_txos(&self, limit: u32) -> Result<Vec<QuarantinedTxo>> {
+        self.observe(
+            "load_quarantined_txos",
+            0,
+            self.inner.load_quarantined_txos(limit),
+        )
+        .await
+    }
+    async fn latest_slot(&self) -> Result<Option<Slot>> {
+        self.observe("latest_slot", 0, self.inner.latest_slot())
+            .await
+    }
+    async fn load_reserve_history(&self, ident: &Ident) -> Result<Vec<PoolReserveSnapshot>> {
+        self.observe(
+            "load_reserve_history",
+            0,
+            self.inner.load_reserve_history(ident),
+        )
+        .await
+    }
+    async fn export_txos(
+        &self,
+        txo_type: &str,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<ExportedTxo>> {
+        self.observe(
+            "export_txos",
+            0,
+            self.inner.export_txos(txo_type, from_slot, to_slot),
+        )
+        .await
+    }
+    async fn load_spent_txos(&self, txo_type: &str, from_slot: u64) -> Result<Vec<ExportedTxo>> {
+        self.observe(
+            "load_spent_txos",
+            0,
+            self.inner.load_spent_txos(txo_type, from_slot),
+        )
+        .await
+    }
+    async fn load_pool_tx(&self, tx_hash: &[u8]) -> Result<Option<PoolTx>> {
+        self.observe("load_pool_tx", 0, self.inner.load_pool_tx(tx_hash))
+            .await
+    }
+    async fn load_settings_history(&self, limit: u32) -> Result<Vec<SettingsRecord>> {
+        self.observe(
+            "load_settings_history",
+            0,
+            self.inner.load_settings_history(limit),
+        )
+        .await
+    }
+    async fn load_trades(&self, ident: &Ident, from_slot: u64, limit: u32) -> Result<Vec<Trade>> {
+        self.observe(
+            "load_trades",
+            0,
+            self.inner.load_trades(ident, from_slot, limit),
+        )
+        .await
+    }
+    async fn load_scoops(&self, ident: &Ident, limit: u32) -> Result<Vec<Scoop>> {
+        self.observe("load_scoops", 0, self.inner.load_scoops(ident, limit))
+            .await
+    }
+    async fn load_order_latencies(&self, from_slot: u64) -> Result<Vec<OrderLatency>> {
+        self.observe(
+            "load_order_latencies",
+            0,
+            self.inner.load_order_latencies(from_slot),
+        )
+        .await
+    }
+    async fn load_order_flow(&self, from_slot: u64) -> Result<Vec<OrderFlow>> {
+        self.observe("load_order_flow", 0, self.inner.load_order_flow(from_slot))
+            .await
+    }
+    async fn export_reserve_snapshots(
+        &self,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<PoolReserveSnapshot>> {
+        self.observe(
+            "export_reserve_snapshots",
+            0,
+            self.inner.export_reserve_snapshots(from_slot, to_slot),
+        )
+        .await
+    }
+    async fn record_rollback(&self, rollback: &RollbackRecord) -> Result<()> {
+        self.observe("record_rollback", 1, self.inner.record_rollback(rollback))
+            .await
+    }
+    async fn load_deepest_rollback(&self) -> Result<Option<RollbackRecord>> {
+        self.observe(
+            "load_deepest_rollback",
+            0,
+            self.inner.load_deepest_rollback(),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::persistence::{PersistenceConfig, connect};
+
+    #[tokio::test]
+    async fn should_count_calls_and_rows() -> Result<()> {
+        let persistence = InstrumentedPersistence::new(
+            connect(&PersistenceConfig::default()).await?,
+            &InstrumentationConfig::default(),
+        );
+        let dao = persistence.sundae_v3_dao();
+        let metrics = metrics::dao_method("load_settings_history");
+        let (calls, rows) = (metrics.calls(), metrics.rows());
+
+        let mut changes = SundaeV3TxChanges::new(Slot(1), BlockHeight(1));
+        changes.settings_history = vec![SettingsRecord {
+            txo_id: crate::cardano_types::TransactionInput::new([1; 32].into(), 0),
+            slot: 1,
+            datum: vec![0],
+        }];
+        dao.apply_tx_changes(changes).await?;
+        dao.load_settings_history(10).await?;
+
+        assert!(metrics.calls() > calls);
+        assert!(metrics.rows() > rows);
+        assert!(metrics::dao_method("apply_tx_changes").rows() > 0);
+        assert!(metrics::render().contains("scooper_dao_calls_total{method=\"load_trades\"}"));
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use acropolis_module_custom_indexer::cursor_store::{CursorEntry, CursorSaveError};
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+
+use crate::{
+    cardano_types::{BlockHeight, Slot},
+    persistence::{
+        ApiKey, ApiKeyDao, CursorDao, CursorDaoImpl, ExportedTxo, ExtensionDao, OrderFlow,
+        OrderLatency, PersistedTxo, Persistence, PoolReserveSnapshot, PoolTx, QuarantinedTxo,
+        RollbackRecord, Scoop, SettingsRecord, SundaeV3Dao, SundaeV3TxChanges, Trade,
+    },
+    sundaev3::Ident,
+};
+
+/// Keeps nothing. The index lives only in memory, and every run starts over
+/// from the configured sync point.
+pub struct NoPersistence;
+
+impl Persistence for NoPersistence {
+    fn sundae_v3_dao(&self) -> Box<dyn SundaeV3Dao> {
+        Box::new(NoOpSundaeV3Dao)
+    }
+
+    fn cursor_store(&self) -> CursorDao {
+        CursorDao::new(Box::new(NoOpCursorDaoImpl))
+    }
+
+    fn api_key_dao(&self) -> Box<dyn ApiKeyDao> {
+        Box::new(NoOpApiKeyDao)
+    }
+
+    fn extension_dao(&self, namespace: &str) -> Box<dyn ExtensionDao> {
+        let _ = namespace;
+        Box::new(NoOpExtensionDao)
+    }
+}
+
+pub struct NoOpSundaeV3Dao;
+
+#[async_trait]
+impl SundaeV3Dao for NoOpSundaeV3Dao {
+    async fn apply_tx_changes(&self, changes: SundaeV3TxChanges) -> Result<()> {
+        let _ = changes;
+        Ok(())
+    }
+    async fn rollback(&self, slot: Slot) -> Result<()> {
+        let _ = slot;
+        Ok(())
+    }
+    async fn load_txos(&self) -> Result<Vec<PersistedTxo>> {
+        Ok(vec![])
+    }
+    async fn prune_txos(&self, min_height: BlockHeight) -> Result<()> {
+        let _ = min_height;
+        Ok(())
+    }
+    async fn load_quarantined_txos(&self, limit: u32) -> Result<Vec<QuarantinedTxo>> {
+        let _ = limit;
+        Ok(vec![])
+    }
+    async fn latest_slot(&self) -> Result<Option<Slot>> {
+        Ok(None)
+    }
+    async fn load_reserve_history(&self, ident: &Ident) -> Result<Vec<PoolReserveSnapshot>> {
+        let _ = ident;
+        Ok(vec![])
+    }
+    async fn export_txos(
+        &self,
+        txo_type: &str,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<ExportedTxo>> {
+        let _ = (txo_type, from_slot, to_slot);
+        Ok(vec![])
+    }
+    async fn load_spent_txos(&self, txo_type: &str, from_slot: u64) -> Result<Vec<ExportedTxo>> {
+        let _ = (txo_type, from_slot);
+        Ok(vec![])
+    }
+    async fn load_pool_tx(&self, tx_hash: &[u8]) -> Result<Option<PoolTx>> {
+        let _ = tx_hash;
+        Ok(None)
+    }
+    async fn load_settings_history(&self, limit: u32) -> Result<Vec<SettingsRecord>> {
+        let _ = limit;
+        Ok(vec![])
+    }
+    async fn load_trades(&self, ident: &Ident, from_slot: u64, limit: u32) -> Result<Vec<Trade>> {
+        let _ = (ident, from_slot, limit);
+        Ok(vec![])
+    }
+    async fn load_scoops(&self, ident: &Ident, limit: u32) -> Result<Vec<Scoop>> {
+        let _ = (ident, limit);
+        Ok(vec![])
+    }
+    async fn load_order_latencies(&self, from_slot: u64) -> Result<Vec<OrderLatency>> {
+        let _ = from_slot;
+        Ok(vec![])
+    }
+    async fn load_order_flow(&self, from_slot: u64) -> Result<Vec<OrderFlow>> {
+        let _ = from_slot;
+        Ok(vec![])
+    }
+    async fn export_reserve_snapshots(
+        &self,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<PoolReserveSnapshot>> {
+        let _ = (from_slot, to_slot);
+        Ok(vec![])
+    }
+    async fn record_rollback(&self, rollback: &RollbackRecord) -> Result<()> {
+        let _ = rollback;
+        Ok(())
+    }
+    async fn load_deepest_rollback(&self) -> Result<Option<RollbackRecord>> {
+        Ok(None)
+    }
+}
+
+/// Never has a cursor, so the indexer always starts from its default point.
+struct NoOpCursorDaoImpl;
+
+#[async_trait]
+impl CursorDaoImpl for NoOpCursorDaoImpl {
+    async fn load(&self) -> Result<HashMap<String, CursorEntry>> {
+        Ok(HashMap::new())
+    }
+
+    async fn save(
+        &self,
+        changed: &HashMap<String, Vec<u8>>,
+        removed: &[String],
+    ) -> Result<(), CursorSaveError> {
+        let _ = (changed, removed);
+        Ok(())
+    }
+}
+
+/// Only the root key from config works without persistence.
+struct NoOpApiKeyDao;
+
+#[async_trait]
+impl ApiKeyDao for NoOpApiKeyDao {
+    async fn save_api_key(&self, key: &ApiKey, secret_hash: &[u8]) -> Result<()> {
+        let _ = (key, secret_hash);
+        bail!("api keys can't be saved without persistence");
+    }
+    async fn find_api_key(&self, secret_hash: &[u8]) -> Result<Option<ApiKey>> {
+        let _ = secret_hash;
+        Ok(None)
+    }
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        Ok(vec![])
+    }
+    async fn delete_api_key(&self, name: &str) -> Result<bool> {
+        let _ = name;
+        Ok(false)
+    }
+}
+
+/// Extension indexes keep what they need in memory, and start over each run.
+struct NoOpExtensionDao;
+
+#[async_trait]
+impl ExtensionDao for NoOpExtensionDao {
+    async fn put(&self, slot: Slot, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        let _ = (slot, key, value);
+        Ok(())
+    }
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let _ = key;
+        Ok(None)
+    }
+    async fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(vec![])
+    }
+    async fn rollback(&self, slot: Slot) -> Result<()> {
+        let _ = slot;
+        Ok(())
+    }
+}
@@ -0,0 +1,1256 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use acropolis_module_custom_indexer::cursor_store::{CursorEntry, CursorSaveError};
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use minicbor::{Decode, Encode};
+use rocksdb::{ColumnFamily, DB, Direction, IteratorMode, Options, WriteBatch};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    cardano_types::{BlockHeight, Slot, TransactionInput},
+    persistence::{
+        ApiKey, ApiKeyDao, CursorDao, CursorDaoImpl, ExportedTxo, ExtensionDao, OrderFlow,
+        OrderLatency, PersistedTxo, Persistence, PoolReserveSnapshot, PoolTx, QuarantinedTxo,
+        RollbackRecord, Scoop, ScoopBlock, SettingsRecord, SundaeV3Dao, SundaeV3TxChanges, Trade,
+    },
+    sundaev3::Ident,
+};
+
+/// Bumped whenever a key or record layout changes incompatibly.
+const FORMAT_VERSION: u64 = 1;
+const FORMAT_VERSION_KEY: &[u8] = b"format-version";
+/// In the default column family, so rolling back leaves it alone.
+const DEEPEST_ROLLBACK_KEY: &[u8] = b"deepest-rollback";
+
+/// Unspent and recently spent txos, keyed by created slot then txo id.
+const TXOS: &str = "txos";
+/// Each txo's created slot, keyed by txo id, to find it again when spent.
+const TXO_SLOTS: &str = "txo-slots";
+/// Keyed by spent slot then txo id, pointing at the txo's created slot.
+const SPENDS: &str = "spends";
+/// Keyed by slot then txo id.
+const QUARANTINE: &str = "quarantine";
+/// Settings datums, keyed by slot then txo id.
+const DATUMS: &str = "datums";
+/// Keyed by ident then epoch.
+const RESERVES: &str = "reserves";
+/// Keyed by tx hash.
+const POOL_TXS: &str = "pool-txs";
+/// Keyed by ident, slot, then the trade's position within the slot.
+const TRADES: &str = "trades";
+/// Keyed by ident, slot, then tx hash, holding the number of orders scooped.
+const SCOOPS: &str = "scoops";
+/// Keyed by scooped slot then the order's txo id.
+const LATENCIES: &str = "latencies";
+/// Keyed by scooped slot then the order's txo id.
+const ORDER_FLOW: &str = "order-flow";
+/// Writes which aren't keyed by slot, keyed by slot, column family, then key,
+/// so that rolling back a slot range finds them without a full scan.
+const UNDO: &str = "undo";
+/// Extension indexes' records, keyed by namespace, key, then slot.
+const EXTENSIONS: &str = "extensions";
+const API_KEYS: &str = "api-keys";
+const CURSORS: &str = "cursors";
+
+const COLUMN_FAMILIES: &[&str] = &[
+    TXOS, TXO_SLOTS, SPENDS, QUARANTINE, DATUMS, RESERVES, POOL_TXS, TRADES, SCOOPS, LATENCIES,
+    ORDER_FLOW, UNDO, EXTENSIONS, API_KEYS, CURSORS,
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RocksdbConfig {
+    path: PathBuf,
+}
+
+pub struct RocksdbPersistence {
+    db: Arc<DB>,
+}
+
+impl RocksdbPersistence {
+    pub fn new(config: &RocksdbConfig) -> Result<Self> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db = DB::open_cf(&opts, &config.path, COLUMN_FAMILIES)?;
+
+        match db.get(FORMAT_VERSION_KEY)? {
+            Some(bytes) => {
+                let version = read_u64(&bytes)?;
+                if version > FORMAT_VERSION {
+                    bail!(
+                        "database format is at version {version}, but this build only supports up to version {FORMAT_VERSION}. Run a newer scooper, or restore a backup from before the upgrade."
+                    );
+                }
+            }
+            None => db.put(FORMAT_VERSION_KEY, FORMAT_VERSION.to_be_bytes())?,
+        }
+
+        Ok(Self { db: Arc::new(db) })
+    }
+}
+
+impl Persistence for RocksdbPersistence {
+    fn sundae_v3_dao(&self) -> Box<dyn SundaeV3Dao> {
+        Box::new(RocksdbSundaeV3Dao {
+            db: self.db.clone(),
+        })
+    }
+
+    fn cursor_store(&self) -> CursorDao {
+        CursorDao::new(Box::new(RocksdbCursorDaoImpl {
+            db: self.db.clone(),
+        }))
+    }
+
+    fn api_key_dao(&self) -> Box<dyn ApiKeyDao> {
+        Box::new(RocksdbApiKeyDao {
+            db: self.db.clone(),
+        })
+    }
+
+    fn extension_dao(&self, namespace: &str) -> Box<dyn ExtensionDao> {
+        let mut prefix = vec![namespace.len() as u8];
+        prefix.extend_from_slice(namespace.as_bytes());
+        Box::new(RocksdbExtensionDao {
+            db: self.db.clone(),
+            prefix,
+        })
+    }
+}
+
+fn cf<'a>(db: &'a DB, name: &str) -> &'a ColumnFamily {
+    db.cf_handle(name)
+        .expect("every column family is created on open")
+}
+
+/// Every entry from `from` on, in either direction.
+fn scan<'a>(
+    db: &'a DB,
+    name: &str,
+    from: &'a [u8],
+    direction: Direction,
+) -> impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>)>> + 'a {
+    db.iterator_cf(cf(db, name), IteratorMode::From(from, direction))
+        .map(|entry| entry.map_err(anyhow::Error::from))
+}
+
+/// Every entry from `from` on whose key starts with `prefix`, in key order.
+fn scan_prefix<'a>(
+    db: &'a DB,
+    name: &str,
+    prefix: &'a [u8],
+    from: &'a [u8],
+) -> impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>)>> + 'a {
+    scan(db, name, from, Direction::Forward).take_while(move |entry| match entry {
+        Ok((key, _)) => key.starts_with(prefix),
+        Err(_) => true,
+    })
+}
+
+fn encode<T: Encode<()>>(record: &T) -> Result<Vec<u8>> {
+    minicbor::to_vec(record).map_err(|err| anyhow!("could not encode record: {err}"))
+}
+
+fn decode<T: for<'b> Decode<'b, ()>>(bytes: &[u8]) -> Result<T> {
+    minicbor::decode(bytes).map_err(|err| anyhow!("could not decode record: {err}"))
+}
+
+fn read_u64(bytes: &[u8]) -> Result<u64> {
+    let bytes: [u8; 8] = bytes
+        .get(..8)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| anyhow!("key too short"))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn read_u32(bytes: &[u8]) -> Result<u32> {
+    let bytes: [u8; 4] = bytes
+        .get(..4)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| anyhow!("key too short"))?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn txo_id_key(txo_id: &TransactionInput) -> Vec<u8> {
+    let mut key = txo_id.0.transaction_id.to_vec();
+    key.extend_from_slice(&txo_id.0.index.to_be_bytes());
+    key
+}
+
+fn parse_txo_id(key: &[u8]) -> Result<TransactionInput> {
+    let Some((tx_id, index)) = key.split_at_checked(32) else {
+        bail!("txo id too short");
+    };
+    Ok(TransactionInput::new(tx_id.into(), read_u64(index)?))
+}
+
+/// A slot followed by a txo id. Big-endian, so keys sort by slot.
+fn slot_txo_key(slot: u64, txo_id: &TransactionInput) -> Vec<u8> {
+    let mut key = slot.to_be_bytes().to_vec();
+    key.extend(txo_id_key(txo_id));
+    key
+}
+
+fn parse_slot_txo_key(key: &[u8]) -> Result<(u64, TransactionInput)> {
+    Ok((read_u64(key)?, parse_txo_id(&key[8..])?))
+}
+
+fn undo_key(slot: u64, name: &str, key: &[u8]) -> Vec<u8> {
+    let mut undo = slot.to_be_bytes().to_vec();
+    undo.push(name.len() as u8);
+    undo.extend_from_slice(name.as_bytes());
+    undo.extend_from_slice(key);
+    undo
+}
+
+fn parse_undo_key(undo: &[u8]) -> Result<(&str, &[u8])> {
+    let Some(&len) = undo.get(8) else {
+        bail!("undo key too short");
+    };
+    let Some((name, key)) = undo[9..].split_at_checked(len as usize) else {
+        bail!("undo key too short");
+    };
+    Ok((std::str::from_utf8(name)?, key))
+}
+
+#[derive(Encode, Decode)]
+struct TxoRecord {
+    #[n(0)]
+    txo_type: String,
+    #[cbor(n(1), with = "minicbor::bytes")]
+    created_block_hash: Option<Vec<u8>>,
+    #[n(2)]
+    era: u16,
+    #[cbor(n(3), with = "minicbor::bytes")]
+    txo: Vec<u8>,
+    #[n(4)]
+    spent: Option<SpentRecord>,
+}
+
+#[derive(Encode, Decode)]
+struct SpentRecord {
+    #[n(0)]
+    slot: u64,
+    #[n(1)]
+    height: u64,
+    #[cbor(n(2), with = "minicbor::bytes")]
+    tx_hash: Option<Vec<u8>>,
+    #[cbor(n(3), with = "minicbor::bytes")]
+    block_hash: Option<Vec<u8>>,
+}
+
+#[derive(Encode, Decode)]
+struct QuarantineRecord {
+    #[n(0)]
+    txo_type: String,
+    #[n(1)]
+    era: u16,
+    #[n(2)]
+    reason: String,
+    #[cbor(n(3), with = "minicbor::bytes")]
+    txo: Vec<u8>,
+}
+
+/// Amounts are kept as decimal strings, as in sqlite.
+#[derive(Encode, Decode)]
+struct ReserveRecord {
+    #[n(0)]
+    slot: u64,
+    #[n(1)]
+    reserve_a: String,
+    #[n(2)]
+    reserve_b: String,
+    #[n(3)]
+    circulating_lp: String,
+}
+
+#[derive(Encode, Decode)]
+struct PoolTxRecord {
+    #[n(0)]
+    slot: u64,
+    #[cbor(n(1), with = "minicbor::bytes")]
+    tx: Vec<u8>,
+}
+
+#[derive(Encode, Decode)]
+struct TradeRecord {
+    #[cbor(n(0), with = "minicbor::bytes")]
+    tx_hash: Vec<u8>,
+    #[cbor(n(1), with = "minicbor::bytes")]
+    order: Vec<u8>,
+    #[n(2)]
+    a_to_b: bool,
+    #[n(3)]
+    gives: String,
+    #[n(4)]
+    takes: String,
+}
+
+#[derive(Encode, Decode)]
+struct LatencyRecord {
+    #[cbor(n(0), with = "minicbor::bytes")]
+    ident: Vec<u8>,
+    #[n(1)]
+    created_slot: u64,
+    #[n(2)]
+    scooped_at: u64,
+}
+
+#[derive(Encode, Decode)]
+struct FlowRecord {
+    #[cbor(n(0), with = "minicbor::bytes")]
+    ident: Vec<u8>,
+    #[cbor(n(1), with = "minicbor::bytes")]
+    datum: Vec<u8>,
+    #[n(2)]
+    lovelace: u64,
+    /// Missing from records written before orders' sequence was kept
+    #[n(3)]
+    created_slot: Option<u64>,
+    #[n(4)]
+    position: Option<u32>,
+}
+
+#[derive(Encode, Decode)]
+struct DeepestRollbackRecord {
+    #[n(0)]
+    depth: u64,
+    #[n(1)]
+    from_slot: u64,
+    #[n(2)]
+    to_slot: u64,
+}
+
+#[derive(Encode, Decode)]
+struct ApiKeyRecord {
+    #[cbor(n(0), with = "minicbor::bytes")]
+    secret_hash: Vec<u8>,
+    #[n(1)]
+    pools: Vec<String>,
+    #[n(2)]
+    admin: bool,
+}
+
+impl TxoRecord {
+    fn into_persisted(self, created_slot: u64, txo_id: TransactionInput) -> PersistedTxo {
+        PersistedTxo {
+            txo_id,
+            txo_type: self.txo_type,
+            created_slot,
+            created_block_hash: self.created_block_hash,
+            era: self.era,
+            txo: self.txo,
+        }
+    }
+
+    fn into_exported(mut self, created_slot: u64, txo_id: TransactionInput) -> ExportedTxo {
+        let spent = self.spent.take();
+        ExportedTxo {
+            txo: self.into_persisted(created_slot, txo_id),
+            spent_slot: spent.as_ref().map(|s| s.slot),
+            spent_height: spent.as_ref().map(|s| s.height),
+            spent_tx_hash: spent.as_ref().and_then(|s| s.tx_hash.clone()),
+            spent_block_hash: spent.and_then(|s| s.block_hash),
+        }
+    }
+}
+
+pub struct RocksdbSundaeV3Dao {
+    db: Arc<DB>,
+}
+
+impl RocksdbSundaeV3Dao {
+    /// Where the next trade against a pool in this slot goes, after any already
+    /// written in the same slot or earlier in this batch.
+    fn next_trade_key(&self, prefix: &[u8], pending: &mut HashMap<Vec<u8>, u32>) -> Vec<u8> {
+        let position = match pending.get(prefix) {
+            Some(position) => *position,
+            None => scan_prefix(&self.db, TRADES, prefix, prefix).count() as u32,
+        };
+        pending.insert(prefix.to_vec(), position + 1);
+        let mut key = prefix.to_vec();
+        key.extend_from_slice(&position.to_be_bytes());
+        key
+    }
+}
+
+#[async_trait]
+impl SundaeV3Dao for RocksdbSundaeV3Dao {
+    async fn apply_tx_changes(&self, changes: SundaeV3TxChanges) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let slot = changes.slot.0;
+        let mut batch = WriteBatch::default();
+
+        for created_txo in changes.created_txos {
+            let record = TxoRecord {
+                txo_type: created_txo.txo_type,
+                created_block_hash: created_txo.created_block_hash,
+                era: created_txo.era,
+                txo: created_txo.txo,
+                spent: None,
+            };
+            batch.put_cf(
+                cf(&self.db, TXOS),
+                slot_txo_key(created_txo.created_slot, &created_txo.txo_id),
+                encode(&record)?,
+            );
+            batch.put_cf(
+                cf(&self.db, TXO_SLOTS),
+                txo_id_key(&created_txo.txo_id),
+                created_txo.created_slot.to_be_bytes(),
+            );
+        }
+
+        for quarantined_txo in changes.quarantined_txos {
+            let record = QuarantineRecord {
+                txo_type: quarantined_txo.txo_type,
+                era: quarantined_txo.era,
+                reason: quarantined_txo.reason,
+                txo: quarantined_txo.txo,
+            };
+            batch.put_cf(
+                cf(&self.db, QUARANTINE),
+                slot_txo_key(quarantined_txo.slot, &quarantined_txo.txo_id),
+                encode(&record)?,
+            );
+        }
+
+        for snapshot in changes.reserve_snapshots {
+            let mut key = snapshot.ident.to_bytes().to_vec();
+            key.extend_from_slice(&snapshot.epoch.to_be_bytes());
+            // Only the first state seen in each epoch is kept
+            if self.db.get_cf(cf(&self.db, RESERVES), &key)?.is_some() {
+                continue;
+            }
+            let record = ReserveRecord {
+                slot: snapshot.slot,
+                reserve_a: snapshot.reserve_a.to_string(),
+                reserve_b: snapshot.reserve_b.to_string(),
+                circulating_lp: snapshot.circulating_lp.to_string(),
+            };
+            batch.put_cf(cf(&self.db, RESERVES), &key, encode(&record)?);
+            batch.put_cf(
+                cf(&self.db, UNDO),
+                undo_key(snapshot.slot, RESERVES, &key),
+                [],
+            );
+        }
+
+        for pool_tx in changes.pool_txs {
+            let record = PoolTxRecord {
+                slot: pool_tx.slot,
+                tx: pool_tx.tx,
+            };
+            batch.put_cf(cf(&self.db, POOL_TXS), &pool_tx.tx_hash, encode(&record)?);
+            batch.put_cf(
+                cf(&self.db, UNDO),
+                undo_key(pool_tx.slot, POOL_TXS, &pool_tx.tx_hash),
+                [],
+            );
+        }
+
+        for record in changes.settings_history {
+            batch.put_cf(
+                cf(&self.db, DATUMS),
+                slot_txo_key(record.slot, &record.txo_id),
+                record.datum,
+            );
+        }
+
+        let mut pending_trades = HashMap::new();
+        for trade in changes.trades {
+            let mut prefix = trade.ident.to_bytes().to_vec();
+            prefix.extend_from_slice(&trade.slot.to_be_bytes());
+            let key = self.next_trade_key(&prefix, &mut pending_trades);
+            let record = TradeRecord {
+                tx_hash: trade.tx_hash,
+                order: txo_id_key(&trade.order),
+                a_to_b: trade.a_to_b,
+                gives: trade.gives.to_string(),
+                takes: trade.takes.to_string(),
+            };
+            batch.put_cf(cf(&self.db, TRADES), &key, encode(&record)?);
+            batch.put_cf(cf(&self.db, UNDO), undo_key(trade.slot, TRADES, &key), []);
+        }
+
+        for scoop in changes.scoops {
+            let mut key = scoop.ident.to_bytes().to_vec();
+            key.extend_from_slice(&scoop.slot.to_be_bytes());
+            key.extend_from_slice(&scoop.tx_hash);
+            let mut record = scoop.orders.to_be_bytes().to_vec();
+            if let Some(block) = &scoop.block {
+                record.extend_from_slice(&block.tx_index.to_be_bytes());
+                record.extend_from_slice(&block.hash);
+            }
+            batch.put_cf(cf(&self.db, SCOOPS), &key, record);
+            batch.put_cf(cf(&self.db, UNDO), undo_key(scoop.slot, SCOOPS, &key), []);
+        }
+
+        for latency in changes.order_latencies {
+            let record = LatencyRecord {
+                ident: latency.ident.to_bytes().to_vec(),
+                created_slot: latency.created_slot,
+                scooped_at: latency.scooped_at,
+            };
+            batch.put_cf(
+                cf(&self.db, LATENCIES),
+                slot_txo_key(latency.scooped_slot, &latency.order),
+                encode(&record)?,
+            );
+        }
+
+        for flow in changes.order_flow {
+            let record = FlowRecord {
+                ident: flow.ident.to_bytes().to_vec(),
+                datum: flow.datum,
+                lovelace: flow.lovelace,
+                created_slot: Some(flow.created_slot),
+                position: Some(flow.position),
+            };
+            batch.put_cf(
+                cf(&self.db, ORDER_FLOW),
+                slot_txo_key(flow.slot, &flow.order),
+                encode(&record)?,
+            );
+        }
+
+        for spent_txo in changes.spent_txos {
+            let id_key = txo_id_key(&spent_txo);
+            let Some(created_slot) = self.db.get_cf(cf(&self.db, TXO_SLOTS), &id_key)? else {
+                continue;
+            };
+            let created_slot = read_u64(&created_slot)?;
+            let key = slot_txo_key(created_slot, &spent_txo);
+            let Some(bytes) = self.db.get_cf(cf(&self.db, TXOS), &key)? else {
+                continue;
+            };
+            let mut record: TxoRecord = decode(&bytes)?;
+            record.spent = Some(SpentRecord {
+                slot,
+                height: changes.height.0,
+                tx_hash: changes.tx_hash.clone(),
+                block_hash: changes.block_hash.clone(),
+            });
+            batch.put_cf(cf(&self.db, TXOS), &key, encode(&record)?);
+            batch.put_cf(
+                cf(&self.db, SPENDS),
+                slot_txo_key(slot, &spent_txo),
+                created_slot.to_be_bytes(),
+            );
+        }
+
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    async fn rollback(&self, slot: Slot) -> Result<()> {
+        let from = (slot.0 + 1).to_be_bytes();
+        let to = u64::MAX.to_be_bytes();
+        let mut batch = WriteBatch::default();
+
+        for entry in scan(&self.db, SPENDS, &from, Direction::Forward) {
+            let (key, created_slot) = entry?;
+            let (_, txo_id) = parse_slot_txo_key(&key)?;
+            let txo_key = slot_txo_key(read_u64(&created_slot)?, &txo_id);
+            if let Some(bytes) = self.db.get_cf(cf(&self.db, TXOS), &txo_key)? {
+                let mut record: TxoRecord = decode(&bytes)?;
+                record.spent = None;
+                batch.put_cf(cf(&self.db, TXOS), &txo_key, encode(&record)?);
+            }
+        }
+
+        for entry in scan(&self.db, TXOS, &from, Direction::Forward) {
+            let (key, _) = entry?;
+            let (_, txo_id) = parse_slot_txo_key(&key)?;
+            batch.delete_cf(cf(&self.db, TXO_SLOTS), txo_id_key(&txo_id));
+        }
+
+        for entry in scan(&self.db, UNDO, &from, Direction::Forward) {
+            let (undo, _) = entry?;
+            let (name, key) = parse_undo_key(&undo)?;
+            batch.delete_cf(cf(&self.db, name), key);
+        }
+
+        for name in [
+            TXOS, SPENDS, QUARANTINE, DATUMS, LATENCIES, ORDER_FLOW, UNDO,
+        ] {
+            batch.delete_range_cf(cf(&self.db, name), from, to);
+        }
+
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    async fn load_txos(&self) -> Result<Vec<PersistedTxo>> {
+        let mut txos = vec![];
+        for entry in scan(&self.db, TXOS, &[], Direction::Forward) {
+            let (key, value) = entry?;
+            let record: TxoRecord = decode(&value)?;
+            if record.spent.is_some() {
+                continue;
+            }
+            let (created_slot, txo_id) = parse_slot_txo_key(&key)?;
+            txos.push(record.into_persisted(created_slot, txo_id));
+        }
+        Ok(txos)
+    }
+
+    async fn prune_txos(&self, min_height: BlockHeight) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        // Heights only grow with slots, so the first recent spend ends the scan
+        for entry in scan(&self.db, SPENDS, &[], Direction::Forward) {
+            let (key, created_slot) = entry?;
+            let (_, txo_id) = parse_slot_txo_key(&key)?;
+            let txo_key = slot_txo_key(read_u64(&created_slot)?, &txo_id);
+            if let Some(bytes) = self.db.get_cf(cf(&self.db, TXOS), &txo_key)? {
+                let record: TxoRecord = decode(&bytes)?;
+                if record
+                    .spent
+                    .is_some_and(|spent| spent.height >= min_height.0)
+                {
+                    break;
+                }
+                batch.delete_cf(cf(&self.db, TXOS), &txo_key);
+            }
+            batch.delete_cf(cf(&self.db, TXO_SLOTS), txo_id_key(&txo_id));
+            batch.delete_cf(cf(&self.db, SPENDS), &key);
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    async fn load_quarantined_txos(&self, limit: u32) -> Result<Vec<QuarantinedTxo>> {
+        let mut txos = vec![];
+        let end = u64::MAX.to_be_bytes();
+        for entry in scan(&self.db, QUARANTINE, &end, Direction::Reverse).take(limit as usize) {
+            let (key, value) = entry?;
+            let (slot, txo_id) = parse_slot_txo_key(&key)?;
+            let record: QuarantineRecord = decode(&value)?;
+            txos.push(QuarantinedTxo {
+                txo_id,
+                txo_type: record.txo_type,
+                slot,
+                era: record.era,
+                reason: record.reason,
+                txo: record.txo,
+            });
+        }
+        Ok(txos)
+    }
+
+    async fn latest_slot(&self) -> Result<Option<Slot>> {
+        let end = u64::MAX.to_be_bytes();
+        let mut latest = None;
+        for name in [TXOS, SPENDS, QUARANTINE] {
+            if let Some(entry) = scan(&self.db, name, &end, Direction::Reverse).next() {
+                let slot = read_u64(&entry?.0)?;
+                latest = latest.max(Some(Slot(slot)));
+            }
+        }
+        Ok(latest)
+    }
+
+    async fn load_reserve_history(&self, ident: &Ident) -> Result<Vec<PoolReserveSnapshot>> {
+        let mut snapshots = vec![];
+        let prefix = ident.to_bytes();
+        for entry in scan_prefix(&self.db, RESERVES, prefix, prefix) {
+            let (key, value) = entry?;
+            snapshots.push(reserve_snapshot(&key, &value)?);
+        }
+        Ok(snapshots)
+    }
+
+    async fn export_txos(
+        &self,
+        txo_type: &str,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<ExportedTxo>> {
+        let from = from_slot.unwrap_or(0).to_be_bytes();
+        let to_slot = to_slot.unwrap_or(u64::MAX);
+        let mut txos = vec![];
+        for entry in scan(&self.db, TXOS, &from, Direction::Forward) {
+            let (key, value) = entry?;
+            let (created_slot, txo_id) = parse_slot_txo_key(&key)?;
+            if created_slot > to_slot {
+                break;
+            }
+            let record: TxoRecord = decode(&value)?;
+            if record.txo_type != txo_type {
+                continue;
+            }
+            txos.push(record.into_exported(created_slot, txo_id));
+        }
+        Ok(txos)
+    }
+
+    async fn load_spent_txos(&self, txo_type: &str, from_slot: u64) -> Result<Vec<ExportedTxo>> {
+        let from = from_slot.to_be_bytes();
+        let mut txos = vec![];
+        for entry in scan(&self.db, SPENDS, &from, Direction::Forward) {
+            let (key, created_slot) = entry?;
+            let (_, txo_id) = parse_slot_txo_key(&key)?;
+            let created_slot = read_u64(&created_slot)?;
+            let txo_key = slot_txo_key(created_slot, &txo_id);
+            let Some(bytes) = self.db.get_cf(cf(&self.db, TXOS), &txo_key)? else {
+                continue;
+            };
+            let record: TxoRecord = decode(&bytes)?;
+            if record.txo_type != txo_type {
+                continue;
+            }
+            txos.push(record.into_exported(created_slot, txo_id));
+        }
+        Ok(txos)
+    }
+
+    async fn load_pool_tx(&self, tx_hash: &[u8]) -> Result<Option<PoolTx>> {
+        let Some(bytes) = self.db.get_cf(cf(&self.db, POOL_TXS), tx_hash)? else {
+            return Ok(None);
+        };
+        let record: PoolTxRecord = decode(&bytes)?;
+        Ok(Some(PoolTx {
+            tx_hash: tx_hash.to_vec(),
+            slot: record.slot,
+            tx: record.tx,
+        }))
+    }
+
+    async fn load_settings_history(&self, limit: u32) -> Result<Vec<SettingsRecord>> {
+        let mut records = vec![];
+        let end = u64::MAX.to_be_bytes();
+        for entry in scan(&self.db, DATUMS, &end, Direction::Reverse).take(limit as usize) {
+            let (key, datum) = entry?;
+            let (slot, txo_id) = parse_slot_txo_key(&key)?;
+            records.push(SettingsRecord {
+                txo_id,
+                slot,
+                datum: datum.into_vec(),
+            });
+        }
+        Ok(records)
+    }
+
+    async fn load_trades(&self, ident: &Ident, from_slot: u64, limit: u32) -> Result<Vec<Trade>> {
+        let prefix = ident.to_bytes();
+        let mut from = prefix.to_vec();
+        from.extend_from_slice(&from_slot.to_be_bytes());
+        let mut trades = vec![];
+        for entry in scan_prefix(&self.db, TRADES, prefix, &from).take(limit as usize) {
+            let (key, value) = entry?;
+            let record: TradeRecord = decode(&value)?;
+            trades.push(Trade {
+                ident: ident.clone(),
+                slot: read_u64(&key[prefix.len()..])?,
+                tx_hash: record.tx_hash,
+                order: parse_txo_id(&record.order)?,
+                a_to_b: record.a_to_b,
+                gives: record.gives.parse()?,
+                takes: record.takes.parse()?,
+            });
+        }
+        Ok(trades)
+    }
+
+    async fn load_scoops(&self, ident: &Ident, limit: u32) -> Result<Vec<Scoop>> {
+        let prefix = ident.to_bytes();
+        let mut from = prefix.to_vec();
+        from.extend_from_slice(&[0xff; 8 + 32 + 1]);
+        let mut scoops = vec![];
+        for entry in scan(&self.db, SCOOPS, &from, Direction::Reverse)
+            .take_while(|entry| match entry {
+                Ok((key, _)) => key.starts_with(prefix),
+                Err(_) => true,
+            })
+            .take(limit as usize)
+        {
+            let (key, record) = entry?;
+            let rest = &key[prefix.len()..];
+            // The orders, then the block if it was recorded
+            let orders = read_u32(&record).map_err(|_| anyhow!("scoop record too short"))?;
+            let block = match record.get(4..) {
+                Some(block) if !block.is_empty() => Some(ScoopBlock {
+                    tx_index: read_u32(block)?,
+                    hash: block[4..].to_vec(),
+                }),
+                _ => None,
+            };
+            scoops.push(Scoop {
+                ident: ident.clone(),
+                slot: read_u64(rest)?,
+                tx_hash: rest[8..].to_vec(),
+                orders,
+                block,
+            });
+        }
+        Ok(scoops)
+    }
+
+    async fn load_order_latencies(&self, from_slot: u64) -> Result<Vec<OrderLatency>> {
+        let mut latencies = vec![];
+        for entry in scan(
+            &self.db,
+            LATENCIES,
+            &from_slot.to_be_bytes(),
+            Direction::Forward,
+        ) {
+            let (key, value) = entry?;
+            let (scooped_slot, order) = parse_slot_txo_key(&key)?;
+            let record: LatencyRecord = decode(&value)?;
+            latencies.push(OrderLatency {
+                ident: Ident::new(&record.ident),
+                order,
+                created_slot: record.created_slot,
+                scooped_slot,
+                scooped_at: record.scooped_at,
+            });
+        }
+        Ok(latencies)
+    }
+
+    async fn load_order_flow(&self, from_slot: u64) -> Result<Vec<OrderFlow>> {
+        let mut flow = vec![];
+        for entry in scan(
+            &self.db,
+            ORDER_FLOW,
+            &from_slot.to_be_bytes(),
+            Direction::Forward,
+        ) {
+            let (key, value) = entry?;
+            let (slot, order) = parse_slot_txo_key(&key)?;
+            let record: FlowRecord = decode(&value)?;
+            flow.push(OrderFlow {
+                ident: Ident::new(&record.ident),
+                order,
+                slot,
+                created_slot: record.created_slot.unwrap_or(0),
+                position: record.position.unwrap_or(0),
+                datum: record.datum,
+                lovelace: record.lovelace,
+            });
+        }
+        Ok(flow)
+    }
+
+    async fn export_reserve_snapshots(
+        &self,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<PoolReserveSnapshot>> {
+        let from_slot = from_slot.unwrap_or(0);
+        let to_slot = to_slot.unwrap_or(u64::MAX);
+        // Snapshots are few, one per pool per epoch, so a full scan is fine
+        let mut snapshots = vec![];
+        for entry in scan(&self.db, RESERVES, &[], Direction::Forward) {
+            let (key, value) = entry?;
+            let snapshot = reserve_snapshot(&key, &value)?;
+            if (from_slot..=to_slot).contains(&snapshot.slot) {
+                snapshots.push(snapshot);
+            }
+        }
+        snapshots.sort_by(|a, b| (a.slot, &a.ident).cmp(&(b.slot, &b.ident)));
+        Ok(snapshots)
+    }
+
+    async fn record_rollback(&self, rollback: &RollbackRecord) -> Result<()> {
+        if let Some(deepest) = self.load_deepest_rollback().await?
+            && deepest.depth >= rollback.depth
+        {
+            return Ok(());
+        }
+        let record = DeepestRollbackRecord {
+            depth: rollback.depth,
+            from_slot: rollback.from_slot,
+            to_slot: rollback.to_slot,
+        };
+        self.db.put(DEEPEST_ROLLBACK_KEY, encode(&record)?)?;
+        Ok(())
+    }
+
+    async fn load_deepest_rollback(&self) -> Result<Option<RollbackRecord>> {
+        let Some(bytes) = self.db.get(DEEPEST_ROLLBACK_KEY)? else {
+            return Ok(None);
+        };
+        let record: DeepestRollbackRecord = decode(&bytes)?;
+        Ok(Some(RollbackRecord {
+            depth: record.depth,
+            from_slot: record.from_slot,
+            to_slot: record.to_slot,
+        }))
+    }
+}
+
+fn reserve_snapshot(key: &[u8], value: &[u8]) -> Result<PoolReserveSnapshot> {
+    let Some((ident, epoch)) = key.split_at_checked(key.len().saturating_sub(8)) else {
+        bail!("reserve key too short");
+    };
+    let record: ReserveRecord = decode(value)?;
+    Ok(PoolReserveSnapshot {
+        ident: Ident::new(ident),
+        epoch: read_u64(epoch)?,
+        slot: record.slot,
+        reserve_a: record.reserve_a.parse()?,
+        reserve_b: record.reserve_b.parse()?,
+        circulating_lp: record.circulating_lp.parse()?,
+    })
+}
+
+pub struct RocksdbApiKeyDao {
+    db: Arc<DB>,
+}
+
+impl RocksdbApiKeyDao {
+    fn api_key(name: &[u8], record: ApiKeyRecord) -> Result<ApiKey> {
+        let pools = record
+            .pools
+            .iter()
+            .map(|p| hex::decode(p).map(|bytes| Ident::new(&bytes)))
+            .collect::<Result<_, _>>()?;
+        Ok(ApiKey {
+            name: String::from_utf8(name.to_vec())?,
+            pools,
+            admin: record.admin,
+        })
+    }
+}
+
+/// Keys are few, so lookups by secret scan them all.
+#[async_trait]
+impl ApiKeyDao for RocksdbApiKeyDao {
+    async fn save_api_key(&self, key: &ApiKey, secret_hash: &[u8]) -> Result<()> {
+        let record = ApiKeyRecord {
+            secret_hash: secret_hash.to_vec(),
+            pools: key.pools.iter().map(Ident::to_string).collect(),
+            admin: key.admin,
+        };
+        self.db.put_cf(
+            cf(&self.db, API_KEYS),
+            key.name.as_bytes(),
+            encode(&record)?,
+        )?;
+        Ok(())
+    }
+
+    async fn find_api_key(&self, secret_hash: &[u8]) -> Result<Option<ApiKey>> {
+        for entry in scan(&self.db, API_KEYS, &[], Direction::Forward) {
+            let (name, value) = entry?;
+            let record: ApiKeyRecord = decode(&value)?;
+            if record.secret_hash == secret_hash {
+                return Ok(Some(Self::api_key(&name, record)?));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let mut keys = vec![];
+        for entry in scan(&self.db, API_KEYS, &[], Direction::Forward) {
+            let (name, value) = entry?;
+            keys.push(Self::api_key(&name, decode(&value)?)?);
+        }
+        Ok(keys)
+    }
+
+    async fn delete_api_key(&self, name: &str) -> Result<bool> {
+        let api_keys = cf(&self.db, API_KEYS);
+        if self.db.get_cf(api_keys, name.as_bytes())?.is_none() {
+            return Ok(false);
+        }
+        self.db.delete_cf(api_keys, name.as_bytes())?;
+        Ok(true)
+    }
+}
+
+#[derive(Encode, Decode)]
+struct ExtensionRecord {
+    /// Missing where the key was deleted
+    #[cbor(n(0), with = "minicbor::bytes")]
+    value: Option<Vec<u8>>,
+}
+
+pub struct RocksdbExtensionDao {
+    db: Arc<DB>,
+    /// The namespace, after its length
+    prefix: Vec<u8>,
+}
+
+impl RocksdbExtensionDao {
+    /// Every version of a key shares this, so they sort together by slot.
+    fn key_prefix(&self, key: &[u8]) -> Vec<u8> {
+        let mut prefixed = self.prefix.clone();
+        prefixed.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        prefixed.extend_from_slice(key);
+        prefixed
+    }
+
+    /// Splits a stored key into the extension's key and the slot it was written at.
+    fn parse_key<'a>(&self, stored: &'a [u8]) -> Result<(&'a [u8], u64)> {
+        let rest = &stored[self.prefix.len()..];
+        let len = read_u32(rest)? as usize;
+        let Some((key, slot)) = rest[4..].split_at_checked(len) else {
+            bail!("extension key too short");
+        };
+        Ok((key, read_u64(slot)?))
+    }
+
+    /// The latest version of each key, in key order.
+    fn latest(&self) -> Result<Vec<(Vec<u8>, Option<Vec<u8>>)>> {
+        let mut latest: Vec<(Vec<u8>, Option<Vec<u8>>)> = vec![];
+        for entry in scan_prefix(&self.db, EXTENSIONS, &self.prefix, &self.prefix) {
+            let (stored, bytes) = entry?;
+            let (key, _) = self.parse_key(&stored)?;
+            let record: ExtensionRecord = decode(&bytes)?;
+            match latest.last_mut() {
+                Some((last, value)) if last == key => *value = record.value,
+                _ => latest.push((key.to_vec(), record.value)),
+            }
+        }
+        Ok(latest)
+    }
+}
+
+/// Versions are ordered by slot within each key, so the last one is current.
+#[async_trait]
+impl ExtensionDao for RocksdbExtensionDao {
+    async fn put(&self, slot: Slot, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        let mut stored = self.key_prefix(key);
+        stored.extend_from_slice(&slot.0.to_be_bytes());
+        let record = ExtensionRecord {
+            value: value.map(<[u8]>::to_vec),
+        };
+        self.db
+            .put_cf(cf(&self.db, EXTENSIONS), stored, encode(&record)?)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let prefix = self.key_prefix(key);
+        let mut value = None;
+        for entry in scan_prefix(&self.db, EXTENSIONS, &prefix, &prefix) {
+            let (_, bytes) = entry?;
+            value = decode::<ExtensionRecord>(&bytes)?.value;
+        }
+        Ok(value)
+    }
+
+    async fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .latest()?
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect())
+    }
+
+    async fn rollback(&self, slot: Slot) -> Result<()> {
+        let extensions = cf(&self.db, EXTENSIONS);
+        let mut batch = WriteBatch::default();
+        for entry in scan_prefix(&self.db, EXTENSIONS, &self.prefix, &self.prefix) {
+            let (stored, _) = entry?;
+            if self.parse_key(&stored)?.1 > slot.0 {
+                batch.delete_cf(extensions, stored);
+            }
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+}
+
+struct RocksdbCursorDaoImpl {
+    db: Arc<DB>,
+}
+
+#[async_trait]
+impl CursorDaoImpl for RocksdbCursorDaoImpl {
+    async fn load(&self) -> Result<HashMap<String, CursorEntry>> {
+        let mut result = HashMap::new();
+        for entry in scan(&self.db, CURSORS, &[], Direction::Forward) {
+            let (id, bytes) = entry?;
+            let cursor = serde_json::from_slice(&bytes)?;
+            result.insert(String::from_utf8(id.into_vec())?, cursor);
+        }
+        Ok(result)
+    }
+
+    async fn save(
+        &self,
+        changed: &HashMap<String, Vec<u8>>,
+        removed: &[String],
+    ) -> Result<(), CursorSaveError> {
+        let cursors = cf(&self.db, CURSORS);
+        let mut batch = WriteBatch::default();
+        for (id, bytes) in changed {
+            batch.put_cf(cursors, id.as_bytes(), bytes);
+        }
+        for id in removed {
+            batch.delete_cf(cursors, id.as_bytes());
+        }
+        self.db.write(batch).map_err(|err| {
+            warn!("could not write cursors: {err:#}");
+            let failed = changed.keys().chain(removed).cloned().collect();
+            CursorSaveError { failed }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bigint::BigInt;
+
+    fn new_db(name: &str) -> Result<RocksdbPersistence> {
+        let path = std::env::temp_dir().join(format!("scooper-rocksdb-{name}"));
+        let _ = std::fs::remove_dir_all(&path);
+        RocksdbPersistence::new(&RocksdbConfig { path })
+    }
+
+    fn txo(slot: u64, index: u64) -> PersistedTxo {
+        PersistedTxo {
+            txo_id: TransactionInput::new([slot as u8; 32].into(), index),
+            txo_type: "order".to_string(),
+            created_slot: slot,
+            created_block_hash: Some(vec![slot as u8; 32]),
+            era: 6,
+            txo: vec![1, 2, 3],
+        }
+    }
+
+    fn trade(slot: u64, index: u64) -> Trade {
+        Trade {
+            ident: Ident::new(&[7; 28]),
+            slot,
+            tx_hash: vec![slot as u8; 32],
+            order: TransactionInput::new([slot as u8; 32].into(), index),
+            a_to_b: true,
+            gives: BigInt::from(100),
+            takes: BigInt::from(90),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_spend_roll_back_and_prune_txos() -> Result<()> {
+        let persistence = new_db("txos")?;
+        let dao = persistence.sundae_v3_dao();
+
+        let mut changes = SundaeV3TxChanges::new(Slot(10), BlockHeight(1));
+        changes.created_txos = vec![txo(10, 0), txo(10, 1)];
+        dao.apply_tx_changes(changes).await?;
+
+        let mut changes = SundaeV3TxChanges::new(Slot(20), BlockHeight(2));
+        changes.tx_hash = Some(vec![9; 32]);
+        changes.created_txos = vec![txo(20, 0)];
+        changes.spent_txos = vec![txo(10, 0).txo_id];
+        dao.apply_tx_changes(changes).await?;
+
+        assert_eq!(dao.load_txos().await?, vec![txo(10, 1), txo(20, 0)]);
+        assert_eq!(dao.latest_slot().await?, Some(Slot(20)));
+        let exported = dao.export_txos("order", None, Some(10)).await?;
+        assert_eq!(exported.len(), 2);
+        assert_eq!(exported[0].spent_slot, Some(20));
+        assert_eq!(exported[0].spent_tx_hash, Some(vec![9; 32]));
+        let spent = dao.load_spent_txos("order", 20).await?;
+        assert_eq!(spent, vec![exported[0].clone()]);
+        assert!(dao.load_spent_txos("order", 21).await?.is_empty());
+
+        dao.rollback(Slot(15)).await?;
+        assert_eq!(dao.load_txos().await?, vec![txo(10, 0), txo(10, 1)]);
+        assert_eq!(dao.latest_slot().await?, Some(Slot(10)));
+
+        let mut changes = SundaeV3TxChanges::new(Slot(30), BlockHeight(3));
+        changes.spent_txos = vec![txo(10, 0).txo_id];
+        dao.apply_tx_changes(changes).await?;
+        dao.prune_txos(BlockHeight(4)).await?;
+        let exported = dao.export_txos("order", None, None).await?;
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].txo, txo(10, 1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_page_trades_in_order_until_rolled_back() -> Result<()> {
+        let persistence = new_db("trades")?;
+        let dao = persistence.sundae_v3_dao();
+        let ident = Ident::new(&[7; 28]);
+
+        let mut changes = SundaeV3TxChanges::new(Slot(10), BlockHeight(1));
+        changes.trades = vec![trade(10, 2), trade(10, 1)];
+        dao.apply_tx_changes(changes).await?;
+        let mut changes = SundaeV3TxChanges::new(Slot(10), BlockHeight(1));
+        changes.trades = vec![trade(10, 0)];
+        dao.apply_tx_changes(changes).await?;
+        let mut changes = SundaeV3TxChanges::new(Slot(20), BlockHeight(2));
+        changes.trades = vec![trade(20, 0)];
+        dao.apply_tx_changes(changes).await?;
+
+        assert_eq!(
+            dao.load_trades(&ident, 0, 10).await?,
+            vec![trade(10, 2), trade(10, 1), trade(10, 0), trade(20, 0)]
+        );
+        assert_eq!(dao.load_trades(&ident, 11, 10).await?, vec![trade(20, 0)]);
+        assert!(
+            dao.load_trades(&Ident::new(&[8; 28]), 0, 10)
+                .await?
+                .is_empty()
+        );
+
+        dao.rollback(Slot(15)).await?;
+        assert_eq!(dao.load_trades(&ident, 0, 1).await?, vec![trade(10, 2)]);
+        assert!(dao.load_trades(&ident, 11, 10).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_save_find_and_delete_api_keys() -> Result<()> {
+        let persistence = new_db("api-keys")?;
+        let dao = persistence.api_key_dao();
+        let key = ApiKey {
+            name: "reader".to_string(),
+            pools: vec![Ident::new(&[7; 28])],
+            admin: false,
+        };
+        dao.save_api_key(&key, b"hash").await?;
+        assert_eq!(dao.find_api_key(b"hash").await?, Some(key.clone()));
+        assert_eq!(dao.list_api_keys().await?, vec![key]);
+        assert!(dao.delete_api_key("reader").await?);
+        assert!(!dao.delete_api_key("reader").await?);
+        assert_eq!(dao.find_api_key(b"hash").await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_keep_extension_records_apart_and_roll_them_back() -> Result<()> {
+        let persistence = new_db("extensions")?;
+        let partner = persistence.extension_dao("partner");
+        let other = persistence.extension_dao("partner-2");
+
+        partner.put(Slot(10), b"a", Some(b"1")).await?;
+        partner.put(Slot(10), b"ab", Some(b"2")).await?;
+        partner.put(Slot(20), b"a", Some(b"3")).await?;
+        partner.put(Slot(20), b"ab", None).await?;
+        other.put(Slot(30), b"a", Some(b"4")).await?;
+
+        assert_eq!(partner.get(b"a").await?, Some(b"3".to_vec()));
+        assert_eq!(partner.get(b"ab").await?, None);
+        assert_eq!(
+            partner.entries().await?,
+            vec![(b"a".to_vec(), b"3".to_vec())]
+        );
+
+        partner.rollback(Slot(15)).await?;
+        assert_eq!(
+            partner.entries().await?,
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"ab".to_vec(), b"2".to_vec())
+            ]
+        );
+        assert_eq!(other.entries().await?, vec![(b"a".to_vec(), b"4".to_vec())]);
+        Ok(())
+    }
+}
@@ -1,20 +1,63 @@
 use std::{collections::HashMap, path::PathBuf};
 
 use acropolis_module_custom_indexer::cursor_store::{CursorEntry, CursorSaveError};
-use anyhow::Result;
+use anyhow::{Result, bail};
 use async_trait::async_trait;
 use serde::Deserialize;
 use sqlx::{
-    FromRow, Pool, Row, Sqlite,
-    sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow},
+    Decode, Encode, FromRow, Pool, Row, Sqlite, Type,
+    encode::IsNull,
+    error::BoxDynError,
+    migrate::Migrator,
+    sqlite::{
+        SqliteArgumentValue, SqliteConnectOptions, SqlitePoolOptions, SqliteRow, SqliteTypeInfo,
+        SqliteValueRef,
+    },
 };
 use tracing::warn;
 
 use crate::{
-    cardano_types::TransactionInput,
-    persistence::{CursorDaoImpl, PersistedTxo, Persistence, SundaeV3Dao, SundaeV3TxChanges},
+    bigint::BigInt,
+    cardano_types::{BlockHeight, Slot, TransactionInput},
+    persistence::{
+        ApiKey, ApiKeyDao, CursorDaoImpl, ExportedTxo, ExtensionDao, MigrationStatus, OrderFlow,
+        OrderLatency, PersistedTxo, Persistence, PoolReserveSnapshot, PoolTx, QuarantinedTxo,
+        RollbackRecord, SchemaStatus, Scoop, ScoopBlock, SettingsRecord, SundaeV3Dao,
+        SundaeV3TxChanges, Trade,
+    },
+    sundaev3::Ident,
 };
 
+/// Slots and heights are stored as sqlite integers, which are signed.
+macro_rules! sqlite_u64 {
+    ($ty:ident) => {
+        impl Type<Sqlite> for $ty {
+            fn type_info() -> SqliteTypeInfo {
+                <i64 as Type<Sqlite>>::type_info()
+            }
+        }
+
+        impl<'q> Encode<'q, Sqlite> for $ty {
+            fn encode_by_ref(
+                &self,
+                buf: &mut Vec<SqliteArgumentValue<'q>>,
+            ) -> Result<IsNull, BoxDynError> {
+                <i64 as Encode<'q, Sqlite>>::encode(i64::try_from(self.0)?, buf)
+            }
+        }
+
+        impl<'r> Decode<'r, Sqlite> for $ty {
+            fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+                let value = <i64 as Decode<'r, Sqlite>>::decode(value)?;
+                Ok(Self(u64::try_from(value)?))
+            }
+        }
+    };
+}
+
+sqlite_u64!(Slot);
+sqlite_u64!(BlockHeight);
+
 #[derive(Debug, Deserialize, Default)]
 #[serde(rename_all = "kebab-case")]
 pub struct SqliteConfig {
@@ -40,6 +83,8 @@ impl SqliteConfig {
     }
 }
 
+static MIGRATOR: Migrator = sqlx::migrate!("db/migrations/sqlite");
+
 pub struct SqlitePersistence {
     pool: Pool<Sqlite>,
 }
@@ -48,11 +93,68 @@ impl SqlitePersistence {
     pub async fn new(config: &SqliteConfig) -> Result<Self> {
         let (pool_opts, conn_opts) = config.to_options();
         let pool = pool_opts.connect_with(conn_opts).await?;
-        sqlx::migrate!("db/migrations/sqlite").run(&pool).await?;
+        check_schema(&pool).await?;
+        MIGRATOR.run(&pool).await?;
         Ok(Self { pool })
     }
 }
 
+pub async fn schema_status(config: &SqliteConfig) -> Result<SchemaStatus> {
+    let (pool_opts, conn_opts) = config.to_options();
+    let pool = pool_opts.connect_with(conn_opts).await?;
+    load_schema_status(&pool).await
+}
+
+/// sqlx records every applied migration in its own table, which doubles as
+/// our schema version.
+async fn load_schema_status(pool: &Pool<Sqlite>) -> Result<SchemaStatus> {
+    let migrations_table: Option<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations';",
+    )
+    .fetch_optional(pool)
+    .await?;
+    let applied: Vec<i64> = if migrations_table.is_some() {
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version;")
+            .fetch_all(pool)
+            .await?
+    } else {
+        vec![]
+    };
+
+    let known = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration());
+    let migrations = known
+        .clone()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            description: m.description.to_string(),
+            applied: applied.contains(&m.version),
+        })
+        .collect();
+    let unknown_versions = applied
+        .into_iter()
+        .filter(|version| !known.clone().any(|m| m.version == *version))
+        .collect();
+    Ok(SchemaStatus {
+        migrations,
+        unknown_versions,
+    })
+}
+
+/// Refuses to touch a database migrated by a newer build, since we would
+/// misread whatever it changed.
+async fn check_schema(pool: &Pool<Sqlite>) -> Result<()> {
+    let status = load_schema_status(pool).await?;
+    if let Some(newest) = status.unknown_versions.last() {
+        let supported = status.migrations.last().map_or(0, |m| m.version);
+        bail!(
+            "database schema is at version {newest}, but this build only supports up to version {supported}. Run a newer scooper, or restore a backup from before the upgrade."
+        );
+    }
+    Ok(())
+}
+
 impl Persistence for SqlitePersistence {
     fn sundae_v3_dao(&self) -> Box<dyn super::SundaeV3Dao> {
         Box::new(SqliteSundaeV3Dao {
@@ -61,10 +163,23 @@ impl Persistence for SqlitePersistence {
     }
 
     fn cursor_store(&self) -> super::CursorDao {
-        super::CursorDao(Box::new(SqliteCursorDaoImpl {
+        super::CursorDao::new(Box::new(SqliteCursorDaoImpl {
             pool: self.pool.clone(),
         }))
     }
+
+    fn api_key_dao(&self) -> Box<dyn super::ApiKeyDao> {
+        Box::new(SqliteApiKeyDao {
+            pool: self.pool.clone(),
+        })
+    }
+
+    fn extension_dao(&self, namespace: &str) -> Box<dyn ExtensionDao> {
+        Box::new(SqliteExtensionDao {
+            pool: self.pool.clone(),
+            namespace: namespace.to_string(),
+        })
+    }
 }
 
 pub struct SqliteSundaeV3Dao {
@@ -82,10 +197,9 @@ impl SundaeV3Dao for SqliteSundaeV3Dao {
 
         if !changes.created_txos.is_empty() {
             let insert_created_txo_query = {
-                let column_names =
-                    "tx_id, txo_index, txo_type, created_slot, spent_slot, spent_height, era, txo";
+                let column_names = "tx_id, txo_index, txo_type, created_slot, created_block_hash, spent_slot, spent_height, era, txo";
                 let values_clauses =
-                    vec!["(?,?,?,?,NULL,NULL,?,?)".to_string(); changes.created_txos.len()]
+                    vec!["(?,?,?,?,?,NULL,NULL,?,?)".to_string(); changes.created_txos.len()]
                         .join(",");
                 format!("INSERT INTO sundae_v3_txos ({column_names}) VALUES {values_clauses};")
             };
@@ -97,6 +211,7 @@ impl SundaeV3Dao for SqliteSundaeV3Dao {
                     .bind(created_txo.txo_id.0.index as i64)
                     .bind(created_txo.txo_type)
                     .bind(created_txo.created_slot as i64)
+                    .bind(created_txo.created_block_hash)
                     .bind(created_txo.era)
                     .bind(created_txo.txo);
             }
@@ -104,44 +219,228 @@ impl SundaeV3Dao for SqliteSundaeV3Dao {
             query.execute(&mut *tx).await?;
         }
 
-        for spent_txo in changes.spent_txos {
+        if !changes.quarantined_txos.is_empty() {
+            let insert_quarantined_txo_query = {
+                let column_names = "tx_id, txo_index, txo_type, slot, era, reason, txo";
+                let values_clauses =
+                    vec!["(?,?,?,?,?,?,?)".to_string(); changes.quarantined_txos.len()].join(",");
+                format!(
+                    "INSERT OR REPLACE INTO sundae_v3_quarantined_txos ({column_names}) VALUES {values_clauses};"
+                )
+            };
+            let mut query = sqlx::query(&insert_quarantined_txo_query);
+
+            for quarantined_txo in changes.quarantined_txos {
+                query = query
+                    .bind(quarantined_txo.txo_id.0.transaction_id.to_vec())
+                    .bind(quarantined_txo.txo_id.0.index as i64)
+                    .bind(quarantined_txo.txo_type)
+                    .bind(quarantined_txo.slot as i64)
+                    .bind(quarantined_txo.era)
+                    .bind(quarantined_txo.reason)
+                    .bind(quarantined_txo.txo);
+            }
+
+            query.execute(&mut *tx).await?;
+        }
+
+        if !changes.reserve_snapshots.is_empty() {
+            let insert_reserve_snapshot_query = {
+                let column_names = "ident, epoch, slot, reserve_a, reserve_b, circulating_lp";
+                let values_clauses =
+                    vec!["(?,?,?,?,?,?)".to_string(); changes.reserve_snapshots.len()].join(",");
+                // Only the first state seen in each epoch is kept
+                format!(
+                    "INSERT OR IGNORE INTO sundae_v3_pool_reserves ({column_names}) VALUES {values_clauses};"
+                )
+            };
+            let mut query = sqlx::query(&insert_reserve_snapshot_query);
+
+            for snapshot in changes.reserve_snapshots {
+                query = query
+                    .bind(snapshot.ident.to_bytes().to_vec())
+                    .bind(snapshot.epoch as i64)
+                    .bind(snapshot.slot as i64)
+                    .bind(snapshot.reserve_a.to_string())
+                    .bind(snapshot.reserve_b.to_string())
+                    .bind(snapshot.circulating_lp.to_string());
+            }
+
+            query.execute(&mut *tx).await?;
+        }
+
+        for pool_tx in changes.pool_txs {
+            sqlx::query(
+                "INSERT OR REPLACE INTO sundae_v3_pool_txs (tx_hash, slot, tx) VALUES (?,?,?);",
+            )
+            .bind(pool_tx.tx_hash)
+            .bind(pool_tx.slot as i64)
+            .bind(pool_tx.tx)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for record in changes.settings_history {
+            sqlx::query(
+                "INSERT OR REPLACE INTO sundae_v3_settings_history (tx_id, txo_index, slot, datum) VALUES (?,?,?,?);",
+            )
+            .bind(record.txo_id.0.transaction_id.to_vec())
+            .bind(record.txo_id.0.index as i64)
+            .bind(record.slot as i64)
+            .bind(record.datum)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for trade in changes.trades {
+            sqlx::query(
+                "INSERT OR REPLACE INTO sundae_v3_trades (order_tx_id, order_txo_index, ident, slot, tx_hash, a_to_b, gives, takes) VALUES (?,?,?,?,?,?,?,?);",
+            )
+            .bind(trade.order.0.transaction_id.to_vec())
+            .bind(trade.order.0.index as i64)
+            .bind(trade.ident.to_bytes().to_vec())
+            .bind(trade.slot as i64)
+            .bind(trade.tx_hash)
+            .bind(trade.a_to_b)
+            .bind(trade.gives.to_string())
+            .bind(trade.takes.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for scoop in changes.scoops {
+            sqlx::query(
+                "INSERT OR REPLACE INTO sundae_v3_scoops (tx_hash, ident, slot, orders, block_hash, tx_index) VALUES (?,?,?,?,?,?);",
+            )
+            .bind(scoop.tx_hash)
+            .bind(scoop.ident.to_bytes().to_vec())
+            .bind(scoop.slot as i64)
+            .bind(scoop.orders as i64)
+            .bind(scoop.block.as_ref().map(|block| block.hash.clone()))
+            .bind(scoop.block.as_ref().map(|block| block.tx_index as i64))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for latency in changes.order_latencies {
             sqlx::query(
-                "UPDATE sundae_v3_txos SET spent_slot = ?, spent_height = ? WHERE tx_id = ? AND txo_index = ?;",
+                "INSERT OR REPLACE INTO sundae_v3_order_latencies (order_tx_id, order_txo_index, ident, created_slot, scooped_slot, scooped_at) VALUES (?,?,?,?,?,?);",
             )
-            .bind(changes.slot as i64)
-            .bind(changes.height as i64)
-            .bind(spent_txo.0.transaction_id.to_vec())
-            .bind(spent_txo.0.index as i64)
+            .bind(latency.order.0.transaction_id.to_vec())
+            .bind(latency.order.0.index as i64)
+            .bind(latency.ident.to_bytes().to_vec())
+            .bind(latency.created_slot as i64)
+            .bind(latency.scooped_slot as i64)
+            .bind(latency.scooped_at as i64)
             .execute(&mut *tx)
             .await?;
         }
 
+        for flow in changes.order_flow {
+            sqlx::query(
+                "INSERT OR REPLACE INTO sundae_v3_order_flow (order_tx_id, order_txo_index, ident, slot, created_slot, position, datum, lovelace) VALUES (?,?,?,?,?,?,?,?);",
+            )
+            .bind(flow.order.0.transaction_id.to_vec())
+            .bind(flow.order.0.index as i64)
+            .bind(flow.ident.to_bytes().to_vec())
+            .bind(flow.slot as i64)
+            .bind(flow.created_slot as i64)
+            .bind(flow.position as i64)
+            .bind(flow.datum)
+            .bind(flow.lovelace as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        if !changes.spent_txos.is_empty() {
+            // One statement for every input, since scoops spend dozens of orders at once
+            let update_spent_txo_query = {
+                let values_clauses = vec!["(?,?)"; changes.spent_txos.len()].join(",");
+                format!(
+                    "UPDATE sundae_v3_txos SET spent_slot = ?, spent_height = ?, spent_tx_hash = ?, spent_block_hash = ? WHERE (tx_id, txo_index) IN (VALUES {values_clauses});"
+                )
+            };
+            let mut query = sqlx::query(&update_spent_txo_query)
+                .bind(changes.slot)
+                .bind(changes.height)
+                .bind(changes.tx_hash)
+                .bind(changes.block_hash);
+
+            for spent_txo in changes.spent_txos {
+                query = query
+                    .bind(spent_txo.0.transaction_id.to_vec())
+                    .bind(spent_txo.0.index as i64);
+            }
+
+            query.execute(&mut *tx).await?;
+        }
+
         tx.commit().await?;
         Ok(())
     }
 
-    async fn rollback(&self, slot: u64) -> Result<()> {
+    async fn rollback(&self, slot: Slot) -> Result<()> {
         let mut tx = self.pool.begin().await?;
 
         sqlx::query("DELETE FROM sundae_v3_txos WHERE created_slot > ?;")
-            .bind(slot as i64)
+            .bind(slot)
             .execute(&mut *tx)
             .await?;
 
         sqlx::query(
-            "UPDATE sundae_v3_txos SET spent_slot = NULL, spent_height = NULL WHERE spent_slot > ?",
+            "UPDATE sundae_v3_txos SET spent_slot = NULL, spent_height = NULL, spent_tx_hash = NULL, spent_block_hash = NULL WHERE spent_slot > ?",
         )
-        .bind(slot as i64)
+        .bind(slot)
         .execute(&mut *tx)
         .await?;
 
+        sqlx::query("DELETE FROM sundae_v3_quarantined_txos WHERE slot > ?;")
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM sundae_v3_pool_reserves WHERE slot > ?;")
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM sundae_v3_pool_txs WHERE slot > ?;")
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM sundae_v3_settings_history WHERE slot > ?;")
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM sundae_v3_trades WHERE slot > ?;")
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM sundae_v3_scoops WHERE slot > ?;")
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM sundae_v3_order_latencies WHERE scooped_slot > ?;")
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM sundae_v3_order_flow WHERE slot > ?;")
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+
         tx.commit().await?;
         Ok(())
     }
 
     async fn load_txos(&self) -> Result<Vec<PersistedTxo>> {
         let query = "
-            SELECT tx_id, txo_index, txo_type, created_slot, era, txo
+            SELECT tx_id, txo_index, txo_type, created_slot, created_block_hash, era, txo
             FROM sundae_v3_txos
             WHERE spent_slot IS NULL
             ORDER BY created_slot, tx_id, txo_index;
@@ -149,15 +448,364 @@ impl SundaeV3Dao for SqliteSundaeV3Dao {
         Ok(sqlx::query_as(query).fetch_all(&self.pool).await?)
     }
 
-    async fn prune_txos(&self, min_height: u64) -> Result<()> {
+    async fn prune_txos(&self, min_height: BlockHeight) -> Result<()> {
         let mut tx = self.pool.begin().await?;
         sqlx::query("DELETE FROM sundae_v3_txos WHERE spent_height < ?")
-            .bind(min_height as i64)
+            .bind(min_height)
             .execute(&mut *tx)
             .await?;
         tx.commit().await?;
         Ok(())
     }
+
+    async fn load_quarantined_txos(&self, limit: u32) -> Result<Vec<QuarantinedTxo>> {
+        let query = "
+            SELECT tx_id, txo_index, txo_type, slot, era, reason, txo
+            FROM sundae_v3_quarantined_txos
+            ORDER BY slot DESC, tx_id, txo_index
+            LIMIT ?;
+        ";
+        Ok(sqlx::query_as(query)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn latest_slot(&self) -> Result<Option<Slot>> {
+        let query = "
+            SELECT MAX(slot) FROM (
+                SELECT MAX(created_slot) AS slot FROM sundae_v3_txos
+                UNION ALL SELECT MAX(spent_slot) FROM sundae_v3_txos
+                UNION ALL SELECT MAX(slot) FROM sundae_v3_quarantined_txos
+            );
+        ";
+        Ok(sqlx::query_scalar(query).fetch_one(&self.pool).await?)
+    }
+
+    async fn load_reserve_history(&self, ident: &Ident) -> Result<Vec<PoolReserveSnapshot>> {
+        let query = "
+            SELECT ident, epoch, slot, reserve_a, reserve_b, circulating_lp
+            FROM sundae_v3_pool_reserves
+            WHERE ident = ?
+            ORDER BY epoch;
+        ";
+        Ok(sqlx::query_as(query)
+            .bind(ident.to_bytes().to_vec())
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn export_txos(
+        &self,
+        txo_type: &str,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<ExportedTxo>> {
+        let query = "
+            SELECT tx_id, txo_index, txo_type, created_slot, created_block_hash, spent_slot,
+                spent_height, spent_tx_hash, spent_block_hash, era, txo
+            FROM sundae_v3_txos
+            WHERE txo_type = ? AND created_slot >= ? AND created_slot <= ?
+            ORDER BY created_slot, tx_id, txo_index;
+        ";
+        Ok(sqlx::query_as(query)
+            .bind(txo_type)
+            .bind(from_slot.unwrap_or(0) as i64)
+            .bind(to_slot.map_or(i64::MAX, |s| s as i64))
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn load_spent_txos(&self, txo_type: &str, from_slot: u64) -> Result<Vec<ExportedTxo>> {
+        let query = "
+            SELECT tx_id, txo_index, txo_type, created_slot, created_block_hash, spent_slot,
+                spent_height, spent_tx_hash, spent_block_hash, era, txo
+            FROM sundae_v3_txos
+            WHERE spent_slot >= ? AND txo_type = ?
+            ORDER BY spent_slot, tx_id, txo_index;
+        ";
+        Ok(sqlx::query_as(query)
+            .bind(from_slot as i64)
+            .bind(txo_type)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn load_pool_tx(&self, tx_hash: &[u8]) -> Result<Option<PoolTx>> {
+        let query = "SELECT tx_hash, slot, tx FROM sundae_v3_pool_txs WHERE tx_hash = ?;";
+        let Some(row) = sqlx::query(query)
+            .bind(tx_hash)
+            .fetch_optional(&self.pool)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let slot: i64 = row.try_get("slot")?;
+        Ok(Some(PoolTx {
+            tx_hash: row.try_get("tx_hash")?,
+            slot: slot as u64,
+            tx: row.try_get("tx")?,
+        }))
+    }
+
+    async fn load_settings_history(&self, limit: u32) -> Result<Vec<SettingsRecord>> {
+        let query = "
+            SELECT tx_id, txo_index, slot, datum
+            FROM sundae_v3_settings_history
+            ORDER BY slot DESC, tx_id, txo_index
+            LIMIT ?;
+        ";
+        let rows = sqlx::query(query).bind(limit).fetch_all(&self.pool).await?;
+        let mut records = vec![];
+        for row in rows {
+            let tx_id: Vec<u8> = row.try_get("tx_id")?;
+            let txo_index: i64 = row.try_get("txo_index")?;
+            let slot: i64 = row.try_get("slot")?;
+            records.push(SettingsRecord {
+                txo_id: TransactionInput::new(tx_id.as_slice().into(), txo_index as u64),
+                slot: slot as u64,
+                datum: row.try_get("datum")?,
+            });
+        }
+        Ok(records)
+    }
+
+    async fn load_trades(&self, ident: &Ident, from_slot: u64, limit: u32) -> Result<Vec<Trade>> {
+        // Rows are inserted in the order the pool processed them
+        let query = "
+            SELECT order_tx_id, order_txo_index, ident, slot, tx_hash, a_to_b, gives, takes
+            FROM sundae_v3_trades
+            WHERE ident = ? AND slot >= ?
+            ORDER BY slot, rowid
+            LIMIT ?;
+        ";
+        Ok(sqlx::query_as(query)
+            .bind(ident.to_bytes().to_vec())
+            .bind(from_slot as i64)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn load_scoops(&self, ident: &Ident, limit: u32) -> Result<Vec<Scoop>> {
+        let query = "
+            SELECT tx_hash, slot, orders, block_hash, tx_index
+            FROM sundae_v3_scoops
+            WHERE ident = ?
+            ORDER BY slot DESC, rowid DESC
+            LIMIT ?;
+        ";
+        let rows = sqlx::query(query)
+            .bind(ident.to_bytes().to_vec())
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut scoops = vec![];
+        for row in rows {
+            let slot: i64 = row.try_get("slot")?;
+            let orders: i64 = row.try_get("orders")?;
+            let block_hash: Option<Vec<u8>> = row.try_get("block_hash")?;
+            let tx_index: Option<i64> = row.try_get("tx_index")?;
+            scoops.push(Scoop {
+                ident: ident.clone(),
+                slot: slot as u64,
+                tx_hash: row.try_get("tx_hash")?,
+                orders: orders as u32,
+                block: block_hash.zip(tx_index).map(|(hash, tx_index)| ScoopBlock {
+                    hash,
+                    tx_index: tx_index as u32,
+                }),
+            });
+        }
+        Ok(scoops)
+    }
+
+    async fn load_order_latencies(&self, from_slot: u64) -> Result<Vec<OrderLatency>> {
+        let query = "
+            SELECT order_tx_id, order_txo_index, ident, created_slot, scooped_slot, scooped_at
+            FROM sundae_v3_order_latencies
+            WHERE scooped_slot >= ?
+            ORDER BY scooped_slot, rowid;
+        ";
+        let rows = sqlx::query(query)
+            .bind(from_slot as i64)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut latencies = vec![];
+        for row in rows {
+            let tx_id: Vec<u8> = row.try_get("order_tx_id")?;
+            let txo_index: i64 = row.try_get("order_txo_index")?;
+            let ident: Vec<u8> = row.try_get("ident")?;
+            let created_slot: i64 = row.try_get("created_slot")?;
+            let scooped_slot: i64 = row.try_get("scooped_slot")?;
+            let scooped_at: i64 = row.try_get("scooped_at")?;
+            latencies.push(OrderLatency {
+                ident: Ident::new(&ident),
+                order: TransactionInput::new(tx_id.as_slice().into(), txo_index as u64),
+                created_slot: created_slot as u64,
+                scooped_slot: scooped_slot as u64,
+                scooped_at: scooped_at as u64,
+            });
+        }
+        Ok(latencies)
+    }
+
+    async fn load_order_flow(&self, from_slot: u64) -> Result<Vec<OrderFlow>> {
+        let query = "
+            SELECT order_tx_id, order_txo_index, ident, slot, created_slot, position, datum,
+                lovelace
+            FROM sundae_v3_order_flow
+            WHERE slot >= ?
+            ORDER BY slot, rowid;
+        ";
+        let rows = sqlx::query(query)
+            .bind(from_slot as i64)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut flow = vec![];
+        for row in rows {
+            let tx_id: Vec<u8> = row.try_get("order_tx_id")?;
+            let txo_index: i64 = row.try_get("order_txo_index")?;
+            let ident: Vec<u8> = row.try_get("ident")?;
+            let slot: i64 = row.try_get("slot")?;
+            let created_slot: i64 = row.try_get("created_slot")?;
+            let position: i64 = row.try_get("position")?;
+            let lovelace: i64 = row.try_get("lovelace")?;
+            flow.push(OrderFlow {
+                ident: Ident::new(&ident),
+                order: TransactionInput::new(tx_id.as_slice().into(), txo_index as u64),
+                slot: slot as u64,
+                created_slot: created_slot as u64,
+                position: position as u32,
+                datum: row.try_get("datum")?,
+                lovelace: lovelace as u64,
+            });
+        }
+        Ok(flow)
+    }
+
+    async fn export_reserve_snapshots(
+        &self,
+        from_slot: Option<u64>,
+        to_slot: Option<u64>,
+    ) -> Result<Vec<PoolReserveSnapshot>> {
+        let query = "
+            SELECT ident, epoch, slot, reserve_a, reserve_b, circulating_lp
+            FROM sundae_v3_pool_reserves
+            WHERE slot >= ? AND slot <= ?
+            ORDER BY slot, ident;
+        ";
+        Ok(sqlx::query_as(query)
+            .bind(from_slot.unwrap_or(0) as i64)
+            .bind(to_slot.map_or(i64::MAX, |s| s as i64))
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn record_rollback(&self, rollback: &RollbackRecord) -> Result<()> {
+        let query = "
+            INSERT INTO sundae_v3_deepest_rollback (id, depth, from_slot, to_slot)
+            VALUES (0, ?, ?, ?)
+            ON CONFLICT (id) DO UPDATE SET
+                depth = excluded.depth,
+                from_slot = excluded.from_slot,
+                to_slot = excluded.to_slot
+            WHERE excluded.depth > sundae_v3_deepest_rollback.depth;
+        ";
+        sqlx::query(query)
+            .bind(rollback.depth as i64)
+            .bind(rollback.from_slot as i64)
+            .bind(rollback.to_slot as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn load_deepest_rollback(&self) -> Result<Option<RollbackRecord>> {
+        let query = "SELECT depth, from_slot, to_slot FROM sundae_v3_deepest_rollback;";
+        let Some(row) = sqlx::query(query).fetch_optional(&self.pool).await? else {
+            return Ok(None);
+        };
+        let depth: i64 = row.try_get("depth")?;
+        let from_slot: i64 = row.try_get("from_slot")?;
+        let to_slot: i64 = row.try_get("to_slot")?;
+        Ok(Some(RollbackRecord {
+            depth: depth as u64,
+            from_slot: from_slot as u64,
+            to_slot: to_slot as u64,
+        }))
+    }
+}
+
+impl FromRow<'_, SqliteRow> for PoolReserveSnapshot {
+    fn from_row(row: &'_ SqliteRow) -> Result<Self, sqlx::Error> {
+        let ident: Vec<u8> = row.try_get("ident")?;
+        let epoch: i64 = row.try_get("epoch")?;
+        let slot: i64 = row.try_get("slot")?;
+        let parse = |column: &str| -> Result<BigInt, sqlx::Error> {
+            let value: String = row.try_get(column)?;
+            value.parse().map_err(|err| sqlx::Error::ColumnDecode {
+                index: column.to_string(),
+                source: Box::new(err),
+            })
+        };
+
+        Ok(Self {
+            ident: Ident::new(&ident),
+            epoch: epoch as u64,
+            slot: slot as u64,
+            reserve_a: parse("reserve_a")?,
+            reserve_b: parse("reserve_b")?,
+            circulating_lp: parse("circulating_lp")?,
+        })
+    }
+}
+
+impl FromRow<'_, SqliteRow> for Trade {
+    fn from_row(row: &'_ SqliteRow) -> Result<Self, sqlx::Error> {
+        let order_tx_id: Vec<u8> = row.try_get("order_tx_id")?;
+        let order_txo_index: i64 = row.try_get("order_txo_index")?;
+        let ident: Vec<u8> = row.try_get("ident")?;
+        let slot: i64 = row.try_get("slot")?;
+        let parse = |column: &str| -> Result<BigInt, sqlx::Error> {
+            let value: String = row.try_get(column)?;
+            value.parse().map_err(|err| sqlx::Error::ColumnDecode {
+                index: column.to_string(),
+                source: Box::new(err),
+            })
+        };
+
+        Ok(Self {
+            ident: Ident::new(&ident),
+            slot: slot as u64,
+            tx_hash: row.try_get("tx_hash")?,
+            order: TransactionInput::new(order_tx_id.as_slice().into(), order_txo_index as u64),
+            a_to_b: row.try_get("a_to_b")?,
+            gives: parse("gives")?,
+            takes: parse("takes")?,
+        })
+    }
+}
+
+impl FromRow<'_, SqliteRow> for QuarantinedTxo {
+    fn from_row(row: &'_ SqliteRow) -> Result<Self, sqlx::Error> {
+        let tx_id: Vec<u8> = row.try_get("tx_id")?;
+        let txo_index: i64 = row.try_get("txo_index")?;
+        let txo_type: String = row.try_get("txo_type")?;
+        let slot: i64 = row.try_get("slot")?;
+        let era: u16 = row.try_get("era")?;
+        let reason: String = row.try_get("reason")?;
+        let txo: Vec<u8> = row.try_get("txo")?;
+
+        Ok(Self {
+            txo_id: TransactionInput::new(tx_id.as_slice().into(), txo_index as u64),
+            txo_type,
+            slot: slot as u64,
+            era,
+            reason,
+            txo,
+        })
+    }
 }
 
 impl FromRow<'_, SqliteRow> for PersistedTxo {
@@ -166,6 +814,7 @@ impl FromRow<'_, SqliteRow> for PersistedTxo {
         let txo_index: i64 = row.try_get("txo_index")?;
         let txo_type: String = row.try_get("txo_type")?;
         let created_slot: i64 = row.try_get("created_slot")?;
+        let created_block_hash: Option<Vec<u8>> = row.try_get("created_block_hash")?;
         let era: u16 = row.try_get("era")?;
         let txo: Vec<u8> = row.try_get("txo")?;
 
@@ -173,12 +822,127 @@ impl FromRow<'_, SqliteRow> for PersistedTxo {
             txo_id: TransactionInput::new(tx_id.as_slice().into(), txo_index as u64),
             txo_type,
             created_slot: created_slot as u64,
+            created_block_hash,
             era,
             txo,
         })
     }
 }
 
+pub struct SqliteApiKeyDao {
+    pool: Pool<Sqlite>,
+}
+
+#[async_trait]
+impl ApiKeyDao for SqliteApiKeyDao {
+    async fn save_api_key(&self, key: &ApiKey, secret_hash: &[u8]) -> Result<()> {
+        let pools: Vec<String> = key.pools.iter().map(Ident::to_string).collect();
+        sqlx::query(
+            "INSERT OR REPLACE INTO api_keys (name, secret_hash, pools, admin) VALUES (?,?,?,?);",
+        )
+        .bind(&key.name)
+        .bind(secret_hash)
+        .bind(pools.join(","))
+        .bind(key.admin)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn find_api_key(&self, secret_hash: &[u8]) -> Result<Option<ApiKey>> {
+        let query = "SELECT name, pools, admin FROM api_keys WHERE secret_hash = ?;";
+        Ok(sqlx::query_as(query)
+            .bind(secret_hash)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let query = "SELECT name, pools, admin FROM api_keys ORDER BY name;";
+        Ok(sqlx::query_as(query).fetch_all(&self.pool).await?)
+    }
+
+    async fn delete_api_key(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE name = ?;")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+impl FromRow<'_, SqliteRow> for ApiKey {
+    fn from_row(row: &'_ SqliteRow) -> Result<Self, sqlx::Error> {
+        let name: String = row.try_get("name")?;
+        let pools: String = row.try_get("pools")?;
+        let admin: bool = row.try_get("admin")?;
+        let pools = pools
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(|p| hex::decode(p).map(|bytes| Ident::new(&bytes)))
+            .collect::<Result<_, _>>()
+            .map_err(|err| sqlx::Error::ColumnDecode {
+                index: "pools".to_string(),
+                source: Box::new(err),
+            })?;
+        Ok(Self { name, pools, admin })
+    }
+}
+
+pub struct SqliteExtensionDao {
+    pool: Pool<Sqlite>,
+    namespace: String,
+}
+
+#[async_trait]
+impl ExtensionDao for SqliteExtensionDao {
+    async fn put(&self, slot: Slot, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO extension_records (namespace, key, slot, value) VALUES (?,?,?,?);",
+        )
+        .bind(&self.namespace)
+        .bind(key)
+        .bind(slot)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let query = "SELECT value FROM extension_records WHERE namespace = ? AND key = ? ORDER BY slot DESC LIMIT 1;";
+        let value: Option<Option<Vec<u8>>> = sqlx::query_scalar(query)
+            .bind(&self.namespace)
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(value.flatten())
+    }
+
+    async fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let query = "
+            SELECT key, value
+            FROM extension_records AS r
+            WHERE namespace = ?
+              AND slot = (SELECT MAX(slot) FROM extension_records WHERE namespace = r.namespace AND key = r.key)
+              AND value IS NOT NULL
+            ORDER BY key;";
+        Ok(sqlx::query_as(query)
+            .bind(&self.namespace)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn rollback(&self, slot: Slot) -> Result<()> {
+        sqlx::query("DELETE FROM extension_records WHERE namespace = ? AND slot > ?;")
+            .bind(&self.namespace)
+            .bind(slot)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
 struct SqliteCursorDaoImpl {
     pool: Pool<Sqlite>,
 }
@@ -202,31 +966,45 @@ impl CursorDaoImpl for SqliteCursorDaoImpl {
         Ok(result)
     }
 
-    async fn save(&self, entries: &HashMap<String, CursorEntry>) -> Result<(), CursorSaveError> {
+    async fn save(
+        &self,
+        changed: &HashMap<String, Vec<u8>>,
+        removed: &[String],
+    ) -> Result<(), CursorSaveError> {
+        let all_failed = || {
+            let failed = changed.keys().chain(removed).cloned().collect();
+            CursorSaveError { failed }
+        };
         let mut tx = self.pool.begin().await.map_err(|err| {
             warn!("could not open transaction: {err:#}");
-            let failed = entries.keys().cloned().collect();
-            CursorSaveError { failed }
+            all_failed()
         })?;
-        sqlx::query("DELETE FROM acropolis_cursors;")
-            .execute(&mut *tx)
-            .await
-            .map_err(|err| {
-                warn!("could not clear cursors: {err:#}");
-                let failed = entries.keys().cloned().collect();
-                CursorSaveError { failed }
-            })?;
         let mut failed = vec![];
-        for (id, cursor) in entries {
-            if let Err(err) = save_entry(&mut tx, id, cursor).await {
+        for (id, bytes) in changed {
+            let result =
+                sqlx::query("INSERT OR REPLACE INTO acropolis_cursors(id, bytes) VALUES(?,?);")
+                    .bind(id)
+                    .bind(bytes)
+                    .execute(&mut *tx)
+                    .await;
+            if let Err(err) = result {
                 warn!("could not save cursor for {id}: {err:#}");
                 failed.push(id.clone());
             }
         }
+        for id in removed {
+            let result = sqlx::query("DELETE FROM acropolis_cursors WHERE id = ?;")
+                .bind(id)
+                .execute(&mut *tx)
+                .await;
+            if let Err(err) = result {
+                warn!("could not remove cursor for {id}: {err:#}");
+                failed.push(id.clone());
+            }
+        }
         tx.commit().await.map_err(|err| {
             warn!("could not commit transaction: {err:#}");
-            let failed = entries.keys().cloned().collect();
-            CursorSaveError { failed }
+            all_failed()
         })?;
         if failed.is_empty() {
             Ok(())
@@ -236,26 +1014,27 @@ impl CursorDaoImpl for SqliteCursorDaoImpl {
     }
 }
 
+impl FromRow<'_, SqliteRow> for ExportedTxo {
+    fn from_row(row: &'_ SqliteRow) -> Result<Self, sqlx::Error> {
+        let spent_slot: Option<i64> = row.try_get("spent_slot")?;
+        let spent_height: Option<i64> = row.try_get("spent_height")?;
+
+        Ok(Self {
+            txo: PersistedTxo::from_row(row)?,
+            spent_slot: spent_slot.map(|s| s as u64),
+            spent_height: spent_height.map(|h| h as u64),
+            spent_tx_hash: row.try_get("spent_tx_hash")?,
+            spent_block_hash: row.try_get("spent_block_hash")?,
+        })
+    }
+}
+
 fn parse_cursor_entry(row: SqliteRow) -> Result<(String, Vec<u8>), sqlx::error::Error> {
     let id: String = row.try_get("id")?;
     let bytes: Vec<u8> = row.try_get("bytes")?;
     Ok((id, bytes))
 }
 
-async fn save_entry(
-    tx: &mut sqlx::SqliteTransaction<'_>,
-    id: &str,
-    cursor: &CursorEntry,
-) -> Result<()> {
-    let bytes = serde_json::to_vec(cursor)?;
-    sqlx::query("INSERT INTO acropolis_cursors(id, bytes) VALUES(?,?);")
-        .bind(id)
-        .bind(bytes)
-        .execute(tx.as_mut())
-        .await?;
-    Ok(())
-}
-
 #[cfg(test)]
 mod tests {
     use acropolis_common::{Point, hash::Hash};
@@ -267,6 +1046,25 @@ mod tests {
         SqlitePersistence::new(&SqliteConfig { filename: None }).await
     }
 
+    #[tokio::test]
+    async fn should_refuse_newer_schemas() -> Result<()> {
+        let persistence = new_db().await?;
+        let status = load_schema_status(&persistence.pool).await?;
+        assert!(status.migrations.iter().all(|m| m.applied));
+        assert!(status.unknown_versions.is_empty());
+        check_schema(&persistence.pool).await?;
+
+        sqlx::query(
+            "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES (99999999, 'from the future', TRUE, x'00', 0);",
+        )
+        .execute(&persistence.pool)
+        .await?;
+        let status = load_schema_status(&persistence.pool).await?;
+        assert_eq!(status.unknown_versions, vec![99999999]);
+        assert!(check_schema(&persistence.pool).await.is_err());
+        Ok(())
+    }
+
     fn preview_pool() -> PersistedTxo {
         let tx_id = "f9fad594fb6cda70fc7a05cf286a77c7c1218a0ecee4bb0d0946c767f3a745d1";
         let txo = "
@@ -295,6 +1093,7 @@ mod tests {
             txo_id: TransactionInput::new(tx_id.parse().unwrap(), 0),
             txo_type: "pool".to_string(),
             created_slot: 48463593,
+            created_block_hash: None,
             era: 7,
             txo: hex::decode(txo).unwrap(),
         }
@@ -329,6 +1128,7 @@ mod tests {
             txo_id: TransactionInput::new(tx_id.parse().unwrap(), 0),
             txo_type: "order".to_string(),
             created_slot: 48465289,
+            created_block_hash: None,
             era: 7,
             txo: hex::decode(txo).unwrap(),
         }
@@ -369,6 +1169,7 @@ mod tests {
             txo_id: TransactionInput::new(tx_id.parse().unwrap(), 0),
             txo_type: "order".to_string(),
             created_slot: 48467939,
+            created_block_hash: None,
             era: 7,
             txo: hex::decode(txo).unwrap(),
         }
@@ -381,18 +1182,38 @@ mod tests {
 
         let pool = preview_pool();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: pool.created_slot,
-            height: 1,
+            slot: Slot(pool.created_slot),
+            height: BlockHeight(1),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![pool.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
         let order = preview_order();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: order.created_slot,
-            height: 2,
+            slot: Slot(order.created_slot),
+            height: BlockHeight(2),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![order.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
@@ -409,28 +1230,58 @@ mod tests {
 
         let pool = preview_pool();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: pool.created_slot,
-            height: 1,
+            slot: Slot(pool.created_slot),
+            height: BlockHeight(1),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![pool.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
         let order = preview_order();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: order.created_slot,
-            height: 2,
+            slot: Slot(order.created_slot),
+            height: BlockHeight(2),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![order.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
         // The order TXO was spent
         let order = preview_order();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: order.created_slot + 10,
-            height: 3,
+            slot: Slot(order.created_slot + 10),
+            height: BlockHeight(3),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![],
             spent_txos: vec![order.txo_id.clone()],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
@@ -440,6 +1291,99 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn should_spend_many_txos_at_once() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+
+        let pool = preview_pool();
+        let order = preview_order();
+        let order_2 = preview_order_2();
+        let mut changes = SundaeV3TxChanges::new(Slot(order_2.created_slot), BlockHeight(1));
+        changes.created_txos = vec![pool.clone(), order.clone(), order_2.clone()];
+        dao.apply_tx_changes(changes).await?;
+
+        let mut changes = SundaeV3TxChanges::new(Slot(order_2.created_slot + 10), BlockHeight(2));
+        changes.spent_txos = vec![order.txo_id.clone(), order_2.txo_id.clone()];
+        dao.apply_tx_changes(changes).await?;
+
+        assert_eq!(dao.load_txos().await?, vec![pool]);
+        let spent = dao.export_txos("order", None, None).await?;
+        assert_eq!(spent.len(), 2);
+        assert!(
+            spent
+                .iter()
+                .all(|txo| txo.spent_slot == Some(order_2.created_slot + 10))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_export_spent_txos_by_type_and_slot() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+
+        let pool = preview_pool();
+        let order = preview_order();
+        let order_2 = preview_order_2();
+        for (height, txo) in [pool, order.clone(), order_2.clone()]
+            .into_iter()
+            .enumerate()
+        {
+            let mut changes =
+                SundaeV3TxChanges::new(Slot(txo.created_slot), BlockHeight(height as u64));
+            changes.created_txos.push(txo);
+            dao.apply_tx_changes(changes).await?;
+        }
+        let mut changes = SundaeV3TxChanges::new(Slot(order_2.created_slot + 10), BlockHeight(4));
+        changes.tx_hash = Some(vec![1; 32]);
+        changes.block_hash = Some(vec![2; 32]);
+        changes.spent_txos.push(order.txo_id.clone());
+        dao.apply_tx_changes(changes).await?;
+
+        let spent_order = ExportedTxo {
+            txo: order.clone(),
+            spent_slot: Some(order_2.created_slot + 10),
+            spent_height: Some(4),
+            spent_tx_hash: Some(vec![1; 32]),
+            spent_block_hash: Some(vec![2; 32]),
+        };
+        let unspent_order = ExportedTxo {
+            txo: order_2.clone(),
+            spent_slot: None,
+            spent_height: None,
+            spent_tx_hash: None,
+            spent_block_hash: None,
+        };
+        assert_eq!(
+            dao.export_txos("order", None, None).await?,
+            vec![spent_order.clone(), unspent_order]
+        );
+        assert_eq!(
+            dao.export_txos("order", None, Some(order.created_slot))
+                .await?,
+            vec![spent_order.clone()]
+        );
+        assert!(
+            dao.export_txos("order", Some(order_2.created_slot + 1), None)
+                .await?
+                .is_empty()
+        );
+        assert_eq!(
+            dao.load_spent_txos("order", order_2.created_slot + 10)
+                .await?,
+            vec![spent_order]
+        );
+        assert!(
+            dao.load_spent_txos("order", order_2.created_slot + 11)
+                .await?
+                .is_empty()
+        );
+        assert!(dao.load_spent_txos("pool", 0).await?.is_empty());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn should_remove_rolled_back_txos() -> Result<()> {
         let db = new_db().await?;
@@ -447,23 +1391,43 @@ mod tests {
 
         let pool = preview_pool();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: pool.created_slot,
-            height: 1,
+            slot: Slot(pool.created_slot),
+            height: BlockHeight(1),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![pool.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
         let order = preview_order();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: order.created_slot,
-            height: 2,
+            slot: Slot(order.created_slot),
+            height: BlockHeight(2),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![order.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
         // Roll back to the pool creation, which was before the order creation
-        dao.rollback(pool.created_slot).await?;
+        dao.rollback(Slot(pool.created_slot)).await?;
 
         let txos = dao.load_txos().await?;
         assert_eq!(txos, vec![pool]);
@@ -471,6 +1435,356 @@ mod tests {
         Ok(())
     }
 
+    fn quarantined(txo: PersistedTxo, reason: &str) -> QuarantinedTxo {
+        QuarantinedTxo {
+            txo_id: txo.txo_id,
+            txo_type: txo.txo_type,
+            slot: txo.created_slot,
+            era: txo.era,
+            reason: reason.to_string(),
+            txo: txo.txo,
+        }
+    }
+
+    #[tokio::test]
+    async fn should_load_and_roll_back_quarantined_txos() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+
+        let pool = quarantined(preview_pool(), "pool NFT is missing");
+        dao.apply_tx_changes(SundaeV3TxChanges {
+            slot: Slot(pool.slot),
+            height: BlockHeight(1),
+            tx_hash: None,
+            block_hash: None,
+            created_txos: vec![],
+            spent_txos: vec![],
+            quarantined_txos: vec![pool.clone()],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
+        })
+        .await?;
+        let order = quarantined(preview_order(), "bad datum");
+        dao.apply_tx_changes(SundaeV3TxChanges {
+            slot: Slot(order.slot),
+            height: BlockHeight(2),
+            tx_hash: None,
+            block_hash: None,
+            created_txos: vec![],
+            spent_txos: vec![],
+            quarantined_txos: vec![order.clone()],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
+        })
+        .await?;
+
+        // Most recent first, and they never show up as live txos
+        assert_eq!(
+            dao.load_quarantined_txos(10).await?,
+            vec![order.clone(), pool.clone()]
+        );
+        assert_eq!(dao.load_quarantined_txos(1).await?, vec![order]);
+        assert!(dao.load_txos().await?.is_empty());
+
+        dao.rollback(Slot(pool.slot)).await?;
+        assert_eq!(dao.load_quarantined_txos(10).await?, vec![pool]);
+
+        Ok(())
+    }
+
+    fn snapshot(epoch: u64, slot: u64, reserve_a: u64) -> PoolReserveSnapshot {
+        PoolReserveSnapshot {
+            ident: Ident::new(&[1, 2, 3]),
+            epoch,
+            slot,
+            reserve_a: BigInt::from(reserve_a),
+            reserve_b: BigInt::from(2 * reserve_a),
+            circulating_lp: BigInt::from(u64::MAX),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_keep_first_reserve_snapshot_per_epoch() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+
+        for (height, snapshot) in [
+            snapshot(1, 10, 100),
+            snapshot(1, 20, 200),
+            snapshot(2, 30, 300),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut changes =
+                SundaeV3TxChanges::new(Slot(snapshot.slot), BlockHeight(height as u64));
+            changes.reserve_snapshots.push(snapshot);
+            dao.apply_tx_changes(changes).await?;
+        }
+
+        let ident = Ident::new(&[1, 2, 3]);
+        assert_eq!(
+            dao.load_reserve_history(&ident).await?,
+            vec![snapshot(1, 10, 100), snapshot(2, 30, 300)]
+        );
+        assert!(
+            dao.load_reserve_history(&Ident::new(&[4]))
+                .await?
+                .is_empty()
+        );
+
+        // A rolled back epoch gets a fresh snapshot from the new chain
+        dao.rollback(Slot(25)).await?;
+        assert_eq!(
+            dao.load_reserve_history(&ident).await?,
+            vec![snapshot(1, 10, 100)]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_store_pool_txs_until_rolled_back() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+
+        let pool_tx = |slot: u64| PoolTx {
+            tx_hash: vec![slot as u8; 32],
+            slot,
+            tx: vec![0x84, slot as u8],
+        };
+        for (height, slot) in [10, 20].into_iter().enumerate() {
+            let mut changes = SundaeV3TxChanges::new(Slot(slot), BlockHeight(height as u64));
+            changes.pool_txs.push(pool_tx(slot));
+            dao.apply_tx_changes(changes).await?;
+        }
+
+        assert_eq!(dao.load_pool_tx(&[10; 32]).await?, Some(pool_tx(10)));
+        assert_eq!(dao.load_pool_tx(&[30; 32]).await?, None);
+
+        dao.rollback(Slot(15)).await?;
+        assert_eq!(dao.load_pool_tx(&[10; 32]).await?, Some(pool_tx(10)));
+        assert_eq!(dao.load_pool_tx(&[20; 32]).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_keep_settings_history_until_rolled_back() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+
+        let record = |slot: u64| SettingsRecord {
+            txo_id: TransactionInput::new([slot as u8; 32].as_slice().into(), 0),
+            slot,
+            datum: vec![0xd8, 0x79, slot as u8],
+        };
+        for (height, slot) in [10, 20, 30].into_iter().enumerate() {
+            let mut changes = SundaeV3TxChanges::new(Slot(slot), BlockHeight(height as u64));
+            changes.settings_history.push(record(slot));
+            dao.apply_tx_changes(changes).await?;
+        }
+        assert_eq!(
+            dao.load_settings_history(2).await?,
+            vec![record(30), record(20)]
+        );
+
+        dao.rollback(Slot(15)).await?;
+        assert_eq!(dao.load_settings_history(10).await?, vec![record(10)]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_page_trades_until_rolled_back() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+
+        let ident = Ident::new(&[3]);
+        let trade = |slot: u64, index: u64| Trade {
+            ident: ident.clone(),
+            slot,
+            tx_hash: vec![slot as u8; 32],
+            order: TransactionInput::new([slot as u8; 32].as_slice().into(), index),
+            a_to_b: index % 2 == 0,
+            gives: BigInt::from(1_000 * (index + 1)),
+            takes: BigInt::from(2_000 * (index + 1)),
+        };
+        for (height, slot) in [10, 20, 30].into_iter().enumerate() {
+            let mut changes = SundaeV3TxChanges::new(Slot(slot), BlockHeight(height as u64));
+            // Stored in processing order, not input order
+            changes.trades.push(trade(slot, 1));
+            changes.trades.push(trade(slot, 0));
+            dao.apply_tx_changes(changes).await?;
+        }
+
+        assert_eq!(
+            dao.load_trades(&ident, 20, 3).await?,
+            vec![trade(20, 1), trade(20, 0), trade(30, 1)]
+        );
+        assert!(dao.load_trades(&Ident::new(&[4]), 0, 10).await?.is_empty());
+
+        dao.rollback(Slot(15)).await?;
+        assert_eq!(
+            dao.load_trades(&ident, 0, 10).await?,
+            vec![trade(10, 1), trade(10, 0)]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_load_recent_scoops_until_rolled_back() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+        let ident = Ident::new(&[3; 28]);
+        let scoop = |slot: u64, orders| Scoop {
+            ident: ident.clone(),
+            slot,
+            tx_hash: vec![slot as u8; 32],
+            orders,
+            block: Some(ScoopBlock {
+                hash: vec![slot as u8 + 1; 32],
+                tx_index: orders,
+            }),
+        };
+
+        for slot in [10, 20, 30] {
+            let mut changes = SundaeV3TxChanges::new(Slot(slot), BlockHeight(slot));
+            changes.scoops.push(scoop(slot, slot as u32 / 10));
+            dao.apply_tx_changes(changes).await?;
+        }
+
+        assert_eq!(
+            dao.load_scoops(&ident, 2).await?,
+            vec![scoop(30, 3), scoop(20, 2)]
+        );
+        assert!(dao.load_scoops(&Ident::new(&[4; 28]), 10).await?.is_empty());
+
+        dao.rollback(Slot(15)).await?;
+        assert_eq!(dao.load_scoops(&ident, 10).await?, vec![scoop(10, 1)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_load_order_latencies_from_slot() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+        let latency = |created_slot: u64, scooped_slot: u64| OrderLatency {
+            ident: Ident::new(&[5; 28]),
+            order: TransactionInput::new([created_slot as u8; 32].into(), 0),
+            created_slot,
+            scooped_slot,
+            scooped_at: 1_700_000_000 + scooped_slot,
+        };
+
+        for (created, scooped) in [(5, 10), (12, 20)] {
+            let mut changes = SundaeV3TxChanges::new(Slot(scooped), BlockHeight(scooped));
+            changes.order_latencies.push(latency(created, scooped));
+            dao.apply_tx_changes(changes).await?;
+        }
+
+        assert_eq!(
+            dao.load_order_latencies(0).await?,
+            vec![latency(5, 10), latency(12, 20)]
+        );
+        assert_eq!(dao.load_order_latencies(11).await?, vec![latency(12, 20)]);
+
+        dao.rollback(Slot(15)).await?;
+        assert!(dao.load_order_latencies(11).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_load_order_flow_from_slot() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+        let flow = |slot: u64| OrderFlow {
+            ident: Ident::new(&[5; 28]),
+            order: TransactionInput::new([slot as u8; 32].into(), 0),
+            slot,
+            created_slot: slot - 5,
+            position: 0,
+            datum: vec![0xd8, 0x79, 0x80],
+            lovelace: slot * 1_000_000,
+        };
+
+        for slot in [10, 20] {
+            let mut changes = SundaeV3TxChanges::new(Slot(slot), BlockHeight(slot));
+            changes.order_flow.push(flow(slot));
+            dao.apply_tx_changes(changes).await?;
+        }
+
+        assert_eq!(dao.load_order_flow(0).await?, vec![flow(10), flow(20)]);
+        assert_eq!(dao.load_order_flow(11).await?, vec![flow(20)]);
+
+        dao.rollback(Slot(15)).await?;
+        assert!(dao.load_order_flow(11).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_find_latest_slot() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+        assert_eq!(dao.latest_slot().await?, None);
+
+        let order = preview_order();
+        dao.apply_tx_changes(SundaeV3TxChanges {
+            slot: Slot(order.created_slot),
+            height: BlockHeight(1),
+            tx_hash: None,
+            block_hash: None,
+            created_txos: vec![order.clone()],
+            spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
+        })
+        .await?;
+        assert_eq!(dao.latest_slot().await?, Some(Slot(order.created_slot)));
+
+        // Spends count too
+        dao.apply_tx_changes(SundaeV3TxChanges {
+            slot: Slot(order.created_slot + 10),
+            height: BlockHeight(2),
+            tx_hash: None,
+            block_hash: None,
+            created_txos: vec![],
+            spent_txos: vec![order.txo_id.clone()],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
+        })
+        .await?;
+        assert_eq!(
+            dao.latest_slot().await?,
+            Some(Slot(order.created_slot + 10))
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn should_load_rolled_back_spends() -> Result<()> {
         let db = new_db().await?;
@@ -478,33 +1792,63 @@ mod tests {
 
         let pool = preview_pool();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: pool.created_slot,
-            height: 1,
+            slot: Slot(pool.created_slot),
+            height: BlockHeight(1),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![pool.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
         let order = preview_order();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: order.created_slot,
-            height: 2,
+            slot: Slot(order.created_slot),
+            height: BlockHeight(2),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![order.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
         // the order was spent
         let order = preview_order();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: order.created_slot + 10,
-            height: 3,
+            slot: Slot(order.created_slot + 10),
+            height: BlockHeight(3),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![],
             spent_txos: vec![order.txo_id.clone()],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
         // Roll back to the order creation
-        dao.rollback(order.created_slot).await?;
+        dao.rollback(Slot(order.created_slot)).await?;
 
         let txos = dao.load_txos().await?;
         assert_eq!(txos, vec![pool, order]);
@@ -520,48 +1864,88 @@ mod tests {
         // Height 1: pool created
         let pool = preview_pool();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: pool.created_slot,
-            height: 1,
+            slot: Slot(pool.created_slot),
+            height: BlockHeight(1),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![pool.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
         // Height 2: order created
         let order = preview_order();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: order.created_slot,
-            height: 2,
+            slot: Slot(order.created_slot),
+            height: BlockHeight(2),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![order.clone()],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
         // Height 3: order spent
         let order = preview_order();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: order.created_slot + 10,
-            height: 3,
+            slot: Slot(order.created_slot + 10),
+            height: BlockHeight(3),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![],
             spent_txos: vec![order.txo_id.clone()],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
         // Height 6: new order placed
         let order_2 = preview_order_2();
         dao.apply_tx_changes(SundaeV3TxChanges {
-            slot: order_2.created_slot,
-            height: 6,
+            slot: Slot(order_2.created_slot),
+            height: BlockHeight(6),
+            tx_hash: None,
+            block_hash: None,
             created_txos: vec![order_2],
             spent_txos: vec![],
+            quarantined_txos: vec![],
+            reserve_snapshots: vec![],
+            pool_txs: vec![],
+            settings_history: vec![],
+            trades: vec![],
+            scoops: vec![],
+            order_latencies: vec![],
+            order_flow: vec![],
         })
         .await?;
 
         // now prune history to after that order spend
-        dao.prune_txos(4).await?;
+        dao.prune_txos(BlockHeight(4)).await?;
 
         // Roll back to the order creation
-        dao.rollback(order.created_slot).await?;
+        dao.rollback(Slot(order.created_slot)).await?;
 
         // We are no longer tracking the order, but we didn't forget the pool
         let txos = dao.load_txos().await?;
@@ -654,4 +2038,160 @@ mod tests {
         assert!(dao.load().await?.is_empty());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn cursor_store_should_debounce_tip_changes() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db
+            .cursor_store()
+            .with_save_interval(std::time::Duration::from_secs(3600));
+        let at = |slot| Point::Specific {
+            hash: Hash::default(),
+            slot,
+        };
+
+        let mut cursor = CursorEntry {
+            tip: at(1337),
+            halted: false,
+        };
+        let mut entries = HashMap::from([("abc".to_string(), cursor.clone())]);
+        dao.save(&entries).await?;
+
+        // Moving the tip again so soon is skipped
+        cursor.tip = at(1338);
+        entries.insert("abc".to_string(), cursor.clone());
+        dao.save(&entries).await?;
+        assert_eq!(db.cursor_store().entries().await?["abc"].tip, at(1337));
+
+        // until it's flushed
+        dao.flush().await?;
+        assert_eq!(db.cursor_store().entries().await?["abc"].tip, at(1338));
+
+        // Halting is saved right away, along with the latest tip
+        cursor.tip = at(1339);
+        cursor.halted = true;
+        entries.insert("abc".to_string(), cursor.clone());
+        dao.save(&entries).await?;
+        let stored = &db.cursor_store().entries().await?["abc"];
+        assert_eq!(stored.tip, at(1339));
+        assert!(stored.halted);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cursor_store_should_unhalt_cursor() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.cursor_store();
+
+        let tip = Point::Specific {
+            hash: Hash::default(),
+            slot: 1337,
+        };
+        let cursor = CursorEntry { tip, halted: true };
+        let mut entries = HashMap::new();
+        entries.insert("abc".to_string(), cursor.clone());
+        dao.save(&entries).await?;
+
+        let rewind_to = Point::Specific {
+            hash: Hash::default(),
+            slot: 1300,
+        };
+        dao.unhalt("abc", Some(rewind_to.clone())).await?;
+
+        let new_cursor = dao.entries().await?.remove("abc").unwrap();
+        assert_eq!(new_cursor.tip, rewind_to);
+        assert!(!new_cursor.halted);
+
+        assert!(dao.unhalt("xyz", None).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_save_find_and_delete_api_keys() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.api_key_dao();
+
+        let tenant = ApiKey {
+            name: "tenant".to_string(),
+            pools: vec![Ident::new(&[0xab]), Ident::new(&[0xcd])],
+            admin: false,
+        };
+        let operator = ApiKey {
+            name: "operator".to_string(),
+            pools: vec![],
+            admin: true,
+        };
+        dao.save_api_key(&tenant, &[1]).await?;
+        dao.save_api_key(&operator, &[2]).await?;
+
+        assert_eq!(dao.find_api_key(&[1]).await?, Some(tenant.clone()));
+        assert_eq!(dao.find_api_key(&[3]).await?, None);
+        assert_eq!(dao.list_api_keys().await?, vec![operator, tenant.clone()]);
+
+        // Saving under the same name rotates the secret
+        dao.save_api_key(&tenant, &[4]).await?;
+        assert_eq!(dao.find_api_key(&[1]).await?, None);
+        assert_eq!(dao.find_api_key(&[4]).await?, Some(tenant));
+
+        assert!(dao.delete_api_key("tenant").await?);
+        assert!(!dao.delete_api_key("tenant").await?);
+        assert_eq!(dao.find_api_key(&[4]).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_keep_extension_records_apart_and_roll_them_back() -> Result<()> {
+        let db = new_db().await?;
+        let partner = db.extension_dao("partner");
+        let other = db.extension_dao("other");
+
+        partner.put(Slot(10), b"a", Some(b"1")).await?;
+        partner.put(Slot(10), b"b", Some(b"2")).await?;
+        partner.put(Slot(20), b"a", Some(b"3")).await?;
+        partner.put(Slot(20), b"b", None).await?;
+        other.put(Slot(30), b"a", Some(b"4")).await?;
+
+        assert_eq!(partner.get(b"a").await?, Some(b"3".to_vec()));
+        assert_eq!(partner.get(b"b").await?, None);
+        assert_eq!(
+            partner.entries().await?,
+            vec![(b"a".to_vec(), b"3".to_vec())]
+        );
+        assert_eq!(other.get(b"a").await?, Some(b"4".to_vec()));
+
+        // Rolling back restores what was overwritten or deleted
+        partner.rollback(Slot(15)).await?;
+        assert_eq!(
+            partner.entries().await?,
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"2".to_vec())
+            ]
+        );
+        assert_eq!(other.get(b"a").await?, Some(b"4".to_vec()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_keep_the_deepest_rollback() -> Result<()> {
+        let db = new_db().await?;
+        let dao = db.sundae_v3_dao();
+        assert_eq!(dao.load_deepest_rollback().await?, None);
+
+        let rollback = |depth: u64| RollbackRecord {
+            depth,
+            from_slot: 100,
+            to_slot: 100 - depth,
+        };
+        dao.record_rollback(&rollback(3)).await?;
+        dao.record_rollback(&rollback(1)).await?;
+        assert_eq!(dao.load_deepest_rollback().await?, Some(rollback(3)));
+        dao.record_rollback(&rollback(5)).await?;
+        assert_eq!(dao.load_deepest_rollback().await?, Some(rollback(5)));
+
+        // Rolling the chain back doesn't forget it
+        dao.rollback(Slot(0)).await?;
+        assert_eq!(dao.load_deepest_rollback().await?, Some(rollback(5)));
+        Ok(())
+    }
 }
@@ -0,0 +1,90 @@
+use pallas_primitives::{Constr, PlutusData, conway::MintedDatumOption};
+use pallas_traverse::{Era, MultiEraOutput};
+use plutus_parser::AsPlutus;
+use serde_json::{Value, json};
+
+use crate::bigint::BigInt;
+
+/// Renders arbitrary PlutusData as readable JSON. Constructors become
+/// `{"constructor": n, "fields": [..]}`, bytes are hex and integers are
+/// strings so that they survive JSON parsers without big number support.
+pub fn to_json(data: &PlutusData) -> Value {
+    match data {
+        PlutusData::Constr(constr) => json!({
+            "constructor": constructor_index(constr),
+            "fields": constr.fields.iter().map(to_json).collect::<Vec<_>>(),
+        }),
+        PlutusData::Map(map) => json!({
+            "map": map
+                .iter()
+                .map(|(k, v)| json!({ "k": to_json(k), "v": to_json(v) }))
+                .collect::<Vec<_>>(),
+        }),
+        PlutusData::BigInt(int) => {
+            let int = BigInt::from_plutus(PlutusData::BigInt(int.clone()))
+                .expect("PlutusData::BigInt is always an integer");
+            json!({ "int": int.to_string() })
+        }
+        PlutusData::BoundedBytes(bytes) => json!({ "bytes": hex::encode(bytes.as_slice()) }),
+        PlutusData::Array(items) => json!({
+            "list": items.iter().map(to_json).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+/// Renders the inline datum of a serialized transaction output, if it has one.
+pub fn output_datum(era: u16, raw_output: &[u8]) -> Option<Value> {
+    let era = Era::try_from(era).ok()?;
+    let output = MultiEraOutput::decode(era, raw_output).ok()?;
+    match output.datum() {
+        Some(MintedDatumOption::Data(d)) => Some(to_json(&d.0.unwrap())),
+        _ => None,
+    }
+}
+
+fn constructor_index(constr: &Constr<PlutusData>) -> u64 {
+    match constr.tag {
+        121..=127 => constr.tag - 121,
+        1280..=1400 => constr.tag - 1280 + 7,
+        _ => constr.any_constructor.unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(cbor: &str) -> PlutusData {
+        minicbor::decode(&hex::decode(cbor).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn should_render_swap_order() {
+        let data = decode("d87a9f9f4100410102ff9f4103410405ffff");
+        assert_eq!(
+            to_json(&data),
+            json!({
+                "constructor": 1,
+                "fields": [
+                    { "list": [{ "bytes": "00" }, { "bytes": "01" }, { "int": "2" }] },
+                    { "list": [{ "bytes": "03" }, { "bytes": "04" }, { "int": "5" }] },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn should_render_high_constructors_maps_and_big_ints() {
+        // constructor 7, with a map of -1 => 2^64
+        let data = decode("d905009fa120c249010000000000000000ff");
+        assert_eq!(
+            to_json(&data),
+            json!({
+                "constructor": 7,
+                "fields": [
+                    { "map": [{ "k": { "int": "-1" }, "v": { "int": "18446744073709551616" } }] },
+                ],
+            })
+        );
+    }
+}
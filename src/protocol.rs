@@ -0,0 +1,218 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, de};
+
+/// Script hashes are blake2b-224.
+pub const SCRIPT_HASH_SIZE: usize = 28;
+
+/// Enough lovelace for any order's outputs under current protocol parameters.
+pub const DEFAULT_ADA_RIDER: u64 = 2_000_000;
+
+/// The deployment of SundaeSwap V3 to index.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SundaeV3Protocol {
+    #[serde(deserialize_with = "script_hash")]
+    pub order_script_hash: Vec<u8>,
+    #[serde(deserialize_with = "script_hash")]
+    pub pool_script_hash: Vec<u8>,
+    /// Where the settings UTxO lives. Settings aren't tracked without it.
+    #[serde(default, deserialize_with = "optional_script_hash")]
+    pub settings_script_hash: Option<Vec<u8>>,
+    /// The lovelace an order must carry beyond its scoop fee, to cover the
+    /// min-UTxO of whatever it pays out.
+    #[serde(default = "default_ada_rider")]
+    pub ada_rider: u64,
+    /// How slots map to POSIX time, for the time conditions in order owners.
+    /// Those conditions aren't checked without it.
+    #[serde(default)]
+    pub slot_config: Option<SlotConfig>,
+}
+
+/// The network's Shelley-era slot timing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlotConfig {
+    /// POSIX time in milliseconds at `zero_slot`
+    pub zero_time: u64,
+    pub zero_slot: u64,
+    pub slot_length: u64,
+}
+
+impl SlotConfig {
+    /// The POSIX time in milliseconds a slot starts at.
+    pub fn slot_to_posix(&self, slot: u64) -> u64 {
+        self.zero_time + slot.saturating_sub(self.zero_slot) * self.slot_length
+    }
+
+    /// The first slot which starts at or after a POSIX time in milliseconds.
+    pub fn posix_to_slot(&self, posix: u64) -> u64 {
+        self.zero_slot
+            + posix
+                .saturating_sub(self.zero_time)
+                .div_ceil(self.slot_length)
+    }
+}
+
+/// Networks with a known deployment, so they can be picked by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Preview,
+}
+
+impl Network {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mainnet" => Some(Self::Mainnet),
+            "preview" => Some(Self::Preview),
+            _ => None,
+        }
+    }
+
+    pub fn protocol(self) -> SundaeV3Protocol {
+        let json = match self {
+            Self::Mainnet => include_str!("../config/protocols/mainnet.json"),
+            Self::Preview => include_str!("../config/protocols/preview.json"),
+        };
+        serde_json::from_str(json).expect("bundled protocols are valid")
+    }
+}
+
+/// The `protocol` key of the config: a network name, the path to a protocol
+/// JSON file, or the protocol itself as a table.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ProtocolConfig {
+    NameOrPath(String),
+    Inline(SundaeV3Protocol),
+}
+
+impl ProtocolConfig {
+    /// Relative paths are resolved against `base_dir`, the directory of the
+    /// config file which named them.
+    pub fn resolve(&self, base_dir: &Path) -> Result<SundaeV3Protocol> {
+        match self {
+            Self::NameOrPath(name) if Network::from_name(name).is_some() => {
+                SundaeV3Protocol::load(name)
+            }
+            Self::NameOrPath(path) => SundaeV3Protocol::from_file(&base_dir.join(path)),
+            Self::Inline(protocol) => Ok(protocol.clone()),
+        }
+    }
+}
+
+impl SundaeV3Protocol {
+    /// Loads a protocol by network name, or from a JSON file.
+    pub fn load(name_or_path: &str) -> Result<Self> {
+        if let Some(network) = Network::from_name(name_or_path) {
+            return Ok(network.protocol());
+        }
+        Self::from_file(Path::new(name_or_path))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("could not read protocol {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("invalid protocol {}", path.display()))
+    }
+}
+
+fn default_ada_rider() -> u64 {
+    DEFAULT_ADA_RIDER
+}
+
+fn script_hash<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex_str = String::deserialize(deserializer)?;
+    let bytes = hex::decode(&hex_str).map_err(|_| {
+        de::Error::invalid_value(de::Unexpected::Str(&hex_str), &"a hex script hash")
+    })?;
+    if bytes.len() != SCRIPT_HASH_SIZE {
+        return Err(de::Error::invalid_length(
+            bytes.len(),
+            &"a 28-byte script hash",
+        ));
+    }
+    Ok(bytes)
+}
+
+fn optional_script_hash<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<u8>>, D::Error> {
+    script_hash(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_load_named_networks() -> Result<()> {
+        assert_eq!(
+            SundaeV3Protocol::load("preview")?,
+            SundaeV3Protocol::from_file(Path::new("testdata/protocol"))?
+        );
+        assert_ne!(Network::Mainnet.protocol(), Network::Preview.protocol());
+        Ok(())
+    }
+
+    #[test]
+    fn should_convert_slots_to_posix_time() {
+        let mainnet = Network::Mainnet.protocol().slot_config.unwrap();
+        assert_eq!(mainnet.slot_to_posix(4_492_800), 1_596_059_091_000);
+        assert_eq!(mainnet.slot_to_posix(4_492_801), 1_596_059_092_000);
+        assert_eq!(mainnet.posix_to_slot(1_596_059_092_000), 4_492_801);
+        // Part way through a slot rounds up to the next one
+        assert_eq!(mainnet.posix_to_slot(1_596_059_091_500), 4_492_801);
+    }
+
+    #[test]
+    fn should_resolve_protocol_config() -> Result<()> {
+        let preview = Network::Preview.protocol();
+        let named = ProtocolConfig::NameOrPath("preview".to_string());
+        assert_eq!(named.resolve(Path::new("/nonexistent"))?, preview);
+        let path = ProtocolConfig::NameOrPath("protocol".to_string());
+        assert_eq!(path.resolve(Path::new("testdata"))?, preview);
+        let inline = ProtocolConfig::Inline(preview.clone());
+        assert_eq!(inline.resolve(Path::new("/nonexistent"))?, preview);
+        Ok(())
+    }
+
+    #[test]
+    fn should_default_ada_rider() -> Result<()> {
+        let hash = "00".repeat(SCRIPT_HASH_SIZE);
+        let json = format!(r#"{{"order_script_hash": "{hash}", "pool_script_hash": "{hash}"}}"#);
+        let protocol: SundaeV3Protocol = serde_json::from_str(&json)?;
+        assert_eq!(protocol.ada_rider, DEFAULT_ADA_RIDER);
+
+        let json = format!(
+            r#"{{"order_script_hash": "{hash}", "pool_script_hash": "{hash}", "ada_rider": 1500000}}"#
+        );
+        let protocol: SundaeV3Protocol = serde_json::from_str(&json)?;
+        assert_eq!(protocol.ada_rider, 1_500_000);
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_malformed_hashes() {
+        let parse = |json: &str| serde_json::from_str::<SundaeV3Protocol>(json).unwrap_err();
+        let short = parse(r#"{"order_script_hash": "abcd", "pool_script_hash": "abcd"}"#);
+        assert!(
+            short
+                .to_string()
+                .starts_with("invalid length 2, expected a 28-byte script hash at line 1"),
+            "{short}"
+        );
+        let typo = parse(r#"{"order_script_hash": "zz"}"#);
+        assert!(
+            typo.to_string().contains("expected a hex script hash"),
+            "{typo}"
+        );
+        let unknown = parse(r#"{"order_script": "abcd"}"#);
+        assert!(
+            unknown.to_string().contains("unknown field `order_script`"),
+            "{unknown}"
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Matching a transaction's redeemers to what they unlock. The ledger points
+//! each redeemer at its script by index: spend redeemers into the tx's inputs
+//! sorted by tx hash then output index, and mint redeemers into its minted
+//! policy ids sorted bytewise. Reference inputs and collateral are never
+//! counted, even when an input is also used as collateral.
+
+use std::collections::BTreeMap;
+
+use pallas_primitives::{PlutusData, conway::RedeemerTag};
+use pallas_traverse::MultiEraTx;
+
+use crate::cardano_types::TransactionInput;
+
+#[derive(Debug, Clone, Default)]
+pub struct TxRedeemers {
+    inputs: Vec<TransactionInput>,
+    policies: Vec<Vec<u8>>,
+    spend: BTreeMap<u32, PlutusData>,
+    mint: BTreeMap<u32, PlutusData>,
+}
+
+impl TxRedeemers {
+    pub fn from_tx(tx: &MultiEraTx) -> Self {
+        let mut inputs = tx
+            .inputs()
+            .into_iter()
+            .map(|i| TransactionInput::new(*i.hash(), i.index()))
+            .collect::<Vec<_>>();
+        inputs.sort();
+        inputs.dedup();
+
+        let mut policies = tx
+            .mints()
+            .iter()
+            .map(|m| m.policy().to_vec())
+            .collect::<Vec<_>>();
+        policies.sort();
+        policies.dedup();
+
+        let mut spend = BTreeMap::new();
+        let mut mint = BTreeMap::new();
+        for redeemer in tx.redeemers() {
+            let by_index = match redeemer.tag() {
+                RedeemerTag::Spend => &mut spend,
+                RedeemerTag::Mint => &mut mint,
+                _ => continue,
+            };
+            by_index.insert(redeemer.index(), redeemer.data().clone());
+        }
+
+        Self {
+            inputs,
+            policies,
+            spend,
+            mint,
+        }
+    }
+
+    /// The inputs in the order spend redeemers count them, which is also the
+    /// order scripts see them in.
+    pub fn inputs(&self) -> &[TransactionInput] {
+        &self.inputs
+    }
+
+    pub fn spends(&self, input: &TransactionInput) -> bool {
+        self.inputs.binary_search(input).is_ok()
+    }
+
+    pub fn spend(&self, input: &TransactionInput) -> Option<&PlutusData> {
+        let index = self.inputs.binary_search(input).ok()?;
+        self.spend.get(&(index as u32))
+    }
+
+    pub fn mint(&self, policy: &[u8]) -> Option<&PlutusData> {
+        let index = self
+            .policies
+            .binary_search_by(|p| p.as_slice().cmp(policy))
+            .ok()?;
+        self.mint.get(&(index as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pallas_traverse::MultiEraBlock;
+    use plutus_parser::AsPlutus;
+
+    use super::*;
+    use crate::sundaev3::{OrderRedeemer, PoolScoop};
+
+    fn input(tx_hash: &str, index: u64) -> TransactionInput {
+        let bytes = hex::decode(tx_hash).unwrap();
+        TransactionInput::new(bytes.as_slice().into(), index)
+    }
+
+    #[test]
+    fn should_index_spends_by_sorted_input() {
+        let bytes = fs::read("testdata/scoop-pool.block").unwrap();
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+        let txs = block.txs();
+
+        // The body lists its inputs unsorted, and one doubles as collateral
+        let first = TxRedeemers::from_tx(&txs[0]);
+        assert_eq!(first.inputs().len(), 3);
+        assert!(first.inputs().is_sorted());
+        let order = first.inputs()[0].clone();
+        assert!(order.to_string().starts_with("3d54f658"));
+        let redeemer = OrderRedeemer::from_plutus(first.spend(&order).unwrap().clone());
+        assert!(matches!(redeemer, Ok(OrderRedeemer::Scoop)));
+        assert!(first.spend(&first.inputs()[2]).is_none());
+
+        // The scoop's input order points at the one order it spends
+        let scoop = TxRedeemers::from_tx(&txs[1]);
+        let pool = scoop.inputs()[2].clone();
+        let pool_scoop = PoolScoop::from_spend_redeemer(scoop.spend(&pool).unwrap()).unwrap();
+        assert_eq!(pool_scoop.input_indexes(), vec![1]);
+        let order = &scoop.inputs()[1];
+        let redeemer = OrderRedeemer::from_plutus(scoop.spend(order).unwrap().clone());
+        assert!(matches!(redeemer, Ok(OrderRedeemer::Scoop)));
+        assert!(scoop.spend(&scoop.inputs()[0]).is_none());
+        assert!(!scoop.spends(&input(&"00".repeat(32), 0)));
+        assert!(scoop.mint(&[0; 28]).is_none());
+    }
+}
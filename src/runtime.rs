@@ -0,0 +1,647 @@
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+use acropolis_common::{Point, messages::Message};
+use acropolis_module_block_unpacker::BlockUnpacker;
+use acropolis_module_custom_indexer::CustomIndexer;
+use acropolis_module_genesis_bootstrapper::GenesisBootstrapper;
+use acropolis_module_mithril_snapshot_fetcher::MithrilSnapshotFetcher;
+use acropolis_module_peer_network_interface::PeerNetworkInterface;
+use anyhow::{Result, bail};
+use caryatid_process::Process;
+use caryatid_sdk::module_registry::ModuleRegistry;
+use config::Config;
+use tokio::{
+    select,
+    sync::{Mutex, broadcast, watch},
+    task::{JoinError, JoinHandle},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::{
+    SundaeV3Protocol,
+    backoff::Backoff,
+    cardano_types::Slot,
+    config::{AppConfig, ROLLBACK_LIMIT, RestartConfig, use_mithril},
+    extensions::{EXTENSION_INDEX_PREFIX, ExtensionIndexes},
+    metrics,
+    persistence::Persistence,
+    sundaev3::{
+        OrderEvent, OrderLimits, PoolIndex, SUNDAE_V3_INDEX_NAME, StartupRepair,
+        SundaeV3HistoricalState, SundaeV3Indexer, SundaeV3Update, coalesce_updates,
+        repair_ahead_of_cursor,
+    },
+};
+
+#[derive(Clone, Debug)]
+pub enum RestartRequest {
+    /// Throw away the indexed state and sync again from the start point
+    Resync,
+    /// Reconnect and resume from the stored cursor, keeping the indexed state
+    SoftResync,
+    /// Clear an index's halted flag, optionally rewinding it, and resume
+    Unhalt {
+        index: String,
+        rewind_to: Option<Point>,
+    },
+}
+
+/// Sent to subscribers each time the pipeline restarts. Updates pause while it
+/// does, so anything a subscriber has built up from them should be rebuilt from
+/// the next state update.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResyncEvent {
+    /// The slot indexing starts again from
+    pub slot: u64,
+    /// Whether the index starts over from the configured start point, rather
+    /// than resuming from its cursor
+    pub from_start: bool,
+}
+
+/// The indexing pipeline: acropolis, the SundaeV3 index and its persistence,
+/// without any of the consumers. Other binaries can embed this and subscribe
+/// to its updates.
+pub struct ScooperRuntime {
+    index: Arc<Mutex<SundaeV3HistoricalState>>,
+    pools: PoolIndex,
+    restart_tx: broadcast::Sender<RestartRequest>,
+    broadcaster: watch::Sender<SundaeV3Update>,
+    order_events: broadcast::Sender<OrderEvent>,
+    resyncs: broadcast::Sender<ResyncEvent>,
+    persistence: Arc<dyn Persistence>,
+    shutdown: CancellationToken,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl ScooperRuntime {
+    /// Repairs the database if it got ahead of the cursor, then starts indexing
+    /// from the stored cursor, or `default_start` if there is none.
+    pub async fn start(
+        config: Arc<Config>,
+        app_config: &AppConfig,
+        protocol: SundaeV3Protocol,
+        persistence: Arc<dyn Persistence>,
+        default_start: Point,
+    ) -> Result<Self> {
+        Self::start_with_extensions(
+            config,
+            app_config,
+            protocol,
+            persistence,
+            default_start,
+            ExtensionIndexes::new(),
+        )
+        .await
+    }
+
+    /// As [`Self::start`], also running extension indexes over the same chain.
+    /// Each resumes from its own cursor, and a resync restarts them all.
+    pub async fn start_with_extensions(
+        config: Arc<Config>,
+        app_config: &AppConfig,
+        protocol: SundaeV3Protocol,
+        persistence: Arc<dyn Persistence>,
+        default_start: Point,
+        extensions: ExtensionIndexes,
+    ) -> Result<Self> {
+        repair_database(persistence.as_ref()).await?;
+        if let Some(deepest) = persistence.sundae_v3_dao().load_deepest_rollback().await? {
+            metrics::ROLLBACK_MAX_DEPTH.set_max(deepest.depth);
+        }
+
+        let index = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let pools = PoolIndex::new();
+        let (restart_tx, _) = broadcast::channel(1);
+        let shutdown = CancellationToken::new();
+        // The indexer publishes to `updates`, and subscribers see the coalesced `broadcaster`
+        let (updates, update_rx) = watch::channel(SundaeV3Update::default());
+        let broadcaster = watch::Sender::default();
+        let (order_events, _) = broadcast::channel(1024);
+        let (resyncs, _) = broadcast::channel(16);
+
+        let manager_handle = tokio::spawn(manager_loop(
+            index.clone(),
+            pools.clone(),
+            restart_tx.clone(),
+            updates,
+            order_events.clone(),
+            resyncs.clone(),
+            config,
+            protocol,
+            persistence.clone(),
+            default_start,
+            app_config.restart.clone(),
+            app_config.order_limits.clone(),
+            Duration::from_millis(app_config.cursors.save_interval_ms),
+            extensions,
+            shutdown.child_token(),
+        ));
+        let broadcast_handle = tokio::spawn(coalesce_updates(
+            update_rx,
+            broadcaster.clone(),
+            app_config.broadcast.clone(),
+            shutdown.child_token(),
+        ));
+
+        Ok(Self {
+            index,
+            pools,
+            restart_tx,
+            broadcaster,
+            order_events,
+            resyncs,
+            persistence,
+            shutdown,
+            handles: vec![manager_handle, broadcast_handle],
+        })
+    }
+
+    /// Runs without acropolis, following the state another node indexes into
+    /// the shared database. The state is reloaded whenever the database's
+    /// latest slot moves, so it has no rollback history and no order events
+    /// are published. Extension indexes only run on the indexing node.
+    pub async fn follow(
+        app_config: &AppConfig,
+        protocol: SundaeV3Protocol,
+        persistence: Arc<dyn Persistence>,
+    ) -> Result<Self> {
+        let index = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let pools = PoolIndex::new();
+        let (restart_tx, _) = broadcast::channel(1);
+        let shutdown = CancellationToken::new();
+        let (updates, update_rx) = watch::channel(SundaeV3Update::default());
+        let broadcaster = watch::Sender::default();
+        let (order_events, _) = broadcast::channel(1024);
+        // Nothing restarts while following, so no resyncs are sent
+        let (resyncs, _) = broadcast::channel(1);
+
+        let mut indexer = SundaeV3Indexer::new(
+            index.clone(),
+            updates,
+            order_events.clone(),
+            protocol,
+            ROLLBACK_LIMIT,
+            persistence.sundae_v3_dao(),
+        )
+        .with_order_limits(app_config.order_limits.clone())
+        .with_pool_index(pools.clone());
+        // Read before loading, so anything written meanwhile is loaded next
+        let loaded = persistence.sundae_v3_dao().latest_slot().await?;
+        indexer.load().await?;
+        let follow_handle = tokio::spawn(follow_loop(
+            indexer,
+            loaded,
+            persistence.clone(),
+            Duration::from_millis(app_config.components.follow_interval_ms),
+            restart_tx.subscribe(),
+            shutdown.child_token(),
+        ));
+        let broadcast_handle = tokio::spawn(coalesce_updates(
+            update_rx,
+            broadcaster.clone(),
+            app_config.broadcast.clone(),
+            shutdown.child_token(),
+        ));
+
+        Ok(Self {
+            index,
+            pools,
+            restart_tx,
+            broadcaster,
+            order_events,
+            resyncs,
+            persistence,
+            shutdown,
+            handles: vec![follow_handle, broadcast_handle],
+        })
+    }
+
+    /// The latest SundaeV3 state, updated as blocks are indexed.
+    pub fn subscribe(&self) -> watch::Receiver<SundaeV3Update> {
+        self.broadcaster.subscribe()
+    }
+
+    /// Orders as they are scooped or cancelled.
+    pub fn subscribe_order_events(&self) -> broadcast::Receiver<OrderEvent> {
+        self.order_events.subscribe()
+    }
+
+    /// Where indexing starts again from, each time the pipeline restarts.
+    pub fn subscribe_resyncs(&self) -> broadcast::Receiver<ResyncEvent> {
+        self.resyncs.subscribe()
+    }
+
+    /// The in-memory history, including states within the rollback window.
+    pub fn index(&self) -> Arc<Mutex<SundaeV3HistoricalState>> {
+        self.index.clone()
+    }
+
+    /// Each pool's latest state, readable without waiting on the indexer.
+    pub fn pools(&self) -> PoolIndex {
+        self.pools.clone()
+    }
+
+    pub fn persistence(&self) -> Arc<dyn Persistence> {
+        self.persistence.clone()
+    }
+
+    /// For asking the pipeline to resync or unhalt.
+    pub fn restarts(&self) -> broadcast::Sender<RestartRequest> {
+        self.restart_tx.clone()
+    }
+
+    /// Cancelled when the runtime shuts down, for tasks which should stop with it.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Waits for the pipeline to stop, which happens after [`Self::shutdown`].
+    pub async fn join(self) -> Result<(), JoinError> {
+        for handle in self.handles {
+            handle.await?;
+        }
+        Ok(())
+    }
+}
+
+async fn verify_resume_point(
+    persistence: &dyn Persistence,
+    history: &Mutex<SundaeV3HistoricalState>,
+) -> Result<()> {
+    let cursors = persistence.cursor_store().entries().await?;
+    let Some(cursor) = cursors.get(SUNDAE_V3_INDEX_NAME) else {
+        return Ok(());
+    };
+    history.lock().await.verify_resume_point(&cursor.tip)
+}
+
+/// Tells subscribers where a restarted pipeline picks up from: the stored
+/// cursor, or the start point if it was forced to or has none.
+async fn announce_resync(
+    persistence: &dyn Persistence,
+    resyncs: &broadcast::Sender<ResyncEvent>,
+    default_start: &Point,
+    force_restart: bool,
+) {
+    let cursor = if force_restart {
+        None
+    } else {
+        match persistence.cursor_store().entries().await {
+            Ok(mut entries) => entries.remove(SUNDAE_V3_INDEX_NAME),
+            Err(err) => {
+                warn!("could not read the cursor to announce the resync: {err:#}");
+                None
+            }
+        }
+    };
+    let event = match cursor {
+        Some(cursor) => ResyncEvent {
+            slot: cursor.tip.slot(),
+            from_start: false,
+        },
+        None => ResyncEvent {
+            slot: default_start.slot(),
+            from_start: true,
+        },
+    };
+    info!(
+        slot = event.slot,
+        from_start = event.from_start,
+        "resyncing subscribers"
+    );
+    let _ = resyncs.send(event);
+}
+
+const HALT_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The custom indexer halts an index which fails, and keeps running the rest.
+/// Reports newly halted indexes, so they don't go unnoticed.
+async fn check_halted_indexes(persistence: &dyn Persistence, halted: &mut BTreeSet<String>) {
+    let entries = match persistence.cursor_store().entries().await {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("could not check for halted indexes: {err:#}");
+            return;
+        }
+    };
+    let now_halted: BTreeSet<String> = entries
+        .into_iter()
+        .filter(|(_, entry)| entry.halted)
+        .map(|(id, _)| id)
+        .collect();
+    for index in now_halted.difference(halted) {
+        error!("index {index} halted after an error, unhalt it with /cursors/{index}/unhalt");
+    }
+    metrics::INDEXES_HALTED.set(now_halted.len() as u64);
+    *halted = now_halted;
+}
+
+pub async fn unhalt_index(
+    persistence: &dyn Persistence,
+    history: &Mutex<SundaeV3HistoricalState>,
+    id: &str,
+    rewind_to: Option<Point>,
+) -> Result<()> {
+    if let Some(point) = &rewind_to {
+        if let Some(namespace) = id.strip_prefix(EXTENSION_INDEX_PREFIX) {
+            persistence
+                .extension_dao(namespace)
+                .rollback(Slot(point.slot()))
+                .await?;
+        } else if id == SUNDAE_V3_INDEX_NAME {
+            persistence
+                .sundae_v3_dao()
+                .rollback(Slot(point.slot()))
+                .await?;
+            history.lock().await.rollback_to_slot(point.slot());
+        } else {
+            bail!("cannot rewind index \"{id}\"");
+        }
+    }
+    persistence.cursor_store().unhalt(id, rewind_to).await
+}
+
+async fn follow_loop(
+    mut indexer: SundaeV3Indexer,
+    mut loaded: Option<Slot>,
+    persistence: Arc<dyn Persistence>,
+    interval: Duration,
+    mut restart_rx: broadcast::Receiver<RestartRequest>,
+    shutdown: CancellationToken,
+) {
+    let dao = persistence.sundae_v3_dao();
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        select! {
+            _ = ticker.tick() => {}
+            Ok(_) = restart_rx.recv() => {
+                warn!("the indexer is disabled, so there is nothing to restart");
+                continue;
+            }
+            _ = shutdown.cancelled() => break,
+        }
+        let latest = match dao.latest_slot().await {
+            Ok(latest) => latest,
+            Err(err) => {
+                warn!("could not check the database for new blocks: {err:#}");
+                continue;
+            }
+        };
+        if latest == loaded {
+            continue;
+        }
+        match indexer.load().await {
+            Ok(()) => loaded = latest,
+            Err(err) => warn!("could not reload the state from the database: {err:#}"),
+        }
+    }
+}
+
+/// Rolls the database back to the stored cursor if it got ahead of it, so
+/// what's loaded is where indexing resumes from.
+async fn repair_database(persistence: &dyn Persistence) -> Result<()> {
+    match repair_ahead_of_cursor(persistence).await? {
+        Some(StartupRepair {
+            db_slot,
+            cursor: Some(cursor),
+        }) => warn!("database was at slot {db_slot}, ahead of cursor {cursor}; rolled it back"),
+        Some(StartupRepair {
+            db_slot,
+            cursor: None,
+        }) => warn!("database was at slot {db_slot} with no cursor stored; cleared it"),
+        None => {}
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn manager_loop(
+    index: Arc<Mutex<SundaeV3HistoricalState>>,
+    pools: PoolIndex,
+    restart_tx: broadcast::Sender<RestartRequest>,
+    broadcaster: watch::Sender<SundaeV3Update>,
+    order_events: broadcast::Sender<OrderEvent>,
+    resyncs: broadcast::Sender<ResyncEvent>,
+    config: Arc<Config>,
+    protocol: SundaeV3Protocol,
+    persistence: Arc<dyn Persistence>,
+    default_start: Point,
+    restart_config: RestartConfig,
+    order_limits: OrderLimits,
+    cursor_save_interval: Duration,
+    extensions: ExtensionIndexes,
+    shutdown: CancellationToken,
+) {
+    let mut force_restart = false;
+    let mut backoff = Backoff::new(restart_config);
+    let mut halted = BTreeSet::new();
+    let mut restarted = false;
+    loop {
+        let history = index.clone();
+        let index = index.clone();
+        let mut restart_rx = restart_tx.subscribe();
+        let config = config.clone();
+        let protocol = protocol.clone();
+        let default_start = default_start.clone();
+        let broadcaster = broadcaster.clone();
+        let order_events = order_events.clone();
+        let enable_mithril = use_mithril(&config);
+
+        let mut process = Process::<Message>::create(config).await;
+        GenesisBootstrapper::register(&mut process);
+        if enable_mithril {
+            MithrilSnapshotFetcher::register(&mut process);
+        }
+        BlockUnpacker::register(&mut process);
+        PeerNetworkInterface::register(&mut process);
+
+        // Kept to flush the cursors the indexer's saves skip, once it stops
+        let cursors = persistence
+            .cursor_store()
+            .with_save_interval(cursor_save_interval);
+        let indexer = Arc::new(CustomIndexer::new(cursors.clone()));
+        process.register(indexer.clone());
+
+        // A crash, or a cursor save that failed, can leave the database ahead
+        if let Err(err) = repair_database(persistence.as_ref()).await {
+            warn!("could not check the database against the cursor: {err:#}");
+        }
+
+        let mut v3_index = SundaeV3Indexer::new(
+            index,
+            broadcaster,
+            order_events,
+            protocol,
+            ROLLBACK_LIMIT,
+            persistence.sundae_v3_dao(),
+        )
+        .with_order_limits(order_limits.clone())
+        .with_pool_index(pools.clone())
+        .with_restart_requests(restart_tx.clone());
+        v3_index.load().await.unwrap();
+
+        indexer
+            .add_index(v3_index, default_start.clone(), force_restart)
+            .await
+            .unwrap();
+        for (extension, start) in extensions.build(persistence.as_ref()).await.unwrap() {
+            indexer
+                .add_index(extension, start, force_restart)
+                .await
+                .unwrap();
+        }
+        if restarted {
+            announce_resync(
+                persistence.as_ref(),
+                &resyncs,
+                &default_start,
+                force_restart,
+            )
+            .await;
+        }
+
+        let request = match process.start().await {
+            Ok(running_process) => {
+                backoff.reset();
+                let mut halt_check = tokio::time::interval(HALT_CHECK_INTERVAL);
+                let request = loop {
+                    select! {
+                        res = restart_rx.recv() => break res.ok(),
+                        _ = shutdown.cancelled() => break None,
+                        _ = halt_check.tick() => {
+                            check_halted_indexes(persistence.as_ref(), &mut halted).await;
+                        }
+                    }
+                };
+
+                info!("terminating acropolis process");
+                match running_process.stop().await {
+                    Ok(()) => info!("terminated acropolis process"),
+                    Err(err) => warn!("could not terminate acropolis process: {err:#}"),
+                }
+                request
+            }
+            Err(err) => {
+                metrics::INDEXER_START_FAILURES.inc();
+                if let Some(delay) = backoff.next_delay() {
+                    warn!("could not start acropolis process, retrying in {delay:?}: {err:#}");
+                    select! {
+                        _ = tokio::time::sleep(delay) => {},
+                        _ = shutdown.cancelled() => break,
+                    }
+                    warn!("Restarting Scooper indexer");
+                    continue;
+                }
+
+                error!(
+                    "could not start acropolis process after {} attempts, waiting for a restart request: {err:#}",
+                    backoff.failures()
+                );
+                metrics::INDEXER_CIRCUIT_OPEN.set(1);
+                let request = select! {
+                    res = restart_rx.recv() => res.ok(),
+                    _ = shutdown.cancelled() => None,
+                };
+                metrics::INDEXER_CIRCUIT_OPEN.set(0);
+                backoff.reset();
+                request
+            }
+        };
+
+        if let Err(err) = cursors.flush().await {
+            warn!("could not save the latest cursors: {err:#}");
+        }
+
+        match request {
+            None => break,
+            Some(RestartRequest::Resync) => force_restart = true,
+            Some(RestartRequest::SoftResync) => {
+                force_restart = false;
+                if let Err(err) = verify_resume_point(persistence.as_ref(), &history).await {
+                    warn!("cannot resume from the stored cursor, resyncing instead: {err:#}");
+                    force_restart = true;
+                }
+            }
+            Some(RestartRequest::Unhalt { index, rewind_to }) => {
+                // The cursors are only safe to edit while nothing else is saving them
+                force_restart = false;
+                match unhalt_index(persistence.as_ref(), &history, &index, rewind_to).await {
+                    Ok(()) => info!("unhalted {index}"),
+                    Err(err) => warn!("could not unhalt {index}: {err:#}"),
+                }
+            }
+        }
+        metrics::INDEXER_RESTARTS.inc();
+        restarted = true;
+
+        warn!("Restarting Scooper indexer");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use acropolis_common::hash::Hash;
+    use acropolis_module_custom_indexer::cursor_store::{CursorEntry, CursorStore};
+
+    use super::*;
+    use crate::persistence::{self, PersistenceConfig};
+
+    #[tokio::test]
+    async fn should_tell_subscribers_where_a_restart_resumes_from() -> Result<()> {
+        let persistence = persistence::connect(&PersistenceConfig::default()).await?;
+        let (resyncs, _) = broadcast::channel(16);
+        let mut subscriber = resyncs.subscribe();
+        let start = Point::Specific {
+            slot: 100,
+            hash: Hash::default(),
+        };
+
+        // Without a cursor, a restart starts over
+        announce_resync(persistence.as_ref(), &resyncs, &start, false).await;
+        assert_eq!(
+            subscriber.recv().await?,
+            ResyncEvent {
+                slot: 100,
+                from_start: true
+            }
+        );
+
+        let cursor = CursorEntry {
+            tip: Point::Specific {
+                slot: 1337,
+                hash: Hash::default(),
+            },
+            halted: false,
+        };
+        persistence
+            .cursor_store()
+            .save(&HashMap::from([(SUNDAE_V3_INDEX_NAME.to_string(), cursor)]))
+            .await?;
+
+        announce_resync(persistence.as_ref(), &resyncs, &start, false).await;
+        assert_eq!(
+            subscriber.recv().await?,
+            ResyncEvent {
+                slot: 1337,
+                from_start: false
+            }
+        );
+
+        // A forced resync ignores the cursor
+        announce_resync(persistence.as_ref(), &resyncs, &start, true).await;
+        assert_eq!(
+            subscriber.recv().await?,
+            ResyncEvent {
+                slot: 100,
+                from_start: true
+            }
+        );
+        Ok(())
+    }
+}
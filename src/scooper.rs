@@ -7,45 +7,159 @@ use std::{
     time::Duration,
 };
 
-use anyhow::Result;
-use serde::Serialize;
-use tokio::{select, sync::watch};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    select,
+    sync::{broadcast, watch},
+};
 use tokio_util::sync::CancellationToken;
-use tracing::warn;
-
-const LOG_DIR: &str = "logs";
+use tracing::{Span, error, info, info_span, warn};
 
 use crate::{
-    bigint::BigInt,
+    bigint::{BigInt, BigRational},
     cardano_types::{AssetClass, TransactionInput},
+    clock::Clock,
+    competition::{CompetitionStats, CompetitionTracker},
+    large_trades::{LargeTrade, LargeTradePolicy},
+    metrics,
+    multisig::TimeWindow,
+    order_filter::{FilterNote, OrderFilter, check_order},
+    owner_policy::{Deferral, OwnerPolicy},
+    protocol::SlotConfig,
     sundaev3::{
-        Ident, PoolError, SundaeV3Order, SundaeV3Pool, SundaeV3State, SundaeV3Update, ValueError,
-        estimate_whether_in_range, get_pool_price, validate_order_for_pool, validate_order_value,
+        Ident, OrderEvent, PauseReason, PauseRules, PoolError, SundaeV3Order, SundaeV3Pool,
+        SundaeV3State, SundaeV3Update, ValidationProfile, ValueError, estimate_whether_in_range,
+        get_pool_price, validate_order_for_pool, validate_order_value,
     },
 };
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ScooperConfig {
+    /// How long to wait after a state update for more to arrive. Updates are
+    /// already coalesced before they're broadcast, so this is off by default.
+    pub debounce_ms: u64,
+    /// Hex hashes of the keys this scooper signs with. If any of them is
+    /// dropped from the authorized scoopers, we alert and report not ready.
+    pub key_hashes: Vec<String>,
+    /// Which order value checks keep an order out of a scoop.
+    pub validation_profile: ValidationProfile,
+    /// Limits on how many orders one owner can have in a scoop.
+    pub owner_policy: OwnerPolicy,
+    /// How long large swaps are held back before they're scooped.
+    pub large_trades: LargeTradePolicy,
+    /// How to tell that a pool is paused, so its orders aren't scooped.
+    pub pause_rules: PauseRules,
+    /// Where the daily update logs are written.
+    pub log_dir: PathBuf,
+}
+
+impl Default for ScooperConfig {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 0,
+            key_hashes: vec![],
+            validation_profile: ValidationProfile::default(),
+            owner_policy: OwnerPolicy::default(),
+            large_trades: LargeTradePolicy::default(),
+            pause_rules: PauseRules::default(),
+            log_dir: PathBuf::from("logs"),
+        }
+    }
+}
+
 pub struct Scooper {
     sundaev3: watch::Receiver<SundaeV3Update>,
     policy: Vec<u8>,
+    ada_rider: u64,
+    validation_profile: ValidationProfile,
+    owner_policy: OwnerPolicy,
+    large_trades: LargeTradePolicy,
+    pause_rules: PauseRules,
+    slot_config: Option<SlotConfig>,
+    log_dir: PathBuf,
+    clock: Arc<dyn Clock>,
+    debounce: Duration,
+    key_hashes: Vec<Vec<u8>>,
+    deauthorized: Vec<Vec<u8>>,
     pools: BTreeMap<Ident, PoolSummary>,
     orders: BTreeMap<TransactionInput, OrderValidity>,
+    competition: CompetitionTracker,
+    filters: Vec<Arc<dyn OrderFilter>>,
 }
 
 impl Scooper {
-    pub fn new(sundaev3: watch::Receiver<SundaeV3Update>, policy: &[u8]) -> Result<Self> {
-        fs::create_dir_all(LOG_DIR)?;
+    pub fn new(
+        sundaev3: watch::Receiver<SundaeV3Update>,
+        policy: &[u8],
+        ada_rider: u64,
+        config: &ScooperConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
+        fs::create_dir_all(&config.log_dir)?;
+        let key_hashes: Vec<Vec<u8>> = config
+            .key_hashes
+            .iter()
+            .map(|key| hex::decode(key).with_context(|| format!("invalid key hash {key}")))
+            .collect::<Result<_>>()?;
         Ok(Self {
             sundaev3,
             policy: policy.to_vec(),
+            ada_rider,
+            validation_profile: config.validation_profile,
+            owner_policy: config.owner_policy.clone(),
+            large_trades: config.large_trades.clone(),
+            pause_rules: config.pause_rules.clone(),
+            slot_config: None,
+            log_dir: config.log_dir.clone(),
+            clock,
+            debounce: Duration::from_millis(config.debounce_ms),
+            competition: CompetitionTracker::new(key_hashes.clone()),
+            key_hashes,
+            deauthorized: vec![],
             pools: BTreeMap::new(),
             orders: BTreeMap::new(),
+            filters: vec![],
         })
     }
 
-    pub async fn run(mut self, shutdown: CancellationToken) {
+    /// Runs `filter` over every order which passes validation, in the order
+    /// filters were added.
+    pub fn with_order_filter(mut self, filter: Arc<dyn OrderFilter>) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Checks the time conditions in order owners against the slot time.
+    pub fn with_slot_config(mut self, slot_config: SlotConfig) -> Self {
+        self.slot_config = Some(slot_config);
+        self
+    }
+
+    /// How the orders we would have scooped were actually scooped.
+    pub fn competition(&self) -> CompetitionStats {
+        self.competition.stats()
+    }
+
+    pub async fn run(
+        mut self,
+        mut order_events: broadcast::Receiver<OrderEvent>,
+        shutdown: CancellationToken,
+    ) {
         loop {
             select! {
                 _ = shutdown.cancelled() => { break; }
+                res = order_events.recv() => {
+                    match res {
+                        Ok(event) => self.competition.record(&event),
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!(skipped, "missed order events, competition stats are incomplete");
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                    continue;
+                }
                 res = self.sundaev3.changed() => {
                     if res.is_err() {
                         break;
@@ -54,27 +168,74 @@ impl Scooper {
             }
 
             // Sleep a bit to deduplicate updates to the state.
-            tokio::time::sleep(Duration::from_millis(250)).await;
+            if !self.debounce.is_zero() {
+                self.clock.sleep(self.debounce).await;
+            }
 
             let update = self.sundaev3.borrow_and_update().clone();
+            let span = info_span!(
+                parent: update.span.as_ref().and_then(Span::id),
+                "check_orders",
+                slot = update.slot
+            );
             // TODO: only "scoop" when we're at the head of the chain
-            self.log_changes(update.slot, &update.state);
+            span.in_scope(|| self.log_changes(update.slot, &update.state));
         }
     }
 
     fn log_changes(&mut self, slot: u64, state: &SundaeV3State) {
+        self.check_authorization(slot, state);
         self.log_pools(slot, state);
         self.log_orders(slot, state);
     }
 
+    fn check_authorization(&mut self, slot: u64, state: &SundaeV3State) {
+        let Some(settings) = &state.settings else {
+            return;
+        };
+        let missing: Vec<Vec<u8>> = settings
+            .datum
+            .unauthorized_scoopers(&self.key_hashes)
+            .into_iter()
+            .cloned()
+            .collect();
+        if missing == self.deauthorized {
+            return;
+        }
+        for key in missing.iter().filter(|k| !self.deauthorized.contains(k)) {
+            error!(
+                slot,
+                key = hex::encode(key),
+                "our scooper key was removed from the authorized scoopers"
+            );
+        }
+        for key in self.deauthorized.iter().filter(|k| !missing.contains(k)) {
+            info!(
+                slot,
+                key = hex::encode(key),
+                "our scooper key is authorized again"
+            );
+        }
+        metrics::SCOOPER_DEAUTHORIZED.set(missing.len() as u64);
+        self.deauthorized = missing;
+    }
+
     fn log_pools(&mut self, slot: u64, state: &SundaeV3State) {
+        let now = self
+            .slot_config
+            .map(|slot_config| BigInt::from(slot_config.slot_to_posix(slot)));
+        let settings = state.settings.as_ref().map(|settings| &settings.datum);
         let mut new_pools = BTreeMap::new();
         for (ident, pool) in &state.pools {
             let price = get_pool_price(&self.policy, &pool.value, &pool.pool_datum.protocol_fees);
+            let paused = self
+                .pause_rules
+                .check(&pool.pool_datum, settings, now.as_ref());
             let summary = PoolSummary {
                 assets: pool.pool_datum.assets.clone(),
                 price,
                 protocol_fees: pool.pool_datum.protocol_fees.clone(),
+                paused,
             };
             new_pools.insert(ident.clone(), summary);
         }
@@ -120,9 +281,16 @@ impl Scooper {
     fn log_orders(&mut self, slot: u64, state: &SundaeV3State) {
         let mut new_orders = BTreeMap::new();
         for order in &state.orders {
-            let validity = self.validate_order(order, &state.pools);
+            let validity = self.validate_order(slot, order, &state.pools);
             new_orders.insert(order.input.clone(), validity);
         }
+        self.apply_owner_policy(state, &mut new_orders);
+        self.competition.update(
+            slot,
+            new_orders
+                .iter()
+                .map(|(txo, validity)| (txo, matches!(validity, OrderValidity::Valid { .. }))),
+        );
 
         let mut updates = vec![];
         for (txo, validity) in &new_orders {
@@ -162,6 +330,49 @@ impl Scooper {
         self.orders = new_orders;
     }
 
+    /// Takes the pools the owner policy holds an order back from out of its
+    /// valid pools. If that leaves none, the order is deferred.
+    fn apply_owner_policy(
+        &self,
+        state: &SundaeV3State,
+        validities: &mut BTreeMap<TransactionInput, OrderValidity>,
+    ) {
+        if !self.owner_policy.is_enabled() {
+            return;
+        }
+        let candidates =
+            state
+                .orders
+                .iter()
+                .filter_map(|order| match validities.get(&order.input) {
+                    Some(OrderValidity::Valid { pools, .. }) => {
+                        Some((order.as_ref(), pools.as_slice()))
+                    }
+                    _ => None,
+                });
+        let deferred = self.owner_policy.defer(candidates);
+        metrics::ORDERS_DEFERRED.set(deferred.len() as u64);
+        for (txo, deferrals) in deferred {
+            let Some(validity) = validities.get_mut(&txo) else {
+                continue;
+            };
+            let OrderValidity::Valid {
+                pools,
+                deferred: held_back,
+                ..
+            } = validity
+            else {
+                continue;
+            };
+            pools.retain(|pool| !deferrals.iter().any(|d| &d.pool == pool));
+            if pools.is_empty() {
+                *validity = OrderValidity::Deferred { deferrals };
+            } else {
+                *held_back = deferrals;
+            }
+        }
+    }
+
     // Log if the order's valid state has changed, unless the change is just becuase the pool price changed
     fn validity_changed(&self, old: &OrderValidity, new: &OrderValidity) -> bool {
         match (old, new) {
@@ -205,32 +416,88 @@ impl Scooper {
 
     fn validate_order(
         &self,
+        slot: u64,
         order: &SundaeV3Order,
         pools: &BTreeMap<Ident, Arc<SundaeV3Pool>>,
     ) -> OrderValidity {
-        if let Err(err) = validate_order_value(&order.datum, &order.output.value) {
-            return OrderValidity::Invalid {
-                reason: OrderInvalidReason::ValueError(err),
-            };
+        // An owner who can no longer act can't have the order scooped either
+        if let Some(slot_config) = &self.slot_config {
+            let now = BigInt::from(slot_config.slot_to_posix(slot));
+            match order.datum.owner.time_window(&now) {
+                TimeWindow::Open => {}
+                TimeWindow::From(time) => {
+                    let from_slot = slot_config.posix_to_slot(time.to_u64().unwrap_or(u64::MAX));
+                    return OrderValidity::Scheduled { from_slot };
+                }
+                TimeWindow::Closed => {
+                    return OrderValidity::Invalid {
+                        reason: OrderInvalidReason::Expired,
+                    };
+                }
+            }
         }
+        let advisories = match validate_order_value(
+            &order.datum,
+            &order.output.value,
+            self.ada_rider,
+            self.validation_profile,
+        ) {
+            Ok(advisories) => advisories,
+            Err(err) => {
+                return OrderValidity::Invalid {
+                    reason: OrderInvalidReason::ValueError(err),
+                };
+            }
+        };
         let mut valid_pools = vec![];
+        let mut notes = vec![];
+        let mut vetoes = vec![];
+        let mut delayed = vec![];
+        let mut released = vec![];
         let mut errors = BTreeMap::new();
         for (ident, pool) in pools {
-            if let Err(error) = validate_order_for_pool(&order.datum, &pool.pool_datum) {
+            if let Err(error) =
+                validate_order_for_pool(&order.datum, &pool.pool_datum, &self.policy)
+            {
                 if matches!(error, PoolError::IdentMismatch) {
                     continue;
                 }
                 errors.insert(ident.clone(), error);
+            } else if let Some(reason) = self.pools.get(ident).and_then(|pool| pool.paused.clone())
+            {
+                errors.insert(ident.clone(), PoolError::Paused(reason));
             } else if let Err(error) =
                 estimate_whether_in_range(&self.policy, &order.datum, &pool.pool_datum, &pool.value)
             {
                 errors.insert(ident.clone(), error);
             } else {
-                valid_pools.push(ident.clone());
+                match check_order(&self.filters, order, pool) {
+                    Ok(pool_notes) => match self.large_trades.check(order, pool) {
+                        Some(trade) if slot < trade.until_slot => delayed.push(trade),
+                        trade => {
+                            valid_pools.push(ident.clone());
+                            notes.extend(pool_notes);
+                            released.extend(trade);
+                        }
+                    },
+                    Err(veto) => vetoes.push(veto),
+                }
             }
         }
         if !valid_pools.is_empty() {
-            OrderValidity::Valid { pools: valid_pools }
+            OrderValidity::Valid {
+                pools: valid_pools,
+                advisories,
+                notes,
+                deferred: vec![],
+                large_trades: released,
+            }
+        } else if !delayed.is_empty() {
+            OrderValidity::Delayed { trades: delayed }
+        } else if !vetoes.is_empty() {
+            OrderValidity::Invalid {
+                reason: OrderInvalidReason::Vetoed(vetoes),
+            }
         } else if !errors.is_empty() {
             OrderValidity::Invalid {
                 reason: OrderInvalidReason::PoolErrors(errors),
@@ -242,17 +509,17 @@ impl Scooper {
         }
     }
 
+    /// Logs are split into a file per day.
+    fn log_path(&self) -> PathBuf {
+        let date = self.clock.now().date_naive().format("%Y-%m-%d").to_string();
+        self.log_dir.join(format!("{date}.jsonl"))
+    }
+
     fn write_updates<T: Serialize>(&self, updates: &[T]) -> Result<()> {
-        let date = chrono::Utc::now()
-            .date_naive()
-            .format("%Y-%m-%d")
-            .to_string();
-        let filename = format!("{date}.jsonl");
-        let path: PathBuf = [LOG_DIR, &filename].iter().collect();
         let file = fs::OpenOptions::new()
             .create(true)
             .append(true)
-            .open(path)?;
+            .open(self.log_path())?;
         let mut file = BufWriter::new(file);
         for update in updates {
             serde_json::to_writer(&mut file, update)?;
@@ -286,8 +553,10 @@ enum PoolAction<'a> {
 #[derive(Serialize, PartialEq)]
 struct PoolSummary {
     assets: (AssetClass, AssetClass),
-    price: Option<f64>,
+    price: Option<BigRational>,
     protocol_fees: BigInt,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    paused: Option<PauseReason>,
 }
 
 #[derive(Serialize)]
@@ -312,8 +581,36 @@ enum OrderAction<'a> {
 #[derive(Debug, PartialEq, Serialize)]
 #[serde(tag = "validity")]
 enum OrderValidity {
-    Valid { pools: Vec<Ident> },
-    Invalid { reason: OrderInvalidReason },
+    Valid {
+        pools: Vec<Ident>,
+        /// Value checks the order fails which the profile lets through
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        advisories: Vec<ValueError>,
+        /// What the order filters had to say about it
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        notes: Vec<FilterNote>,
+        /// Pools the owner policy keeps it out of for now
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        deferred: Vec<Deferral>,
+        /// Pools it's a large swap against, now its delay is over
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        large_trades: Vec<LargeTrade>,
+    },
+    /// A large swap still waiting out its delay against every pool it's valid for
+    Delayed {
+        trades: Vec<LargeTrade>,
+    },
+    /// Its owner's time conditions don't allow it until this slot
+    Scheduled {
+        from_slot: u64,
+    },
+    /// Valid, but the owner policy keeps it out of every pool's next scoop
+    Deferred {
+        deferrals: Vec<Deferral>,
+    },
+    Invalid {
+        reason: OrderInvalidReason,
+    },
 }
 
 #[derive(Debug, PartialEq, Serialize)]
@@ -321,4 +618,92 @@ enum OrderInvalidReason {
     NoPools,
     ValueError(ValueError),
     PoolErrors(BTreeMap<Ident, PoolError>),
+    /// Every pool it was valid for was vetoed by an order filter
+    Vetoed(Vec<FilterNote>),
+    /// Its owner's time conditions will never allow it again
+    Expired,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{clock::MockClock, protocol::DEFAULT_ADA_RIDER};
+
+    #[tokio::test]
+    async fn should_use_clock_for_log_dates() -> Result<()> {
+        let clock = Arc::new(MockClock::new(
+            Utc.with_ymd_and_hms(2025, 3, 31, 23, 59, 59).unwrap(),
+        ));
+        let (_tx, rx) = watch::channel(SundaeV3Update::default());
+        let scooper = Scooper::new(
+            rx,
+            &[],
+            DEFAULT_ADA_RIDER,
+            &ScooperConfig::default(),
+            clock.clone(),
+        )?;
+        assert_eq!(scooper.log_path(), PathBuf::from("logs/2025-03-31.jsonl"));
+
+        // The debounce sleep moves the mock clock instead of waiting
+        scooper.clock.sleep(Duration::from_secs(1)).await;
+        assert_eq!(scooper.log_path(), PathBuf::from("logs/2025-04-01.jsonl"));
+        Ok(())
+    }
+
+    #[test]
+    fn should_expire_and_schedule_orders_by_owner_time() -> Result<()> {
+        use crate::{
+            multisig::Multisig,
+            sundaev3::{
+                Order, OrderDatum,
+                test_utils::{self, datum, nothing},
+            },
+        };
+
+        let (_tx, rx) = watch::channel(SundaeV3Update::default());
+        let slot_config = SlotConfig {
+            zero_time: 1_000_000,
+            zero_slot: 0,
+            slot_length: 1000,
+        };
+        let scooper = Scooper::new(
+            rx,
+            &[],
+            DEFAULT_ADA_RIDER,
+            &ScooperConfig::default(),
+            Arc::new(MockClock::new(Utc::now())),
+        )?
+        .with_slot_config(slot_config);
+        let order = |condition: Multisig| {
+            let datum = OrderDatum {
+                owner: Multisig::AllOf(vec![Multisig::Signature(vec![0]), condition]),
+                ..datum(Order::Swap(nothing(), nothing()))
+            };
+            test_utils::order(0, 0, datum)
+        };
+        let pools = BTreeMap::new();
+        // Slot 10 starts at 1,010,000
+        let validity = |condition| scooper.validate_order(10, &order(condition), &pools);
+
+        assert_eq!(
+            validity(Multisig::Before(BigInt::from(1_010_000))),
+            OrderValidity::Invalid {
+                reason: OrderInvalidReason::Expired
+            }
+        );
+        assert_eq!(
+            validity(Multisig::After(BigInt::from(1_020_500))),
+            OrderValidity::Scheduled { from_slot: 21 }
+        );
+        // Otherwise it's validated as usual, and this one holds nothing
+        assert!(matches!(
+            validity(Multisig::Before(BigInt::from(1_020_000))),
+            OrderValidity::Invalid {
+                reason: OrderInvalidReason::ValueError(_)
+            }
+        ));
+        Ok(())
+    }
 }
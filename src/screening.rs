@@ -0,0 +1,256 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    metrics,
+    order_filter::{OrderFilter, Verdict},
+    sundaev3::{Destination, OrderDatum, Referenced, SundaeV3Order, SundaeV3Pool},
+};
+
+/// Screens order owners and destinations against a denylist of key and
+/// script hashes, one hex hash per line. Blank lines and `#` comments are
+/// ignored.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScreeningConfig {
+    pub source: DenylistSource,
+    /// How often the denylist is reloaded
+    #[serde(default = "default_refresh_secs")]
+    pub refresh_secs: u64,
+}
+
+fn default_refresh_secs() -> u64 {
+    3600
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DenylistSource {
+    File(PathBuf),
+    /// Fetched with `If-None-Match`, so an unchanged list isn't downloaded again
+    Http(String),
+}
+
+pub struct Screening {
+    config: ScreeningConfig,
+    client: reqwest::Client,
+    denylist: RwLock<HashSet<Vec<u8>>>,
+    etag: RwLock<Option<String>>,
+}
+
+impl Screening {
+    /// Loads the denylist once up front. Scooping without it would let
+    /// flagged orders through, so failing to load it is fatal.
+    pub async fn new(config: &ScreeningConfig) -> Result<Arc<Self>> {
+        let screening = Self {
+            config: config.clone(),
+            client: reqwest::Client::new(),
+            denylist: RwLock::default(),
+            etag: RwLock::default(),
+        };
+        screening
+            .refresh()
+            .await
+            .context("could not load the screening denylist")?;
+        Ok(Arc::new(screening))
+    }
+
+    /// Reloads the denylist until shutdown. If a reload fails, the last list
+    /// loaded stays in use.
+    pub async fn run(self: Arc<Self>, shutdown: CancellationToken) {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(self.config.refresh_secs.max(1)));
+        ticker.tick().await;
+        loop {
+            select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticker.tick() => {
+                    if let Err(error) = self.refresh().await {
+                        metrics::SCREENING_REFRESH_FAILURES.inc();
+                        warn!("could not reload the screening denylist, keeping the last one: {error:#}");
+                    }
+                }
+            }
+        }
+    }
+
+    async fn refresh(&self) -> Result<()> {
+        let text = match &self.config.source {
+            DenylistSource::File(path) => tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("could not read {}", path.display()))?,
+            DenylistSource::Http(url) => {
+                let mut request = self.client.get(url);
+                if let Some(etag) = self.etag.read().unwrap().clone() {
+                    request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+                }
+                let response = request.send().await?;
+                if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+                    return Ok(());
+                }
+                let response = response.error_for_status()?;
+                let etag = response
+                    .headers()
+                    .get(reqwest::header::ETAG)
+                    .and_then(|etag| etag.to_str().ok())
+                    .map(str::to_string);
+                let text = response.text().await?;
+                *self.etag.write().unwrap() = etag;
+                text
+            }
+        };
+        let denylist = parse_denylist(&text)?;
+        if denylist.len() != self.denylist.read().unwrap().len() {
+            info!(entries = denylist.len(), "loaded screening denylist");
+        }
+        metrics::SCREENING_DENYLIST_ENTRIES.set(denylist.len() as u64);
+        *self.denylist.write().unwrap() = denylist;
+        Ok(())
+    }
+
+    /// Why an order is flagged, if it is.
+    pub fn screen(&self, datum: &OrderDatum) -> Option<String> {
+        let denylist = self.denylist.read().unwrap();
+        if let Some(hash) = datum
+            .owner
+            .hashes()
+            .into_iter()
+            .find(|hash| denylist.contains(*hash))
+        {
+            return Some(format!("owner {} is denylisted", hex::encode(hash)));
+        }
+        let Destination::Fixed(address, _) = &datum.destination else {
+            return None;
+        };
+        let stake = match &address.stake_credential {
+            Some(Referenced::Inline(credential)) => Some(credential),
+            _ => None,
+        };
+        [Some(&address.payment_credential), stake]
+            .into_iter()
+            .flatten()
+            .find(|credential| denylist.contains(credential.hash()))
+            .map(|credential| {
+                format!(
+                    "destination {} is denylisted",
+                    hex::encode(credential.hash())
+                )
+            })
+    }
+}
+
+impl OrderFilter for Screening {
+    fn name(&self) -> &str {
+        "screening"
+    }
+
+    fn check(&self, order: &SundaeV3Order, _pool: &SundaeV3Pool) -> Verdict {
+        match self.screen(&order.datum) {
+            Some(reason) => Verdict::Veto(reason),
+            None => Verdict::Allow,
+        }
+    }
+}
+
+fn parse_denylist(text: &str) -> Result<HashSet<Vec<u8>>> {
+    let mut denylist = HashSet::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Ok(hash) = hex::decode(line) else {
+            bail!("line {} of the denylist is not hex", number + 1);
+        };
+        denylist.insert(hash);
+    }
+    Ok(denylist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cardano_types::ADA_ASSET_CLASS,
+        multisig::Multisig,
+        sundaev3::{AikenDatum, Credential, Order, PlutusAddress, test_utils},
+    };
+
+    fn datum(owner: Multisig, destination: Destination) -> OrderDatum {
+        OrderDatum {
+            owner,
+            destination,
+            ..test_utils::datum(Order::Record(ADA_ASSET_CLASS))
+        }
+    }
+
+    fn screening(text: &str) -> Result<Screening> {
+        let screening = Screening {
+            config: ScreeningConfig {
+                source: DenylistSource::File(PathBuf::new()),
+                refresh_secs: 1,
+            },
+            client: reqwest::Client::new(),
+            denylist: RwLock::default(),
+            etag: RwLock::default(),
+        };
+        *screening.denylist.write().unwrap() = parse_denylist(text)?;
+        Ok(screening)
+    }
+
+    #[test]
+    fn should_parse_denylists() {
+        let denylist = parse_denylist("# flagged\naabb\n\n  ccdd  # trailing\n").unwrap();
+        assert_eq!(
+            denylist,
+            HashSet::from([vec![0xaa, 0xbb], vec![0xcc, 0xdd]])
+        );
+        assert_eq!(
+            parse_denylist("aabb\nnot hex").unwrap_err().to_string(),
+            "line 2 of the denylist is not hex"
+        );
+    }
+
+    #[test]
+    fn should_flag_owners_and_destinations() -> Result<()> {
+        let screening = screening("aa\nbb")?;
+        let fixed = |payment: u8, stake: Option<u8>| {
+            Destination::Fixed(
+                PlutusAddress {
+                    payment_credential: Credential::VerificationKey(vec![payment]),
+                    stake_credential: stake
+                        .map(|s| Referenced::Inline(Credential::VerificationKey(vec![s]))),
+                },
+                AikenDatum::NoDatum,
+            )
+        };
+
+        let nested = Multisig::AnyOf(vec![
+            Multisig::Signature(vec![1]),
+            Multisig::Script(vec![0xaa]),
+        ]);
+        assert_eq!(
+            screening.screen(&datum(nested, Destination::SelfDestination)),
+            Some("owner aa is denylisted".to_string())
+        );
+        assert_eq!(
+            screening.screen(&datum(Multisig::Signature(vec![1]), fixed(2, Some(0xbb)))),
+            Some("destination bb is denylisted".to_string())
+        );
+        assert_eq!(
+            screening.screen(&datum(Multisig::Signature(vec![1]), fixed(2, Some(3)))),
+            None
+        );
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use pallas_primitives::{Fragment, PlutusData};
 use serde::{Serializer, ser::Error};
 
 pub fn serialize_address<S>(
@@ -13,3 +14,15 @@ where
 
     serializer.serialize_str(&bech)
 }
+
+/// Writes arbitrary PlutusData as hex-encoded CBOR.
+pub fn serialize_plutus_data<S>(data: &PlutusData, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let bytes = data
+        .encode_fragment()
+        .map_err(|e| S::Error::custom(e.to_string()))?;
+
+    serializer.serialize_str(&hex::encode(bytes))
+}
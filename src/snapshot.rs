@@ -0,0 +1,196 @@
+use anyhow::{Result, anyhow, bail};
+use minicbor::{Decode, Encode};
+
+use crate::{cardano_types::TransactionInput, persistence::PersistedTxo};
+
+/// Starts every snapshot file, followed by a major and a minor version byte.
+pub const MAGIC: &[u8; 8] = b"SCOOPSNP";
+
+/// Readers reject any other major version. A minor version may only add
+/// optional fields under new indices; readers skip fields they don't know and
+/// read fields a writer left out as missing, so any minor version can be read.
+pub const MAJOR_VERSION: u8 = 1;
+pub const MINOR_VERSION: u8 = 0;
+
+/// The txos which make up a `SundaeV3State`, as they were on chain. Outputs
+/// are kept as raw CBOR so their datums come back exactly, where JSON would
+/// lose how the PlutusData was encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The latest slot any of the txos was created in
+    pub slot: u64,
+    pub txos: Vec<PersistedTxo>,
+}
+
+#[derive(Encode, Decode)]
+struct SnapshotRecord {
+    #[n(0)]
+    slot: u64,
+    #[n(1)]
+    txos: Vec<TxoRecord>,
+}
+
+#[derive(Encode, Decode)]
+struct TxoRecord {
+    #[cbor(n(0), with = "minicbor::bytes")]
+    tx_id: Vec<u8>,
+    #[n(1)]
+    index: u64,
+    #[n(2)]
+    txo_type: String,
+    #[n(3)]
+    created_slot: u64,
+    #[cbor(n(4), with = "minicbor::bytes")]
+    created_block_hash: Option<Vec<u8>>,
+    #[n(5)]
+    era: u16,
+    #[cbor(n(6), with = "minicbor::bytes")]
+    txo: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn new(txos: Vec<PersistedTxo>) -> Self {
+        let slot = txos
+            .iter()
+            .map(|t| t.created_slot)
+            .max()
+            .unwrap_or_default();
+        Self { slot, txos }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let record = SnapshotRecord {
+            slot: self.slot,
+            txos: self
+                .txos
+                .iter()
+                .map(|txo| TxoRecord {
+                    tx_id: txo.txo_id.0.transaction_id.to_vec(),
+                    index: txo.txo_id.0.index,
+                    txo_type: txo.txo_type.clone(),
+                    created_slot: txo.created_slot,
+                    created_block_hash: txo.created_block_hash.clone(),
+                    era: txo.era,
+                    txo: txo.txo.clone(),
+                })
+                .collect(),
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([MAJOR_VERSION, MINOR_VERSION]);
+        minicbor::encode(&record, &mut bytes)
+            .map_err(|err| anyhow!("could not encode snapshot: {err}"))?;
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let Some(body) = bytes.strip_prefix(MAGIC.as_slice()) else {
+            bail!("not a snapshot");
+        };
+        let [major, _minor, body @ ..] = body else {
+            bail!("snapshot has no version");
+        };
+        if *major != MAJOR_VERSION {
+            bail!("snapshot is version {major}, but only version {MAJOR_VERSION} can be read");
+        }
+        let record: SnapshotRecord =
+            minicbor::decode(body).map_err(|err| anyhow!("could not decode snapshot: {err}"))?;
+        let txos = record
+            .txos
+            .into_iter()
+            .map(|txo| {
+                let tx_id: [u8; 32] = txo
+                    .tx_id
+                    .try_into()
+                    .map_err(|_| anyhow!("snapshot has a tx id of the wrong length"))?;
+                Ok(PersistedTxo {
+                    txo_id: TransactionInput::new(tx_id.into(), txo.index),
+                    txo_type: txo.txo_type,
+                    created_slot: txo.created_slot,
+                    created_block_hash: txo.created_block_hash,
+                    era: txo.era,
+                    txo: txo.txo,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            slot: record.slot,
+            txos,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txo(index: u64, slot: u64) -> PersistedTxo {
+        PersistedTxo {
+            txo_id: TransactionInput::new([7; 32].into(), index),
+            txo_type: "order".to_string(),
+            created_slot: slot,
+            created_block_hash: (index % 2 == 0).then(|| vec![index as u8; 32]),
+            era: 6,
+            // Indefinite-length CBOR, which a JSON round trip would not keep
+            txo: vec![0x9f, 0x01, 0x02, 0xff],
+        }
+    }
+
+    #[test]
+    fn should_round_trip() -> Result<()> {
+        let snapshot = Snapshot::new(vec![txo(0, 30), txo(1, 50), txo(2, 40)]);
+        assert_eq!(snapshot.slot, 50);
+        let bytes = snapshot.encode()?;
+        assert_eq!(&bytes[..10], b"SCOOPSNP\x01\x00");
+        assert_eq!(Snapshot::decode(&bytes)?, snapshot);
+
+        let empty = Snapshot::new(vec![]);
+        assert_eq!(Snapshot::decode(&empty.encode()?)?, empty);
+        Ok(())
+    }
+
+    #[test]
+    fn should_check_the_header() -> Result<()> {
+        let mut bytes = Snapshot::new(vec![txo(0, 30)]).encode()?;
+        assert_eq!(
+            Snapshot::decode(b"{\"slot\":1}").unwrap_err().to_string(),
+            "not a snapshot"
+        );
+        assert!(Snapshot::decode(&bytes[..9]).is_err());
+
+        bytes[8] = MAJOR_VERSION + 1;
+        assert_eq!(
+            Snapshot::decode(&bytes).unwrap_err().to_string(),
+            "snapshot is version 2, but only version 1 can be read"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_read_newer_minor_versions() -> Result<()> {
+        #[derive(Encode)]
+        struct NewerSnapshot {
+            #[n(0)]
+            slot: u64,
+            #[n(1)]
+            txos: Vec<TxoRecord>,
+            #[n(2)]
+            note: Option<String>,
+        }
+        let newer = NewerSnapshot {
+            slot: 30,
+            txos: vec![],
+            note: Some("added in 1.1".to_string()),
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([MAJOR_VERSION, MINOR_VERSION + 1]);
+        minicbor::encode(&newer, &mut bytes).map_err(|err| anyhow!("{err}"))?;
+        assert_eq!(
+            Snapshot::decode(&bytes)?,
+            Snapshot {
+                slot: 30,
+                txos: vec![],
+            }
+        );
+        Ok(())
+    }
+}
@@ -1,9 +1,31 @@
+mod aliases;
+mod amm_math;
+mod broadcast;
+mod cip67;
 mod indexer;
+mod limits;
+mod pause;
+mod pool_index;
+mod prefilter;
+mod settings;
+#[cfg(test)]
+pub mod test_utils;
 mod types;
 mod utils;
 mod validation;
+mod versioned;
 
+pub use aliases::*;
+pub use amm_math::*;
+pub use broadcast::*;
+pub use cip67::*;
 pub use indexer::*;
+pub use limits::*;
+pub use pause::*;
+pub use pool_index::*;
+pub use prefilter::*;
+pub use settings::*;
 pub use types::*;
 pub use utils::*;
 pub use validation::*;
+pub use versioned::*;
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::sundaev3::{Ident, IdentError};
+
+/// Human-friendly names for pools, such as "ADA/SUNDAE", accepted anywhere a
+/// pool ident is. Names are matched ignoring case.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct PoolAliases(BTreeMap<String, Ident>);
+
+impl PoolAliases {
+    /// Reads a pool from user input, which is either an alias or the full ident.
+    pub fn resolve(&self, ident_or_alias: &str) -> Result<Ident, IdentError> {
+        let aliased = self
+            .0
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(ident_or_alias));
+        match aliased {
+            Some((_, ident)) => Ok(ident.clone()),
+            None => ident_or_alias.parse(),
+        }
+    }
+
+    /// The name a pool goes by, if it has one. A pool with several goes by
+    /// the first in alphabetical order.
+    pub fn alias_of(&self, ident: &Ident) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(_, aliased)| *aliased == ident)
+            .map(|(alias, _)| alias.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_resolve_aliases_and_idents() {
+        let ident = "9f7459d311f3b79bd3dccfe37231189d3bb7df2dd108c435af286878";
+        let aliases: PoolAliases =
+            serde_json::from_value(serde_json::json!({ "ADA/SUNDAE": ident })).unwrap();
+        let expected: Ident = ident.parse().unwrap();
+
+        assert_eq!(aliases.resolve("ada/sundae"), Ok(expected.clone()));
+        assert_eq!(aliases.resolve(ident), Ok(expected.clone()));
+        assert_eq!(aliases.alias_of(&expected), Some("ADA/SUNDAE"));
+        assert_eq!(aliases.resolve("ADA/SNEK"), Err(IdentError::InvalidHex));
+        assert_eq!(aliases.alias_of(&Ident::new(&[0; 28])), None);
+    }
+}
@@ -0,0 +1,295 @@
+//! Swap math for V3 pools, following the pool validator. Amounts are rounded
+//! down exactly as they are on-chain, so any disagreement between the two is a
+//! bug here.
+//!
+//! For a pool with reserves `G` (the asset given) and `T` (the asset taken), and
+//! a fee of `f` basis points, giving `g` takes
+//! `T * g * (10000 - f) / (G * 10000 + g * (10000 - f))`. This never empties the
+//! pool, never decreases `G * T`, and never decreases as `g` grows.
+
+use serde::Serialize;
+
+use crate::{
+    bigint::BigInt,
+    sundaev3::{PoolDatum, SwapDirection},
+};
+
+const FEE_DENOMINATOR: i64 = 10_000;
+
+/// Swaps from A to B pay the bid fee, and swaps from B to A the ask fee.
+pub fn swap_fee<'a>(pool: &'a PoolDatum, direction: &SwapDirection) -> &'a BigInt {
+    match direction {
+        SwapDirection::AtoB => &pool.bid_fees_per_10_thousand,
+        SwapDirection::BtoA => &pool.ask_fees_per_10_thousand,
+    }
+}
+
+/// How much a pool pays out for `gives`. `None` if either reserve is empty, or
+/// the fee is out of range.
+pub fn swap_takes(
+    give_reserve: &BigInt,
+    take_reserve: &BigInt,
+    gives: &BigInt,
+    fee: &BigInt,
+) -> Option<BigInt> {
+    let zero = BigInt::from(0);
+    let denominator = BigInt::from(FEE_DENOMINATOR);
+    if *give_reserve <= zero || *take_reserve <= zero || *fee < zero || *fee >= denominator {
+        return None;
+    }
+    if *gives <= zero {
+        return Some(zero);
+    }
+    if let Some(takes) = swap_takes_i128(give_reserve, take_reserve, gives, fee) {
+        return Some(BigInt::from(takes));
+    }
+    let difference = &denominator - fee;
+    let numerator = take_reserve * gives * &difference;
+    Some(numerator / (give_reserve * &denominator + gives * &difference))
+}
+
+/// [`swap_takes`] without allocating, for the usual case where every step
+/// fits in an `i128`. Expects the inputs to have been checked already.
+fn swap_takes_i128(
+    give_reserve: &BigInt,
+    take_reserve: &BigInt,
+    gives: &BigInt,
+    fee: &BigInt,
+) -> Option<i128> {
+    let difference = i128::from(FEE_DENOMINATOR) - fee.to_i128()?;
+    let gives = gives.to_i128()?;
+    let numerator = take_reserve
+        .to_i128()?
+        .checked_mul(gives)?
+        .checked_mul(difference)?;
+    let divisor = give_reserve
+        .to_i128()?
+        .checked_mul(i128::from(FEE_DENOMINATOR))?
+        .checked_add(gives.checked_mul(difference)?)?;
+    // Everything is positive, so this rounds down like the validator
+    Some(numerator / divisor)
+}
+
+/// The least a swap can give and still take `takes`. `None` if the pool can't
+/// pay that much.
+pub fn minimum_gives(
+    give_reserve: &BigInt,
+    take_reserve: &BigInt,
+    takes: &BigInt,
+    fee: &BigInt,
+) -> Option<BigInt> {
+    // Zero reserves and bad fees are rejected the same way as for a swap
+    swap_takes(give_reserve, take_reserve, &BigInt::from(0), fee)?;
+    let zero = BigInt::from(0);
+    if *takes <= zero {
+        return Some(zero);
+    }
+    if takes >= take_reserve {
+        return None;
+    }
+    // takes <= T * g * d / (G * 10000 + g * d), solved for g and rounded up
+    let denominator = BigInt::from(FEE_DENOMINATOR);
+    let difference = &denominator - fee;
+    let numerator = takes * give_reserve * &denominator;
+    let divisor = difference * (take_reserve - takes);
+    let one = BigInt::from(1);
+    Some((numerator + &divisor - one) / divisor)
+}
+
+/// Whether giving any less would take less. A scoop which takes an order's
+/// whole offer for less than it could have is wasting the owner's funds.
+pub fn is_efficient(
+    give_reserve: &BigInt,
+    take_reserve: &BigInt,
+    gives: &BigInt,
+    fee: &BigInt,
+) -> bool {
+    let Some(takes) = swap_takes(give_reserve, take_reserve, gives, fee) else {
+        return false;
+    };
+    minimum_gives(give_reserve, take_reserve, &takes, fee).is_some_and(|min| min == *gives)
+}
+
+/// The intermediate values behind a swap, for tracking down a scoop which
+/// disagrees with this module by a lovelace or two.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SwapExplanation {
+    pub give_reserve: BigInt,
+    pub take_reserve: BigInt,
+    pub gives: BigInt,
+    pub fee: BigInt,
+    /// `T * g * (10000 - f)`
+    pub takes_numerator: BigInt,
+    /// `G * 10000 + g * (10000 - f)`
+    pub takes_denominator: BigInt,
+    pub takes: BigInt,
+    /// How much less the swap takes than it would with no fee
+    pub fee_takes: BigInt,
+    /// The least the swap could give and take as much
+    pub minimum_gives: Option<BigInt>,
+    /// Whether `gives` is that least amount
+    pub efficient: bool,
+}
+
+/// Works through a swap the way [`swap_takes`] does, keeping every step.
+pub fn explain_swap(
+    give_reserve: &BigInt,
+    take_reserve: &BigInt,
+    gives: &BigInt,
+    fee: &BigInt,
+) -> Option<SwapExplanation> {
+    let takes = swap_takes(give_reserve, take_reserve, gives, fee)?;
+    let without_fee = swap_takes(give_reserve, take_reserve, gives, &BigInt::from(0))?;
+    let denominator = BigInt::from(FEE_DENOMINATOR);
+    let difference = &denominator - fee;
+    let minimum_gives = minimum_gives(give_reserve, take_reserve, &takes, fee);
+    Some(SwapExplanation {
+        give_reserve: give_reserve.clone(),
+        take_reserve: take_reserve.clone(),
+        gives: gives.clone(),
+        fee: fee.clone(),
+        takes_numerator: take_reserve * gives * &difference,
+        takes_denominator: give_reserve * &denominator + gives * &difference,
+        fee_takes: without_fee - &takes,
+        efficient: minimum_gives.as_ref() == Some(gives),
+        minimum_gives,
+        takes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(i: i64) -> BigInt {
+        BigInt::from(i)
+    }
+
+    fn takes(give_reserve: i64, take_reserve: i64, gives: i64, fee: i64) -> Option<BigInt> {
+        swap_takes(
+            &int(give_reserve),
+            &int(take_reserve),
+            &int(gives),
+            &int(fee),
+        )
+    }
+
+    // Worked by hand from the formula above. These aren't the validator's own
+    // test vectors, which are still to be ported from the Aiken source
+    #[test]
+    fn should_match_hand_worked_outputs() {
+        assert_eq!(
+            takes(1_000_000_000, 1_000_000_000, 10_000_000, 30),
+            Some(int(9_871_580))
+        );
+        assert_eq!(
+            takes(1_000_000_000, 1_000_000_000, 10_000_000, 0),
+            Some(int(9_900_990))
+        );
+        assert_eq!(
+            takes(6_181_255_175, 6_397_550_387, 100_000_000, 100),
+            Some(int(100_849_005))
+        );
+        assert_eq!(takes(1000, 1000, 1, 30), Some(int(0)));
+        assert_eq!(takes(0, 1000, 1, 30), None);
+        assert_eq!(takes(1000, 1000, 1, 10_000), None);
+    }
+
+    #[test]
+    fn should_keep_invariants() {
+        let fees = [0, 5, 30, 100, 9_999];
+        let reserves = [1, 7, 1_000, 1_000_000_000];
+        for fee in fees {
+            for give_reserve in reserves {
+                for take_reserve in reserves {
+                    let mut previous = int(0);
+                    for gives in [0, 1, 2, 3, 10, 999, 1_000_000, 1_000_000_000_000] {
+                        let out = takes(give_reserve, take_reserve, gives, fee).unwrap();
+                        assert!(out >= previous, "takes must not shrink as gives grows");
+                        assert!(out < int(take_reserve), "the pool is never emptied");
+                        let before = int(give_reserve) * int(take_reserve);
+                        let after =
+                            (int(give_reserve) + int(gives)) * (int(take_reserve) - out.clone());
+                        assert!(after >= before, "the reserve product never shrinks");
+                        previous = out;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_find_the_cheapest_swap() {
+        for fee in [0, 30, 100] {
+            for gives in [1, 2, 3, 50, 12_345, 10_000_000] {
+                let (give_reserve, take_reserve) = (int(1_000_000_000), int(3_000_000));
+                let fee = int(fee);
+                let gives = int(gives);
+                let out = swap_takes(&give_reserve, &take_reserve, &gives, &fee).unwrap();
+                let min = minimum_gives(&give_reserve, &take_reserve, &out, &fee).unwrap();
+                assert!(min <= gives);
+                assert_eq!(
+                    swap_takes(&give_reserve, &take_reserve, &min, &fee),
+                    Some(out.clone())
+                );
+                assert!(is_efficient(&give_reserve, &take_reserve, &min, &fee));
+                assert_eq!(
+                    is_efficient(&give_reserve, &take_reserve, &gives, &fee),
+                    min == gives
+                );
+            }
+        }
+        assert!(!is_efficient(&int(1000), &int(1000), &int(1), &int(30)));
+        assert!(is_efficient(&int(1000), &int(1000), &int(2), &int(30)));
+        assert_eq!(
+            minimum_gives(&int(1000), &int(1000), &int(1000), &int(30)),
+            None
+        );
+    }
+
+    #[test]
+    fn should_agree_past_i128() {
+        // T * g * (10000 - f) overflows an i128 here, so the BigInt path runs
+        let big = int(i64::MAX) * int(i64::MAX);
+        assert_eq!(swap_takes_i128(&big, &big, &big, &int(30)), None);
+        let out = swap_takes(&big, &big, &big, &int(30)).unwrap();
+        assert!(out > int(0) && out < big);
+
+        // Both paths round the same way
+        let (give_reserve, take_reserve, gives) =
+            (int(6_181_255_175), int(6_397_550_387), int(100_000_000));
+        let difference = int(10_000) - int(100);
+        let exact = &take_reserve * &gives * &difference
+            / (&give_reserve * &int(10_000) + &gives * &difference);
+        assert_eq!(
+            swap_takes_i128(&give_reserve, &take_reserve, &gives, &int(100)).map(BigInt::from),
+            Some(exact)
+        );
+    }
+
+    #[test]
+    fn should_explain_swaps() {
+        let explanation = explain_swap(
+            &int(1_000_000_000),
+            &int(1_000_000_000),
+            &int(10_000_000),
+            &int(30),
+        )
+        .unwrap();
+        assert_eq!(
+            explanation.takes_numerator,
+            int(1_000_000_000) * int(10_000_000) * int(9_970)
+        );
+        assert_eq!(
+            explanation.takes_denominator,
+            int(1_000_000_000) * int(10_000) + int(10_000_000) * int(9_970)
+        );
+        assert_eq!(explanation.takes, int(9_871_580));
+        assert_eq!(explanation.fee_takes, int(9_900_990 - 9_871_580));
+        assert_eq!(
+            explanation.efficient,
+            explanation.minimum_gives == Some(int(10_000_000))
+        );
+        assert_eq!(explain_swap(&int(0), &int(1000), &int(1), &int(30)), None);
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::{select, sync::watch, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::sundaev3::SundaeV3Update;
+
+/// How state updates from the indexer are merged before subscribers see them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct BroadcastConfig {
+    /// While syncing, wait this long for another update before publishing
+    pub window_ms: u64,
+    /// Never hold an update back for longer than this
+    pub max_latency_ms: u64,
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        Self {
+            window_ms: 250,
+            max_latency_ms: 2000,
+        }
+    }
+}
+
+/// Republishes indexer updates to `broadcaster`. During bulk sync, bursts of
+/// updates are merged into one; at the tip, and for rollbacks and reloads
+/// (which carry no tip), updates go out straight away.
+pub async fn coalesce_updates(
+    mut updates: watch::Receiver<SundaeV3Update>,
+    broadcaster: watch::Sender<SundaeV3Update>,
+    config: BroadcastConfig,
+    shutdown: CancellationToken,
+) {
+    let window = Duration::from_millis(config.window_ms);
+    let max_latency = Duration::from_millis(config.max_latency_ms);
+    loop {
+        select! {
+            _ = shutdown.cancelled() => break,
+            res = updates.changed() => {
+                if res.is_err() {
+                    break;
+                }
+            }
+        }
+        let first_pending = Instant::now();
+        loop {
+            let urgent = {
+                let update = updates.borrow_and_update();
+                update.is_at_tip() || update.tip_slot.is_none()
+            };
+            if urgent || window.is_zero() {
+                break;
+            }
+            let deadline = (Instant::now() + window).min(first_pending + max_latency);
+            select! {
+                _ = shutdown.cancelled() => return,
+                _ = tokio::time::sleep_until(deadline) => break,
+                res = updates.changed() => {
+                    if res.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        broadcaster.send_replace(updates.borrow().clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(slot: u64, tip_slot: u64) -> SundaeV3Update {
+        SundaeV3Update {
+            slot,
+            block: None,
+            tip_slot: Some(tip_slot),
+            state: Default::default(),
+            span: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn should_merge_sync_updates_but_not_tip_updates() {
+        let (updates, update_rx) = watch::channel(SundaeV3Update::default());
+        let broadcaster = watch::Sender::new(SundaeV3Update::default());
+        let mut rx = broadcaster.subscribe();
+        let config = BroadcastConfig {
+            window_ms: 200,
+            max_latency_ms: 5000,
+        };
+        let shutdown = CancellationToken::new();
+        let handle = tokio::spawn(coalesce_updates(
+            update_rx,
+            broadcaster,
+            config,
+            shutdown.clone(),
+        ));
+
+        for slot in 1..=5 {
+            updates.send_replace(update(slot, 1000));
+            tokio::task::yield_now().await;
+        }
+        rx.changed().await.unwrap();
+        assert_eq!(rx.borrow_and_update().slot, 5);
+
+        // Much sooner than the window
+        updates.send_replace(update(1000, 1000));
+        tokio::time::timeout(Duration::from_millis(100), rx.changed())
+            .await
+            .expect("tip updates are not delayed")
+            .unwrap();
+        assert_eq!(rx.borrow_and_update().slot, 1000);
+
+        shutdown.cancel();
+        handle.await.unwrap();
+    }
+}
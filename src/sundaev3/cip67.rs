@@ -0,0 +1,79 @@
+//! CIP-67 asset names for the tokens a pool's script mints. Each is a 4-byte
+//! label followed by the pool ident, all under the pool script's policy.
+
+use crate::{cardano_types::AssetClass, sundaev3::Ident};
+
+/// The pool's reference token, which carries its metadata.
+pub const CIP_67_LABEL_100: [u8; 4] = [0x00, 0x06, 0x43, 0xb0];
+/// The NFT which marks the pool's UTxO.
+pub const CIP_67_LABEL_222: [u8; 4] = [0x00, 0x0d, 0xe1, 0x40];
+/// The fungible LP token.
+pub const CIP_67_LABEL_333: [u8; 4] = [0x00, 0x14, 0xdf, 0x10];
+
+fn labelled_name(label: [u8; 4], ident: &Ident) -> Vec<u8> {
+    let mut name = label.to_vec();
+    name.extend_from_slice(ident);
+    name
+}
+
+pub fn pool_record_name(ident: &Ident) -> Vec<u8> {
+    labelled_name(CIP_67_LABEL_100, ident)
+}
+
+pub fn pool_nft_name(ident: &Ident) -> Vec<u8> {
+    labelled_name(CIP_67_LABEL_222, ident)
+}
+
+pub fn pool_lp_name(ident: &Ident) -> Vec<u8> {
+    labelled_name(CIP_67_LABEL_333, ident)
+}
+
+pub fn pool_record_asset(pool_script_hash: &[u8], ident: &Ident) -> AssetClass {
+    AssetClass {
+        policy: pool_script_hash.into(),
+        token: pool_record_name(ident).into(),
+    }
+}
+
+pub fn pool_nft_asset(pool_script_hash: &[u8], ident: &Ident) -> AssetClass {
+    AssetClass {
+        policy: pool_script_hash.into(),
+        token: pool_nft_name(ident).into(),
+    }
+}
+
+pub fn pool_lp_asset(pool_script_hash: &[u8], ident: &Ident) -> AssetClass {
+    AssetClass {
+        policy: pool_script_hash.into(),
+        token: pool_lp_name(ident).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_prefix_names_with_labels() {
+        let ident = Ident::new(&hex::decode("70a5be631ece").unwrap());
+        assert_eq!(
+            hex::encode(pool_record_name(&ident)),
+            "000643b070a5be631ece"
+        );
+        assert_eq!(hex::encode(pool_nft_name(&ident)), "000de14070a5be631ece");
+        assert_eq!(hex::encode(pool_lp_name(&ident)), "0014df1070a5be631ece");
+    }
+
+    #[test]
+    fn should_use_the_pool_policy() {
+        let ident = Ident::new(&[1, 2]);
+        let policy = [9; 28];
+        for asset in [
+            pool_record_asset(&policy, &ident),
+            pool_nft_asset(&policy, &ident),
+            pool_lp_asset(&policy, &ident),
+        ] {
+            assert_eq!(asset.policy, policy.to_vec());
+        }
+    }
+}
@@ -1,59 +1,339 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet},
+    hash::{DefaultHasher, Hash as _, Hasher},
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
 
 use acropolis_common::{BlockInfo, Point};
 use acropolis_module_custom_indexer::chain_index::ChainIndex;
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use async_trait::async_trait;
 use pallas_addresses::Address;
-use pallas_primitives::conway::RedeemerTag;
+use pallas_primitives::{Fragment, Hash};
 use pallas_traverse::{Era, MultiEraOutput, MultiEraTx};
 use plutus_parser::AsPlutus;
-use tokio::sync::{Mutex, watch};
-use tracing::{trace, warn};
+use serde::Serialize;
+use tokio::sync::{Mutex, broadcast, watch};
+use tracing::{Span, field, info, instrument, trace, warn};
 
 use crate::{
     SundaeV3Protocol,
-    cardano_types::{self, AssetClass, Datum, TransactionInput, TransactionOutput},
-    historical_state::HistoricalState,
-    persistence::{PersistedTxo, SundaeV3Dao, SundaeV3TxChanges},
-    sundaev3::{Ident, OrderRedeemer, PoolDatum, SundaeV3Order, SundaeV3Pool, validate_order},
+    bigint::BigInt,
+    cardano_types::{
+        self, ADA_ASSET_CLASS, AssetClass, BlockHeight, Datum, Slot, TransactionInput,
+        TransactionOutput, Value,
+    },
+    historical_state::{BlockMeta, HistoricalState},
+    metrics,
+    persistence::{
+        OrderFlow, OrderLatency, PersistedTxo, Persistence, PoolReserveSnapshot, PoolTx,
+        QuarantinedTxo, RollbackRecord, Scoop, ScoopBlock, SettingsRecord, SundaeV3Dao,
+        SundaeV3TxChanges, Trade,
+    },
+    redeemers::TxRedeemers,
+    runtime::RestartRequest,
+    snapshot::Snapshot,
+    sundaev3::{
+        Credential, Ident, Order, OrderDatum, OrderLimits, OrderRedeemer, PoolDatum, PoolIndex,
+        PoolScoop, SettingsDatum, SundaeV3Order, SundaeV3Pool, SundaeV3Settings, SwapDirection,
+        ValidationProfile, Versioned, explain_swap, get_pool_reserves, may_concern, pool_nft_asset,
+        shed_orders, stake_credential, swap_fee, validate_order, validate_order_for_pool,
+        validate_order_value,
+    },
+    telemetry,
 };
 
 #[derive(Debug, Clone, Default)]
 pub struct SundaeV3State {
     pub pools: BTreeMap<Ident, Arc<SundaeV3Pool>>,
     pub orders: Vec<Arc<SundaeV3Order>>,
+    pub settings: Option<Arc<SundaeV3Settings>>,
+    /// Orders scooped or cancelled within the last few slots, oldest first
+    pub closed_orders: Vec<Arc<ClosedOrder>>,
+    /// Orders the order limits dropped from memory, which are still open in
+    /// the database until we see them spent
+    pub shed_orders: Arc<BTreeSet<TransactionInput>>,
 }
 
 pub type SundaeV3HistoricalState = HistoricalState<SundaeV3State>;
 
+/// What a tx changes in the state, held apart until the tx is written so a
+/// failed write leaves nothing behind in memory for the retry to trip over.
+#[derive(Default)]
+struct StateDelta {
+    spent_orders: BTreeSet<TransactionInput>,
+    spent_shed_orders: Vec<TransactionInput>,
+    orders: Vec<Arc<SundaeV3Order>>,
+    closed_orders: Vec<Arc<ClosedOrder>>,
+    spent_pools: BTreeSet<Ident>,
+    pools: Vec<Arc<SundaeV3Pool>>,
+    /// Set when the settings are spent, to whatever replaced them
+    settings: Option<Option<Arc<SundaeV3Settings>>>,
+}
+
+impl StateDelta {
+    /// The settings as of the changes so far.
+    fn settings<'a>(&'a self, state: &'a SundaeV3State) -> Option<&'a SundaeV3Settings> {
+        match &self.settings {
+            Some(settings) => settings.as_deref(),
+            None => state.settings.as_deref(),
+        }
+    }
+
+    /// Applies the changes made at `slot`, forgetting orders closed more than
+    /// `closed_order_slots` before it.
+    fn apply(self, state: &mut SundaeV3State, slot: u64, closed_order_slots: u64) {
+        if !self.spent_orders.is_empty() {
+            state
+                .orders
+                .retain(|order| !self.spent_orders.contains(&order.input));
+        }
+        state.orders.extend(self.orders);
+        if !self.spent_shed_orders.is_empty() {
+            let shed_orders = Arc::make_mut(&mut state.shed_orders);
+            for input in &self.spent_shed_orders {
+                shed_orders.remove(input);
+            }
+        }
+        state
+            .closed_orders
+            .retain(|closed| closed.slot + closed_order_slots >= slot);
+        state.closed_orders.extend(self.closed_orders);
+        for ident in &self.spent_pools {
+            state.pools.remove(ident);
+        }
+        for pool in self.pools {
+            state.pools.insert(pool.pool_datum.ident.clone(), pool);
+        }
+        if let Some(settings) = self.settings {
+            state.settings = settings;
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct SundaeV3Update {
     pub slot: u64,
+    /// The latest block applied to the state, unless it was restored from the
+    /// database. After a rollback, this can be before `slot`.
+    pub block: Option<BlockMeta>,
     pub tip_slot: Option<u64>,
     pub state: SundaeV3State,
+    /// The span of the tx which made the update, for whatever acts on it to
+    /// join the same trace
+    pub span: Option<Span>,
 }
 impl SundaeV3Update {
-    #[allow(unused)]
     pub fn is_at_tip(&self) -> bool {
         self.tip_slot.is_some_and(|s| s <= self.slot)
     }
 }
 
-const CIP_67_ASSET_LABEL_222: &[u8] = &[0x00, 0x0d, 0xe1, 0x40];
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderOutcome {
+    Scooped,
+    Cancelled,
+}
+
+/// An order which has left the order book, kept for a while so its owner can
+/// find out where it went.
+#[derive(Clone, Debug, Serialize)]
+pub struct ClosedOrder {
+    pub outcome: OrderOutcome,
+    #[serde(serialize_with = "hex::serialize")]
+    pub tx_hash: Vec<u8>,
+    pub slot: u64,
+    pub order: Arc<SundaeV3Order>,
+}
+
+/// An order leaving the order book. Sent once the change has been persisted,
+/// so it can still be undone by a later rollback.
+#[derive(Clone, Debug, Serialize)]
+pub struct OrderEvent {
+    pub outcome: OrderOutcome,
+    pub order: TransactionInput,
+    #[serde(serialize_with = "hex::serialize")]
+    pub tx_hash: Vec<u8>,
+    pub slot: u64,
+    pub datum: OrderDatum,
+    pub value: Value,
+    /// For a cancellation, the order placed in its stead by the same transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaced_by: Option<TransactionInput>,
+    /// For a scoop, the key hash of the authorized scooper which ran it
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_key_hash"
+    )]
+    pub scooper: Option<Vec<u8>>,
+    /// The trace the tx was indexed under, when traces are exported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+fn serialize_key_hash<S: serde::Serializer>(
+    hash: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    hash.as_ref().map(hex::encode).serialize(serializer)
+}
+
+/// Orders and pools which were visible before a rollback, but are not part of
+/// the restored state. Anything built on top of them is no longer valid.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RollbackInvalidations {
+    pub orders: Vec<TransactionInput>,
+    pub pools: Vec<Ident>,
+}
+
+impl RollbackInvalidations {
+    pub fn between(before: &SundaeV3State, after: &SundaeV3State) -> Self {
+        let remaining_orders: BTreeSet<&TransactionInput> =
+            after.orders.iter().map(|o| &o.input).collect();
+        let orders = before
+            .orders
+            .iter()
+            .filter(|o| !remaining_orders.contains(&o.input))
+            .map(|o| o.input.clone())
+            .collect();
+        let pools = before
+            .pools
+            .iter()
+            .filter(|(ident, pool)| {
+                after
+                    .pools
+                    .get(*ident)
+                    .is_none_or(|p| p.input != pool.input)
+            })
+            .map(|(ident, _)| ident.clone())
+            .collect();
+        Self { orders, pools }
+    }
+}
+
+/// The name the indexer's cursor is stored under.
+pub const SUNDAE_V3_INDEX_NAME: &str = "sundae-v3";
+
+/// The database changes undone at startup because they were ahead of the cursor.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StartupRepair {
+    pub db_slot: u64,
+    pub cursor: Option<Point>,
+}
+
+/// The cursor and the indexed data are saved separately, so a crash between the
+/// two can leave the database ahead of the cursor. Replaying from the cursor on
+/// top of that would apply the same changes twice, so roll the database back to
+/// the cursor first.
+pub async fn repair_ahead_of_cursor(
+    persistence: &dyn Persistence,
+) -> Result<Option<StartupRepair>> {
+    let dao = persistence.sundae_v3_dao();
+    let Some(Slot(db_slot)) = dao.latest_slot().await? else {
+        return Ok(None);
+    };
+    let cursor = persistence
+        .cursor_store()
+        .entries()
+        .await?
+        .remove(SUNDAE_V3_INDEX_NAME)
+        .map(|c| c.tip);
+    let cursor_slot = cursor.as_ref().map_or(0, Point::slot);
+    if db_slot <= cursor_slot {
+        return Ok(None);
+    }
+    dao.rollback(Slot(cursor_slot)).await?;
+    Ok(Some(StartupRepair { db_slot, cursor }))
+}
+
+/// A pool's delegation may only move to a staking key the settings authorize.
+fn check_pool_stake(
+    slot: u64,
+    ident: &Ident,
+    stake: Option<&Credential>,
+    settings: Option<&SundaeV3Settings>,
+) {
+    let Some(settings) = settings else {
+        return;
+    };
+    let stake_hex = stake.map(|c| hex::encode(c.hash()));
+    if stake.is_some_and(|c| settings.datum.authorizes_staking(c)) {
+        info!(slot, %ident, stake = ?stake_hex, "pool delegation changed");
+    } else {
+        metrics::POOL_STAKE_UNAUTHORIZED.inc();
+        warn!(slot, %ident, stake = ?stake_hex, "pool recreated with an unauthorized stake credential");
+    }
+}
+
+/// A scoop adds each order's protocol fee to the pool's protocol fees.
+/// Returns whether it added what was expected.
+fn check_scoop_fee(
+    slot: u64,
+    ident: &Ident,
+    before: &BigInt,
+    after: &BigInt,
+    expected: &BigInt,
+) -> bool {
+    let charged = after.clone() - before;
+    if charged == *expected {
+        return true;
+    }
+    metrics::SCOOP_FEE_MISMATCHES.inc();
+    warn!(slot, %ident, %charged, %expected, "scoop charged an unexpected protocol fee");
+    false
+}
+
+/// Counts off the transactions of the block being indexed, which are handed
+/// over one at a time. A transaction handed over again after a failed write
+/// keeps the position it had.
+#[derive(Default)]
+struct BlockPosition {
+    slot: u64,
+    next: u32,
+    last: Option<u64>,
+}
+
+impl BlockPosition {
+    fn tx_index(&mut self, slot: u64, raw_tx: &[u8]) -> u32 {
+        let mut hasher = DefaultHasher::new();
+        raw_tx.hash(&mut hasher);
+        let fingerprint = hasher.finish();
+        if self.slot != slot {
+            *self = Self {
+                slot,
+                ..Self::default()
+            };
+        }
+        if self.last == Some(fingerprint) {
+            return self.next - 1;
+        }
+        self.last = Some(fingerprint);
+        self.next += 1;
+        self.next - 1
+    }
+}
 
 pub struct SundaeV3Indexer {
     state: Arc<Mutex<SundaeV3HistoricalState>>,
     broadcaster: watch::Sender<SundaeV3Update>,
+    order_events: broadcast::Sender<OrderEvent>,
     protocol: SundaeV3Protocol,
     rollback_limit: u64,
     dao: Box<dyn SundaeV3Dao>,
+    order_limits: OrderLimits,
+    pools: PoolIndex,
+    block_position: BlockPosition,
+    restart_requests: Option<broadcast::Sender<RestartRequest>>,
 }
 
 impl SundaeV3Indexer {
     pub fn new(
         state: Arc<Mutex<SundaeV3HistoricalState>>,
         broadcaster: watch::Sender<SundaeV3Update>,
+        order_events: broadcast::Sender<OrderEvent>,
         protocol: SundaeV3Protocol,
         rollback_limit: u64,
         dao: Box<dyn SundaeV3Dao>,
@@ -61,14 +341,101 @@ impl SundaeV3Indexer {
         Self {
             state,
             broadcaster,
+            order_events,
             protocol,
             rollback_limit,
             dao,
+            order_limits: OrderLimits::default(),
+            pools: PoolIndex::new(),
+            block_position: BlockPosition::default(),
+            restart_requests: None,
         }
     }
 
+    pub fn with_order_limits(mut self, order_limits: OrderLimits) -> Self {
+        self.order_limits = order_limits;
+        self
+    }
+
+    /// Keeps `pools` up to date as well as the history.
+    pub fn with_pool_index(mut self, pools: PoolIndex) -> Self {
+        self.pools = pools;
+        self
+    }
+
+    /// Where to ask for a restart from before a block on another fork. Without
+    /// it, rolling back past such a block halts the index.
+    pub fn with_restart_requests(
+        mut self,
+        restart_requests: broadcast::Sender<RestartRequest>,
+    ) -> Self {
+        self.restart_requests = Some(restart_requests);
+        self
+    }
+
     pub async fn load(&mut self) -> Result<()> {
-        let txos = self.dao.load_txos().await?;
+        let (slot, state) = self.load_state().await?;
+        self.restore_state(slot, state).await
+    }
+
+    /// Replaces the state with the one a snapshot was taken of.
+    pub async fn load_snapshot(&mut self, snapshot: Snapshot) -> Result<()> {
+        self.restore(snapshot.txos).await
+    }
+
+    async fn restore(&mut self, txos: Vec<PersistedTxo>) -> Result<()> {
+        let (slot, state) = self.state_from_txos(txos)?;
+        self.restore_state(slot, state).await
+    }
+
+    async fn restore_state(&mut self, slot: u64, state: SundaeV3State) -> Result<()> {
+        // Anything already in memory is replaced
+        self.state.lock().await.restore(slot, state.clone());
+        self.pools.update(slot, &state, None);
+        self.broadcaster.send_replace(SundaeV3Update {
+            slot,
+            block: None,
+            tip_slot: None,
+            state,
+            span: None,
+        });
+        Ok(())
+    }
+
+    /// Rebuilds the state from the database, with the orders it still has
+    /// record of closing within the window.
+    async fn load_state(&self) -> Result<(u64, SundaeV3State)> {
+        let (slot, mut state) = self.state_from_txos(self.dao.load_txos().await?)?;
+        let since = slot.saturating_sub(self.order_limits.closed_order_slots);
+        let scooped: BTreeSet<_> = self
+            .dao
+            .load_order_latencies(since)
+            .await?
+            .into_iter()
+            .map(|latency| latency.order)
+            .collect();
+        for exported in self.dao.load_spent_txos("order", since).await? {
+            let (Some(spent_slot), Some(tx_hash)) = (exported.spent_slot, exported.spent_tx_hash)
+            else {
+                continue;
+            };
+            let outcome = if scooped.contains(&exported.txo.txo_id) {
+                OrderOutcome::Scooped
+            } else {
+                OrderOutcome::Cancelled
+            };
+            state.closed_orders.push(Arc::new(ClosedOrder {
+                outcome,
+                tx_hash,
+                slot: spent_slot,
+                order: Arc::new(decode_order(exported.txo)?),
+            }));
+        }
+        Ok((slot, state))
+    }
+
+    /// Rebuilds the state from persisted txos, along with the latest slot they were created in.
+    fn state_from_txos(&self, txos: Vec<PersistedTxo>) -> Result<(u64, SundaeV3State)> {
         let mut slot = 0;
         let mut state = SundaeV3State::default();
         for txo in txos {
@@ -78,7 +445,11 @@ impl SundaeV3Indexer {
             slot = slot.max(txo.created_slot);
             match txo.txo_type.as_str() {
                 "pool" => {
-                    let Some(pool_datum) = self.parse_pool(&output) else {
+                    let Some(Versioned {
+                        datum: pool_datum,
+                        datum_extension,
+                    }) = self.parse_pool(&output)
+                    else {
                         bail!("invalid pool datum");
                     };
                     state.pools.insert(
@@ -88,43 +459,74 @@ impl SundaeV3Indexer {
                             address: output.address,
                             value: output.value,
                             pool_datum,
+                            datum_extension,
                             slot: txo.created_slot,
                         }),
                     );
                 }
                 "order" => {
-                    let Datum::ParsedOrder(datum) = &output.datum else {
-                        bail!("invalid order datum");
+                    state.orders.push(Arc::new(order_from_output(
+                        txo.txo_id,
+                        txo.created_slot,
+                        output,
+                    )?));
+                }
+                "settings" => {
+                    // Settings indexed before the script was unconfigured
+                    let Some(hash) = &self.protocol.settings_script_hash else {
+                        continue;
                     };
-                    state.orders.push(Arc::new(SundaeV3Order {
+                    let datum = SettingsDatum::from_output(parsed.datum(), &output.value, hash)
+                        .map_err(|error| anyhow!("invalid settings datum: {error}"))?;
+                    state.settings = Some(Arc::new(SundaeV3Settings {
                         input: txo.txo_id,
-                        datum: datum.clone(),
-                        output,
+                        datum,
                         slot: txo.created_slot,
                     }));
                 }
                 other => bail!("unrecognized txo type \"{other}\""),
             }
         }
-        *self.state.lock().await.update_slot(slot)? = state.clone();
-        self.broadcaster.send_replace(SundaeV3Update {
-            slot,
-            tip_slot: None,
-            state,
-        });
-        Ok(())
+        self.shed_orders(&mut state);
+        Ok((slot, state))
+    }
+
+    fn pool_nft(&self, ident: &Ident) -> AssetClass {
+        pool_nft_asset(&self.protocol.pool_script_hash, ident)
+    }
+
+    /// Pools should only ever hold ADA, their two assets and their NFT.
+    fn check_pool_value(
+        &self,
+        slot: u64,
+        input: &TransactionInput,
+        pool: &PoolDatum,
+        value: &Value,
+    ) {
+        let mut expected = Value::new();
+        for asset in [
+            &ADA_ASSET_CLASS,
+            &pool.assets.0,
+            &pool.assets.1,
+            &self.pool_nft(&pool.ident),
+        ] {
+            expected.insert(asset, value.get_asset_class(asset));
+        }
+        let unexpected = value.diff(&expected);
+        if !unexpected.is_empty() {
+            let assets = unexpected
+                .iter()
+                .map(|(asset, delta)| format!("{asset}: {delta}"))
+                .collect::<Vec<_>>();
+            warn!(slot, pool = %input, ident = %pool.ident, "pool has incorrect value: [{}]", assets.join(", "));
+        }
     }
 
-    fn parse_pool(&self, tx_out: &TransactionOutput) -> Option<PoolDatum> {
+    fn parse_pool(&self, tx_out: &TransactionOutput) -> Option<Versioned<PoolDatum>> {
         let Datum::ParsedPool(pool_datum) = &tx_out.datum else {
             return None;
         };
-        let mut asset_name = CIP_67_ASSET_LABEL_222.to_vec();
-        asset_name.extend_from_slice(&pool_datum.ident);
-        let nft_asset_id = AssetClass {
-            policy: self.protocol.pool_script_hash.clone(),
-            token: asset_name,
-        };
+        let nft_asset_id = self.pool_nft(&pool_datum.datum.ident);
         if tx_out.value.get_asset_class(&nft_asset_id) > 0 {
             Some(pool_datum.clone())
         } else {
@@ -132,12 +534,44 @@ impl SundaeV3Indexer {
         }
     }
 
-    fn parse_order_redeemer(&self, tx: &MultiEraTx, spend_index: usize) -> Option<OrderRedeemer> {
-        let redeemers = tx.redeemers();
-        let redeemer = redeemers
-            .iter()
-            .find(|r| r.tag() == RedeemerTag::Spend && r.index() == spend_index as u32)?;
-        OrderRedeemer::from_plutus(redeemer.data().clone()).ok()
+    /// Whether any orders were shed.
+    fn shed_orders(&self, state: &mut SundaeV3State) -> bool {
+        let shed = shed_orders(&mut state.orders, &self.order_limits, |order| {
+            self.is_invalid(order, &state.pools)
+        });
+        if shed.is_empty() {
+            return false;
+        }
+        Arc::make_mut(&mut state.shed_orders).extend(shed);
+        true
+    }
+
+    /// Whether an order can't be scooped as it is, as far as can be told
+    /// without knowing when it would be.
+    fn is_invalid(
+        &self,
+        order: &SundaeV3Order,
+        pools: &BTreeMap<Ident, Arc<SundaeV3Pool>>,
+    ) -> bool {
+        let value = validate_order_value(
+            &order.datum,
+            &order.output.value,
+            self.protocol.ada_rider,
+            ValidationProfile::Strict,
+        );
+        if value.is_err() {
+            return true;
+        }
+        order.datum.ident.as_ref().is_some_and(|ident| {
+            pools.get(ident).is_none_or(|pool| {
+                validate_order_for_pool(
+                    &order.datum,
+                    &pool.pool_datum,
+                    &self.protocol.pool_script_hash,
+                )
+                .is_err()
+            })
+        })
     }
 
     fn validate_scoop(
@@ -157,6 +591,8 @@ impl SundaeV3Indexer {
                 &pool.pool_datum,
                 &pool.value,
                 &self.protocol.pool_script_hash,
+                self.protocol.ada_rider,
+                ValidationProfile::Strict,
             ) {
                 warn!(slot, order = %order.input, ident = %ident, "invalid order was scooped: {error:#}");
             }
@@ -169,8 +605,10 @@ impl SundaeV3Indexer {
                     &pool.pool_datum,
                     &pool.value,
                     &self.protocol.pool_script_hash,
+                    self.protocol.ada_rider,
+                    ValidationProfile::Strict,
                 ) {
-                    Ok(()) => return,
+                    Ok(_) => return,
                     Err(error) => errors.push(format!("{ident}: {error:#}")),
                 }
             }
@@ -179,146 +617,823 @@ impl SundaeV3Indexer {
     }
 }
 
+enum ScriptKind {
+    Pool,
+    Order,
+    Settings,
+}
+
+impl ScriptKind {
+    fn txo_type(&self) -> &'static str {
+        match self {
+            ScriptKind::Pool => "pool",
+            ScriptKind::Order => "order",
+            ScriptKind::Settings => "settings",
+        }
+    }
+}
+
+struct DecodedOutput {
+    input: TransactionInput,
+    script: ScriptKind,
+    output: TransactionOutput,
+    /// Why the datum didn't parse as the type expected at this address, if it didn't
+    datum_error: Option<String>,
+    /// Only for settings, which are parsed here rather than with other datums
+    settings: Option<SettingsDatum>,
+    era: u16,
+    raw: Vec<u8>,
+}
+
+impl DecodedOutput {
+    fn quarantine(self, slot: u64, reason: String) -> QuarantinedTxo {
+        warn!(slot, txo = %self.input, txo_type = self.script.txo_type(), "quarantining unparseable output: {reason}");
+        QuarantinedTxo {
+            txo_id: self.input,
+            txo_type: self.script.txo_type().to_string(),
+            slot,
+            era: self.era,
+            reason,
+            txo: self.raw,
+        }
+    }
+}
+
+/// The parts of a transaction which the indexer cares about. These are decoded
+/// up front, away from the indexer state, so that a pathological transaction
+/// can be skipped without leaving the state half-updated.
+struct DecodedTx {
+    hash: Hash<32>,
+    redeemers: TxRedeemers,
+    outputs: Vec<DecodedOutput>,
+    /// Whether the tx carries Conway votes or proposals, which the protocol ignores
+    governance: bool,
+}
+
+impl DecodedTx {
+    fn decode(raw_tx: &[u8], protocol: &SundaeV3Protocol) -> Result<Self> {
+        let tx = MultiEraTx::decode(raw_tx)?;
+        let hash = tx.hash();
+
+        let redeemers = TxRedeemers::from_tx(&tx);
+
+        let governance = tx.as_conway().is_some_and(|tx| {
+            tx.transaction_body.voting_procedures.is_some()
+                || tx.transaction_body.proposal_procedures.is_some()
+        });
+
+        let settings_script_hash = protocol.settings_script_hash.as_deref();
+        let mut outputs = vec![];
+        for (ix, output) in tx.outputs().iter().enumerate() {
+            let address = match output.address() {
+                Ok(address) => address,
+                Err(error) => {
+                    warn!(tx = %hex::encode(hash), index = ix, "skipping output with an invalid address: {error:#}");
+                    continue;
+                }
+            };
+            let script = if payment_hash_equals(&address, &protocol.pool_script_hash) {
+                ScriptKind::Pool
+            } else if payment_hash_equals(&address, &protocol.order_script_hash) {
+                ScriptKind::Order
+            } else if settings_script_hash.is_some_and(|h| payment_hash_equals(&address, h)) {
+                ScriptKind::Settings
+            } else {
+                continue;
+            };
+            let converted = cardano_types::convert_transaction_output(output);
+            let mut settings = None;
+            let datum_error = match (&script, &converted.datum) {
+                (ScriptKind::Pool, Datum::ParsedPool(_)) => None,
+                (ScriptKind::Order, Datum::ParsedOrder(_)) => None,
+                (ScriptKind::Pool, _) => Some(cardano_types::datum_error::<Versioned<PoolDatum>>(
+                    output.datum(),
+                )),
+                (ScriptKind::Order, _) => Some(
+                    cardano_types::datum_error::<Versioned<OrderDatum>>(output.datum()),
+                ),
+                (ScriptKind::Settings, _) => match SettingsDatum::from_output(
+                    output.datum(),
+                    &converted.value,
+                    settings_script_hash.unwrap_or_default(),
+                ) {
+                    Ok(datum) => {
+                        settings = Some(datum);
+                        None
+                    }
+                    Err(reason) => Some(reason),
+                },
+            };
+            outputs.push(DecodedOutput {
+                input: TransactionInput::new(hash, ix as u64),
+                script,
+                output: converted,
+                datum_error,
+                settings,
+                era: output.era().into(),
+                raw: output.encode(),
+            });
+        }
+
+        Ok(Self {
+            hash,
+            redeemers,
+            outputs,
+            governance,
+        })
+    }
+
+    /// A new order from the same owner for the same pool, as placed when a
+    /// wallet cancels an order to change it. Each is only matched once.
+    fn replacement_order(
+        &self,
+        cancelled: &OrderDatum,
+        matched: &mut BTreeSet<usize>,
+    ) -> Option<TransactionInput> {
+        let (ix, output) = self.outputs.iter().enumerate().find(|(ix, output)| {
+            !matched.contains(ix)
+                && matches!(
+                    &output.output.datum,
+                    Datum::ParsedOrder(od)
+                        if od.datum.owner == cancelled.owner && od.datum.ident == cancelled.ident
+                )
+        })?;
+        matched.insert(ix);
+        Some(output.input.clone())
+    }
+
+    /// Which authorized scooper ran this tx's scoop, if it scooped any of `pools`.
+    fn scooper_key(
+        &self,
+        pools: &BTreeMap<Ident, Arc<SundaeV3Pool>>,
+        settings: Option<&SundaeV3Settings>,
+    ) -> Option<Vec<u8>> {
+        let scoop = pools
+            .values()
+            .filter_map(|pool| self.redeemers.spend(&pool.input))
+            .find_map(PoolScoop::from_spend_redeemer)?;
+        let key = settings?.datum.scooper_key(scoop.scooper_index()?)?;
+        Some(key.to_vec())
+    }
+
+    fn order_redeemer(&self, input: &TransactionInput) -> Option<OrderRedeemer> {
+        let data = self.redeemers.spend(input)?;
+        OrderRedeemer::from_plutus(data.clone()).ok()
+    }
+
+    /// The swaps a scoop executed against `pool`, replayed from its reserves
+    /// before the tx. Deposits and withdrawals move the reserves in ways we
+    /// don't model, so any swaps after one are left out.
+    fn scoop_trades(
+        &self,
+        slot: u64,
+        pool: &SundaeV3Pool,
+        scooped: &BTreeMap<TransactionInput, Arc<SundaeV3Order>>,
+    ) -> Vec<Trade> {
+        let Some(scoop) = self
+            .redeemers
+            .spend(&pool.input)
+            .and_then(PoolScoop::from_spend_redeemer)
+        else {
+            return vec![];
+        };
+
+        let pd = &pool.pool_datum;
+        let (mut reserve_a, mut reserve_b) = get_pool_reserves(pd, &pool.value);
+        let mut trades = vec![];
+        for index in scoop.input_indexes() {
+            let Some(order) = self
+                .redeemers
+                .inputs()
+                .get(index)
+                .and_then(|input| scooped.get(input))
+            else {
+                continue;
+            };
+            if order.datum.ident.as_ref().is_some_and(|i| *i != pd.ident) {
+                continue;
+            }
+            let Order::Swap(gives, _) = &order.datum.action else {
+                trace!(slot, order = %order.input, "not replaying the rest of the scoop");
+                break;
+            };
+            let gives_asset = AssetClass::from_pair((&gives.policy, &gives.token));
+            let direction = if gives_asset == pd.assets.0 {
+                SwapDirection::AtoB
+            } else if gives_asset == pd.assets.1 {
+                SwapDirection::BtoA
+            } else {
+                continue;
+            };
+            let (give_reserve, take_reserve) = match direction {
+                SwapDirection::AtoB => (&mut reserve_a, &mut reserve_b),
+                SwapDirection::BtoA => (&mut reserve_b, &mut reserve_a),
+            };
+            let fee = swap_fee(pd, &direction);
+            let Some(explanation) = explain_swap(give_reserve, take_reserve, &gives.amount, fee)
+            else {
+                break;
+            };
+            trace!(slot, order = %order.input, ?explanation, "replayed swap");
+            let takes = explanation.takes;
+            *give_reserve += &gives.amount;
+            *take_reserve -= &takes;
+            trades.push(Trade {
+                ident: pd.ident.clone(),
+                slot,
+                tx_hash: self.hash.to_vec(),
+                order: order.input.clone(),
+                a_to_b: direction == SwapDirection::AtoB,
+                gives: gives.amount.clone(),
+                takes,
+            });
+        }
+        trades
+    }
+}
+
+fn order_from_output(
+    input: TransactionInput,
+    slot: u64,
+    output: TransactionOutput,
+) -> Result<SundaeV3Order> {
+    let Datum::ParsedOrder(datum) = &output.datum else {
+        bail!("invalid order datum");
+    };
+    Ok(SundaeV3Order {
+        input,
+        datum: datum.datum.clone(),
+        output,
+        slot,
+    })
+}
+
+fn decode_order(txo: PersistedTxo) -> Result<SundaeV3Order> {
+    let era = Era::try_from(txo.era)?;
+    let output = cardano_types::convert_transaction_output(&MultiEraOutput::decode(era, &txo.txo)?);
+    order_from_output(txo.txo_id, txo.created_slot, output)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
 #[async_trait]
 impl ChainIndex for SundaeV3Indexer {
     fn name(&self) -> String {
-        "sundae-v3".to_string()
+        SUNDAE_V3_INDEX_NAME.to_string()
     }
 
+    #[instrument(
+        name = "tx",
+        skip_all,
+        fields(slot = info.slot, block = info.number, tx = field::Empty, trace_id = field::Empty)
+    )]
     async fn handle_onchain_tx_bytes(&mut self, info: &BlockInfo, raw_tx: &[u8]) -> Result<()> {
-        let tx = MultiEraTx::decode(raw_tx)?;
-        let this_tx_hash = tx.hash();
-        trace!("Ingesting tx: {}", hex::encode(this_tx_hash));
+        let tx_index = self.block_position.tx_index(info.slot, raw_tx);
+        if !may_concern(raw_tx, info.era, &self.protocol) {
+            metrics::INDEXER_TXS_FILTERED.inc();
+            let mut history = self.state.lock().await;
+            // The block is still recorded, as it would be by a tx which changed nothing
+            let block = BlockMeta::from(info);
+            if history.latest_block() != Some(&block) {
+                history.commit_block(&block, |_| {})?;
+                self.pools.advance_to(info.slot);
+            }
+            return self.prune(&mut history, info.number).await;
+        }
+        let decoded = panic::catch_unwind(AssertUnwindSafe(|| {
+            DecodedTx::decode(raw_tx, &self.protocol)
+        }));
+        let tx = match decoded {
+            Ok(Ok(tx)) => tx,
+            Ok(Err(error)) => {
+                metrics::INDEXER_TXS_SKIPPED.inc();
+                warn!(
+                    slot = info.slot,
+                    raw_tx = hex::encode(raw_tx),
+                    "skipping transaction which could not be decoded: {error:#}"
+                );
+                return Ok(());
+            }
+            Err(payload) => {
+                metrics::INDEXER_TXS_SKIPPED.inc();
+                warn!(
+                    slot = info.slot,
+                    raw_tx = hex::encode(raw_tx),
+                    "skipping transaction which panicked while decoding: {}",
+                    panic_message(&*payload)
+                );
+                return Ok(());
+            }
+        };
+        let trace_id = telemetry::trace_id();
+        Span::current()
+            .record("tx", field::display(hex::encode(tx.hash)))
+            .record("trace_id", trace_id.as_deref());
+        trace!("Ingesting tx: {}", hex::encode(tx.hash));
         let mut history = self.state.lock().await;
 
-        let state = history.update_slot(info.slot)?;
-        let mut changes = SundaeV3TxChanges::new(info.slot, info.number);
+        // Changes are gathered apart from the state, and only applied to it
+        // once they're written
+        let block = BlockMeta::from(info);
+        let state = history.state_for_block(&block)?;
+        let mut delta = StateDelta::default();
+        let mut changes = SundaeV3TxChanges::new(Slot(info.slot), BlockHeight(info.number));
+        changes.tx_hash = Some(tx.hash.to_vec());
+        changes.block_hash = Some(info.hash.to_vec());
+        let mut order_events = vec![];
+        let mut replacements = BTreeSet::new();
+        let mut scooped = BTreeMap::new();
+        let scooper = tx.scooper_key(&state.pools, state.settings.as_deref());
+        let mut orders_created = false;
+        // The pools whose shard of the pool index this tx changes
+        let mut touched = BTreeSet::new();
 
-        let mut spent_inputs = tx
-            .inputs()
-            .into_iter()
-            .map(|i| TransactionInput::new(*i.hash(), i.index()))
-            .collect::<Vec<_>>();
-        spent_inputs.sort();
-
-        state.orders.retain(|order| {
-            let Ok(spend_index) = spent_inputs.binary_search(&order.input) else {
-                // not spent
-                return true;
+        for order in &state.orders {
+            if !tx.redeemers.spends(&order.input) {
+                continue;
+            }
+            let outcome = match tx.order_redeemer(&order.input) {
+                Some(OrderRedeemer::Scoop) => {
+                    self.validate_scoop(info.slot, order, &state.pools);
+                    scooped.insert(order.input.clone(), order.clone());
+                    Some(OrderOutcome::Scooped)
+                }
+                Some(OrderRedeemer::Cancel) => Some(OrderOutcome::Cancelled),
+                None => {
+                    warn!(order = %order.input, "order spent without a valid redeemer!");
+                    None
+                }
             };
-            match self.parse_order_redeemer(&tx, spend_index) {
-                Some(OrderRedeemer::Scoop) => self.validate_scoop(info.slot, order, &state.pools),
-                Some(OrderRedeemer::Cancel) => {}
-                None => warn!(order = %order.input, "order spent without a valid redeemer!"),
+            if let Some(outcome) = outcome {
+                let (replaced_by, scooped_by) = match outcome {
+                    OrderOutcome::Cancelled => {
+                        (tx.replacement_order(&order.datum, &mut replacements), None)
+                    }
+                    OrderOutcome::Scooped => (None, scooper.clone()),
+                };
+                delta.closed_orders.push(Arc::new(ClosedOrder {
+                    outcome: outcome.clone(),
+                    tx_hash: tx.hash.to_vec(),
+                    slot: info.slot,
+                    order: order.clone(),
+                }));
+                order_events.push(OrderEvent {
+                    outcome,
+                    order: order.input.clone(),
+                    tx_hash: tx.hash.to_vec(),
+                    slot: info.slot,
+                    datum: order.datum.clone(),
+                    value: order.output.value.clone(),
+                    replaced_by,
+                    scooper: scooped_by,
+                    trace_id: trace_id.clone(),
+                });
             }
             changes.spent_txos.push(order.input.clone());
-            false
-        });
+            delta.spent_orders.insert(order.input.clone());
+            touched.extend(order.datum.ident.clone());
+        }
+        // Shed orders aren't in memory, but the database still has them open
+        for input in tx.redeemers.inputs() {
+            if state.shed_orders.contains(input) {
+                changes.spent_txos.push(input.clone());
+                delta.spent_shed_orders.push(input.clone());
+            }
+        }
 
-        state.pools.retain(|_, pool| {
-            if spent_inputs.binary_search(&pool.input).is_ok() {
+        let mut spent_pools = BTreeMap::new();
+        let mut scoop_fees = BTreeMap::new();
+        for (ident, pool) in &state.pools {
+            if tx.redeemers.spends(&pool.input) {
                 changes.spent_txos.push(pool.input.clone());
-                false
-            } else {
-                true
+                delta.spent_pools.insert(ident.clone());
+                touched.insert(ident.clone());
+                spent_pools.insert(
+                    ident.clone(),
+                    (pool.pool_datum.fees(), pool.stake_credential()),
+                );
+                if let Some(scoop) = tx
+                    .redeemers
+                    .spend(&pool.input)
+                    .and_then(PoolScoop::from_spend_redeemer)
+                {
+                    changes.scoops.push(Scoop {
+                        ident: ident.clone(),
+                        slot: info.slot,
+                        tx_hash: tx.hash.to_vec(),
+                        orders: scoop.input_indexes().len() as u32,
+                        block: Some(ScoopBlock {
+                            hash: info.hash.to_vec(),
+                            tx_index,
+                        }),
+                    });
+                    let orders: Vec<_> = scoop
+                        .input_indexes()
+                        .into_iter()
+                        .filter_map(|index| tx.redeemers.inputs().get(index))
+                        .filter_map(|input| scooped.get(input))
+                        .map(|order| &order.datum)
+                        .collect();
+                    // Only checked when every order in the scoop is one we know
+                    if let Some(settings) = &state.settings
+                        && orders.len() == scoop.input_indexes().len()
+                    {
+                        match settings.datum.scoop_fee(&orders) {
+                            Ok(expected) => {
+                                scoop_fees.insert(
+                                    ident.clone(),
+                                    (pool.pool_datum.protocol_fees.clone(), expected),
+                                );
+                            }
+                            Err(error) => {
+                                metrics::SCOOP_FEE_MISMATCHES.inc();
+                                warn!(slot = info.slot, %ident, "scoop overcharged an order: {error}");
+                            }
+                        }
+                    }
+                    for (position, index) in scoop.input_indexes().into_iter().enumerate() {
+                        let Some(order) = tx
+                            .redeemers
+                            .inputs()
+                            .get(index)
+                            .and_then(|input| scooped.get(input))
+                        else {
+                            continue;
+                        };
+                        changes.order_latencies.push(OrderLatency {
+                            ident: ident.clone(),
+                            order: order.input.clone(),
+                            created_slot: order.slot,
+                            scooped_slot: info.slot,
+                            scooped_at: info.timestamp,
+                        });
+                        if let Ok(datum) = order.datum.clone().to_plutus().encode_fragment() {
+                            changes.order_flow.push(OrderFlow {
+                                ident: ident.clone(),
+                                order: order.input.clone(),
+                                slot: info.slot,
+                                created_slot: order.slot,
+                                position: position as u32,
+                                datum,
+                                lovelace: 0,
+                            });
+                        }
+                    }
+                }
+                if !scooped.is_empty() {
+                    let trades = tx.scoop_trades(info.slot, pool, &scooped);
+                    // Pools sort their assets, so ADA is always asset A
+                    if pool.pool_datum.assets.0 == ADA_ASSET_CLASS {
+                        for trade in &trades {
+                            let lovelace = if trade.a_to_b {
+                                &trade.gives
+                            } else {
+                                &trade.takes
+                            };
+                            if let Some(flow) = changes
+                                .order_flow
+                                .iter_mut()
+                                .find(|f| f.order == trade.order)
+                            {
+                                flow.lovelace = lovelace.to_u64().unwrap_or(0);
+                            }
+                        }
+                    }
+                    changes.trades.extend(trades);
+                }
             }
-        });
+        }
+        if let Some(settings) = &state.settings
+            && tx.redeemers.spends(&settings.input)
+        {
+            changes.spent_txos.push(settings.input.clone());
+            delta.settings = Some(None);
+        }
 
-        for (ix, output) in tx.outputs().iter().enumerate() {
-            let address = output.address()?;
-            if payment_hash_equals(&address, &self.protocol.pool_script_hash) {
-                let this_input = TransactionInput(pallas_primitives::TransactionInput {
-                    transaction_id: this_tx_hash,
-                    index: ix as u64,
-                });
-                let tx_out = cardano_types::convert_transaction_output(output);
-                if let Some(pd) = self.parse_pool(&tx_out) {
-                    changes.created_txos.push(PersistedTxo {
-                        txo_id: this_input.clone(),
-                        txo_type: "pool".to_string(),
-                        created_slot: info.slot,
-                        era: output.era().into(),
-                        txo: output.encode(),
-                    });
+        // Scoops and manage actions are kept whole, for later investigation
+        if !spent_pools.is_empty() {
+            if tx.governance {
+                warn!(tx = %hex::encode(tx.hash), "pool spent alongside governance actions");
+            }
+            changes.pool_txs.push(PoolTx {
+                tx_hash: tx.hash.to_vec(),
+                slot: info.slot,
+                tx: raw_tx.to_vec(),
+            });
+        }
 
-                    let pool_id = pd.ident.clone();
-                    let pool_record = SundaeV3Pool {
-                        input: this_input,
-                        address: tx_out.address,
-                        value: tx_out.value,
-                        pool_datum: pd,
-                        slot: info.slot,
-                    };
-                    state.pools.insert(pool_id, Arc::new(pool_record));
+        for mut decoded in tx.outputs {
+            match decoded.script {
+                ScriptKind::Pool => {
+                    if let Some(Versioned {
+                        datum: pd,
+                        datum_extension,
+                    }) = self.parse_pool(&decoded.output)
+                    {
+                        changes.created_txos.push(PersistedTxo {
+                            txo_id: decoded.input.clone(),
+                            txo_type: "pool".to_string(),
+                            created_slot: info.slot,
+                            created_block_hash: Some(info.hash.to_vec()),
+                            era: decoded.era,
+                            txo: decoded.raw,
+                        });
+
+                        let fees = pd.fees();
+                        let previous = spent_pools.get(&pd.ident);
+                        if let Some((previous, _)) = previous
+                            && *previous != fees
+                        {
+                            metrics::POOL_FEE_CHANGES.inc();
+                            info!(
+                                slot = info.slot,
+                                ident = %pd.ident,
+                                bid = %fees.bid_per_10_thousand,
+                                ask = %fees.ask_per_10_thousand,
+                                "pool fees changed from {}/{}",
+                                previous.bid_per_10_thousand,
+                                previous.ask_per_10_thousand,
+                            );
+                        }
+                        if let Some((before, expected)) = scoop_fees.get(&pd.ident) {
+                            check_scoop_fee(
+                                info.slot,
+                                &pd.ident,
+                                before,
+                                &pd.protocol_fees,
+                                expected,
+                            );
+                        }
+                        self.check_pool_value(
+                            info.slot,
+                            &decoded.input,
+                            &pd,
+                            &decoded.output.value,
+                        );
+                        let stake = stake_credential(&decoded.output.address);
+                        if let Some((_, previous_stake)) = previous
+                            && *previous_stake != stake
+                        {
+                            check_pool_stake(
+                                info.slot,
+                                &pd.ident,
+                                stake.as_ref(),
+                                delta.settings(&state),
+                            );
+                        }
+                        let (reserve_a, reserve_b) = get_pool_reserves(&pd, &decoded.output.value);
+                        changes.reserve_snapshots.push(PoolReserveSnapshot {
+                            ident: pd.ident.clone(),
+                            epoch: info.epoch,
+                            slot: info.slot,
+                            reserve_a,
+                            reserve_b,
+                            circulating_lp: pd.circulating_lp.clone(),
+                        });
+
+                        touched.insert(pd.ident.clone());
+                        let pool_record = SundaeV3Pool {
+                            input: decoded.input,
+                            address: decoded.output.address,
+                            value: decoded.output.value,
+                            pool_datum: pd,
+                            datum_extension,
+                            slot: info.slot,
+                        };
+                        delta.pools.push(Arc::new(pool_record));
+                    } else {
+                        let reason = decoded
+                            .datum_error
+                            .clone()
+                            .unwrap_or_else(|| "pool NFT is missing".to_string());
+                        changes
+                            .quarantined_txos
+                            .push(decoded.quarantine(info.slot, reason));
+                    }
                 }
-            } else if payment_hash_equals(&address, &self.protocol.order_script_hash) {
-                let this_input = TransactionInput(pallas_primitives::TransactionInput {
-                    transaction_id: this_tx_hash,
-                    index: ix as u64,
-                });
-                let tx_out = cardano_types::convert_transaction_output(output);
-                if let Datum::ParsedOrder(od) = &tx_out.datum {
-                    changes.created_txos.push(PersistedTxo {
-                        txo_id: this_input.clone(),
-                        txo_type: "order".to_string(),
-                        created_slot: info.slot,
-                        era: output.era().into(),
-                        txo: output.encode(),
-                    });
+                ScriptKind::Order => {
+                    if let Datum::ParsedOrder(od) = &decoded.output.datum {
+                        changes.created_txos.push(PersistedTxo {
+                            txo_id: decoded.input.clone(),
+                            txo_type: "order".to_string(),
+                            created_slot: info.slot,
+                            created_block_hash: Some(info.hash.to_vec()),
+                            era: decoded.era,
+                            txo: decoded.raw,
+                        });
 
-                    let datum = od.clone();
-                    let order = SundaeV3Order {
-                        input: this_input,
-                        output: tx_out,
-                        datum,
-                        slot: info.slot,
-                    };
-                    state.orders.push(Arc::new(order));
+                        let datum = od.datum.clone();
+                        touched.extend(datum.ident.clone());
+                        let order = SundaeV3Order {
+                            input: decoded.input,
+                            output: decoded.output,
+                            datum,
+                            slot: info.slot,
+                        };
+                        delta.orders.push(Arc::new(order));
+                        orders_created = true;
+                    } else {
+                        let reason = decoded.datum_error.clone().unwrap_or_default();
+                        changes
+                            .quarantined_txos
+                            .push(decoded.quarantine(info.slot, reason));
+                    }
+                }
+                ScriptKind::Settings => {
+                    if let Some(datum) = decoded.settings.take() {
+                        changes.created_txos.push(PersistedTxo {
+                            txo_id: decoded.input.clone(),
+                            txo_type: "settings".to_string(),
+                            created_slot: info.slot,
+                            created_block_hash: Some(info.hash.to_vec()),
+                            era: decoded.era,
+                            txo: decoded.raw,
+                        });
+                        changes.settings_history.push(SettingsRecord {
+                            txo_id: decoded.input.clone(),
+                            slot: info.slot,
+                            datum: datum
+                                .clone()
+                                .to_plutus()
+                                .encode_fragment()
+                                .map_err(|error| anyhow!("could not encode settings: {error}"))?,
+                        });
+                        info!(slot = info.slot, settings = %decoded.input, "settings updated");
+                        delta.settings = Some(Some(Arc::new(SundaeV3Settings {
+                            input: decoded.input,
+                            datum,
+                            slot: info.slot,
+                        })));
+                    } else {
+                        let reason = decoded.datum_error.clone().unwrap_or_default();
+                        changes
+                            .quarantined_txos
+                            .push(decoded.quarantine(info.slot, reason));
+                    }
                 }
             }
         }
 
-        if !changes.is_empty() {
+        let written = !changes.is_empty();
+        if written {
             self.dao.apply_tx_changes(changes).await?;
+        }
+        let mut shed = false;
+        history.commit_block(&block, |state| {
+            delta.apply(state, info.slot, self.order_limits.closed_order_slots);
+            shed = orders_created && self.shed_orders(state);
+        })?;
+        if written {
+            let state = history.latest();
+            // Shedding can drop orders for any pool
+            let touched = (!shed).then_some(&touched);
+            self.pools.update(info.slot, &state, touched);
             self.broadcaster.send_replace(SundaeV3Update {
                 slot: info.slot,
+                block: Some(block),
                 tip_slot: info.tip_slot,
-                state: state.clone(),
+                state: state.into_owned(),
+                span: Some(Span::current()),
             });
         }
+        self.pools.advance_to(info.slot);
+        for event in order_events {
+            // Nobody may be listening, which is fine
+            let _ = self.order_events.send(event);
+        }
+
+        self.prune(&mut history, info.number).await
+    }
 
-        if history.prune_history(self.rollback_limit)
-            && let Some(min_height) = info.number.checked_sub(self.rollback_limit)
+    /// Drops whatever history is now past the rollback limit.
+    async fn prune(&self, history: &mut SundaeV3HistoricalState, height: u64) -> Result<()> {
+        if let Some(min_height) = height.checked_sub(self.rollback_limit)
+            && history.prune_below_height(min_height)
         {
-            self.dao.prune_txos(min_height).await?;
+            self.dao.prune_txos(BlockHeight(min_height)).await?;
         }
-
         Ok(())
     }
 
+    #[instrument(name = "rollback", skip_all, fields(point = %point))]
     async fn handle_rollback(&mut self, point: &Point) -> Result<()> {
+        // The block at this slot may be replaced by another fork's
+        self.block_position = BlockPosition::default();
+        let mut rollback_slot = point.slot();
+        let mut conflict = None;
+        let mut rewind_to = None;
+        // Whether the database has already been rolled back to `rollback_slot`
+        let mut db_rolled_back = false;
         match point {
             Point::Origin => {
                 self.reset(point).await?;
+                db_rolled_back = true;
             }
             Point::Specific { slot, .. } => {
                 warn!("rolling back to {point}");
                 let mut history = self.state.lock().await;
-                history.rollback_to_slot(*slot);
+                let before = history.latest().into_owned();
+                let from_slot = history.slot_range().map_or(0, |(_, latest)| latest);
+                let retained = history
+                    .slot_range()
+                    .is_some_and(|(oldest, _)| oldest <= *slot);
+                let mut depth = history.rollback_to_slot(*slot).len() as u64;
+                // If the block we applied at this slot isn't the one we're rolling back
+                // to, it was on another fork, and so is everything we derived from it.
+                conflict = history
+                    .latest_block()
+                    .map(BlockMeta::point)
+                    .filter(|tip| tip.slot() == *slot && tip != point);
+                if let Some(tip) = &conflict {
+                    warn!("rollback point {point} conflicts with applied block {tip}");
+                    rollback_slot = slot.saturating_sub(1);
+                    depth += history.rollback_to_slot(rollback_slot).len() as u64;
+                    rewind_to = history.latest_block().map(BlockMeta::point);
+                }
+                if !retained {
+                    // We no longer have the history for this slot in memory,
+                    // so rebuild it from what's left in the database
+                    warn!("rollback to {point} is past in-memory history, reloading state");
+                    self.dao.rollback(Slot(rollback_slot)).await?;
+                    db_rolled_back = true;
+                    let (_, state) = self.load_state().await?;
+                    history.restore(rollback_slot, state);
+                }
+                let invalidated = RollbackInvalidations::between(&before, &history.latest());
+                for order in &invalidated.orders {
+                    warn!(slot, order = %order, "rollback invalidated order");
+                }
+                for ident in &invalidated.pools {
+                    warn!(slot, ident = %ident, "rollback invalidated pool state");
+                }
+                // Rollbacks to our own tip, as on every reconnect, undo nothing.
+                // Past the in-memory history, this only counts what was in it.
+                if depth > 0 {
+                    metrics::ROLLBACK_DEPTH.observe(depth);
+                    metrics::ROLLBACK_MAX_DEPTH.set_max(depth);
+                    self.dao
+                        .record_rollback(&RollbackRecord {
+                            depth,
+                            from_slot,
+                            to_slot: rollback_slot,
+                        })
+                        .await?;
+                }
             }
         }
-        self.dao.rollback(point.slot()).await?;
+        if !db_rolled_back {
+            self.dao.rollback(Slot(rollback_slot)).await?;
+        }
+        let (block, state) = {
+            let history = self.state.lock().await;
+            let block = history.latest_block().cloned();
+            (block, history.latest().into_owned())
+        };
+        self.pools.update(rollback_slot, &state, None);
         self.broadcaster.send_replace(SundaeV3Update {
-            slot: point.slot(),
+            slot: rollback_slot,
+            block,
             tip_slot: None,
-            state: self.state.lock().await.latest().into_owned(),
+            state,
+            span: Some(Span::current()),
         });
+        if let Some(tip) = conflict {
+            // The block at this slot on our new fork won't be sent again, so
+            // resume from before it, or from the start if we can't tell where
+            let Some(restart_requests) = &self.restart_requests else {
+                bail!(
+                    "rolled back past conflicting block {tip}, the index must be resynced from {point} or earlier"
+                );
+            };
+            let request = match rewind_to {
+                Some(rewind_to) => {
+                    warn!("rolled back past conflicting block {tip}, rewinding to {rewind_to}");
+                    RestartRequest::Unhalt {
+                        index: SUNDAE_V3_INDEX_NAME.to_string(),
+                        rewind_to: Some(rewind_to),
+                    }
+                }
+                None => {
+                    warn!("rolled back past conflicting block {tip}, resyncing");
+                    RestartRequest::Resync
+                }
+            };
+            let _ = restart_requests.send(request);
+        }
         Ok(())
     }
 
     async fn reset(&mut self, point: &Point) -> Result<Point> {
         warn!("clearing all state and resetting to {point}");
-        self.dao.rollback(0).await?;
+        self.dao.rollback(Slot(0)).await?;
         self.state.lock().await.rollback_to_origin();
         Ok(point.clone())
     }
@@ -338,51 +1453,53 @@ mod tests {
 
     use std::fs;
 
-    use acropolis_common::{BlockHash, BlockIntent, BlockStatus, Era};
+    use acropolis_common::BlockHash;
     use pallas_traverse::MultiEraBlock;
 
-    struct NoOpSundaeV3Dao;
+    use crate::{mock_chain::block_info, persistence::NoOpSundaeV3Dao};
 
-    #[async_trait]
-    impl SundaeV3Dao for NoOpSundaeV3Dao {
-        async fn apply_tx_changes(&self, changes: SundaeV3TxChanges) -> Result<()> {
-            let _ = changes;
-            Ok(())
-        }
-        async fn rollback(&self, slot: u64) -> Result<()> {
-            let _ = slot;
-            Ok(())
-        }
-        async fn load_txos(&self) -> Result<Vec<PersistedTxo>> {
-            Ok(vec![])
-        }
-        async fn prune_txos(&self, min_height: u64) -> Result<()> {
-            let _ = min_height;
-            Ok(())
+    async fn handle_block(indexer: &mut SundaeV3Indexer, block: MultiEraBlock<'_>) -> Result<()> {
+        let info = block_info(&block, 0);
+        for tx in block.txs() {
+            let raw_tx = tx.encode();
+            indexer.handle_onchain_tx_bytes(&info, &raw_tx).await?
         }
+        Ok(())
     }
 
-    async fn handle_block(indexer: &mut SundaeV3Indexer, block: MultiEraBlock<'_>) -> Result<()> {
-        let info = BlockInfo {
-            status: BlockStatus::Volatile,
-            intent: BlockIntent::none(),
-            slot: block.slot(),
-            number: 0,
-            hash: BlockHash::new(*block.hash()),
-            epoch: 0,
-            epoch_slot: 0,
-            new_epoch: false,
-            tip_slot: None,
-            timestamp: 0,
-            era: Era::Conway,
-        };
+    #[test]
+    fn should_not_filter_out_any_tx_which_concerns_us() -> Result<()> {
+        let protocol: SundaeV3Protocol =
+            serde_json::from_reader(fs::File::open("testdata/protocol")?)?;
+        let bytes = fs::read("testdata/scoop-pool.block")?;
+        let block = MultiEraBlock::decode(&bytes)?;
+        let era = block_info(&block, 0).era;
+        let mut concerned = 0;
         for tx in block.txs() {
             let raw_tx = tx.encode();
-            indexer.handle_onchain_tx_bytes(&info, &raw_tx).await?
+            let decoded = DecodedTx::decode(&raw_tx, &protocol)?;
+            let inputs = decoded.redeemers.inputs();
+            let spends_script = inputs.iter().any(|i| decoded.redeemers.spend(i).is_some());
+            if !decoded.outputs.is_empty() || spends_script {
+                assert!(may_concern(&raw_tx, era, &protocol));
+                concerned += 1;
+            }
         }
+        assert!(concerned > 0);
         Ok(())
     }
 
+    #[test]
+    fn should_count_txs_within_each_block() {
+        let mut position = BlockPosition::default();
+        assert_eq!(position.tx_index(10, &[1]), 0);
+        assert_eq!(position.tx_index(10, &[2]), 1);
+        // Handed over again after a failed write
+        assert_eq!(position.tx_index(10, &[2]), 1);
+        assert_eq!(position.tx_index(10, &[3]), 2);
+        assert_eq!(position.tx_index(11, &[3]), 0);
+    }
+
     #[tokio::test]
     async fn test_ingest_block() {
         let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
@@ -391,6 +1508,7 @@ mod tests {
         let mut indexer = SundaeV3Indexer::new(
             state.clone(),
             watch::Sender::default(),
+            broadcast::channel(16).0,
             protocol,
             2160,
             Box::new(NoOpSundaeV3Dao),
@@ -417,12 +1535,110 @@ mod tests {
         assert_eq!(index.pools.len(), 1);
         let first_pool = index.pools.first_entry().unwrap();
         let pool_value = &first_pool.get().value.0;
-        assert_eq!(pool_value[&ada_policy][&ada_token], 6181255175);
-        assert_eq!(pool_value[&pool_policy][&pool_token], 1);
-        assert_eq!(pool_value[&coin_b_policy][&coin_b_token], 6397550387);
+        assert_eq!(
+            pool_value[ada_policy.as_slice()][ada_token.as_slice()],
+            6181255175
+        );
+        assert_eq!(pool_value[pool_policy.as_slice()][pool_token.as_slice()], 1);
+        assert_eq!(
+            pool_value[coin_b_policy.as_slice()][coin_b_token.as_slice()],
+            6397550387
+        );
         assert_eq!(index.orders.len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_skip_undecodable_tx() {
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let protocol_file = fs::File::open("testdata/protocol").unwrap();
+        let protocol = serde_json::from_reader(protocol_file).unwrap();
+        let mut indexer = SundaeV3Indexer::new(
+            state.clone(),
+            watch::Sender::default(),
+            broadcast::channel(16).0,
+            protocol,
+            2160,
+            Box::new(NoOpSundaeV3Dao),
+        );
+        let block_bytes = std::fs::read("testdata/scoop-pool.block").unwrap();
+        let block = pallas_traverse::MultiEraBlock::decode(&block_bytes).unwrap();
+
+        // A garbage transaction is skipped without halting the index
+        let info = block_info(&block, 0);
+        indexer
+            .handle_onchain_tx_bytes(&info, &[0x84, 0xff, 0x00])
+            .await
+            .unwrap();
+        assert!(state.lock().await.latest().pools.is_empty());
+        assert!(metrics::INDEXER_TXS_SKIPPED.get() > 0);
+
+        // and the following transactions are still indexed
+        handle_block(&mut indexer, block).await.unwrap();
+        assert_eq!(state.lock().await.latest().pools.len(), 1);
+    }
+
+    /// Adds a DRep vote to a transaction, as a Conway wallet might.
+    fn with_vote(raw_tx: &[u8]) -> Vec<u8> {
+        use pallas_primitives::{
+            NonEmptyKeyValuePairs, Nullable,
+            conway::{GovActionId, Tx, Vote, Voter, VotingProcedure},
+        };
+        let mut tx: Tx = minicbor::decode(raw_tx).unwrap();
+        let procedure = VotingProcedure {
+            vote: Vote::Yes,
+            anchor: Nullable::Null,
+        };
+        let action = GovActionId {
+            transaction_id: Hash::new([1; 32]),
+            action_index: 0,
+        };
+        tx.transaction_body.voting_procedures = Some(NonEmptyKeyValuePairs::Def(vec![(
+            Voter::DRepKey(Hash::new([2; 28])),
+            NonEmptyKeyValuePairs::Def(vec![(action, procedure)]),
+        )]));
+        minicbor::to_vec(&tx).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_index_txs_with_governance_actions() {
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let protocol_file = fs::File::open("testdata/protocol").unwrap();
+        let protocol: SundaeV3Protocol = serde_json::from_reader(protocol_file).unwrap();
+        let mut indexer = SundaeV3Indexer::new(
+            state.clone(),
+            watch::Sender::default(),
+            broadcast::channel(16).0,
+            protocol.clone(),
+            2160,
+            Box::new(NoOpSundaeV3Dao),
+        );
+        let block_bytes = std::fs::read("testdata/scoop-pool.block").unwrap();
+        let block = pallas_traverse::MultiEraBlock::decode(&block_bytes).unwrap();
+
+        let info = block_info(&block, 0);
+        for tx in block.txs() {
+            let raw_tx = with_vote(&tx.encode());
+            let decoded = DecodedTx::decode(&raw_tx, &protocol).unwrap();
+            assert!(decoded.governance);
+            assert!(
+                !DecodedTx::decode(&tx.encode(), &protocol)
+                    .unwrap()
+                    .governance
+            );
+            indexer
+                .handle_onchain_tx_bytes(&info, &raw_tx)
+                .await
+                .unwrap();
+        }
+
+        // The votes don't get in the way of indexing the pool
+        let index = state.lock().await.latest().into_owned();
+        assert_eq!(index.pools.len(), 1);
+        let ada: Vec<u8> = vec![];
+        let pool = index.pools.values().next().unwrap();
+        assert_eq!(pool.value.0[ada.as_slice()][ada.as_slice()], 6181255175);
+    }
+
     #[tokio::test]
     async fn test_rollback() {
         let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
@@ -431,6 +1647,7 @@ mod tests {
         let mut indexer = SundaeV3Indexer::new(
             state.clone(),
             watch::Sender::default(),
+            broadcast::channel(16).0,
             protocol,
             2160,
             Box::new(NoOpSundaeV3Dao),
@@ -463,4 +1680,193 @@ mod tests {
             assert!(!index.pools.contains_key(&pool_id));
         }
     }
+
+    #[tokio::test]
+    async fn test_record_rollback_depth() -> Result<()> {
+        let persistence =
+            crate::persistence::connect(&crate::persistence::PersistenceConfig::default()).await?;
+        let protocol: SundaeV3Protocol =
+            serde_json::from_reader(fs::File::open("testdata/protocol")?)?;
+        let mut indexer = SundaeV3Indexer::new(
+            Arc::new(Mutex::new(SundaeV3HistoricalState::new())),
+            watch::Sender::default(),
+            broadcast::channel(16).0,
+            protocol,
+            2160,
+            persistence.sundae_v3_dao(),
+        );
+        let block_bytes = std::fs::read("testdata/scoop-pool.block")?;
+        let block = MultiEraBlock::decode(&block_bytes)?;
+        handle_block(&mut indexer, block.clone()).await?;
+
+        // Rolling back to our own tip undoes nothing, and isn't recorded
+        let tip = indexer.state.lock().await.latest_block().unwrap().point();
+        indexer.handle_rollback(&tip).await?;
+        let dao = persistence.sundae_v3_dao();
+        assert_eq!(dao.load_deepest_rollback().await?, None);
+
+        let before = Point::Specific {
+            slot: block.slot() - 1,
+            hash: BlockHash::new([0; 32]),
+        };
+        indexer.handle_rollback(&before).await?;
+        assert_eq!(
+            dao.load_deepest_rollback().await?,
+            Some(RollbackRecord {
+                depth: 1,
+                from_slot: block.slot(),
+                to_slot: block.slot() - 1,
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_snapshot() -> Result<()> {
+        let persistence =
+            crate::persistence::connect(&crate::persistence::PersistenceConfig::default()).await?;
+        let protocol: SundaeV3Protocol =
+            serde_json::from_reader(fs::File::open("testdata/protocol")?)?;
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let mut indexer = SundaeV3Indexer::new(
+            state.clone(),
+            watch::Sender::default(),
+            broadcast::channel(16).0,
+            protocol.clone(),
+            2160,
+            persistence.sundae_v3_dao(),
+        );
+        let block_bytes = std::fs::read("testdata/scoop-pool.block")?;
+        handle_block(&mut indexer, MultiEraBlock::decode(&block_bytes)?).await?;
+
+        let snapshot = Snapshot::new(persistence.sundae_v3_dao().load_txos().await?);
+        let snapshot = Snapshot::decode(&snapshot.encode()?)?;
+
+        let restored = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let mut indexer = SundaeV3Indexer::new(
+            restored.clone(),
+            watch::Sender::default(),
+            broadcast::channel(16).0,
+            protocol,
+            2160,
+            Box::new(NoOpSundaeV3Dao),
+        );
+        indexer.load_snapshot(snapshot).await?;
+        let expected = state.lock().await.latest().into_owned();
+        let actual = restored.lock().await.latest().into_owned();
+        assert_eq!(actual.pools, expected.pools);
+        assert_eq!(actual.orders, expected.orders);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rollback_to_conflicting_block() {
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let protocol_file = fs::File::open("testdata/protocol").unwrap();
+        let protocol = serde_json::from_reader(protocol_file).unwrap();
+        let mut indexer = SundaeV3Indexer::new(
+            state.clone(),
+            watch::Sender::default(),
+            broadcast::channel(16).0,
+            protocol,
+            2160,
+            Box::new(NoOpSundaeV3Dao),
+        );
+        let block_bytes = std::fs::read("testdata/scoop-pool.block").unwrap();
+        let block = pallas_traverse::MultiEraBlock::decode(&block_bytes).unwrap();
+        let applied = Point::Specific {
+            slot: block.slot(),
+            hash: BlockHash::new(*block.hash()),
+        };
+
+        // Rolling back to the block we applied keeps it
+        handle_block(&mut indexer, block.clone()).await.unwrap();
+        indexer.handle_rollback(&applied).await.unwrap();
+        assert_eq!(state.lock().await.latest().pools.len(), 1);
+
+        // Rolling back to another block in the same slot does not, and with
+        // no way to restart from before it, the index halts
+        let other_fork = Point::Specific {
+            slot: block.slot(),
+            hash: BlockHash::new([0; 32]),
+        };
+        assert!(indexer.handle_rollback(&other_fork).await.is_err());
+        assert!(state.lock().await.latest().pools.is_empty());
+
+        // With one, and nothing before it to rewind to, it's resynced instead
+        let (restart_tx, mut restart_rx) = broadcast::channel(1);
+        let mut indexer = indexer.with_restart_requests(restart_tx);
+        handle_block(&mut indexer, block.clone()).await.unwrap();
+        indexer.handle_rollback(&other_fork).await.unwrap();
+        assert!(state.lock().await.latest().pools.is_empty());
+        assert!(matches!(restart_rx.try_recv(), Ok(RestartRequest::Resync)));
+    }
+
+    #[tokio::test]
+    async fn test_rollback_invalidations() {
+        let state = Arc::new(Mutex::new(SundaeV3HistoricalState::new()));
+        let protocol_file = fs::File::open("testdata/protocol").unwrap();
+        let protocol = serde_json::from_reader(protocol_file).unwrap();
+        let mut indexer = SundaeV3Indexer::new(
+            state.clone(),
+            watch::Sender::default(),
+            broadcast::channel(16).0,
+            protocol,
+            2160,
+            Box::new(NoOpSundaeV3Dao),
+        );
+        let block_bytes = std::fs::read("testdata/scoop-pool.block").unwrap();
+        let block = pallas_traverse::MultiEraBlock::decode(&block_bytes).unwrap();
+        let pool_id = Ident::new(
+            &hex::decode("32c43f096fa05626da1ead9383793ccd7bba6a1b259e77597766aee8").unwrap(),
+        );
+
+        handle_block(&mut indexer, block.clone()).await.unwrap();
+        let before = state.lock().await.latest().into_owned();
+
+        let rollback_block_point = Point::Specific {
+            slot: block.slot() - 1,
+            hash: BlockHash::new([0; 32]),
+        };
+        indexer
+            .handle_rollback(&rollback_block_point)
+            .await
+            .unwrap();
+        let after = state.lock().await.latest().into_owned();
+
+        let invalidated = RollbackInvalidations::between(&before, &after);
+        assert_eq!(invalidated.pools, vec![pool_id]);
+        assert!(invalidated.orders.is_empty());
+
+        // Nothing is invalidated when the state is unchanged
+        assert_eq!(
+            RollbackInvalidations::between(&after, &after),
+            RollbackInvalidations::default()
+        );
+    }
+
+    #[test]
+    fn test_check_scoop_fee() {
+        let ident = Ident::new(&[1]);
+        let before = BigInt::from(1_000_000);
+        let mismatches = metrics::SCOOP_FEE_MISMATCHES.get();
+        assert!(check_scoop_fee(
+            1,
+            &ident,
+            &before,
+            &BigInt::from(2_582_000),
+            &BigInt::from(1_582_000)
+        ));
+        assert_eq!(metrics::SCOOP_FEE_MISMATCHES.get(), mismatches);
+
+        // e.g. a strategy charged the simple fee
+        assert!(!check_scoop_fee(
+            1,
+            &ident,
+            &before,
+            &BigInt::from(2_332_000),
+            &BigInt::from(1_582_000)
+        ));
+        assert!(metrics::SCOOP_FEE_MISMATCHES.get() > mismatches);
+    }
 }
@@ -0,0 +1,143 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{cardano_types::TransactionInput, metrics, sundaev3::SundaeV3Order};
+
+/// Caps on the orders kept in memory, so spam at the order address can't grow
+/// the state without bound.
+///
+/// Once a cap is passed, orders are shed until it isn't: invalid orders first,
+/// oldest first, then valid ones, newest first. Valid orders resting in a pool
+/// are kept, so flooding it with new orders only sheds the flood. Shed orders
+/// are only dropped from memory. They stay in the database, so a reload sheds
+/// them again, and their spends are still recorded.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct OrderLimits {
+    /// The most orders tracked across every pool
+    pub max_orders: usize,
+    /// The most orders tracked for any one pool. Orders which any pool can
+    /// take only count towards `max-orders`.
+    pub max_orders_per_pool: usize,
+    /// How many slots orders stay queryable for once scooped or cancelled
+    pub closed_order_slots: u64,
+}
+
+impl Default for OrderLimits {
+    fn default() -> Self {
+        Self {
+            max_orders: 100_000,
+            max_orders_per_pool: 20_000,
+            closed_order_slots: 3600,
+        }
+    }
+}
+
+/// Drops orders until they're within `limits`, returning the inputs of those
+/// dropped.
+pub fn shed_orders(
+    orders: &mut Vec<Arc<SundaeV3Order>>,
+    limits: &OrderLimits,
+    is_invalid: impl Fn(&SundaeV3Order) -> bool,
+) -> BTreeSet<TransactionInput> {
+    let mut shed = BTreeSet::new();
+    let mut by_pool = BTreeMap::<_, Vec<_>>::new();
+    for order in orders.iter() {
+        if let Some(ident) = &order.datum.ident {
+            by_pool.entry(ident).or_default().push(order);
+        }
+    }
+    for orders in by_pool.into_values() {
+        if let Some(excess) = orders.len().checked_sub(limits.max_orders_per_pool) {
+            shed.extend(first_to_shed(orders, excess, &is_invalid));
+        }
+    }
+    if let Some(excess) = (orders.len() - shed.len()).checked_sub(limits.max_orders) {
+        let unshed = orders
+            .iter()
+            .filter(|order| !shed.contains(&order.input))
+            .collect();
+        shed.extend(first_to_shed(unshed, excess, &is_invalid));
+    }
+    if shed.is_empty() {
+        return shed;
+    }
+
+    orders.retain(|order| !shed.contains(&order.input));
+    metrics::ORDERS_SHED.add(shed.len() as u64);
+    warn!(
+        shed = shed.len(),
+        kept = orders.len(),
+        "shed orders to stay within the order limits"
+    );
+    shed
+}
+
+fn first_to_shed(
+    mut orders: Vec<&Arc<SundaeV3Order>>,
+    count: usize,
+    is_invalid: impl Fn(&SundaeV3Order) -> bool,
+) -> impl Iterator<Item = TransactionInput> {
+    // Stable, so orders from the same slot keep the order they arrived in
+    orders.sort_by_key(|order| order.slot);
+    let (invalid, mut valid): (Vec<_>, Vec<_>) =
+        orders.into_iter().partition(|order| is_invalid(order));
+    valid.reverse();
+    invalid
+        .into_iter()
+        .chain(valid)
+        .take(count)
+        .map(|order| order.input.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sundaev3::{
+        Ident, Order, OrderDatum,
+        test_utils::{self, datum, nothing},
+    };
+
+    fn order(index: u64, ident: Option<u8>, slot: u64) -> Arc<SundaeV3Order> {
+        let datum = OrderDatum {
+            ident: ident.map(|ident| Ident::new(&[ident])),
+            ..datum(Order::Swap(nothing(), nothing()))
+        };
+        Arc::new(test_utils::order(index, slot, datum))
+    }
+
+    fn kept(orders: &[Arc<SundaeV3Order>]) -> Vec<u64> {
+        orders.iter().map(|order| order.input.0.index).collect()
+    }
+
+    #[test]
+    fn should_shed_invalid_orders_then_the_newest() {
+        let limits = OrderLimits {
+            max_orders: 4,
+            max_orders_per_pool: 2,
+            ..OrderLimits::default()
+        };
+        let mut orders: Vec<_> = (0..6)
+            .map(|index| order(index, (index < 3).then_some(1), 10 + index))
+            .collect();
+        // Order 2 is the newest of pool 1's, but the only invalid one
+        let shed = shed_orders(&mut orders, &limits, |order| order.input.0.index == 2);
+        assert_eq!(shed.len(), 2);
+        assert_eq!(kept(&orders), vec![0, 1, 3, 4]);
+
+        // Within the limits, nothing is shed
+        let shed = shed_orders(&mut orders, &limits, |_| false);
+        assert!(shed.is_empty());
+        assert_eq!(kept(&orders), vec![0, 1, 3, 4]);
+
+        // A flood of new orders doesn't push out those already resting
+        orders.extend((6..9).map(|index| order(index, Some(1), 20 + index)));
+        shed_orders(&mut orders, &limits, |_| false);
+        assert_eq!(kept(&orders), vec![0, 1, 3, 4]);
+    }
+}
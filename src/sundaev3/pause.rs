@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    bigint::BigInt,
+    sundaev3::{PoolDatum, SettingsDatum},
+};
+
+/// How to tell that governance has paused trading on a pool. Nothing on chain
+/// says so outright, so a pause is read from the values one would set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct PauseRules {
+    /// A pool whose bid or ask fee per 10 thousand is at least this is
+    /// paused. The default takes the whole of every swap, and 0 turns the
+    /// rule off.
+    pub fee_sentinel_per_10_thousand: u64,
+    /// Whether a pool is paused until its market opens
+    pub market_open: bool,
+    /// Whether every pool is paused while the settings authorize no scoopers
+    pub no_authorized_scoopers: bool,
+}
+
+impl Default for PauseRules {
+    fn default() -> Self {
+        Self {
+            fee_sentinel_per_10_thousand: 10_000,
+            market_open: true,
+            no_authorized_scoopers: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Error)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum PauseReason {
+    #[error(
+        "fees are at the pause sentinel ({bid_per_10_thousand} bid, {ask_per_10_thousand} ask)"
+    )]
+    Fees {
+        bid_per_10_thousand: BigInt,
+        ask_per_10_thousand: BigInt,
+    },
+    #[error("market opens at {market_open}")]
+    MarketNotOpen { market_open: BigInt },
+    #[error("the settings authorize no scoopers")]
+    NoAuthorizedScoopers,
+}
+
+impl PauseRules {
+    /// Why trading on the pool is paused, if it is. `now` is in POSIX
+    /// milliseconds, and without it the market is taken to be open.
+    pub fn check(
+        &self,
+        pool: &PoolDatum,
+        settings: Option<&SettingsDatum>,
+        now: Option<&BigInt>,
+    ) -> Option<PauseReason> {
+        let no_scoopers = settings
+            .and_then(|settings| settings.authorized_scoopers.as_ref())
+            .is_some_and(Vec::is_empty);
+        if self.no_authorized_scoopers && no_scoopers {
+            return Some(PauseReason::NoAuthorizedScoopers);
+        }
+        if self.fee_sentinel_per_10_thousand > 0 {
+            let sentinel = BigInt::from(self.fee_sentinel_per_10_thousand);
+            if pool.bid_fees_per_10_thousand >= sentinel
+                || pool.ask_fees_per_10_thousand >= sentinel
+            {
+                return Some(PauseReason::Fees {
+                    bid_per_10_thousand: pool.bid_fees_per_10_thousand.clone(),
+                    ask_per_10_thousand: pool.ask_fees_per_10_thousand.clone(),
+                });
+            }
+        }
+        if self.market_open
+            && let Some(now) = now
+            && pool.market_open > *now
+        {
+            return Some(PauseReason::MarketNotOpen {
+                market_open: pool.market_open.clone(),
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cardano_types::{ADA_ASSET_CLASS, AssetClass},
+        sundaev3::Ident,
+    };
+
+    fn pool(bid: i64, ask: i64, market_open: i64) -> PoolDatum {
+        PoolDatum {
+            ident: Ident::new(&[1; 28]),
+            assets: (
+                ADA_ASSET_CLASS,
+                AssetClass::from_pair((vec![2; 28], b"TOKEN".to_vec())),
+            ),
+            circulating_lp: BigInt::from(1000),
+            bid_fees_per_10_thousand: BigInt::from(bid),
+            ask_fees_per_10_thousand: BigInt::from(ask),
+            fee_manager: None,
+            market_open: BigInt::from(market_open),
+            protocol_fees: BigInt::from(0),
+        }
+    }
+
+    #[test]
+    fn should_detect_paused_pools() {
+        let rules = PauseRules::default();
+        let now = BigInt::from(1_000);
+        assert_eq!(rules.check(&pool(30, 30, 0), None, Some(&now)), None);
+        assert!(matches!(
+            rules.check(&pool(30, 10_000, 0), None, Some(&now)),
+            Some(PauseReason::Fees { .. })
+        ));
+        assert_eq!(
+            rules.check(&pool(30, 30, 2_000), None, Some(&now)),
+            Some(PauseReason::MarketNotOpen {
+                market_open: BigInt::from(2_000)
+            })
+        );
+        // Without the time, the market is taken to be open
+        assert_eq!(rules.check(&pool(30, 30, 2_000), None, None), None);
+
+        let off = PauseRules {
+            fee_sentinel_per_10_thousand: 0,
+            market_open: false,
+            no_authorized_scoopers: false,
+        };
+        assert_eq!(
+            off.check(&pool(10_000, 10_000, 2_000), None, Some(&now)),
+            None
+        );
+    }
+}
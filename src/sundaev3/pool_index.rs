@@ -0,0 +1,148 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::sundaev3::{
+    ClosedOrder, Ident, SundaeV3Order, SundaeV3Pool, SundaeV3Settings, SundaeV3State,
+};
+
+/// A pool as of the latest block, with the orders placed for it.
+#[derive(Clone, Debug)]
+pub struct PoolShard {
+    pub pool: Arc<SundaeV3Pool>,
+    /// Orders for this pool alone. Orders which any pool can take are kept
+    /// apart, with those for pools we don't know of.
+    pub orders: Vec<Arc<SundaeV3Order>>,
+}
+
+impl PoolShard {
+    /// The pool's shard of a full state, such as one from the history.
+    pub fn from_state(state: &SundaeV3State, ident: &Ident) -> Option<Self> {
+        let pool = state.pools.get(ident)?.clone();
+        let orders = state
+            .orders
+            .iter()
+            .filter(|order| order.datum.ident.as_ref() == Some(ident))
+            .cloned()
+            .collect();
+        Some(Self { pool, orders })
+    }
+}
+
+/// The latest state of each pool behind a lock of its own. Reading a pool
+/// waits neither on the indexer, which holds the history's lock while it
+/// writes to the database, nor on updates to other pools. The indexer brings
+/// it up to date after each change it makes, in chain order.
+#[derive(Clone, Default)]
+pub struct PoolIndex {
+    inner: Arc<Shards>,
+}
+
+#[derive(Default)]
+struct Shards {
+    pools: RwLock<BTreeMap<Ident, Arc<RwLock<PoolShard>>>>,
+    unpooled_orders: RwLock<Vec<Arc<SundaeV3Order>>>,
+    closed_orders: RwLock<Vec<Arc<ClosedOrder>>>,
+    settings: RwLock<Option<Arc<SundaeV3Settings>>>,
+    slot: AtomicU64,
+}
+
+impl PoolIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The slot of the latest update.
+    pub fn slot(&self) -> u64 {
+        self.inner.slot.load(Ordering::Acquire)
+    }
+
+    pub fn get(&self, ident: &Ident) -> Option<PoolShard> {
+        let shard = self.inner.pools.read().unwrap().get(ident)?.clone();
+        let shard = shard.read().unwrap().clone();
+        Some(shard)
+    }
+
+    /// The protocol settings as of the latest update.
+    pub fn settings(&self) -> Option<Arc<SundaeV3Settings>> {
+        self.inner.settings.read().unwrap().clone()
+    }
+
+    /// Orders no shard holds: those which name no pool, so any pool can take
+    /// them, and those for a pool we don't know of.
+    pub fn unpooled_orders(&self) -> Vec<Arc<SundaeV3Order>> {
+        self.inner.unpooled_orders.read().unwrap().clone()
+    }
+
+    /// Orders scooped or cancelled within the last few slots, oldest first.
+    pub fn closed_orders(&self) -> Vec<Arc<ClosedOrder>> {
+        self.inner.closed_orders.read().unwrap().clone()
+    }
+
+    pub fn idents(&self) -> Vec<Ident> {
+        self.inner.pools.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Moves the slot on, for blocks which changed no pools.
+    pub fn advance_to(&self, slot: u64) {
+        self.inner.slot.store(slot, Ordering::Release);
+    }
+
+    /// Brings the `touched` pools up to date with `state`, or every pool if
+    /// it isn't known which changed.
+    pub fn update(&self, slot: u64, state: &SundaeV3State, touched: Option<&BTreeSet<Ident>>) {
+        let idents: BTreeSet<Ident> = match touched {
+            Some(touched) => touched.clone(),
+            None => {
+                let shards = self.inner.pools.read().unwrap();
+                shards.keys().chain(state.pools.keys()).cloned().collect()
+            }
+        };
+        let mut orders = BTreeMap::<_, Vec<_>>::new();
+        let mut unpooled_orders = vec![];
+        for order in &state.orders {
+            match &order.datum.ident {
+                Some(ident) if !state.pools.contains_key(ident) => {
+                    unpooled_orders.push(order.clone());
+                }
+                Some(ident) if idents.contains(ident) => {
+                    orders.entry(ident).or_default().push(order.clone());
+                }
+                Some(_) => {}
+                None => unpooled_orders.push(order.clone()),
+            }
+        }
+
+        for ident in &idents {
+            let updated = state.pools.get(ident).map(|pool| PoolShard {
+                pool: pool.clone(),
+                orders: orders.remove(ident).unwrap_or_default(),
+            });
+            // The map is only locked for writing when pools come and go
+            let existing = self.inner.pools.read().unwrap().get(ident).cloned();
+            match (existing, updated) {
+                (Some(existing), Some(updated)) => *existing.write().unwrap() = updated,
+                (None, Some(updated)) => {
+                    let shard = Arc::new(RwLock::new(updated));
+                    self.inner
+                        .pools
+                        .write()
+                        .unwrap()
+                        .insert(ident.clone(), shard);
+                }
+                (Some(_), None) => {
+                    self.inner.pools.write().unwrap().remove(ident);
+                }
+                (None, None) => {}
+            }
+        }
+        *self.inner.unpooled_orders.write().unwrap() = unpooled_orders;
+        *self.inner.closed_orders.write().unwrap() = state.closed_orders.clone();
+        *self.inner.settings.write().unwrap() = state.settings.clone();
+        self.inner.slot.store(slot, Ordering::Release);
+    }
+}
@@ -0,0 +1,145 @@
+//! Telling apart, from a tx's raw bytes, the txs which can't concern the
+//! protocol, so most of the chain is never fully decoded. Errs towards
+//! decoding: a tx is only skipped when it can't create or spend a tracked UTxO.
+
+use acropolis_common::Era;
+use minicbor::{Decoder, data::Type};
+
+use crate::SundaeV3Protocol;
+
+/// The witness set key of a tx's redeemers.
+const REDEEMERS_KEY: u64 = 5;
+
+/// Whether a tx might create or spend a pool, order or settings UTxO.
+///
+/// Outputs at our scripts carry the script hash in their address bytes.
+/// Everything we track is locked by a Plutus script, so spending it takes a
+/// redeemer. A tx with neither can't concern us.
+pub fn may_concern(raw_tx: &[u8], era: Era, protocol: &SundaeV3Protocol) -> bool {
+    // Byron had no scripts to pay to
+    if era == Era::Byron {
+        return false;
+    }
+    let mut hashes = [&protocol.pool_script_hash, &protocol.order_script_hash]
+        .into_iter()
+        .chain(&protocol.settings_script_hash);
+    if hashes.any(|hash| contains(raw_tx, hash)) {
+        return true;
+    }
+    // Anything we can't make sense of is left for the full decode to judge
+    has_redeemers(raw_tx).unwrap_or(true)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    !needle.is_empty()
+        && haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
+
+/// Reads as far as the witness set, without decoding the body.
+fn has_redeemers(raw_tx: &[u8]) -> Result<bool, minicbor::decode::Error> {
+    let mut decoder = Decoder::new(raw_tx);
+    decoder.array()?;
+    decoder.skip()?;
+    let entries = decoder.map()?;
+    let mut read = 0;
+    loop {
+        match entries {
+            Some(len) if read == len => break,
+            None if decoder.datatype()? == Type::Break => break,
+            _ => {}
+        }
+        if decoder.u64()? == REDEEMERS_KEY {
+            return Ok(true);
+        }
+        decoder.skip()?;
+        read += 1;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use minicbor::Encoder;
+
+    use super::*;
+
+    fn protocol() -> SundaeV3Protocol {
+        serde_json::from_reader(std::fs::File::open("testdata/protocol").unwrap()).unwrap()
+    }
+
+    /// An enterprise address paying to a script.
+    fn script_address(hash: &[u8]) -> Vec<u8> {
+        [&[0x70][..], hash].concat()
+    }
+
+    /// A tx with one output to `address`, in the shape `era` encodes it.
+    fn tx(era: Era, address: &[u8], redeemers: bool) -> Vec<u8> {
+        let mut e = Encoder::new(vec![]);
+        // Shelley to Mary txs have no validity flag
+        let pre_alonzo = matches!(era, Era::Shelley | Era::Allegra | Era::Mary);
+        e.array(if pre_alonzo { 3 } else { 4 }).unwrap();
+
+        e.map(3).unwrap();
+        e.u8(0).unwrap().array(1).unwrap();
+        e.array(2).unwrap().bytes(&[1; 32]).unwrap().u8(0).unwrap();
+        e.u8(1).unwrap().array(1).unwrap();
+        if pre_alonzo || era == Era::Alonzo {
+            e.array(2)
+                .unwrap()
+                .bytes(address)
+                .unwrap()
+                .u64(2_000_000)
+                .unwrap();
+        } else {
+            e.map(2).unwrap();
+            e.u8(0).unwrap().bytes(address).unwrap();
+            e.u8(1).unwrap().u64(2_000_000).unwrap();
+        }
+        e.u8(2).unwrap().u64(200_000).unwrap();
+
+        if redeemers {
+            e.map(2).unwrap();
+            e.u8(0).unwrap().array(0).unwrap();
+            e.u8(REDEEMERS_KEY as u8).unwrap().array(0).unwrap();
+        } else {
+            e.begin_map().unwrap();
+            e.u8(0).unwrap().array(0).unwrap();
+            e.end().unwrap();
+        }
+        if !pre_alonzo {
+            e.bool(true).unwrap();
+        }
+        e.null().unwrap();
+        e.into_writer()
+    }
+
+    #[test]
+    fn should_only_skip_txs_which_cant_concern_us() {
+        let protocol = protocol();
+        let order = script_address(&protocol.order_script_hash);
+        let pool = script_address(&protocol.pool_script_hash);
+        let elsewhere = script_address(&[9; 28]);
+        for era in [
+            Era::Shelley,
+            Era::Allegra,
+            Era::Mary,
+            Era::Alonzo,
+            Era::Babbage,
+            Era::Conway,
+        ] {
+            assert!(may_concern(&tx(era, &order, false), era, &protocol));
+            assert!(may_concern(&tx(era, &pool, false), era, &protocol));
+            // It might spend something of ours
+            assert!(may_concern(&tx(era, &elsewhere, true), era, &protocol));
+            assert!(!may_concern(&tx(era, &elsewhere, false), era, &protocol));
+        }
+        assert!(!may_concern(
+            &tx(Era::Conway, &order, true),
+            Era::Byron,
+            &protocol
+        ));
+        assert!(may_concern(&[0xff, 0x00], Era::Conway, &protocol));
+    }
+}
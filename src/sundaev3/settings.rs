@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+
+use pallas_primitives::{Fragment, PlutusData, conway::MintedDatumOption};
+use plutus_parser::AsPlutus;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    bigint::{BigInt, BigRational},
+    cardano_types::{AssetClass, TransactionInput, Value},
+    multisig::Multisig,
+    serde_compat::serialize_plutus_data,
+    sundaev3::{Credential, Order, OrderDatum, PlutusAddress},
+};
+
+/// The asset name of the NFT which marks the one real settings UTxO.
+pub const SETTINGS_NFT_NAME: &[u8] = b"settings";
+
+/// Protocol-wide parameters, held in a single UTxO at the settings script.
+#[derive(Clone, AsPlutus, Debug, PartialEq, Eq, Serialize)]
+pub struct SettingsDatum {
+    pub settings_admin: Multisig,
+    pub metadata_admin: PlutusAddress,
+    pub treasury_admin: Multisig,
+    pub treasury_address: PlutusAddress,
+    pub treasury_allowance: (BigInt, BigInt),
+    #[serde(serialize_with = "serialize_key_hashes")]
+    pub authorized_scoopers: Option<Vec<Vec<u8>>>,
+    pub authorized_staking_keys: Vec<Credential>,
+    pub base_fee: BigInt,
+    pub simple_fee: BigInt,
+    pub strategy_fee: BigInt,
+    pub pool_creation_fee: BigInt,
+    #[serde(serialize_with = "serialize_plutus_data")]
+    pub extensions: PlutusData,
+}
+
+impl SettingsDatum {
+    /// The share of the protocol fees the treasury admin may withdraw.
+    /// `None` if the allowance has a zero denominator.
+    pub fn treasury_share(&self) -> Option<BigRational> {
+        let (numerator, denominator) = self.treasury_allowance.clone();
+        BigRational::new(numerator, denominator)
+    }
+
+    /// Whether pools may be delegated with this credential.
+    pub fn authorizes_staking(&self, credential: &Credential) -> bool {
+        self.authorized_staking_keys.contains(credential)
+    }
+
+    /// Parses the datum of an output at the settings script, which must also
+    /// hold the settings NFT.
+    pub fn from_output(
+        datum: Option<MintedDatumOption>,
+        value: &Value,
+        settings_script_hash: &[u8],
+    ) -> Result<Self, String> {
+        let nft = AssetClass {
+            policy: settings_script_hash.into(),
+            token: SETTINGS_NFT_NAME.into(),
+        };
+        if value.get_asset_class(&nft) == 0 {
+            return Err("settings NFT is missing".to_string());
+        }
+        match datum {
+            Some(MintedDatumOption::Data(d)) => {
+                Self::from_plutus(d.0.unwrap()).map_err(|error| format!("{error:?}"))
+            }
+            Some(MintedDatumOption::Hash(h)) => Err(format!(
+                "output has datum hash {h} instead of an inline datum"
+            )),
+            None => Err("output has no datum".to_string()),
+        }
+    }
+
+    /// Parses a datum as stored in the settings history.
+    pub fn from_cbor(cbor: &[u8]) -> anyhow::Result<Self> {
+        let data = PlutusData::decode_fragment(cbor)
+            .map_err(|error| anyhow::anyhow!("invalid settings CBOR: {error}"))?;
+        Self::from_plutus(data).map_err(|error| anyhow::anyhow!("invalid settings: {error:?}"))
+    }
+
+    /// Which of `keys` may not scoop under these settings. When there is no
+    /// list of scoopers, anyone may scoop.
+    pub fn unauthorized_scoopers<'a>(&self, keys: &'a [Vec<u8>]) -> Vec<&'a Vec<u8>> {
+        let Some(authorized) = &self.authorized_scoopers else {
+            return vec![];
+        };
+        keys.iter()
+            .filter(|key| !authorized.contains(key))
+            .collect()
+    }
+
+    /// The key hash a scoop redeemer's scooper index refers to.
+    pub fn scooper_key(&self, index: usize) -> Option<&[u8]> {
+        self.authorized_scoopers
+            .as_ref()?
+            .get(index)
+            .map(Vec::as_slice)
+    }
+
+    /// The fee an order pays on top of its share of the base fee: strategies
+    /// pay the strategy fee, and everything else the simple fee.
+    pub fn order_fee(&self, order: &Order) -> &BigInt {
+        match order {
+            Order::Strategy(_) => &self.strategy_fee,
+            _ => &self.simple_fee,
+        }
+    }
+
+    /// What the pool validator charges an order in a scoop of `order_count`
+    /// orders: the base fee split between them, rounded up, plus the order's
+    /// own fee. The order's `scoop_fee` is the most it agreed to pay, and it
+    /// can't be scooped for more; it never pays less than the charge.
+    pub fn protocol_fee(&self, order: &OrderDatum, order_count: usize) -> Result<BigInt, FeeError> {
+        let count = BigInt::from(order_count.max(1) as u64);
+        let amortized_base_fee = (self.base_fee.clone() + &count - BigInt::from(1)) / count;
+        let fee = amortized_base_fee + self.order_fee(&order.action);
+        if fee > order.scoop_fee {
+            return Err(FeeError::ExceedsMaximum {
+                fee,
+                maximum: order.scoop_fee.clone(),
+            });
+        }
+        Ok(fee)
+    }
+
+    /// What a scoop of these orders adds to the pool's protocol fees.
+    pub fn scoop_fee(&self, orders: &[&OrderDatum]) -> Result<BigInt, FeeError> {
+        orders.iter().try_fold(BigInt::from(0), |total, order| {
+            Ok(total + self.protocol_fee(order, orders.len())?)
+        })
+    }
+
+    /// Every top-level field which differs from `previous`, as JSON.
+    pub fn changes_since(&self, previous: &SettingsDatum) -> BTreeMap<String, SettingsChange> {
+        let to_map = |settings: &SettingsDatum| match serde_json::to_value(settings) {
+            Ok(serde_json::Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        };
+        let before = to_map(previous);
+        to_map(self)
+            .into_iter()
+            .filter_map(|(field, to)| {
+                let from = before.get(&field).cloned().unwrap_or_default();
+                (from != to).then_some((field, SettingsChange { from, to }))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Error)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum FeeError {
+    #[error("protocol fee {fee} is more than the order's maximum of {maximum}")]
+    ExceedsMaximum { fee: BigInt, maximum: BigInt },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SettingsChange {
+    pub from: serde_json::Value,
+    pub to: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SundaeV3Settings {
+    pub input: TransactionInput,
+    pub datum: SettingsDatum,
+    pub slot: u64,
+}
+
+fn serialize_key_hashes<S: serde::Serializer>(
+    hashes: &Option<Vec<Vec<u8>>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let hex = hashes
+        .as_ref()
+        .map(|hashes| hashes.iter().map(hex::encode).collect::<Vec<_>>());
+    hex.serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sundaev3::{Destination, SingletonValue, StrategyAuthorization, empty_cons};
+
+    fn settings(scoopers: Option<Vec<Vec<u8>>>, base_fee: i64) -> SettingsDatum {
+        let address = PlutusAddress {
+            payment_credential: Credential::VerificationKey(vec![1; 28]),
+            stake_credential: None,
+        };
+        SettingsDatum {
+            settings_admin: Multisig::Signature(vec![2; 28]),
+            metadata_admin: address.clone(),
+            treasury_admin: Multisig::Signature(vec![3; 28]),
+            treasury_address: address,
+            treasury_allowance: (BigInt::from(1), BigInt::from(10)),
+            authorized_scoopers: scoopers,
+            authorized_staking_keys: vec![],
+            base_fee: BigInt::from(base_fee),
+            simple_fee: BigInt::from(500_000),
+            strategy_fee: BigInt::from(500_000),
+            pool_creation_fee: BigInt::from(0),
+            extensions: empty_cons(),
+        }
+    }
+
+    #[test]
+    fn should_roundtrip_through_plutus() {
+        let datum = settings(Some(vec![vec![4; 28]]), 332_000);
+        assert_eq!(
+            SettingsDatum::from_plutus(datum.clone().to_plutus()).unwrap(),
+            datum
+        );
+        assert_eq!(
+            datum.treasury_share().map(|share| share.to_string()),
+            Some("0.1".to_string())
+        );
+    }
+
+    #[test]
+    fn should_find_unauthorized_scoopers() {
+        let ours = vec![vec![4; 28], vec![5; 28]];
+        let listed = settings(Some(vec![vec![4; 28]]), 332_000);
+        assert_eq!(listed.unauthorized_scoopers(&ours), vec![&vec![5; 28]]);
+        let anyone = settings(None, 332_000);
+        assert!(anyone.unauthorized_scoopers(&ours).is_empty());
+        assert_eq!(listed.scooper_key(0), Some([4; 28].as_slice()));
+        assert_eq!(listed.scooper_key(1), None);
+        assert_eq!(anyone.scooper_key(0), None);
+    }
+
+    #[test]
+    fn should_parse_stored_cbor() {
+        let datum = settings(None, 332_000);
+        let cbor = datum.clone().to_plutus().encode_fragment().unwrap();
+        assert_eq!(SettingsDatum::from_cbor(&cbor).unwrap(), datum);
+        assert!(SettingsDatum::from_cbor(&[0xff]).is_err());
+    }
+
+    fn order(action: Order, scoop_fee: i64) -> OrderDatum {
+        OrderDatum {
+            ident: None,
+            owner: Multisig::Signature(vec![5; 28]),
+            scoop_fee: BigInt::from(scoop_fee),
+            destination: Destination::SelfDestination,
+            action,
+            extra: empty_cons(),
+        }
+    }
+
+    fn swap() -> Order {
+        Order::Swap(
+            SingletonValue {
+                policy: vec![],
+                token: vec![],
+                amount: BigInt::from(10_000_000),
+            },
+            SingletonValue {
+                policy: vec![7; 28],
+                token: b"RBERRY".to_vec(),
+                amount: BigInt::from(1),
+            },
+        )
+    }
+
+    fn strategy() -> Order {
+        Order::Strategy(StrategyAuthorization::Signature(vec![6; 32]))
+    }
+
+    #[test]
+    fn should_charge_each_order_its_protocol_fee() {
+        let mut datum = settings(None, 332_000);
+        datum.strategy_fee = BigInt::from(750_000);
+        // (action, maximum, orders in the scoop, charged)
+        let cases = [
+            (swap(), 1_000_000, 1, Ok(832_000)),
+            (strategy(), 1_082_000, 1, Ok(1_082_000)),
+            (strategy(), 1_000_000, 1, Err(1_082_000)),
+            (swap(), 1_000_000, 0, Ok(832_000)),
+            // The base fee is split between the orders
+            (swap(), 1_000_000, 2, Ok(666_000)),
+            (strategy(), 916_000, 2, Ok(916_000)),
+            // and rounded up when it doesn't split evenly
+            (swap(), 1_000_000, 3, Ok(610_667)),
+            (swap(), 610_666, 3, Err(610_667)),
+        ];
+        for (action, maximum, count, charged) in cases {
+            let expected = charged
+                .map(BigInt::from)
+                .map_err(|fee| FeeError::ExceedsMaximum {
+                    fee: BigInt::from(fee),
+                    maximum: BigInt::from(maximum),
+                });
+            assert_eq!(
+                datum.protocol_fee(&order(action.clone(), maximum), count),
+                expected,
+                "{action:?} with a maximum of {maximum} in a scoop of {count}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_add_up_a_scoops_protocol_fees() {
+        let mut datum = settings(None, 332_000);
+        datum.strategy_fee = BigInt::from(750_000);
+        let swap_order = order(swap(), 1_000_000);
+        let strategy_order = order(strategy(), 1_000_000);
+        assert_eq!(
+            datum.scoop_fee(&[&swap_order, &strategy_order, &swap_order]),
+            Ok(BigInt::from(3 * 110_667 + 500_000 + 750_000 + 500_000))
+        );
+        assert_eq!(datum.scoop_fee(&[]), Ok(BigInt::from(0)));
+        let cheap = order(strategy(), 500_000);
+        assert!(datum.scoop_fee(&[&swap_order, &cheap]).is_err());
+    }
+
+    #[test]
+    fn should_list_changed_fields() {
+        let before = settings(Some(vec![vec![4; 28]]), 332_000);
+        let after = settings(Some(vec![vec![4; 28], vec![5; 28]]), 400_000);
+        let changes = after.changes_since(&before);
+        assert_eq!(
+            changes.keys().collect::<Vec<_>>(),
+            vec!["authorized_scoopers", "base_fee"]
+        );
+        assert_eq!(changes["base_fee"].to, serde_json::json!(400_000));
+        assert!(before.changes_since(&before).is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+//! Fixtures shared by the unit tests.
+
+use pallas_addresses::{
+    Address, Network, ShelleyAddress, ShelleyDelegationPart, ShelleyPaymentPart,
+};
+
+use crate::{
+    bigint::BigInt,
+    cardano_types::{
+        ADA_ASSET_CLASS, AssetClass, Datum, TransactionInput, TransactionOutput, Value,
+    },
+    multisig::Multisig,
+    sundaev3::{
+        DatumExtension, Destination, Ident, Order, OrderDatum, OrderEvent, OrderOutcome, PoolDatum,
+        SingletonValue, SundaeV3Order, SundaeV3Pool, empty_cons,
+    },
+};
+
+/// A testnet key address with no stake part.
+pub fn address() -> Address {
+    Address::Shelley(ShelleyAddress::new(
+        Network::Testnet,
+        ShelleyPaymentPart::Key([0; 28].into()),
+        ShelleyDelegationPart::Null,
+    ))
+}
+
+pub fn rberry() -> AssetClass {
+    AssetClass::from_pair((vec![1; 28], b"RBERRY".to_vec()))
+}
+
+pub fn singleton(asset: &AssetClass, amount: i64) -> SingletonValue {
+    SingletonValue {
+        policy: asset.policy.to_vec(),
+        token: asset.token.to_vec(),
+        amount: BigInt::from(amount),
+    }
+}
+
+/// No ADA, for orders whose amounts don't matter.
+pub fn nothing() -> SingletonValue {
+    singleton(&ADA_ASSET_CLASS, 0)
+}
+
+/// A datum for `action`, owned by key 00, paying out to itself, for any pool.
+pub fn datum(action: Order) -> OrderDatum {
+    OrderDatum {
+        ident: None,
+        owner: Multisig::Signature(vec![0]),
+        scoop_fee: BigInt::from(1),
+        destination: Destination::SelfDestination,
+        action,
+        extra: empty_cons(),
+    }
+}
+
+/// Output `index` of tx 0101.., created at `slot` holding nothing.
+pub fn order(index: u64, slot: u64, datum: OrderDatum) -> SundaeV3Order {
+    SundaeV3Order {
+        input: TransactionInput::new([1; 32].into(), index),
+        output: TransactionOutput {
+            address: address(),
+            value: Default::default(),
+            datum: Datum::None,
+            script_ref: None,
+        },
+        datum,
+        slot,
+    }
+}
+
+/// An ADA/RBERRY pool with a 0.3% fee.
+pub fn pool(value: Value, protocol_fees: i64) -> SundaeV3Pool {
+    SundaeV3Pool {
+        input: TransactionInput::new([2; 32].into(), 0),
+        address: address(),
+        value,
+        pool_datum: PoolDatum {
+            ident: Ident::new(&[3; 28]),
+            assets: (ADA_ASSET_CLASS, rberry()),
+            circulating_lp: BigInt::from(1_000),
+            bid_fees_per_10_thousand: BigInt::from(30),
+            ask_fees_per_10_thousand: BigInt::from(30),
+            fee_manager: None,
+            market_open: BigInt::from(0),
+            protocol_fees: BigInt::from(protocol_fees),
+        },
+        datum_extension: DatumExtension(vec![]),
+        slot: 0,
+    }
+}
+
+/// The order at output 0 of tx 0000.. leaving the book at slot 10.
+pub fn event(outcome: OrderOutcome, datum: OrderDatum) -> OrderEvent {
+    OrderEvent {
+        outcome,
+        order: TransactionInput::new([0; 32].as_slice().into(), 0),
+        tx_hash: vec![1; 32],
+        slot: 10,
+        datum,
+        value: Value::default(),
+        replaced_by: None,
+        scooper: None,
+        trace_id: None,
+    }
+}
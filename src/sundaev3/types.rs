@@ -1,24 +1,48 @@
 #![allow(unused)]
 
+use pallas_addresses::ShelleyDelegationPart;
 use pallas_primitives::{Fragment, PlutusData};
 use plutus_parser::AsPlutus;
 use serde::ser::SerializeStruct;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
 
 use crate::bigint::BigInt;
 use crate::cardano_types::{AssetClass, TransactionInput, TransactionOutput, Value};
 use crate::multisig::Multisig;
-use crate::serde_compat::serialize_address;
+use crate::serde_compat::{serialize_address, serialize_plutus_data};
+use crate::sundaev3::DatumExtension;
+
+/// Pool idents are a blake2b-224 hash of an input spent to create the pool.
+pub const IDENT_SIZE: usize = 28;
 
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Ident(Vec<u8>);
 
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum IdentError {
+    #[error("ident is not valid hex")]
+    InvalidHex,
+    #[error("ident must be {IDENT_SIZE} bytes, not {0}")]
+    WrongLength(usize),
+}
+
 impl Ident {
     pub fn new(bytes: &[u8]) -> Self {
         Self(bytes.to_vec())
     }
 
+    /// Parses an ident from user input, which must be the full hash.
+    pub fn from_hex(hex_str: &str) -> Result<Self, IdentError> {
+        let bytes = hex::decode(hex_str).map_err(|_| IdentError::InvalidHex)?;
+        if bytes.len() != IDENT_SIZE {
+            return Err(IdentError::WrongLength(bytes.len()));
+        }
+        Ok(Self(bytes))
+    }
+
     pub fn to_bytes(&self) -> &[u8] {
         &self.0
     }
@@ -34,6 +58,21 @@ impl serde::Serialize for Ident {
     }
 }
 
+impl FromStr for Ident {
+    type Err = IdentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ident {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex_str = String::deserialize(deserializer)?;
+        Self::from_hex(&hex_str).map_err(serde::de::Error::custom)
+    }
+}
+
 impl fmt::Display for Ident {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", hex::encode(&self.0))
@@ -70,6 +109,23 @@ pub struct PoolDatum {
     pub protocol_fees: BigInt,
 }
 
+/// The fees a pool currently charges. A pool with a fee manager can have these
+/// changed by a manage transaction at any time.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PoolFees {
+    pub bid_per_10_thousand: BigInt,
+    pub ask_per_10_thousand: BigInt,
+}
+
+impl PoolDatum {
+    pub fn fees(&self) -> PoolFees {
+        PoolFees {
+            bid_per_10_thousand: self.bid_fees_per_10_thousand.clone(),
+            ask_per_10_thousand: self.ask_fees_per_10_thousand.clone(),
+        }
+    }
+}
+
 enum PlutusOption<T> {
     PlutusNone,
     PlutusSome(T),
@@ -109,6 +165,35 @@ pub struct PoolScoop {
     input_order: Vec<(BigInt, Option<SSEBytes>, BigInt)>,
 }
 
+impl PoolScoop {
+    /// Reads a scoop from the redeemer a pool is spent with. The pool script
+    /// is a multi-validator, so the ledger sees its `PoolRedeemer` wrapped in
+    /// a second constructor; manage actions decode as `None`.
+    pub fn from_spend_redeemer(data: &PlutusData) -> Option<Self> {
+        let PlutusData::Constr(wrapper) = data else {
+            return None;
+        };
+        if wrapper.tag != 122 || wrapper.fields.len() != 1 {
+            return None;
+        }
+        Self::from_plutus(wrapper.fields[0].clone()).ok()
+    }
+
+    /// Indexes into the tx's sorted inputs, in the order the pool processes them.
+    pub fn input_indexes(&self) -> Vec<usize> {
+        self.input_order
+            .iter()
+            .filter_map(|(index, _, _)| index.to_u64())
+            .map(|index| index as usize)
+            .collect()
+    }
+
+    /// Index into the settings' authorized scoopers of whoever ran the scoop.
+    pub fn scooper_index(&self) -> Option<usize> {
+        self.scooper_index.to_u64().map(|index| index as usize)
+    }
+}
+
 #[derive(AsPlutus, Debug, PartialEq)]
 pub struct SignedStrategyExecution {
     execution: StrategyExecution,
@@ -240,6 +325,7 @@ pub struct OrderDatum {
     pub scoop_fee: BigInt,
     pub destination: Destination,
     pub action: Order,
+    #[serde(serialize_with = "serialize_plutus_data")]
     pub extra: PlutusData,
 }
 
@@ -260,21 +346,6 @@ impl serde::Serialize for Destination {
             Destination::SelfDestination => serializer.serialize_str("self"),
 
             Destination::Fixed(addr, datum) => {
-                let payment_hex = match &addr.payment_credential {
-                    Credential::VerificationKey(vkh) => hex::encode(vkh.as_slice()),
-                    Credential::Script(sh) => hex::encode(sh.as_slice()),
-                };
-
-                let stake_hex: Option<String> = match &addr.stake_credential {
-                    Some(Referenced::Inline(Credential::VerificationKey(vkh))) => {
-                        Some(hex::encode(vkh.as_slice()))
-                    }
-                    Some(Referenced::Inline(Credential::Script(sh))) => {
-                        Some(hex::encode(sh.as_slice()))
-                    }
-                    _ => None,
-                };
-
                 let datum_hex: Option<String> = match datum {
                     AikenDatum::NoDatum => None,
                     AikenDatum::DatumHash(v) => Some(hex::encode(v)),
@@ -283,13 +354,7 @@ impl serde::Serialize for Destination {
 
                 let mut map = serializer.serialize_map(Some(2))?;
 
-                map.serialize_entry(
-                    "address",
-                    &serde_json::json!({
-                        "payment": payment_hex,
-                        "stake": stake_hex
-                    }),
-                )?;
+                map.serialize_entry("address", addr)?;
 
                 map.serialize_entry("datum", &datum_hex)?;
                 map.end()
@@ -325,6 +390,41 @@ pub enum Credential {
     Script(ScriptHash),
 }
 
+impl Credential {
+    pub fn hash(&self) -> &[u8] {
+        match self {
+            Credential::VerificationKey(hash) | Credential::Script(hash) => hash,
+        }
+    }
+}
+
+impl Serialize for Credential {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&hex::encode(self.hash()))
+    }
+}
+
+/// Serializes as `{"payment": <hex>, "stake": <hex or null>}`. Stake pointers
+/// are shown as null.
+impl Serialize for PlutusAddress {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let stake = match &self.stake_credential {
+            Some(Referenced::Inline(credential)) => Some(credential),
+            _ => None,
+        };
+        let mut st = serializer.serialize_struct("PlutusAddress", 2)?;
+        st.serialize_field("payment", &self.payment_credential)?;
+        st.serialize_field("stake", &stake)?;
+        st.end()
+    }
+}
+
 type VerificationKeyHash = Vec<u8>;
 type ScriptHash = Vec<u8>;
 
@@ -378,9 +478,30 @@ pub struct SundaeV3Pool {
     pub address: pallas_addresses::Address,
     pub value: Value,
     pub pool_datum: PoolDatum,
+    #[serde(skip_serializing_if = "DatumExtension::is_empty")]
+    pub datum_extension: DatumExtension,
     pub slot: u64,
 }
 
+impl SundaeV3Pool {
+    /// The credential the pool's ADA is delegated with, if any.
+    pub fn stake_credential(&self) -> Option<Credential> {
+        stake_credential(&self.address)
+    }
+}
+
+/// The stake credential of a Shelley address. Pointers count as none.
+pub fn stake_credential(address: &pallas_addresses::Address) -> Option<Credential> {
+    let pallas_addresses::Address::Shelley(address) = address else {
+        return None;
+    };
+    match address.delegation() {
+        ShelleyDelegationPart::Key(hash) => Some(Credential::VerificationKey(hash.to_vec())),
+        ShelleyDelegationPart::Script(hash) => Some(Credential::Script(hash.to_vec())),
+        ShelleyDelegationPart::Pointer(_) | ShelleyDelegationPart::Null => None,
+    }
+}
+
 impl PartialOrd for SundaeV3Pool {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.slot.cmp(&other.slot))
@@ -399,6 +520,47 @@ pub struct SundaeV3Order {
 mod tests {
     use super::*;
 
+    use pallas_addresses::{Address, Network, ShelleyAddress, ShelleyPaymentPart};
+
+    #[test]
+    fn should_read_stake_credentials() {
+        let script = [1; 28].into();
+        let key = [2; 28].into();
+        let address = |delegation| {
+            Address::Shelley(ShelleyAddress::new(
+                Network::Mainnet,
+                ShelleyPaymentPart::Script(script),
+                delegation,
+            ))
+        };
+        assert_eq!(
+            stake_credential(&address(ShelleyDelegationPart::Key(key))),
+            Some(Credential::VerificationKey(vec![2; 28]))
+        );
+        assert_eq!(
+            stake_credential(&address(ShelleyDelegationPart::Script(key))),
+            Some(Credential::Script(vec![2; 28]))
+        );
+        assert_eq!(
+            stake_credential(&address(ShelleyDelegationPart::Null)),
+            None
+        );
+    }
+
+    #[test]
+    fn should_parse_idents_from_hex() {
+        let hex_str = "32c43f096fa05626da1ead9383793ccd7bba6a1b259e77597766aee8";
+        let ident: Ident = hex_str.parse().unwrap();
+        assert_eq!(ident.to_string(), hex_str);
+        assert_eq!(
+            serde_json::from_str::<Ident>(&format!("\"{hex_str}\"")).unwrap(),
+            ident
+        );
+        assert_eq!(Ident::from_hex("zz"), Err(IdentError::InvalidHex));
+        assert_eq!(Ident::from_hex("abcd"), Err(IdentError::WrongLength(2)));
+        assert!(serde_json::from_str::<Ident>("\"abcd\"").is_err());
+    }
+
     #[test]
     fn test_decode_singletonvalue() {
         let bytes = hex::decode("9f4100410102ff").unwrap();
@@ -472,6 +634,15 @@ mod tests {
         assert_eq!(order.extra, empty_cons());
     }
 
+    #[test]
+    fn test_serialize_orderdatum_extra() {
+        let od_bytes = hex::decode("d8799fd8799f581c99999999999999999999999999999999999999999999999999999999ffd8799f581c88888888888888888888888888888888888888888888888888888888ff0ad8799fd8799fd8799f581c77777777777777777777777777777777777777777777777777777777ffd87a80ffd87980ffd87a9f9f4100410102ff9f4103410405ffffd87980ff").unwrap();
+        let order: OrderDatum =
+            AsPlutus::from_plutus(minicbor::decode(&od_bytes).unwrap()).unwrap();
+        let json = serde_json::to_value(&order).unwrap();
+        assert_eq!(json["extra"], "d87980");
+    }
+
     #[test]
     fn test_decode_orderdatum_2() {
         let od_bytes = hex::decode("d8799fd8799f581c12d88c7f234493742d583c219101050b39e925d715a93060752d60d3ffd8799f581c621be66c7f488b22f66003fff0b7427c30f70da678c532b7233d85caff1a00138800d8799fd8799fd8799f581c1c1381a51312b9da9782b3f507af94bab78780f85196007fad5fbde3ffd8799fd8799fd8799f581c621be66c7f488b22f66003fff0b7427c30f70da678c532b7233d85caffffffffd8799fffffd87a9f9f581cac597ca62a32cab3f4766c8f9cd577e50ebb1d00383ec7fa3990b01646435241574a551a0002113eff9f40401a066b2bc2ffff43d87980ff").unwrap();
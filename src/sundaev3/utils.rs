@@ -1,9 +1,21 @@
 use crate::{
-    bigint::BigInt,
+    bigint::{BigInt, BigRational},
     cardano_types::{ADA_ASSET_CLASS, AssetClass, Value},
-    sundaev3::{Order, OrderDatum},
+    sundaev3::{Order, OrderDatum, PoolDatum, SingletonValue},
 };
 
+/// The amounts of each pool asset available to trade against. Protocol fees
+/// collected in ADA aren't part of the reserves.
+pub fn get_pool_reserves(pool_datum: &PoolDatum, v: &Value) -> (BigInt, BigInt) {
+    let (coin_a, coin_b) = &pool_datum.assets;
+    let mut reserve_a = BigInt::from(v.get_asset_class(coin_a));
+    if *coin_a == ADA_ASSET_CLASS {
+        reserve_a -= &pool_datum.protocol_fees;
+    }
+    let reserve_b = BigInt::from(v.get_asset_class(coin_b));
+    (reserve_a, reserve_b)
+}
+
 pub fn get_pool_asset_pair(pool_policy: &[u8], v: &Value) -> Option<(AssetClass, AssetClass)> {
     let mut native_token_a = None;
     let mut native_token_b = None;
@@ -36,7 +48,13 @@ pub fn get_pool_asset_pair(pool_policy: &[u8], v: &Value) -> Option<(AssetClass,
     }
 }
 
-pub fn get_pool_price(pool_policy: &[u8], v: &Value, rewards: &BigInt) -> Option<f64> {
+/// How much of each asset the pool holds, less the protocol fees it has
+/// collected in ADA. `None` if that leaves less than nothing.
+pub fn get_pool_quantities(
+    pool_policy: &[u8],
+    v: &Value,
+    rewards: &BigInt,
+) -> Option<(BigInt, BigInt)> {
     let (coin_a, coin_b) = get_pool_asset_pair(pool_policy, v)?;
     let mut quantity_a = BigInt::from(v.get_asset_class(&coin_a));
     if coin_a == ADA_ASSET_CLASS {
@@ -46,7 +64,13 @@ pub fn get_pool_price(pool_policy: &[u8], v: &Value, rewards: &BigInt) -> Option
         quantity_a -= rewards;
     }
     let quantity_b = BigInt::from(v.get_asset_class(&coin_b));
-    Some(quantity_a.to_f64()? / quantity_b.to_f64()?)
+    Some((quantity_a, quantity_b))
+}
+
+/// The pool's price of B, in A. `None` if it holds no B.
+pub fn get_pool_price(pool_policy: &[u8], v: &Value, rewards: &BigInt) -> Option<BigRational> {
+    let (quantity_a, quantity_b) = get_pool_quantities(pool_policy, v, rewards)?;
+    BigRational::new(quantity_a, quantity_b)
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -58,28 +82,28 @@ pub enum SwapDirection {
 // Get the marginal pool price for this swap. This figure being in agreement
 // with the pool price does not guarantee that the order will succeed; for
 // instance, swap fees and finite CPP liquidity will cause the takes to be lower
-// than expected.
-pub fn swap_price(order: &OrderDatum) -> Option<(SwapDirection, f64)> {
+// than expected. The price is what the order gives per unit it takes, so there
+// is none for an order which takes nothing.
+pub fn swap_price(order: &OrderDatum) -> Option<(SwapDirection, BigRational)> {
     match &order.action {
         Order::Swap(a, b) => {
-            let gives = a.amount.clone();
-            let takes = b.amount.clone();
-            let coin_a = AssetClass::from_pair((a.policy.clone(), a.token.clone()));
-            let coin_b = AssetClass::from_pair((b.policy.clone(), b.token.clone()));
-            let mut price = gives.to_f64()? / takes.to_f64()?;
-            if takes == 0.into() {
-                price = f64::MAX;
-            }
-            if coin_a < coin_b {
-                Some((SwapDirection::AtoB, price))
-            } else {
-                Some((SwapDirection::BtoA, price))
-            }
+            let price = BigRational::new(a.amount.clone(), b.amount.clone())?;
+            Some((swap_direction(a, b), price))
         }
         _ => None,
     }
 }
 
+/// Which way a swap giving `gives` for `takes` trades through its pool, whose
+/// A is the lesser asset.
+pub fn swap_direction(gives: &SingletonValue, takes: &SingletonValue) -> SwapDirection {
+    if (&gives.policy, &gives.token) < (&takes.policy, &takes.token) {
+        SwapDirection::AtoB
+    } else {
+        SwapDirection::BtoA
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
@@ -106,7 +130,7 @@ mod tests {
         let protocol_fees = 3_000_000;
         let pool_value = value![103_000_000, (&rberry_asset_class, 100_000_000)];
         let price = get_pool_price(&pool_policy, &pool_value, &BigInt::from(protocol_fees));
-        assert_eq!(price, Some(1.0));
+        assert_eq!(price, BigRational::new(i64_to_bigint(1), i64_to_bigint(1)));
     }
 
     #[test]
@@ -121,7 +145,7 @@ mod tests {
         let protocol_fees = 3_000_000;
         let pool_value = value![103_000_000, (&rberry_asset_class, 1_000_000_000)];
         let price = get_pool_price(&pool_policy, &pool_value, &BigInt::from(protocol_fees));
-        assert_eq!(price, Some(0.1));
+        assert_eq!(price, BigRational::new(i64_to_bigint(1), i64_to_bigint(10)));
     }
 
     #[test]
@@ -152,7 +176,8 @@ mod tests {
             extra: empty_cons(),
         };
         let swap_price = swap_price(&od);
-        assert_eq!(swap_price, Some((SwapDirection::AtoB, 0.1)));
+        let tenth = BigRational::new(i64_to_bigint(1), i64_to_bigint(10)).unwrap();
+        assert_eq!(swap_price, Some((SwapDirection::AtoB, tenth)));
     }
 
     #[test]
@@ -183,6 +208,7 @@ mod tests {
             extra: empty_cons(),
         };
         let swap_price = swap_price(&od);
-        assert_eq!(swap_price, Some((SwapDirection::BtoA, 0.1)));
+        let tenth = BigRational::new(i64_to_bigint(1), i64_to_bigint(10)).unwrap();
+        assert_eq!(swap_price, Some((SwapDirection::BtoA, tenth)));
     }
 }
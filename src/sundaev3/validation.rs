@@ -1,55 +1,35 @@
 #![allow(unused)]
 
-use std::fmt;
-
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::{
-    bigint::BigInt,
+    bigint::{BigInt, BigRational},
     cardano_types::{ADA_ASSET_CLASS, AssetClass, Value},
-    sundaev3::{Order, OrderDatum, PoolDatum, SwapDirection, get_pool_price, swap_price},
+    sundaev3::{
+        Order, OrderDatum, PauseReason, PoolDatum, SwapDirection, get_pool_quantities,
+        pool_lp_asset, swap_direction, swap_price,
+    },
 };
 
-const ADA_RIDER: i128 = 2000000;
-
+/// Serializes as the inner error, e.g. `{"code": "out_of_range", ...}`.
+#[derive(Debug, Error, Serialize)]
+#[serde(untagged)]
 pub enum ValidationError {
-    ValueError(ValueError),
-    PoolError(PoolError),
+    #[error(transparent)]
+    ValueError(#[from] ValueError),
+    #[error(transparent)]
+    PoolError(#[from] PoolError),
+    #[error(transparent)]
+    OwnerError(#[from] OwnerError),
 }
 
-impl fmt::Display for ValidationError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ValidationError::PoolError(e) => match e {
-                PoolError::IdentMismatch => write!(f, "order ident does not match pool ident"),
-                PoolError::CoinPairMismatch => {
-                    write!(f, "order coin pair does not match pool coin pair")
-                }
-                PoolError::Empty => write!(f, "pool is empty"),
-                PoolError::OutOfRange {
-                    swap_price,
-                    pool_price,
-                } => {
-                    write!(
-                        f,
-                        "order out of range (swap price {swap_price}, pool price {pool_price})"
-                    )
-                }
-            },
-            ValidationError::ValueError(e) => match e {
-                ValueError::GivesZeroTokens => write!(f, "gives zero tokens"),
-                ValueError::HasInsufficientAda { expected, actual } => {
-                    write!(f, "has insufficient ada ({actual} < {expected})")
-                }
-                ValueError::DeclaredExceedsActual { declared, actual } => {
-                    write!(
-                        f,
-                        "offers value in excess of available funds ({actual} < {declared})"
-                    )
-                }
-            },
-        }
-    }
+/// What the order's owner multisig rules out, whatever the pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Error)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum OwnerError {
+    #[error("the owner's time conditions will never allow it again")]
+    Expired,
 }
 
 pub fn validate_order(
@@ -58,121 +38,187 @@ pub fn validate_order(
     pool: &PoolDatum,
     pool_value: &Value,
     policy: &[u8],
-) -> Result<(), ValidationError> {
-    validate_order_value(order, value).map_err(ValidationError::ValueError)?;
-    validate_order_for_pool(order, pool).map_err(ValidationError::PoolError)?;
-    estimate_whether_in_range(policy, order, pool, pool_value)
-        .map_err(ValidationError::PoolError)?;
-    Ok(())
+    ada_rider: u64,
+    profile: ValidationProfile,
+) -> Result<Vec<ValueError>, ValidationError> {
+    let advisories = validate_order_value(order, value, ada_rider, profile)?;
+    validate_order_for_pool(order, pool, policy)?;
+    estimate_whether_in_range(policy, order, pool, pool_value)?;
+    Ok(advisories)
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Error)]
+#[serde(tag = "code", rename_all = "snake_case")]
 pub enum ValueError {
+    #[error("gives zero tokens")]
     GivesZeroTokens,
+    #[error("has insufficient ada ({actual} < {expected})")]
     HasInsufficientAda { expected: BigInt, actual: BigInt },
+    #[error("offers value in excess of available funds ({actual} < {declared})")]
     DeclaredExceedsActual { declared: BigInt, actual: BigInt },
 }
 
-pub fn validate_order_value(datum: &OrderDatum, value: &Value) -> Result<(), ValueError> {
-    let scoop_fee = datum.scoop_fee.clone();
+/// Which value checks stop an order from being scooped. The rest are
+/// advisory: reported alongside the order, which is still scooped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValidationProfile {
+    /// Every check is unrecoverable
+    #[default]
+    Strict,
+    /// Only what the order validator rejects on-chain is unrecoverable. Orders
+    /// may declare more than they hold, and are scooped for what they have.
+    OnChainEquivalent,
+    /// Only orders which give nothing at all are unrecoverable
+    Lenient,
+}
+
+impl ValidationProfile {
+    pub fn is_advisory(self, error: &ValueError) -> bool {
+        match self {
+            Self::Strict => false,
+            Self::OnChainEquivalent => matches!(error, ValueError::DeclaredExceedsActual { .. }),
+            Self::Lenient => !matches!(error, ValueError::GivesZeroTokens),
+        }
+    }
+}
+
+/// `ada_rider` is the lovelace an order must hold beyond its scoop fee, so that
+/// whatever the scoop pays out meets the min-UTxO. Returns the first
+/// unrecoverable finding under `profile`, or else the advisory ones.
+pub fn validate_order_value(
+    datum: &OrderDatum,
+    value: &Value,
+    ada_rider: u64,
+    profile: ValidationProfile,
+) -> Result<Vec<ValueError>, ValueError> {
+    let (advisory, unrecoverable): (Vec<_>, Vec<_>) = order_value_findings(datum, value, ada_rider)
+        .into_iter()
+        .partition(|error| profile.is_advisory(error));
+    match unrecoverable.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(advisory),
+    }
+}
+
+/// Every value check the order fails, in the order they're checked.
+fn order_value_findings(datum: &OrderDatum, value: &Value, ada_rider: u64) -> Vec<ValueError> {
+    // Every order is checked on every update, so this works in place and
+    // only clones what goes into a finding
+    let mut findings = vec![];
     match &datum.action {
-        Order::Strategy(_) => Ok(()),
+        Order::Strategy(_) => {}
         Order::Swap(a, b) => {
-            let minimum_ada = BigInt::from(ADA_RIDER) + scoop_fee.clone();
-            let gives = a.amount.clone();
-            let gives_asset = AssetClass::from_pair((a.policy.clone(), a.token.clone()));
-            let gives_ada = if gives_asset == ADA_ASSET_CLASS {
-                gives.clone()
-            } else {
-                BigInt::from(0)
-            };
+            let gives = &a.amount;
+            let gives_asset = AssetClass::from_pair((&a.policy, &a.token));
             let actual_ada = BigInt::from(value.get_asset_class(&ADA_ASSET_CLASS));
-            let expected_ada = gives_ada + minimum_ada.clone();
+            let mut actual_amount_of_give_token = BigInt::from(value.get_asset_class(&gives_asset));
+            let mut expected_ada = BigInt::from(ada_rider);
+            expected_ada += &datum.scoop_fee;
+            if gives_asset == ADA_ASSET_CLASS {
+                actual_amount_of_give_token -= &expected_ada;
+                expected_ada += gives;
+            }
             if actual_ada < expected_ada {
-                return Err(ValueError::HasInsufficientAda {
+                findings.push(ValueError::HasInsufficientAda {
                     expected: expected_ada,
                     actual: actual_ada,
                 });
             }
 
-            let actual_amount_of_give_token = BigInt::from(value.get_asset_class(&gives_asset))
-                - if gives_asset == ADA_ASSET_CLASS {
-                    minimum_ada
-                } else {
-                    BigInt::from(0)
-                };
             if actual_amount_of_give_token < BigInt::from(0) {
-                return Err(ValueError::GivesZeroTokens);
-            }
-
-            if actual_amount_of_give_token < gives {
-                // This is an error in sundaedatum, even though the smart contract appears to allow it
-                return Err(ValueError::DeclaredExceedsActual {
-                    declared: gives,
+                findings.push(ValueError::GivesZeroTokens);
+            } else if &actual_amount_of_give_token < gives {
+                // The smart contract allows this, scooping only what the order holds
+                findings.push(ValueError::DeclaredExceedsActual {
+                    declared: gives.clone(),
                     actual: actual_amount_of_give_token,
                 });
             }
-            Ok(())
         }
         Order::Deposit((a, b)) => {
-            let gives_a = a.amount.clone();
-            let gives_b = b.amount.clone();
-            let asset_a = AssetClass::from_pair((a.policy.clone(), a.token.clone()));
-            let asset_b = AssetClass::from_pair((b.policy.clone(), b.token.clone()));
-            let mut actual_a = BigInt::from(value.get_asset_class(&asset_a));
-            if asset_a == ADA_ASSET_CLASS {
-                let minimum = BigInt::from(ADA_RIDER) + scoop_fee.clone();
-                if actual_a < minimum {
-                    return Err(ValueError::HasInsufficientAda {
-                        expected: minimum,
-                        actual: actual_a,
+            // The pool issues LP for the smaller side and returns the rest as
+            // change, so lopsided deposits are fine but an empty side is not
+            let zero = BigInt::from(0);
+            if a.amount <= zero || b.amount <= zero {
+                return vec![ValueError::GivesZeroTokens];
+            }
+            let actual_ada = BigInt::from(value.get_asset_class(&ADA_ASSET_CLASS));
+            let mut expected_ada = BigInt::from(ada_rider);
+            expected_ada += &datum.scoop_fee;
+            for side in [a, b] {
+                let asset = AssetClass::from_pair((&side.policy, &side.token));
+                if asset == ADA_ASSET_CLASS {
+                    expected_ada += &side.amount;
+                    continue;
+                }
+                let actual = BigInt::from(value.get_asset_class(&asset));
+                if actual < side.amount {
+                    findings.push(ValueError::DeclaredExceedsActual {
+                        declared: side.amount.clone(),
+                        actual,
                     });
                 }
-                actual_a -= minimum;
             }
-            let actual_b = BigInt::from(value.get_asset_class(&asset_b));
-
-            let deposits_zero_tokens =
-                actual_a == BigInt::from(0u64) && actual_b == BigInt::from(0u64);
-            if !deposits_zero_tokens {
-                return Err(ValueError::GivesZeroTokens);
+            if actual_ada < expected_ada {
+                findings.push(ValueError::HasInsufficientAda {
+                    expected: expected_ada,
+                    actual: actual_ada,
+                });
             }
-            Ok(())
         }
         Order::Withdrawal(singleton) => {
             if singleton.amount == BigInt::from(0) {
-                return Err(ValueError::GivesZeroTokens);
+                return vec![ValueError::GivesZeroTokens];
             }
             let actual = BigInt::from(value.get_asset_class(&AssetClass::from_pair((
-                singleton.policy.clone(),
-                singleton.token.clone(),
+                &singleton.policy,
+                &singleton.token,
             ))));
             if singleton.amount > actual {
-                return Err(ValueError::DeclaredExceedsActual {
+                findings.push(ValueError::DeclaredExceedsActual {
                     declared: singleton.amount.clone(),
                     actual,
                 });
             }
-            let expected = BigInt::from(ADA_RIDER) + scoop_fee;
+            let mut expected = BigInt::from(ada_rider);
+            expected += &datum.scoop_fee;
             let actual = BigInt::from(value.get_asset_class(&ADA_ASSET_CLASS));
             if actual < expected {
-                return Err(ValueError::HasInsufficientAda { expected, actual });
+                findings.push(ValueError::HasInsufficientAda { expected, actual });
             }
-            Ok(())
         }
-        _ => Ok(()),
+        _ => {}
     }
+    findings
 }
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Error)]
+#[serde(tag = "code", rename_all = "snake_case")]
 pub enum PoolError {
+    #[error("order ident does not match pool ident")]
     IdentMismatch,
+    #[error("order coin pair does not match pool coin pair")]
     CoinPairMismatch,
+    #[error("withdrawal does not offer the pool's LP token")]
+    NotPoolLpToken,
+    #[error("pool is empty")]
     Empty,
-    OutOfRange { swap_price: f64, pool_price: f64 },
+    #[error("pool is paused: {0}")]
+    Paused(PauseReason),
+    #[error("order out of range (swap price {swap_price}, pool price {pool_price})")]
+    OutOfRange {
+        swap_price: BigRational,
+        pool_price: BigRational,
+    },
 }
 
-pub fn validate_order_for_pool(order: &OrderDatum, pool: &PoolDatum) -> Result<(), PoolError> {
+/// `policy` is the pool script hash, which also mints the pool's LP tokens.
+pub fn validate_order_for_pool(
+    order: &OrderDatum,
+    pool: &PoolDatum,
+    policy: &[u8],
+) -> Result<(), PoolError> {
     if let Some(i) = &order.ident
         && i != &pool.ident
     {
@@ -180,8 +226,8 @@ pub fn validate_order_for_pool(order: &OrderDatum, pool: &PoolDatum) -> Result<(
     }
     match &order.action {
         Order::Swap(a, b) => {
-            let give_coin = AssetClass::from_pair((a.policy.clone(), a.token.clone()));
-            let take_coin = AssetClass::from_pair((b.policy.clone(), b.token.clone()));
+            let give_coin = AssetClass::from_pair((&a.policy, &a.token));
+            let take_coin = AssetClass::from_pair((&b.policy, &b.token));
             let matches_a_to_b = pool.assets.0 == give_coin && pool.assets.1 == take_coin;
             let matches_b_to_a = pool.assets.0 == take_coin && pool.assets.1 == give_coin;
             if !(matches_a_to_b || matches_b_to_a) {
@@ -190,8 +236,8 @@ pub fn validate_order_for_pool(order: &OrderDatum, pool: &PoolDatum) -> Result<(
             Ok(())
         }
         Order::Deposit((a, b)) => {
-            let give_coin = AssetClass::from_pair((a.policy.clone(), a.token.clone()));
-            let take_coin = AssetClass::from_pair((b.policy.clone(), b.token.clone()));
+            let give_coin = AssetClass::from_pair((&a.policy, &a.token));
+            let take_coin = AssetClass::from_pair((&b.policy, &b.token));
             let matches_a_to_b = pool.assets.0 == give_coin && pool.assets.1 == take_coin;
             let matches_b_to_a = pool.assets.0 == take_coin && pool.assets.1 == give_coin;
             if !(matches_a_to_b || matches_b_to_a) {
@@ -199,6 +245,13 @@ pub fn validate_order_for_pool(order: &OrderDatum, pool: &PoolDatum) -> Result<(
             }
             Ok(())
         }
+        Order::Withdrawal(lp) => {
+            let offered = AssetClass::from_pair((&lp.policy, &lp.token));
+            if offered != pool_lp_asset(policy, &pool.ident) {
+                return Err(PoolError::NotPoolLpToken);
+            }
+            Ok(())
+        }
         _ => Ok(()),
     }
 }
@@ -210,33 +263,37 @@ pub fn estimate_whether_in_range(
     pool_value: &Value,
 ) -> Result<(), PoolError> {
     let rewards = &pd.protocol_fees;
-    let Some(pool_price) = get_pool_price(policy, pool_value, rewards) else {
+    let Some((quantity_a, quantity_b)) = get_pool_quantities(policy, pool_value, rewards) else {
         return Err(PoolError::Empty);
     };
-    let Some(swap_price) = swap_price(od) else {
+    let Order::Swap(gives, takes) = &od.action else {
         return Ok(());
     };
-    match swap_price {
-        (SwapDirection::AtoB, swap_price) => {
-            if pool_price <= swap_price {
-                Ok(())
-            } else {
-                Err(PoolError::OutOfRange {
-                    swap_price,
-                    pool_price,
-                })
-            }
-        }
-        (SwapDirection::BtoA, swap_price) => {
-            if pool_price >= (1.0 / swap_price) {
-                Ok(())
-            } else {
-                Err(PoolError::OutOfRange {
-                    swap_price: 1.0 / swap_price,
-                    pool_price,
-                })
-            }
-        }
+    let direction = swap_direction(gives, takes);
+    // Cross-multiplied rather than divided, so large reserves can't round an
+    // order near the pool price onto the wrong side of it
+    let in_range = match direction {
+        SwapDirection::AtoB => &quantity_a * &takes.amount <= &gives.amount * &quantity_b,
+        SwapDirection::BtoA => &quantity_a * &gives.amount >= &takes.amount * &quantity_b,
+    };
+    if in_range {
+        return Ok(());
+    }
+    // Both prices are reported as A per B
+    let swap_price = swap_price(od).and_then(|(_, price)| match direction {
+        SwapDirection::AtoB => Some(price),
+        SwapDirection::BtoA => price.recip(),
+    });
+    match (swap_price, BigRational::new(quantity_a, quantity_b)) {
+        (Some(swap_price), Some(pool_price)) => Err(PoolError::OutOfRange {
+            swap_price,
+            pool_price,
+        }),
+        // The pool holds no B to trade
+        (_, None) => Err(PoolError::Empty),
+        // Only an order giving nothing has no price, and validate_order_value
+        // turns those away
+        (None, _) => Ok(()),
     }
 }
 
@@ -245,7 +302,8 @@ mod tests {
     use crate::{
         cardano_types::{ADA_POLICY, ADA_TOKEN},
         multisig::Multisig,
-        sundaev3::{Destination, SingletonValue, empty_cons},
+        protocol::DEFAULT_ADA_RIDER,
+        sundaev3::{Destination, Ident, SingletonValue, empty_cons, pool_nft_asset},
         value,
     };
 
@@ -277,8 +335,8 @@ mod tests {
             destination: Destination::SelfDestination,
             action: Order::Swap(
                 SingletonValue {
-                    policy: ADA_POLICY,
-                    token: ADA_TOKEN,
+                    policy: ADA_POLICY.to_vec(),
+                    token: ADA_TOKEN.to_vec(),
                     amount: i64_to_bigint(test_case.ada_offered),
                 },
                 SingletonValue {
@@ -294,7 +352,7 @@ mod tests {
             test_case.actual_ada,
             (&rberry_asset_class, test_case.actual_rberry)
         ];
-        validate_order_value(&order, &value).is_ok()
+        validate_order_value(&order, &value, DEFAULT_ADA_RIDER, ValidationProfile::Strict).is_ok()
     }
 
     struct ValidateRBerrySBerrySwapTestCase {
@@ -343,7 +401,7 @@ mod tests {
             (&rberry_asset_class, test_case.actual_rberry),
             (&sberry_asset_class, test_case.actual_sberry)
         ];
-        validate_order_value(&order, &value).is_ok()
+        validate_order_value(&order, &value, DEFAULT_ADA_RIDER, ValidationProfile::Strict).is_ok()
     }
 
     #[test]
@@ -374,4 +432,318 @@ mod tests {
             }
         ))
     }
+
+    #[test]
+    fn should_serialize_errors_with_codes() {
+        let json = |err: ValidationError| serde_json::to_value(err).unwrap();
+        assert_eq!(
+            json(ValueError::GivesZeroTokens.into()),
+            serde_json::json!({ "code": "gives_zero_tokens" })
+        );
+        assert_eq!(
+            json(
+                ValueError::HasInsufficientAda {
+                    expected: BigInt::from(3),
+                    actual: BigInt::from(2),
+                }
+                .into()
+            ),
+            serde_json::json!({ "code": "has_insufficient_ada", "expected": 3, "actual": 2 })
+        );
+        assert_eq!(
+            json(
+                PoolError::OutOfRange {
+                    swap_price: BigRational::new(i64_to_bigint(3), i64_to_bigint(2)).unwrap(),
+                    pool_price: BigRational::from(i64_to_bigint(2)),
+                }
+                .into()
+            ),
+            serde_json::json!({
+                "code": "out_of_range",
+                "swap_price": { "numerator": 3, "denominator": 2, "decimal": "1.5" },
+                "pool_price": { "numerator": 2, "denominator": 1, "decimal": "2" },
+            })
+        );
+    }
+
+    fn ada_rberry_swap(a_to_b: bool, gives: i64, takes: i64) -> OrderDatum {
+        let rberry = AssetClass::from_pair((vec![1; 28], b"RBERRY".to_vec()));
+        let singleton = |asset: &AssetClass, amount: i64| SingletonValue {
+            policy: asset.policy.to_vec(),
+            token: asset.token.to_vec(),
+            amount: i64_to_bigint(amount),
+        };
+        let (give_asset, take_asset) = if a_to_b {
+            (ADA_ASSET_CLASS, rberry)
+        } else {
+            (rberry, ADA_ASSET_CLASS)
+        };
+        OrderDatum {
+            ident: None,
+            owner: Multisig::Signature(vec![0]),
+            scoop_fee: i64_to_bigint(1_000_000),
+            destination: Destination::SelfDestination,
+            action: Order::Swap(singleton(&give_asset, gives), singleton(&take_asset, takes)),
+            extra: empty_cons(),
+        }
+    }
+
+    #[test]
+    fn should_compare_prices_exactly_near_the_boundary() {
+        let rberry = AssetClass::from_pair((vec![1; 28], b"RBERRY".to_vec()));
+        let pool = PoolDatum {
+            ident: Ident::new(&[3; 28]),
+            assets: (ADA_ASSET_CLASS, rberry.clone()),
+            circulating_lp: i64_to_bigint(1_000),
+            bid_fees_per_10_thousand: i64_to_bigint(30),
+            ask_fees_per_10_thousand: i64_to_bigint(30),
+            fee_manager: None,
+            market_open: i64_to_bigint(0),
+            protocol_fees: i64_to_bigint(0),
+        };
+        let two_53: i64 = 1 << 53;
+        let in_range = |order: &OrderDatum, ada: i64| {
+            estimate_whether_in_range(&[9], order, &pool, &value![ada as i128, (&rberry, 1)])
+                .is_ok()
+        };
+
+        // 2^53 + 1 lovelace per RBERRY rounds down to 2^53 as a float, which
+        // would let an order asking exactly 2^53 through
+        let a_to_b = ada_rberry_swap(true, two_53, 1);
+        assert!(!in_range(&a_to_b, two_53 + 1));
+        assert!(in_range(&a_to_b, two_53));
+        assert!(in_range(&a_to_b, two_53 - 1));
+
+        // Asking 2^53 + 1 lovelace for one RBERRY rounds down to 2^53 as a
+        // float, which would look like it matches a pool at 2^53
+        let b_to_a = ada_rberry_swap(false, 1, two_53 + 1);
+        assert!(!in_range(&b_to_a, two_53));
+        assert!(in_range(&b_to_a, two_53 + 1));
+
+        // A swap which takes nothing is in range of any pool
+        assert!(in_range(&ada_rberry_swap(false, 1, 0), 1));
+    }
+
+    #[test]
+    fn should_use_the_given_ada_rider() {
+        let lp = AssetClass::from_pair((vec![1; 28], vec![2; 4]));
+        let order = OrderDatum {
+            ident: None,
+            owner: Multisig::Signature(vec![0]),
+            scoop_fee: i64_to_bigint(1_000_000),
+            destination: Destination::SelfDestination,
+            action: Order::Withdrawal(SingletonValue {
+                policy: lp.policy.to_vec(),
+                token: lp.token.to_vec(),
+                amount: i64_to_bigint(10),
+            }),
+            extra: empty_cons(),
+        };
+        let value = value![2_500_000, (&lp, 10)];
+        assert_eq!(
+            validate_order_value(&order, &value, DEFAULT_ADA_RIDER, ValidationProfile::Strict),
+            Err(ValueError::HasInsufficientAda {
+                expected: i64_to_bigint(3_000_000),
+                actual: i64_to_bigint(2_500_000),
+            })
+        );
+        assert_eq!(
+            validate_order_value(&order, &value, 1_500_000, ValidationProfile::Strict),
+            Ok(vec![])
+        );
+    }
+
+    fn deposit(a: (&AssetClass, i64), b: (&AssetClass, i64)) -> OrderDatum {
+        let singleton = |(asset, amount): (&AssetClass, i64)| SingletonValue {
+            policy: asset.policy.to_vec(),
+            token: asset.token.to_vec(),
+            amount: i64_to_bigint(amount),
+        };
+        OrderDatum {
+            ident: None,
+            owner: Multisig::Signature(vec![0]),
+            scoop_fee: i64_to_bigint(1_000_000),
+            destination: Destination::SelfDestination,
+            action: Order::Deposit((singleton(a), singleton(b))),
+            extra: empty_cons(),
+        }
+    }
+
+    #[test]
+    fn should_validate_ada_deposits() {
+        let ada = ADA_ASSET_CLASS;
+        let rberry = AssetClass::from_pair((vec![1; 28], b"RBERRY".to_vec()));
+        let check = |gives_ada: i64, gives_rberry: i64, value: Value| {
+            validate_order_value(
+                &deposit((&ada, gives_ada), (&rberry, gives_rberry)),
+                &value,
+                DEFAULT_ADA_RIDER,
+                ValidationProfile::Strict,
+            )
+        };
+
+        assert_eq!(check(10, 20, value![3_000_010, (&rberry, 20)]), Ok(vec![]));
+        // Lopsided amounts are fine, the pool returns change
+        assert_eq!(
+            check(1, 1_000_000, value![3_000_001, (&rberry, 1_000_000)]),
+            Ok(vec![])
+        );
+        // So is dust, as long as both sides are there
+        assert_eq!(check(1, 1, value![3_000_001, (&rberry, 1)]), Ok(vec![]));
+
+        for (gives_ada, gives_rberry) in [(0, 20), (10, 0), (0, 0), (-1, 20), (10, -1)] {
+            assert_eq!(
+                check(gives_ada, gives_rberry, value![3_000_010, (&rberry, 20)]),
+                Err(ValueError::GivesZeroTokens),
+                "{gives_ada}/{gives_rberry}"
+            );
+        }
+        assert_eq!(
+            check(10, 20, value![3_000_009, (&rberry, 20)]),
+            Err(ValueError::HasInsufficientAda {
+                expected: i64_to_bigint(3_000_010),
+                actual: i64_to_bigint(3_000_009),
+            })
+        );
+        assert_eq!(
+            check(10, 20, value![3_000_010, (&rberry, 19)]),
+            Err(ValueError::DeclaredExceedsActual {
+                declared: i64_to_bigint(20),
+                actual: i64_to_bigint(19),
+            })
+        );
+    }
+
+    #[test]
+    fn should_demote_checks_by_profile() {
+        let rberry = AssetClass::from_pair((vec![1; 28], b"RBERRY".to_vec()));
+        let sberry = AssetClass::from_pair((vec![1; 28], b"SBERRY".to_vec()));
+        let order = deposit((&rberry, 10), (&sberry, 20));
+        let check = |value: Value, profile| {
+            validate_order_value(&order, &value, DEFAULT_ADA_RIDER, profile)
+        };
+        let short = ValueError::DeclaredExceedsActual {
+            declared: i64_to_bigint(10),
+            actual: i64_to_bigint(9),
+        };
+        let poor = ValueError::HasInsufficientAda {
+            expected: i64_to_bigint(3_000_000),
+            actual: i64_to_bigint(2_999_999),
+        };
+
+        let holds_less = value![3_000_000, (&rberry, 9), (&sberry, 20)];
+        assert_eq!(
+            check(holds_less.clone(), ValidationProfile::Strict),
+            Err(short.clone())
+        );
+        assert_eq!(
+            check(holds_less, ValidationProfile::OnChainEquivalent),
+            Ok(vec![short.clone()])
+        );
+
+        // A demoted check doesn't hide an unrecoverable one after it
+        let also_poor = value![2_999_999, (&rberry, 9), (&sberry, 20)];
+        assert_eq!(
+            check(also_poor.clone(), ValidationProfile::OnChainEquivalent),
+            Err(poor.clone())
+        );
+        assert_eq!(
+            check(also_poor, ValidationProfile::Lenient),
+            Ok(vec![short, poor])
+        );
+
+        let gives_nothing = deposit((&rberry, 0), (&sberry, 20));
+        assert_eq!(
+            validate_order_value(
+                &gives_nothing,
+                &value![3_000_000, (&sberry, 20)],
+                DEFAULT_ADA_RIDER,
+                ValidationProfile::Lenient,
+            ),
+            Err(ValueError::GivesZeroTokens)
+        );
+    }
+
+    #[test]
+    fn should_validate_token_deposits() {
+        let rberry = AssetClass::from_pair((vec![1; 28], b"RBERRY".to_vec()));
+        let sberry = AssetClass::from_pair((vec![1; 28], b"SBERRY".to_vec()));
+        let order = deposit((&rberry, 10), (&sberry, 20));
+        let check = |value: Value| {
+            validate_order_value(&order, &value, DEFAULT_ADA_RIDER, ValidationProfile::Strict)
+        };
+
+        assert_eq!(
+            check(value![3_000_000, (&rberry, 10), (&sberry, 20)]),
+            Ok(vec![])
+        );
+        // Neither side is ADA, but the rider and scoop fee are still owed
+        assert_eq!(
+            check(value![2_999_999, (&rberry, 10), (&sberry, 20)]),
+            Err(ValueError::HasInsufficientAda {
+                expected: i64_to_bigint(3_000_000),
+                actual: i64_to_bigint(2_999_999),
+            })
+        );
+        assert_eq!(
+            check(value![3_000_000, (&rberry, 9), (&sberry, 20)]),
+            Err(ValueError::DeclaredExceedsActual {
+                declared: i64_to_bigint(10),
+                actual: i64_to_bigint(9),
+            })
+        );
+        assert_eq!(
+            check(value![3_000_000, (&rberry, 10)]),
+            Err(ValueError::DeclaredExceedsActual {
+                declared: i64_to_bigint(20),
+                actual: i64_to_bigint(0),
+            })
+        );
+    }
+
+    #[test]
+    fn should_only_withdraw_the_pools_lp_token() {
+        let pool_policy = vec![9; 28];
+        let pool = PoolDatum {
+            ident: Ident::new(&[1, 2, 3]),
+            assets: (
+                ADA_ASSET_CLASS,
+                AssetClass::from_pair((vec![1; 28], b"RBERRY".to_vec())),
+            ),
+            circulating_lp: i64_to_bigint(1_000),
+            bid_fees_per_10_thousand: i64_to_bigint(30),
+            ask_fees_per_10_thousand: i64_to_bigint(30),
+            fee_manager: None,
+            market_open: i64_to_bigint(0),
+            protocol_fees: i64_to_bigint(0),
+        };
+        let withdraw = |asset: AssetClass| OrderDatum {
+            ident: None,
+            owner: Multisig::Signature(vec![0]),
+            scoop_fee: i64_to_bigint(1_000_000),
+            destination: Destination::SelfDestination,
+            action: Order::Withdrawal(SingletonValue {
+                policy: asset.policy.to_vec(),
+                token: asset.token.to_vec(),
+                amount: i64_to_bigint(10),
+            }),
+            extra: empty_cons(),
+        };
+        let lp = pool_lp_asset(&pool_policy, &pool.ident);
+        assert_eq!(lp.token, hex::decode("0014df10010203").unwrap());
+        assert_eq!(
+            validate_order_for_pool(&withdraw(lp.clone()), &pool, &pool_policy),
+            Ok(())
+        );
+
+        let other_pool = pool_lp_asset(&pool_policy, &Ident::new(&[4]));
+        let nft = pool_nft_asset(&pool_policy, &pool.ident);
+        let wrong_policy = AssetClass::from_pair((vec![8; 28], lp.token));
+        for asset in [other_pool, nft, wrong_policy] {
+            assert_eq!(
+                validate_order_for_pool(&withdraw(asset), &pool, &pool_policy),
+                Err(PoolError::NotPoolLpToken)
+            );
+        }
+    }
 }
@@ -0,0 +1,122 @@
+use pallas_primitives::{Fragment, MaybeIndefArray, PlutusData};
+use plutus_parser::AsPlutus;
+use serde::{Serialize, Serializer, ser::SerializeSeq};
+
+use crate::sundaev3::{OrderDatum, PoolDatum};
+
+/// A datum whose constructor has a fixed set of known fields. Later versions of
+/// the protocol may append fields to it, which we carry along untouched.
+pub trait DatumLayout: AsPlutus {
+    const FIELDS: usize;
+}
+
+impl DatumLayout for PoolDatum {
+    const FIELDS: usize = 8;
+}
+
+impl DatumLayout for OrderDatum {
+    const FIELDS: usize = 6;
+}
+
+/// Trailing fields past the end of a datum's known layout.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DatumExtension(pub Vec<PlutusData>);
+
+impl DatumExtension {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Serialize for DatumExtension {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for field in &self.0 {
+            let bytes = field.encode_fragment().map_err(serde::ser::Error::custom)?;
+            seq.serialize_element(&hex::encode(bytes))?;
+        }
+        seq.end()
+    }
+}
+
+/// A datum parsed leniently: any fields after the ones we know about are kept
+/// in `datum_extension` instead of failing the parse.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Versioned<T> {
+    #[serde(flatten)]
+    pub datum: T,
+    #[serde(skip_serializing_if = "DatumExtension::is_empty")]
+    pub datum_extension: DatumExtension,
+}
+
+impl<T: DatumLayout> AsPlutus for Versioned<T> {
+    fn from_plutus(data: PlutusData) -> Result<Self, plutus_parser::DecodeError> {
+        let (known, datum_extension) = split_extension(data, T::FIELDS);
+        Ok(Self {
+            datum: T::from_plutus(known)?,
+            datum_extension,
+        })
+    }
+
+    fn to_plutus(self) -> PlutusData {
+        let mut data = self.datum.to_plutus();
+        if let PlutusData::Constr(constr) = &mut data {
+            match &mut constr.fields {
+                MaybeIndefArray::Def(fields) | MaybeIndefArray::Indef(fields) => {
+                    fields.extend(self.datum_extension.0)
+                }
+            }
+        }
+        data
+    }
+}
+
+fn split_extension(data: PlutusData, known_fields: usize) -> (PlutusData, DatumExtension) {
+    let PlutusData::Constr(mut constr) = data else {
+        return (data, DatumExtension::default());
+    };
+    let extension = match &mut constr.fields {
+        MaybeIndefArray::Def(fields) | MaybeIndefArray::Indef(fields)
+            if fields.len() > known_fields =>
+        {
+            fields.split_off(known_fields)
+        }
+        _ => vec![],
+    };
+    (PlutusData::Constr(constr), DatumExtension(extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL_DATUM: &str = "d8799f581cba228444515fbefd2c8725338e49589f206c7f18a33e002b157aac3c9f9f4040ff9f581c99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e1546534245525259ffff1a01c9c3801901f41901f4d8799fd87f9f581ce8dc0595c8d3a7e2c0323a11f5519c32d3b3fb7a994519e38b698b5dffff001a003d0900ff";
+
+    #[test]
+    fn should_parse_current_datum_without_extension() {
+        let pd: PlutusData = minicbor::decode(&hex::decode(POOL_DATUM).unwrap()).unwrap();
+        let pool: Versioned<PoolDatum> = AsPlutus::from_plutus(pd.clone()).unwrap();
+        assert!(pool.datum_extension.is_empty());
+        assert_eq!(pool.datum, PoolDatum::from_plutus(pd.clone()).unwrap());
+        assert_eq!(pool.to_plutus(), pd);
+    }
+
+    #[test]
+    fn should_preserve_trailing_fields() {
+        // The same pool datum, with two extra fields appended before the break
+        let extended = format!("{}4201020a{}", &POOL_DATUM[..POOL_DATUM.len() - 2], "ff");
+        let pd: PlutusData = minicbor::decode(&hex::decode(extended).unwrap()).unwrap();
+        assert!(PoolDatum::from_plutus(pd.clone()).is_err());
+
+        let pool: Versioned<PoolDatum> = AsPlutus::from_plutus(pd.clone()).unwrap();
+        assert_eq!(pool.datum_extension.0.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&pool.datum_extension).unwrap(),
+            serde_json::json!(["420102", "0a"])
+        );
+        assert_eq!(pool.to_plutus(), pd);
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use serde::Deserialize;
+use tracing::warn;
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct TelemetryConfig {
+    /// Which logs to write, as in `RUST_LOG`
+    pub log_filter: String,
+    /// Where to export traces over OTLP/HTTP, e.g.
+    /// `http://localhost:4318/v1/traces`. Needs the `otel` feature.
+    pub otlp_endpoint: Option<String>,
+    /// The `service.name` traces are exported under
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            log_filter: "info".to_string(),
+            otlp_endpoint: None,
+            service_name: "scooper-v2".to_string(),
+        }
+    }
+}
+
+/// Exports whatever traces are still buffered when dropped, so it should live
+/// as long as `main`.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+/// Starts logging and, if an endpoint is configured, trace export.
+pub fn init(config: &TelemetryConfig) -> Result<Telemetry> {
+    let registry = tracing_subscriber::registry()
+        .with(EnvFilter::try_new(&config.log_filter)?)
+        .with(tracing_subscriber::fmt::layer());
+
+    #[cfg(feature = "otel")]
+    let provider = config
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| otel::provider(endpoint, &config.service_name))
+        .transpose()?;
+    #[cfg(feature = "otel")]
+    let registry = registry.with(provider.as_ref().map(otel::layer));
+
+    registry.try_init()?;
+    #[cfg(not(feature = "otel"))]
+    if config.otlp_endpoint.is_some() {
+        warn!("otlp-endpoint is set, but traces can't be exported without the otel feature");
+    }
+    Ok(Telemetry {
+        #[cfg(feature = "otel")]
+        provider,
+    })
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take()
+            && let Err(error) = provider.shutdown()
+        {
+            warn!("could not export the last traces: {error}");
+        }
+    }
+}
+
+/// The trace the current span belongs to, as hex, so it can be passed on to
+/// whatever consumes our events. `None` when traces aren't exported.
+#[cfg(feature = "otel")]
+pub fn trace_id() -> Option<String> {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn trace_id() -> Option<String> {
+    None
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use anyhow::Result;
+    use opentelemetry::{KeyValue, trace::TracerProvider as _};
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{
+        Resource, runtime,
+        trace::{Tracer, TracerProvider},
+    };
+    use tracing::Subscriber;
+    use tracing_opentelemetry::OpenTelemetryLayer;
+    use tracing_subscriber::registry::LookupSpan;
+
+    pub fn provider(endpoint: &str, service_name: &str) -> Result<TracerProvider> {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()?;
+        Ok(TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new([KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )]))
+            .build())
+    }
+
+    pub fn layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer("scooper-v2"))
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Context, Result, anyhow, bail};
+use bech32::{ToBase32, Variant};
+use serde::Deserialize;
+
+use crate::{
+    bigint::BigInt,
+    cardano_types::{ADA_ASSET_CLASS, AssetClass, TransactionInput, Value},
+    protocol::SundaeV3Protocol,
+    sundaev3::SundaeV3State,
+};
+
+/// How many UTxOs Blockfrost returns per page, at most.
+const PAGE_SIZE: usize = 100;
+
+/// Reads the UTxO set from a Blockfrost-compatible API.
+pub struct Blockfrost {
+    client: reqwest::Client,
+    url: String,
+    project_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BlockfrostUtxo {
+    tx_hash: String,
+    output_index: u64,
+    amount: Vec<BlockfrostAmount>,
+}
+
+#[derive(Deserialize)]
+struct BlockfrostAmount {
+    unit: String,
+    quantity: String,
+}
+
+#[derive(Deserialize)]
+struct BlockfrostBlock {
+    slot: Option<u64>,
+}
+
+impl Blockfrost {
+    pub fn new(url: &str, project_id: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            project_id,
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<reqwest::Response> {
+        let mut request = self.client.get(format!("{}{path}", self.url));
+        if let Some(project_id) = &self.project_id {
+            request = request.header("project_id", project_id);
+        }
+        Ok(request.send().await?)
+    }
+
+    /// The slot of the latest block the source has seen.
+    pub async fn tip_slot(&self) -> Result<u64> {
+        let response = self.get("/blocks/latest").await?.error_for_status()?;
+        let block: BlockfrostBlock = serde_json::from_slice(&response.bytes().await?)?;
+        block.slot.context("latest block has no slot")
+    }
+
+    /// Every UTxO locked by a script, whatever its stake credential.
+    pub async fn utxos_at_script(
+        &self,
+        script_hash: &[u8],
+    ) -> Result<BTreeMap<TransactionInput, Value>> {
+        let credential = bech32::encode("script", script_hash.to_base32(), Variant::Bech32)?;
+        let mut utxos = BTreeMap::new();
+        for page in 1.. {
+            let response = self
+                .get(&format!(
+                    "/addresses/{credential}/utxos?count={PAGE_SIZE}&page={page}"
+                ))
+                .await?;
+            // Addresses which have never been used aren't found
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                break;
+            }
+            let body = response.error_for_status()?.bytes().await?;
+            let batch: Vec<BlockfrostUtxo> = serde_json::from_slice(&body)?;
+            let done = batch.len() < PAGE_SIZE;
+            for utxo in batch {
+                let tx_hash: [u8; 32] = hex::decode(&utxo.tx_hash)?
+                    .try_into()
+                    .map_err(|_| anyhow!("invalid tx hash {}", utxo.tx_hash))?;
+                let input = TransactionInput::new(tx_hash.into(), utxo.output_index);
+                utxos.insert(input, parse_amounts(&utxo.amount)?);
+            }
+            if done {
+                break;
+            }
+        }
+        Ok(utxos)
+    }
+
+    /// The UTxOs at every script the index tracks.
+    pub async fn tracked_utxos(
+        &self,
+        protocol: &SundaeV3Protocol,
+    ) -> Result<BTreeMap<TransactionInput, Value>> {
+        let mut utxos = self.utxos_at_script(&protocol.pool_script_hash).await?;
+        utxos.append(&mut self.utxos_at_script(&protocol.order_script_hash).await?);
+        if let Some(hash) = &protocol.settings_script_hash {
+            utxos.append(&mut self.utxos_at_script(hash).await?);
+        }
+        Ok(utxos)
+    }
+}
+
+fn parse_amounts(amounts: &[BlockfrostAmount]) -> Result<Value> {
+    let mut value = Value::new();
+    for amount in amounts {
+        let quantity = amount.quantity.parse()?;
+        let asset = if amount.unit == "lovelace" {
+            ADA_ASSET_CLASS
+        } else {
+            let unit = hex::decode(&amount.unit)?;
+            if unit.len() < 28 {
+                bail!("invalid unit {}", amount.unit);
+            }
+            let (policy, token) = unit.split_at(28);
+            AssetClass::from_pair((policy, token))
+        };
+        value.insert(&asset, quantity);
+    }
+    Ok(value)
+}
+
+/// Where our state and the chain disagree.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Drift {
+    /// On chain, but not in our state
+    pub missing: Vec<TransactionInput>,
+    /// In our state, but spent or never created on chain
+    pub extra: Vec<TransactionInput>,
+    /// In both, holding different amounts, with ours minus theirs per asset
+    pub mismatched: Vec<(TransactionInput, Vec<(AssetClass, BigInt)>)>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// The UTxOs our state holds. Settings don't keep their value, so only their
+/// presence can be checked.
+pub fn state_utxos(state: &SundaeV3State) -> BTreeMap<TransactionInput, Option<Value>> {
+    let pools = state
+        .pools
+        .values()
+        .map(|pool| (pool.input.clone(), Some(pool.value.clone())));
+    let orders = state
+        .orders
+        .iter()
+        .map(|order| (order.input.clone(), Some(order.output.value.clone())));
+    let settings = state
+        .settings
+        .iter()
+        .map(|settings| (settings.input.clone(), None));
+    pools.chain(orders).chain(settings).collect()
+}
+
+pub fn diff(
+    ours: &BTreeMap<TransactionInput, Option<Value>>,
+    theirs: &BTreeMap<TransactionInput, Value>,
+) -> Drift {
+    let inputs: BTreeSet<_> = ours.keys().chain(theirs.keys()).collect();
+    let mut drift = Drift::default();
+    for input in inputs {
+        match (ours.get(input), theirs.get(input)) {
+            (None, Some(_)) => drift.missing.push(input.clone()),
+            (Some(_), None) => drift.extra.push(input.clone()),
+            (Some(Some(ours)), Some(theirs)) => {
+                let deltas = ours.diff(theirs);
+                if !deltas.is_empty() {
+                    drift.mismatched.push((input.clone(), deltas));
+                }
+            }
+            _ => {}
+        }
+    }
+    drift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value;
+
+    #[test]
+    fn should_parse_blockfrost_amounts() -> Result<()> {
+        let rberry = AssetClass::from_pair((vec![1; 28], b"RBERRY".to_vec()));
+        let amounts = [
+            BlockfrostAmount {
+                unit: "lovelace".to_string(),
+                quantity: "2000000".to_string(),
+            },
+            BlockfrostAmount {
+                unit: format!("{}{}", hex::encode([1; 28]), hex::encode("RBERRY")),
+                quantity: "5".to_string(),
+            },
+        ];
+        assert_eq!(parse_amounts(&amounts)?, value![2_000_000, (&rberry, 5)]);
+        Ok(())
+    }
+
+    #[test]
+    fn should_report_drift() {
+        let input = |index| TransactionInput::new([1; 32].into(), index);
+        let ada = |lovelace| {
+            let mut value = Value::new();
+            value.insert(&ADA_ASSET_CLASS, lovelace);
+            value
+        };
+        let ours = BTreeMap::from([
+            (input(0), Some(ada(10))),
+            (input(1), Some(ada(10))),
+            (input(2), None),
+            (input(3), Some(ada(10))),
+        ]);
+        let theirs = BTreeMap::from([
+            (input(0), ada(10)),
+            (input(1), ada(12)),
+            (input(2), ada(5)),
+            (input(4), ada(10)),
+        ]);
+        assert_eq!(
+            diff(&ours, &theirs),
+            Drift {
+                missing: vec![input(4)],
+                extra: vec![input(3)],
+                mismatched: vec![(input(1), vec![(ADA_ASSET_CLASS, BigInt::from(-2))])],
+            }
+        );
+    }
+}
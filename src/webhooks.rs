@@ -0,0 +1,324 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Result, bail};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::{select, sync::broadcast};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, info_span, warn};
+
+use crate::{
+    backoff::Backoff,
+    config::{WebhookConfig, WebhookRetryConfig},
+    sundaev3::OrderEvent,
+};
+
+pub const SIGNATURE_HEADER: &str = "X-Scooper-Signature";
+/// Unix seconds when the delivery was signed, which the signature covers.
+pub const TIMESTAMP_HEADER: &str = "X-Scooper-Timestamp";
+/// How far a delivery's timestamp may be from the receiver's clock. Receivers
+/// should reject anything older, so a captured delivery can't be replayed.
+pub const TIMESTAMP_TOLERANCE_SECS: i64 = 300;
+
+/// How deliveries to each webhook have gone, keyed by URL.
+pub type WebhookStatuses = Arc<Mutex<BTreeMap<String, DeliveryStatus>>>;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DeliveryStatus {
+    pub delivered: u64,
+    pub failed: u64,
+    pub last_delivered_slot: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+struct Webhook {
+    url: String,
+    secret: Vec<u8>,
+    pools: Vec<Vec<u8>>,
+    owners: Vec<Vec<u8>>,
+    max_attempts: u32,
+    retry: WebhookRetryConfig,
+}
+
+impl Webhook {
+    fn new(config: &WebhookConfig) -> Result<Self> {
+        if config.max_attempts == 0 {
+            bail!("webhook {} must allow at least one attempt", config.url);
+        }
+        let decode = |values: &[String]| -> Result<Vec<Vec<u8>>> {
+            Ok(values.iter().map(hex::decode).collect::<Result<_, _>>()?)
+        };
+        Ok(Self {
+            url: config.url.clone(),
+            secret: config.secret.clone().into_bytes(),
+            pools: decode(&config.pools)?,
+            owners: decode(&config.owners)?,
+            max_attempts: config.max_attempts,
+            retry: config.retry.clone(),
+        })
+    }
+
+    fn matches(&self, event: &OrderEvent) -> bool {
+        let pool_matches = self.pools.is_empty()
+            || event
+                .datum
+                .ident
+                .as_ref()
+                .is_some_and(|ident| self.pools.iter().any(|p| p == ident.to_bytes()));
+        let owner_matches =
+            self.owners.is_empty() || self.owners.iter().any(|o| event.datum.owner.mentions(o));
+        pool_matches && owner_matches
+    }
+}
+
+/// Hex HMAC-SHA256 of `<timestamp>.<payload>`, so receivers can check it
+/// came from us, and when.
+pub fn sign(secret: &[u8], timestamp: i64, payload: &[u8]) -> String {
+    hex::encode(mac(secret, timestamp, payload).finalize().into_bytes())
+}
+
+/// Whether `signature` is ours for the payload, signed within
+/// [`TIMESTAMP_TOLERANCE_SECS`] of `now`. What a receiver should check.
+pub fn verify(secret: &[u8], timestamp: i64, payload: &[u8], signature: &str, now: i64) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    (now - timestamp).abs() <= TIMESTAMP_TOLERANCE_SECS
+        && mac(secret, timestamp, payload)
+            .verify_slice(&signature)
+            .is_ok()
+}
+
+fn mac(secret: &[u8], timestamp: i64, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    mac
+}
+
+/// POSTs order events to every webhook whose filters they match.
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    webhooks: Vec<Arc<Webhook>>,
+    statuses: WebhookStatuses,
+}
+
+impl WebhookDispatcher {
+    pub fn new(configs: &[WebhookConfig]) -> Result<Self> {
+        let webhooks = configs
+            .iter()
+            .map(|c| Webhook::new(c).map(Arc::new))
+            .collect::<Result<Vec<_>>>()?;
+        let statuses = webhooks
+            .iter()
+            .map(|w| (w.url.clone(), DeliveryStatus::default()))
+            .collect();
+        Ok(Self {
+            client: reqwest::Client::new(),
+            webhooks,
+            statuses: Arc::new(Mutex::new(statuses)),
+        })
+    }
+
+    pub fn statuses(&self) -> WebhookStatuses {
+        self.statuses.clone()
+    }
+
+    pub async fn run(
+        self,
+        mut events: broadcast::Receiver<OrderEvent>,
+        shutdown: CancellationToken,
+    ) {
+        loop {
+            let event = select! {
+                res = events.recv() => res,
+                _ = shutdown.cancelled() => break,
+            };
+            let event = match event {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("webhooks fell behind, dropped {skipped} order events");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let payload = match serde_json::to_vec(&event) {
+                Ok(payload) => Arc::new(payload),
+                Err(err) => {
+                    warn!(order = %event.order, "could not serialize order event: {err:#}");
+                    continue;
+                }
+            };
+            for webhook in self.webhooks.iter().filter(|w| w.matches(&event)) {
+                let span = info_span!(
+                    "webhook",
+                    url = %webhook.url,
+                    order = %event.order,
+                    trace_id = event.trace_id.as_deref()
+                );
+                tokio::spawn(
+                    deliver(
+                        self.client.clone(),
+                        webhook.clone(),
+                        payload.clone(),
+                        event.slot,
+                        self.statuses.clone(),
+                        shutdown.child_token(),
+                    )
+                    .instrument(span),
+                );
+            }
+        }
+    }
+}
+
+async fn deliver(
+    client: reqwest::Client,
+    webhook: Arc<Webhook>,
+    payload: Arc<Vec<u8>>,
+    slot: u64,
+    statuses: WebhookStatuses,
+    shutdown: CancellationToken,
+) {
+    let mut backoff = Backoff::with_limits(
+        webhook.retry.initial_backoff_secs,
+        webhook.retry.max_backoff_secs,
+        webhook.max_attempts,
+    );
+    loop {
+        // Signed afresh for each attempt, so a late retry isn't turned away
+        let timestamp = Utc::now().timestamp();
+        let signature = sign(&webhook.secret, timestamp, &payload);
+        let result = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, format!("sha256={signature}"))
+            .header(TIMESTAMP_HEADER, timestamp)
+            .body(payload.as_ref().clone())
+            .send()
+            .await
+            .and_then(|res| res.error_for_status());
+        let error = match result {
+            Ok(_) => {
+                let mut statuses = statuses.lock().unwrap();
+                let status = statuses.entry(webhook.url.clone()).or_default();
+                status.delivered += 1;
+                status.last_delivered_slot = Some(slot);
+                return;
+            }
+            Err(err) => err,
+        };
+        let Some(delay) = backoff.next_delay() else {
+            warn!(
+                url = %webhook.url,
+                "giving up on webhook delivery: {error:#}"
+            );
+            let mut statuses = statuses.lock().unwrap();
+            let status = statuses.entry(webhook.url.clone()).or_default();
+            status.failed += 1;
+            status.last_error = Some(error.to_string());
+            return;
+        };
+        warn!(
+            url = %webhook.url,
+            "webhook delivery failed, retrying in {delay:?}: {error:#}"
+        );
+        select! {
+            _ = tokio::time::sleep(delay) => {},
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cardano_types::ADA_ASSET_CLASS,
+        multisig::Multisig,
+        sundaev3::{
+            Ident, Order, OrderDatum, OrderOutcome,
+            test_utils::{self, datum},
+        },
+    };
+
+    fn config(pools: &[&str], owners: &[&str]) -> WebhookConfig {
+        WebhookConfig {
+            url: "http://localhost/hook".to_string(),
+            secret: "key".to_string(),
+            pools: pools.iter().map(|p| p.to_string()).collect(),
+            owners: owners.iter().map(|o| o.to_string()).collect(),
+            max_attempts: 1,
+            retry: WebhookRetryConfig::default(),
+        }
+    }
+
+    fn event(ident: Option<&[u8]>, owner: Multisig) -> OrderEvent {
+        let datum = OrderDatum {
+            ident: ident.map(Ident::new),
+            owner,
+            ..datum(Order::Record(ADA_ASSET_CLASS))
+        };
+        test_utils::event(OrderOutcome::Scooped, datum)
+    }
+
+    #[test]
+    fn should_filter_by_pool_and_owner() -> Result<()> {
+        let owner = Multisig::AnyOf(vec![
+            Multisig::Signature(vec![0xaa]),
+            Multisig::Script(vec![0xbb]),
+        ]);
+        let scooped = event(Some(&[0x01]), owner.clone());
+        let unassigned = event(None, owner);
+
+        let everything = Webhook::new(&config(&[], &[]))?;
+        assert!(everything.matches(&scooped));
+        assert!(everything.matches(&unassigned));
+
+        let by_pool = Webhook::new(&config(&["01"], &[]))?;
+        assert!(by_pool.matches(&scooped));
+        assert!(!by_pool.matches(&unassigned));
+        assert!(!Webhook::new(&config(&["02"], &[]))?.matches(&scooped));
+
+        assert!(Webhook::new(&config(&[], &["bb"]))?.matches(&scooped));
+        assert!(!Webhook::new(&config(&["01"], &["cc"]))?.matches(&scooped));
+
+        assert!(Webhook::new(&config(&["zz"], &[])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_sign_payload() {
+        assert_eq!(
+            sign(
+                b"key",
+                1_700_000_000,
+                b"The quick brown fox jumps over the lazy dog"
+            ),
+            "2f658d6aef4f246e91cd741bbcded7479e9605f9d41c9e248122a117e0e1765b"
+        );
+    }
+
+    #[test]
+    fn should_verify_only_recent_untampered_payloads() {
+        let signed = 1_700_000_000;
+        let signature = sign(b"key", signed, b"payload");
+        assert!(verify(b"key", signed, b"payload", &signature, signed + 300));
+        assert!(!verify(
+            b"key",
+            signed,
+            b"payload",
+            &signature,
+            signed + 301
+        ));
+        assert!(!verify(b"key", signed + 1, b"payload", &signature, signed));
+        assert!(!verify(b"key", signed, b"tampered", &signature, signed));
+        assert!(!verify(b"other", signed, b"payload", &signature, signed));
+    }
+}